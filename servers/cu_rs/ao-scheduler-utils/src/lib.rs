@@ -13,6 +13,8 @@ mod locate;
 mod dal;
 pub mod index_common;
 
+pub use locate::LocateMaker;
+
 /// lib.rs is effectively index.js
 
 static CONNECT: OnceCell<ConnectReturn> = OnceCell::new();
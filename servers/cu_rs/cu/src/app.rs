@@ -3,7 +3,9 @@ use actix_cors::Cors;
 use actix_web::{ HttpServer, App, http::header, middleware::Logger, web };
 use log::info;
 use dotenv::dotenv;
-use crate::{app_state::AppState, config::get_domain_config_schema, routes::index::index};
+use ao_common::domain::UnitLog;
+use crate::{app_state::AppState, config::{get_domain_config_schema, get_server_config_schema}, routes::index::index};
+use crate::domain::{client::arweave::InternalArweave, DomainContext};
 
 pub async fn server() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
@@ -13,8 +15,16 @@ pub async fn server() -> std::io::Result<()> {
     let host = env::var("HOST").unwrap();
     let port = env::var("PORT").unwrap().parse::<u16>().unwrap();
 
+    let domain = get_domain_config_schema(true).clone().unwrap();  // todo: update how this is set later
+    let server_config = get_server_config_schema(true).as_ref().unwrap();
+    let ctx = DomainContext::new(
+        InternalArweave::new(&server_config.WALLET_FILE, &server_config.UPLOADER_URL),
+        domain.clone(),
+        UnitLog::init()
+    );
     let app_data = actix_web::web::Data::new(AppState {
-        domain: get_domain_config_schema(true).clone().unwrap()  // todo: update how this is set later
+        domain,
+        apis: DomainContext::create_api(ctx).await
     });
 
     _ = HttpServer::new(move || {
@@ -1,5 +1,6 @@
-use crate::domain::model::domain_config_schema::DomainConfigSchema;
+use crate::domain::{model::domain_config_schema::DomainConfigSchema, Apis};
 
 pub struct AppState {
-    pub domain: DomainConfigSchema
+    pub domain: DomainConfigSchema,
+    pub apis: Apis
 }
\ No newline at end of file
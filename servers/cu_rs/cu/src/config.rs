@@ -290,6 +290,32 @@ fn get_start_config_env(is_development: bool) -> StartConfigEnv {
                         )
                 )
                 .or(Some("0".to_string())), // disabled
+            DRY_RUN_MAX_EXECUTION_TIME: env::var("DRY_RUN_MAX_EXECUTION_TIME")
+                .ok()
+                .and_then(|ec| 
+                    ec.is_empty()
+                        .then_some("60_000".to_string())
+                        .or(
+                            ec.parse::<i64>()
+                                .ok()
+                                .and_then(|val| Some(val.to_string()))
+                                .or(Some("60_000".to_string()))
+                        )
+                )
+                .or(Some("60_000".to_string())), // 1 minute
+            DRY_RUN_MEMORY_MAX_LIMIT: env::var("DRY_RUN_MEMORY_MAX_LIMIT")
+                .ok()
+                .and_then(|ec| 
+                    ec.is_empty()
+                        .then_some("100_000_000".to_string())
+                        .or(
+                            ec.parse::<i64>()
+                                .ok()
+                                .and_then(|val| Some(val.to_string()))
+                                .or(Some("100_000_000".to_string()))
+                        )
+                )
+                .or(Some("100_000_000".to_string())), // 100MB
             RESTRICT_PROCESSES: env::var("RESTRICT_PROCESSES") // this is a comma delimited list!
                 .ok()
                 .and_then(|pi|
@@ -565,6 +591,32 @@ fn get_start_config_env(is_development: bool) -> StartConfigEnv {
                         )
                 )
                 .or(Some("0".to_string())), // disabled
+            DRY_RUN_MAX_EXECUTION_TIME: env::var("DRY_RUN_MAX_EXECUTION_TIME")
+                .ok()
+                .and_then(|ec| 
+                    ec.is_empty()
+                        .then_some("60_000".to_string())
+                        .or(
+                            ec.parse::<i64>()
+                                .ok()
+                                .and_then(|val| Some(val.to_string()))
+                                .or(Some("60_000".to_string()))
+                        )
+                )
+                .or(Some("60_000".to_string())), // 1 minute
+            DRY_RUN_MEMORY_MAX_LIMIT: env::var("DRY_RUN_MEMORY_MAX_LIMIT")
+                .ok()
+                .and_then(|ec| 
+                    ec.is_empty()
+                        .then_some("100_000_000".to_string())
+                        .or(
+                            ec.parse::<i64>()
+                                .ok()
+                                .and_then(|val| Some(val.to_string()))
+                                .or(Some("100_000_000".to_string()))
+                        )
+                )
+                .or(Some("100_000_000".to_string())), // 100MB
             RESTRICT_PROCESSES: env::var("RESTRICT_PROCESSES") // this is a comma delimited list!
                 .ok()
                 .and_then(|pi|
@@ -627,6 +679,8 @@ pub struct StartConfigEnv {
     pub PROCESS_MEMORY_CACHE_FILE_DIR: Option<String>,
     pub PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: Option<String>,
     pub BUSY_THRESHOLD: Option<String>, // process.env.BUSY_THRESHOLD || 0 // disabled
+    pub DRY_RUN_MAX_EXECUTION_TIME: Option<String>, // 60_000, // 1 minute
+    pub DRY_RUN_MEMORY_MAX_LIMIT: Option<String>, // 100_000_000, // 100MB
     pub RESTRICT_PROCESSES: Option<String>,
     pub ALLOW_PROCESSES: Option<String>
 }
@@ -663,6 +717,8 @@ pub struct ConfigEnv {
     pub PROCESS_MEMORY_CACHE_FILE_DIR: String,
     pub PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: i64, 
     pub BUSY_THRESHOLD: i64, // process.env.BUSY_THRESHOLD || 0 // disabled
+    pub DRY_RUN_MAX_EXECUTION_TIME: i64, // 60_000, // 1 minute
+    pub DRY_RUN_MEMORY_MAX_LIMIT: i64, // 100_000_000, // 100MB
     pub RESTRICT_PROCESSES: Vec<String>,
     pub ALLOW_PROCESSES: Vec<String>
 }
@@ -699,6 +755,8 @@ impl ConfigEnv {
             PROCESS_MEMORY_CACHE_FILE_DIR: final_server_config.base.PROCESS_MEMORY_CACHE_FILE_DIR,
             PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: final_server_config.base.PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL,
             BUSY_THRESHOLD: final_server_config.base.BUSY_THRESHOLD,
+            DRY_RUN_MAX_EXECUTION_TIME: final_server_config.base.DRY_RUN_MAX_EXECUTION_TIME,
+            DRY_RUN_MEMORY_MAX_LIMIT: final_server_config.base.DRY_RUN_MEMORY_MAX_LIMIT,
             RESTRICT_PROCESSES: final_server_config.base.RESTRICT_PROCESSES,
             ALLOW_PROCESSES: final_server_config.base.ALLOW_PROCESSES
         }
@@ -737,6 +795,8 @@ impl Default for ConfigEnv {
             PROCESS_MEMORY_CACHE_FILE_DIR: "".to_string(),
             PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: 0,
             BUSY_THRESHOLD: 0,
+            DRY_RUN_MAX_EXECUTION_TIME: 0,
+            DRY_RUN_MEMORY_MAX_LIMIT: 0,
             RESTRICT_PROCESSES: vec![],
             ALLOW_PROCESSES: vec![]
         }
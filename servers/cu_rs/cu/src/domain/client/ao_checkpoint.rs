@@ -0,0 +1,593 @@
+use std::{cmp::Ordering, io::{Read, Write}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use ao_common::{domain::dal::Log, models::gql_models::{Node, TransactionConnectionSchema}};
+use bundlr_sdk::tags::Tag;
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use moka::future::Cache;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::domain::{
+    client::{arweave::InternalArweave, ao_su::find_tag_value},
+    dal::{FindLatestCheckpointSchema, SaveCheckpointSchema},
+    model::model::{EvaluationSchema, ProcessCheckpoint, RawTagSchema},
+    schema_utils::{is_earlier_than, PartialEvaluationSchema},
+    utils::error::{CuErrors, HttpError}
+};
+
+/// max number of checkpoints considered when restoring a process
+const CHECKPOINTS_QUERY_LIMIT: i64 = 50;
+/// checkpoints are always uploaded gzipped
+const CHECKPOINT_ENCODING: &str = "gzip";
+
+const GET_AO_PROCESS_CHECKPOINTS: &str = r#"
+    query GetAoProcessCheckpoints(
+        $owner: String!
+        $processId: String!
+        $limit: Int!
+    ) {
+        transactions(
+            tags: [
+                { name: "Process", values: [$processId] }
+                { name: "Type", values: ["Checkpoint"] }
+                { name: "Data-Protocol", values: ["ao"] }
+            ],
+            owners: [$owner]
+            first: $limit,
+            sort: HEIGHT_DESC
+        ) {
+            edges {
+                node {
+                    id
+                    owner {
+                        address
+                    }
+                    tags {
+                        name
+                        value
+                    }
+                }
+            }
+        }
+    }"#;
+
+/**
+ * Whether this CU already created a Checkpoint for an evaluation.
+ * cron is only specified for Cron Messages, so it is conditionally included
+ */
+fn get_ao_process_checkpoint_query(with_cron: bool) -> String {
+    format!(r#"
+    query GetAoProcessCheckpoint(
+        $owner: String!
+        $processId: String!
+        $timestamp: String!
+        $nonce: String!
+        {}
+    ) {{
+        transactions(
+            tags: [
+                {{ name: "Process", values: [$processId] }}
+                {{ name: "Nonce", values: [$nonce] }}
+                {{ name: "Timestamp", values: [$timestamp] }}
+                {}
+                {{ name: "Type", values: ["Checkpoint"] }}
+                {{ name: "Data-Protocol", values: ["ao"] }}
+            ],
+            owners: [$owner]
+            first: 1
+        ) {{
+            edges {{
+                node {{
+                    id
+                    owner {{
+                        address
+                    }}
+                    tags {{
+                        name
+                        value
+                    }}
+                }}
+            }}
+        }}
+    }}"#,
+        if with_cron { "$cron: String!" } else { "" },
+        if with_cron { r#"{ name: "Cron-Interval", values: [$cron] }"# } else { "" }
+    )
+}
+
+#[derive(Serialize)]
+struct CheckpointsQueryVariables {
+    owner: String,
+    #[serde(rename = "processId")]
+    process_id: String,
+    limit: i64
+}
+
+#[derive(Serialize)]
+struct CheckpointQueryVariables {
+    owner: String,
+    #[serde(rename = "processId")]
+    process_id: String,
+    timestamp: String,
+    nonce: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cron: Option<String>
+}
+
+/// A Checkpoint found on the gateway, whose memory is yet to be downloaded
+#[derive(Debug)]
+pub struct CheckpointMeta {
+    pub id: String,
+    pub module_id: String,
+    pub encoding: Option<String>,
+    /// hex encoded sha-256 of the uncompressed memory
+    pub sha: Option<String>,
+    pub evaluation: EvaluationSchema
+}
+
+fn gateway_error(message: String) -> CuErrors {
+    CuErrors::HttpStatus(HttpError { status: 502, message })
+}
+
+pub fn compress(memory: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(memory)?;
+    encoder.finish()
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut memory = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut memory)?;
+    Ok(memory)
+}
+
+/// hex encoded sha-256 of the uncompressed memory
+pub fn hash_memory(memory: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(memory))
+}
+
+/**
+ * Some messages do not have a nonce (Cron Messages), but every
+ * message has an ordinate set to the most recent nonce
+ */
+fn checkpoint_nonce(evaluation: &EvaluationSchema) -> String {
+    evaluation.nonce.map(|nonce| nonce.to_string()).unwrap_or(evaluation.ordinate.clone())
+}
+
+pub fn checkpoint_tags(module_id: &str, evaluation: &EvaluationSchema, sha: &str) -> Vec<RawTagSchema> {
+    let tag = |name: &str, value: String| RawTagSchema { name: name.to_string(), value: value.trim().to_string() };
+    let mut tags = vec![
+        tag("Data-Protocol", "ao".to_string()),
+        tag("Variant", "ao.TN.1".to_string()),
+        tag("Type", "Checkpoint".to_string()),
+        tag("Module", module_id.to_string()),
+        tag("Process", evaluation.process_id.clone()),
+        tag("Epoch", evaluation.epoch.unwrap_or(0).to_string()),
+        tag("Nonce", checkpoint_nonce(evaluation)),
+        tag("Timestamp", evaluation.timestamp.to_string()),
+        tag("Block-Height", evaluation.block_height.to_string()),
+        tag("Content-Type", "application/octet-stream".to_string()),
+        tag("SHA-256", sha.to_string()),
+        tag("Content-Encoding", CHECKPOINT_ENCODING.to_string())
+    ];
+    if let Some(cron) = &evaluation.cron {
+        tags.push(tag("Cron-Interval", cron.clone()));
+    }
+    tags
+}
+
+/// None when the node is missing any of the tags required to resume from it
+pub fn parse_checkpoint(process_id: &str, node: &Node) -> Option<CheckpointMeta> {
+    let tags: Vec<RawTagSchema> = node.tags.clone().unwrap_or_default().into_iter()
+        .map(|tag| RawTagSchema { name: tag.name, value: tag.value })
+        .collect();
+    let int = |name: &str| find_tag_value(name, &tags).and_then(|value| value.trim().parse::<i64>().ok());
+    let nonce = find_tag_value("Nonce", &tags)?;
+
+    Some(CheckpointMeta {
+        id: node.id.clone()?,
+        module_id: find_tag_value("Module", &tags)?,
+        encoding: find_tag_value("Content-Encoding", &tags),
+        sha: find_tag_value("SHA-256", &tags),
+        evaluation: EvaluationSchema {
+            process_id: process_id.to_string(),
+            message_id: None,
+            deep_hash: None,
+            timestamp: int("Timestamp")?,
+            epoch: int("Epoch"),
+            nonce: nonce.trim().parse::<i64>().ok(),
+            ordinate: nonce,
+            block_height: int("Block-Height")?,
+            cron: find_tag_value("Cron-Interval", &tags),
+            evaluated_at: Utc::now(),
+            output: Value::Null
+        }
+    })
+}
+
+/// The checkpoints, latest first by timestamp and then cron
+pub fn latest_first(mut checkpoints: Vec<CheckpointMeta>) -> Vec<CheckpointMeta> {
+    let partial = |c: &CheckpointMeta| PartialEvaluationSchema { timestamp: c.evaluation.timestamp, cron: c.evaluation.cron.clone() };
+    checkpoints.sort_by(|a, b| {
+        if is_earlier_than(partial(a), partial(b)) {
+            Ordering::Greater
+        } else if is_earlier_than(partial(b), partial(a)) {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
+    });
+    checkpoints
+}
+
+/// The memory of a downloaded Checkpoint, decoded and checked against its SHA-256 tag
+pub fn decode_checkpoint(data: Vec<u8>, encoding: Option<&str>, sha: Option<&str>) -> Result<Vec<u8>, String> {
+    let memory = match encoding {
+        None => data,
+        Some(CHECKPOINT_ENCODING) => decompress(&data).map_err(|e| format!("Could not decode: {}", e))?,
+        Some(encoding) => return Err(format!("Only GZIP encoding is currently supported for Checkpoints, found {}", encoding))
+    };
+    match sha {
+        Some(sha) if hash_memory(&memory) != sha.trim() => Err(format!("Memory does not match its SHA-256 {}", sha)),
+        _ => Ok(memory)
+    }
+}
+
+/**
+ * Creates Checkpoints of process memory on Arweave, and finds the
+ * latest one created by this CU to resume evaluation from
+ */
+pub struct AoCheckpoint {
+    arweave: Arc<InternalArweave>,
+    graphql_url: String,
+    /// queried when the default gateway fails
+    checkpoint_graphql_url: String,
+    arweave_url: String,
+    uploader_url: String,
+    disabled: bool,
+    ignore_processes: Vec<String>,
+    /// processes checkpointed within the PROCESS_CHECKPOINT_CREATION_THROTTLE
+    recent_checkpoints: Cache<String, ()>,
+    logger: Arc<dyn Log>
+}
+
+impl AoCheckpoint {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        arweave: Arc<InternalArweave>,
+        graphql_url: &str,
+        checkpoint_graphql_url: &str,
+        arweave_url: &str,
+        uploader_url: &str,
+        creation_throttle: u64,
+        disabled: bool,
+        ignore_processes: Vec<String>,
+        logger: Arc<dyn Log>
+    ) -> Self {
+        AoCheckpoint {
+            arweave,
+            graphql_url: graphql_url.to_string(),
+            checkpoint_graphql_url: checkpoint_graphql_url.to_string(),
+            arweave_url: arweave_url.to_string(),
+            uploader_url: uploader_url.to_string(),
+            disabled,
+            ignore_processes,
+            recent_checkpoints: Cache::builder()
+                .time_to_live(Duration::from_millis(creation_throttle.max(1)))
+                .build(),
+            logger
+        }
+    }
+
+    /**
+     * Creates the Checkpoint in the background, so evaluations and cache evictions
+     * are never held up by the upload. Errors are only logged
+     */
+    pub fn spawn_save_checkpoint(self: &Arc<Self>, module_id: String, evaluation: EvaluationSchema, memory: Vec<u8>) {
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => {
+                self.logger.error(format!("No runtime to create Checkpoint for process \"{}\" on. Skipping...", evaluation.process_id));
+                return;
+            }
+        };
+        let ao_checkpoint = self.clone();
+        handle.spawn(async move {
+            if let Err(e) = ao_checkpoint.save_checkpoint(&module_id, &evaluation, &memory).await {
+                ao_checkpoint.logger.error(format!(
+                    "Error occurred when creating Checkpoint for process \"{}\" at timestamp {}. Skipping... {:?}",
+                    evaluation.process_id, evaluation.timestamp, e
+                ));
+            }
+        });
+    }
+
+    fn owner(&self) -> Result<String, CuErrors> {
+        self.arweave.address().map_err(|e| gateway_error(format!("Could not load the CU wallet address: {}", e)))
+    }
+
+    /// Queries the default gateway, falling back to the gateway configured for Checkpoints
+    async fn query_checkpoints<T: Serialize + Send + Sync>(&self, query: &str, variables: T, process_id: &str) -> Result<Vec<Node>, CuErrors> {
+        let result = match self.arweave.query_gateway::<&T, TransactionConnectionSchema>(&self.graphql_url, query, &variables).await {
+            Err(e) if self.checkpoint_graphql_url != self.graphql_url => {
+                self.logger.error(format!(
+                    "Error encountered querying gateway for Checkpoint for process \"{}\": {}. Querying Checkpoint gateway...", process_id, e
+                ));
+                self.arweave.query_gateway::<&T, TransactionConnectionSchema>(&self.checkpoint_graphql_url, query, &variables).await
+            },
+            result => result
+        };
+        match result {
+            Ok(res) => Ok(res.data.transactions.edges.into_iter().map(|edge| edge.node).collect()),
+            Err(e) => Err(gateway_error(format!("Could not query Checkpoints for process \"{}\": {}", process_id, e)))
+        }
+    }
+
+    async fn download_checkpoint(&self, checkpoint: &CheckpointMeta) -> Result<Vec<u8>, CuErrors> {
+        let data = match self.arweave.load_tx_data(&self.arweave_url, &checkpoint.id).await {
+            Ok(res) => res.bytes().await,
+            Err(e) => Err(e)
+        }.map_err(|e| gateway_error(format!("Could not download Checkpoint {}: {}", checkpoint.id, e)))?;
+
+        let data = data.to_vec();
+        let encoding = checkpoint.encoding.clone();
+        let sha = checkpoint.sha.clone();
+        tokio::task::spawn_blocking(move || decode_checkpoint(data, encoding.as_deref(), sha.as_deref())).await
+            .map_err(|e| gateway_error(e.to_string()))?
+            .map_err(|e| gateway_error(format!("Checkpoint {}: {}", checkpoint.id, e)))
+    }
+
+    async fn create_checkpoint(&self, module_id: &str, evaluation: &EvaluationSchema, memory: &[u8]) -> Result<Option<String>, CuErrors> {
+        let process_id = &evaluation.process_id;
+        let owner = self.owner()?;
+        let existing = self.query_checkpoints(&get_ao_process_checkpoint_query(evaluation.cron.is_some()), CheckpointQueryVariables {
+            owner,
+            process_id: process_id.clone(),
+            timestamp: evaluation.timestamp.to_string(),
+            nonce: checkpoint_nonce(evaluation),
+            cron: evaluation.cron.clone()
+        }, process_id).await?;
+        // this CU has already created a Checkpoint for this evaluation, so simply noop
+        if let Some(id) = existing.into_iter().find_map(|node| node.id) {
+            return Ok(Some(id));
+        }
+
+        self.logger.log(format!(
+            "Creating Checkpoint for process \"{}\" at timestamp {}, ordinate {}, cron {:?}",
+            process_id, evaluation.timestamp, evaluation.ordinate, evaluation.cron
+        ));
+        let owned = memory.to_vec();
+        let (sha, data) = tokio::task::spawn_blocking(move || (hash_memory(&owned), compress(&owned))).await
+            .map_err(|e| gateway_error(e.to_string()))?;
+        let data = data.map_err(|e| gateway_error(format!("Could not encode Checkpoint for process \"{}\": {}", process_id, e)))?;
+
+        let tags = checkpoint_tags(module_id, evaluation, &sha).iter()
+            .map(|tag| Tag::new(&tag.name, &tag.value))
+            .collect();
+        let data_item = self.arweave.build_sign_dataitem(data, tags)
+            .map_err(|e| gateway_error(format!("Could not sign Checkpoint for process \"{}\": {}", process_id, e)))?;
+        let id = data_item.id();
+
+        if let Err(e) = self.arweave.upload_data_item(&self.uploader_url, data_item).await {
+            self.logger.error(format!("Failed to upload Checkpoint DataItem for process \"{}\": {}", process_id, e));
+            return Err(gateway_error(format!("Could not upload Checkpoint for process \"{}\": {}", process_id, e)));
+        }
+        self.logger.log(format!("Successfully uploaded Checkpoint DataItem {} for process \"{}\"", id, process_id));
+
+        Ok(Some(id))
+    }
+}
+
+#[async_trait]
+impl SaveCheckpointSchema for AoCheckpoint {
+    async fn save_checkpoint(&self, module_id: &str, evaluation: &EvaluationSchema, memory: &[u8]) -> Result<Option<String>, CuErrors> {
+        let process_id = &evaluation.process_id;
+        if self.disabled {
+            self.logger.log(format!("Checkpoint creation is disabled on this CU, so no work needs to be done for process \"{}\"", process_id));
+            return Ok(None);
+        }
+        // /**
+        // * Reserve the process before uploading, so a save of the same process that
+        // * starts meanwhile is throttled. A failed upload releases it
+        // */
+        if !self.recent_checkpoints.entry(process_id.clone()).or_insert(()).await.is_fresh() {
+            self.logger.log(format!("Checkpoint was recently created for process \"{}\", and so not creating another one.", process_id));
+            return Ok(None);
+        }
+
+        let created = self.create_checkpoint(module_id, evaluation, memory).await;
+        if created.is_err() {
+            self.recent_checkpoints.invalidate(process_id).await;
+        }
+        created
+    }
+}
+
+#[async_trait]
+impl FindLatestCheckpointSchema for AoCheckpoint {
+    async fn find_latest_checkpoint(&self, process_id: &str, module_id: &str) -> Result<Option<ProcessCheckpoint>, CuErrors> {
+        if self.ignore_processes.iter().any(|id| id == process_id) {
+            self.logger.log(format!("Arweave Checkpoints are ignored for process \"{}\". Not attempting to query gateway...", process_id));
+            return Ok(None);
+        }
+
+        let nodes = self.query_checkpoints(GET_AO_PROCESS_CHECKPOINTS, CheckpointsQueryVariables {
+            owner: self.owner()?,
+            process_id: process_id.to_string(),
+            limit: CHECKPOINTS_QUERY_LIMIT
+        }, process_id).await?;
+        // checkpoints of a different module can not be resumed from
+        let checkpoints = nodes.iter()
+            .filter_map(|node| parse_checkpoint(process_id, node))
+            .filter(|checkpoint| checkpoint.module_id == module_id)
+            .collect();
+        // a Checkpoint that can't be downloaded or doesn't match its SHA-256 is skipped for the next latest
+        for checkpoint in latest_first(checkpoints) {
+            match self.download_checkpoint(&checkpoint).await {
+                Ok(memory) => return Ok(Some(ProcessCheckpoint {
+                    id: checkpoint.id,
+                    module_id: checkpoint.module_id,
+                    evaluation: checkpoint.evaluation,
+                    memory
+                })),
+                Err(e) => self.logger.error(format!("Could not restore process \"{}\" from Checkpoint. Trying the next... {:?}", process_id, e))
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ao_common::models::shared_models::Tag as GqlTag;
+
+    fn evaluation(nonce: Option<i64>, cron: Option<&str>) -> EvaluationSchema {
+        EvaluationSchema {
+            process_id: "process-123".to_string(),
+            message_id: None,
+            deep_hash: None,
+            timestamp: 1702677252111,
+            epoch: Some(0),
+            nonce,
+            ordinate: "7".to_string(),
+            block_height: 1234,
+            cron: cron.map(|cron| cron.to_string()),
+            evaluated_at: Utc::now(),
+            output: Value::Null
+        }
+    }
+
+    fn node(id: &str, tags: Vec<(&str, &str)>) -> Node {
+        Node {
+            id: Some(id.to_string()),
+            anchor: None,
+            signature: None,
+            recipient: None,
+            owner: None,
+            fee: None,
+            quantity: None,
+            data: None,
+            tags: Some(tags.into_iter().map(|(name, value)| GqlTag { name: name.to_string(), value: value.to_string() }).collect()),
+            block: None,
+            parent: None,
+            bundled_in: None
+        }
+    }
+
+    mod ao_checkpoint {
+        use super::*;
+
+        mod compress {
+            use super::*;
+
+            #[test]
+            fn test_round_trip_the_memory() {
+                let memory: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+                let compressed = compress(&memory).unwrap();
+                assert!(compressed.len() < memory.len());
+                assert!(decompress(&compressed).unwrap() == memory);
+            }
+
+            #[test]
+            fn test_hash_the_uncompressed_memory() {
+                assert!(hash_memory(b"hello") == "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+            }
+        }
+
+        mod checkpoint_tags {
+            use super::*;
+
+            #[test]
+            fn test_create_the_checkpoint_tags() {
+                let tags = checkpoint_tags("module-123", &evaluation(Some(7), None), "sha-123");
+                let value = |name: &str| find_tag_value(name, &tags);
+                assert!(value("Type") == Some("Checkpoint".to_string()));
+                assert!(value("Module") == Some("module-123".to_string()));
+                assert!(value("Process") == Some("process-123".to_string()));
+                assert!(value("Nonce") == Some("7".to_string()));
+                assert!(value("Timestamp") == Some("1702677252111".to_string()));
+                assert!(value("Block-Height") == Some("1234".to_string()));
+                assert!(value("SHA-256") == Some("sha-123".to_string()));
+                assert!(value("Content-Encoding") == Some("gzip".to_string()));
+                assert!(value("Cron-Interval").is_none());
+            }
+
+            #[test]
+            fn test_use_the_ordinate_and_cron_of_a_cron_message() {
+                let tags = checkpoint_tags("module-123", &evaluation(None, Some("0-10-minutes")), "sha-123");
+                assert!(find_tag_value("Nonce", &tags) == Some("7".to_string()));
+                assert!(find_tag_value("Cron-Interval", &tags) == Some("0-10-minutes".to_string()));
+            }
+        }
+
+        mod latest_checkpoint {
+            use super::*;
+
+            fn checkpoint_node(id: &str, timestamp: &str, cron: Option<&str>) -> Node {
+                let mut tags = vec![
+                    ("Module", "module-123"),
+                    ("Nonce", "3"),
+                    ("Epoch", "0"),
+                    ("Timestamp", timestamp),
+                    ("Block-Height", "1234"),
+                    ("Content-Encoding", "gzip")
+                ];
+                if let Some(cron) = cron { tags.push(("Cron-Interval", cron)) }
+                node(id, tags)
+            }
+
+            #[test]
+            fn test_parse_a_checkpoint() {
+                let checkpoint = parse_checkpoint("process-123", &checkpoint_node("tx-123", "1702677252111", Some("1-10-minutes"))).unwrap();
+                assert!(checkpoint.id == "tx-123");
+                assert!(checkpoint.module_id == "module-123");
+                assert!(checkpoint.encoding == Some("gzip".to_string()));
+                assert!(checkpoint.evaluation.timestamp == 1702677252111);
+                assert!(checkpoint.evaluation.ordinate == "3");
+                assert!(checkpoint.evaluation.nonce == Some(3));
+                assert!(checkpoint.evaluation.block_height == 1234);
+                assert!(checkpoint.evaluation.cron == Some("1-10-minutes".to_string()));
+            }
+
+            #[test]
+            fn test_skip_a_checkpoint_without_a_timestamp() {
+                assert!(parse_checkpoint("process-123", &node("tx-123", vec![("Module", "module-123"), ("Nonce", "3")])).is_none());
+            }
+
+            #[test]
+            fn test_find_the_latest_checkpoint() {
+                let checkpoints = vec![
+                    checkpoint_node("tx-1", "1702677252111", None),
+                    checkpoint_node("tx-3", "1702677252999", Some("1-10-minutes")),
+                    checkpoint_node("tx-2", "1702677252999", None)
+                ].iter().filter_map(|node| parse_checkpoint("process-123", node)).collect();
+                let ids: Vec<String> = latest_first(checkpoints).into_iter().map(|checkpoint| checkpoint.id).collect();
+                assert!(ids == vec!["tx-3", "tx-2", "tx-1"]);
+                assert!(latest_first(vec![]).is_empty());
+            }
+        }
+
+        mod decode_checkpoint {
+            use super::*;
+
+            #[test]
+            fn test_decode_and_verify_the_memory() {
+                let memory = b"hello".to_vec();
+                let sha = hash_memory(&memory);
+                assert!(decode_checkpoint(compress(&memory).unwrap(), Some(CHECKPOINT_ENCODING), Some(&sha)).unwrap() == memory);
+                assert!(decode_checkpoint(memory.clone(), None, Some(&sha)).unwrap() == memory);
+                // older Checkpoints may not be tagged with a SHA-256
+                assert!(decode_checkpoint(memory.clone(), None, None).unwrap() == memory);
+            }
+
+            #[test]
+            fn test_reject_a_mismatched_memory() {
+                let sha = hash_memory(b"hello");
+                assert!(decode_checkpoint(compress(b"world").unwrap(), Some(CHECKPOINT_ENCODING), Some(&sha)).is_err());
+                assert!(decode_checkpoint(b"hello".to_vec(), Some("br"), Some(&sha)).is_err());
+            }
+        }
+    }
+}
@@ -90,9 +90,8 @@ impl AoModule {
         }
     }
 
-    #[allow(unused)]
-    /// will be used once the EvaluatorSchema is done
-    fn stream_id() -> [u8; 8] {
+    /// identifies one evaluation stream, every message of the stream is evaluated with it
+    pub fn stream_id() -> [u8; 8] {
         let mut buffer = [0u8; 8];
         rand::thread_rng().fill_bytes(&mut buffer);
         buffer
@@ -145,8 +144,6 @@ impl SaveModuleSchema for AoModule {
     }
 }
 
-// todo: need to implement EvaluatorSchema, but let's do after the Docker infra
-
 #[cfg(test)]
 mod tests {
     use super::*;
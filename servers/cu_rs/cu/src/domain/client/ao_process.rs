@@ -1,4 +1,4 @@
-use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 use async_trait::async_trait;
 use moka::{future::Cache, Expiry};
 use sqlx::{prelude::{FromRow, Row}, sqlite::SqliteRow, Sqlite};
@@ -10,7 +10,8 @@ use crate::domain::{
     schema_utils::{is_earlier_than, is_equal_to, is_later_then, PartialEvaluationSchema}, 
     utils::error::{CuErrors, HttpError, SchemaValidationError}
 };
-use futures::{future::{AbortHandle, Abortable}, Future};
+use futures::future::{AbortHandle, Abortable};
+use uuid::Uuid;
 use super::sqlite::{SqliteClient, PROCESSES_TABLE, ConnGetter};
 
 pub struct SelectQuery {
@@ -44,6 +45,8 @@ pub enum Expiration {
     OneSecond,
     FiveSeconds,
     TenSeconds,
+    /// Any other ttl, in milliseconds
+    After(u64)
 }
 
 impl Expiration {
@@ -52,7 +55,8 @@ impl Expiration {
             Expiration::Never => None,
             Expiration::OneSecond => Some(Duration::from_secs(1)),
             Expiration::FiveSeconds => Some(Duration::from_secs(5)),
-            Expiration::TenSeconds => Some(Duration::from_secs(10))
+            Expiration::TenSeconds => Some(Duration::from_secs(10)),
+            Expiration::After(ttl) => Some(Duration::from_millis(*ttl))
         }
     }
 
@@ -63,7 +67,7 @@ impl Expiration {
             1 => Expiration::OneSecond,
             2..=5 => Expiration::FiveSeconds,
             6..=10 => Expiration::TenSeconds,
-            _ => panic!("Unexpected ttl value for cache expiration") // todo: need to fix this to return error
+            _ => Expiration::After(ttl)
         }
     }
 }
@@ -82,11 +86,13 @@ pub struct Process {
 
 #[derive(Clone)]
 pub struct CacheEntry {
-    evaluation: EvaluationSchema,
-    memory: Option<Vec<u8>>,
+    pub evaluation: EvaluationSchema,
+    /// the module the memory was evaluated by, needed to create a Checkpoint from the memory
+    pub module_id: String,
+    pub memory: Option<Vec<u8>>,
     /// As name says represents a file, using this as File is not cloneable
-    file: Option<Vec<u8>>,
-    expiration: Expiration
+    pub file: Option<Vec<u8>>,
+    pub expiration: Expiration
 }
 
 enum Destination {
@@ -157,16 +163,37 @@ pub struct AoProcess {
     ttl: u64,
     drain_to_file_threshold: u64,
     drain_to_file_timers: Arc<RwLock<HashMap<String, AbortHandle>>>,
-    clear_drain_to_file_timer: Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = ()> + 'static>> + std::marker::Sync + std::marker::Send + 'static>,
-    write_process_memory_file: Box<dyn Fn(CacheEntry) -> Vec<u8> + std::marker::Sync + std::marker::Send + 'static>
+    /// returns the reference to the file the memory was written to
+    write_process_memory_file: Arc<dyn Fn(CacheEntry) -> std::io::Result<Vec<u8>> + std::marker::Sync + std::marker::Send + 'static>
+}
+
+/**
+ * Writes the process memory to a file in PROCESS_MEMORY_CACHE_FILE_DIR, returning the path of the file.
+ * The memory is written to a tmp file first, so a reader never sees a partially written file
+ */
+pub fn write_process_memory_file_with(dir: &str) -> impl Fn(CacheEntry) -> std::io::Result<Vec<u8>> + std::marker::Sync + std::marker::Send + 'static {
+    let dir = PathBuf::from(dir);
+    move |value: CacheEntry| {
+        let process_id = value.evaluation.process_id;
+        let memory = value.memory.ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, format!("No memory to write to a file for process {}", process_id)
+        ))?;
+        std::fs::create_dir_all(&dir)?;
+
+        let path = dir.join(format!("state-{}.dat", process_id));
+        let tmp_path = dir.join(format!("state-{}.{}.tmp", process_id, Uuid::new_v4()));
+        std::fs::write(&tmp_path, memory)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(path.to_string_lossy().as_bytes().to_vec())
+    }
 }
 
 impl AoProcess {
     pub fn create_process_memory_cache<Fa, Fb>(sql_client: Arc<SqliteClient>, max_size: u64, ttl: u64, drain_to_file_threshold: u64, on_eviction: Fa, write_process_memory_file: Fb) -> Self 
         where 
             Fa: Fn(String, CacheEntry) + std::marker::Sync + std::marker::Send + 'static,
-            Fb: Fn(CacheEntry) -> Vec<u8> + std::marker::Sync + std::marker::Send + 'static {
-        let drain_to_file_timers = Arc::new(RwLock::new(HashMap::new()));
+            Fb: Fn(CacheEntry) -> std::io::Result<Vec<u8>> + std::marker::Sync + std::marker::Send + 'static {
         let ao_process = AoProcess { 
             sql_client,
             process_memory_cache: Cache::builder()
@@ -176,16 +203,18 @@ impl AoProcess {
                     value.file.clone().unwrap().len() as u32
                 })
                 .max_capacity(max_size)
-                .eviction_listener(move |key: Arc<String>, value, _cause| {
-                    on_eviction(key.clone().as_ref().to_string(), value);
+                .eviction_listener(move |key: Arc<String>, value, cause| {
+                    // entries are also "removed" when replaced by set or get
+                    if cause.was_evicted() {
+                        on_eviction(key.clone().as_ref().to_string(), value);
+                    }
                 })
                 .expire_after(AoProcessCacheExpiry)
                 .build(),
             ttl,
             drain_to_file_threshold,
-            drain_to_file_timers: drain_to_file_timers.clone(),
-            clear_drain_to_file_timer: AoProcess::clear_timer(drain_to_file_timers),
-            write_process_memory_file: Box::new(write_process_memory_file)
+            drain_to_file_timers: Arc::new(RwLock::new(HashMap::new())),
+            write_process_memory_file: Arc::new(write_process_memory_file)
         };
         
         ao_process
@@ -200,36 +229,60 @@ impl AoProcess {
         }
     }
     
-    /// resets timer duration
-    fn clear_timer(map: Arc<RwLock<HashMap<String, AbortHandle>>>) -> Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = ()> + 'static>> + std::marker::Sync + std::marker::Send + 'static> {
-        Box::new(move |key: String| {
-            let cloned_map = map.clone();
-            Box::pin(async move {          
-                let mut _map = cloned_map.write().await;
-                if _map.contains_key(&key) {
-                    _map.get(&key).unwrap().abort();
-                    _map.remove(&key);            
-                }
-            })
-        })
+    /// Cancels the pending drain of the process memory to a file, if any
+    pub async fn clear_drain_to_file_timer(&self, key: &str) {
+        if let Some(timer) = self.drain_to_file_timers.write().await.remove(key) {
+            timer.abort();
+        }
+    }
+
+    pub async fn has_drain_to_file_timer(&self, key: &str) -> bool {
+        self.drain_to_file_timers.read().await.contains_key(key)
+    }
+
+    /// The memory of the process drained to a file
+    pub async fn read_process_memory_file(file: &[u8]) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(String::from_utf8_lossy(file).as_ref()).await
     }
 
+    /**
+     * The cached process, with its memory read back in from
+     * a file if it was drained to one
+     */
     pub async fn get(&self, key: &str) -> Option<CacheEntry> {
-        if !self.process_memory_cache.contains_key(key) {
-            return None;
+        let mut value = self.process_memory_cache.get(key).await?;
+        if value.memory.is_none() {
+            if let Some(file) = value.file.take() {
+                match AoProcess::read_process_memory_file(&file).await {
+                    Ok(memory) => value.memory = Some(memory),
+                    // the file is gone, so the memory can only be loaded from a Checkpoint
+                    Err(_) => {
+                        self.process_memory_cache.invalidate(key).await;
+                        return None;
+                    }
+                }
+            }
         }
 
         // /**
         // * Will subsequently renew the age
         // * and recency of the cached value
         // */
-        let value = self.process_memory_cache.get(key).await;
-        self.process_memory_cache.insert(key.to_string(), value.clone().unwrap()).await;
+        self.set(key, value.clone()).await;
+
+        Some(value)
+    }
 
-        Some(value.unwrap())
+    pub fn ttl(&self) -> u64 {
+        self.ttl
     }
 
-    pub async fn set(&mut self, key: &str, value: CacheEntry) {
+    /// A handle to the underlying cache, for work done on every cached process ie. Checkpoints
+    pub fn memory_cache(&self) -> Cache<String, CacheEntry> {
+        self.process_memory_cache.clone()
+    }
+
+    pub async fn set(&self, key: &str, value: CacheEntry) {
         // /**
         // * Set up timer to drain Process memory to a file, if not accessed
         // * within the DRAIN_TO_FILE period ie. 30 seconds
@@ -241,39 +294,81 @@ impl AoProcess {
         // * a file
         // */
         if value.memory.is_some() && self.drain_to_file_threshold > 0 {
-            self.clear_drain_to_file_timer.as_mut()(key.to_string()).await;
+            let mut timers = self.drain_to_file_timers.clone().write_owned().await;
+            if let Some(timer) = timers.remove(key) {
+                timer.abort();
+            }
 
+            // /**
+            // * The timer is registered while holding the lock, so it is
+            // * always cancellable, even before the task is first polled
+            // */
             let (abort_handle, abort_registration) = AbortHandle::new_pair();
-            let drain_to_file_abortable = Abortable::new(async {
-                tokio::time::sleep(Duration::from_millis(self.drain_to_file_threshold)).await;
-
-                let file = self.write_process_memory_file.as_ref()(value.clone());
-                // /**
-                //  * Update the cache entry with the file reference containing the memory
-                //  * and remove the reference to the Memory, so that it can be GC'd.
-                //  *
-                //  * Since we are setting on the underlying data store directly,
-                //  * this won't reset the ttl
-                //  *
-                //  * Note we do not mutate the old object, and instead cache a new one,
-                //  * in case the old object containing the memory is in use elsewhere
-                //  */
-                self.process_memory_cache.insert(key.to_string(), CacheEntry {
-                    evaluation: value.clone().evaluation,
-                    file: Some(file),
-                    memory: None,
-                    expiration: Expiration::get_expiration_from_ms(self.ttl)
-                }).await;
-                self.drain_to_file_timers.clone().write().await.remove(key);
+            timers.insert(key.to_string(), abort_handle);
+            drop(timers);
+
+            let threshold = self.drain_to_file_threshold;
+            let timers = self.drain_to_file_timers.clone();
+            let wait = Abortable::new(async move {
+                tokio::time::sleep(Duration::from_millis(threshold)).await;
+                timers.write_owned().await
             }, abort_registration);
-            _ = drain_to_file_abortable.await;
-
-            self.drain_to_file_timers.clone().write().await.insert(key.to_string(), abort_handle);
+            tokio::spawn(AoProcess::drain_to_file(
+                key.to_string(),
+                value.clone(),
+                wait,
+                self.process_memory_cache.clone(),
+                self.write_process_memory_file.clone()
+            ));
         }
 
         self.process_memory_cache.insert(key.to_string(), value).await;
     }
 
+    async fn drain_to_file(
+        key: String,
+        value: CacheEntry,
+        wait: Abortable<impl std::future::Future<Output = tokio::sync::OwnedRwLockWriteGuard<HashMap<String, AbortHandle>>>>,
+        process_memory_cache: Cache<String, CacheEntry>,
+        write_process_memory_file: Arc<dyn Fn(CacheEntry) -> std::io::Result<Vec<u8>> + std::marker::Sync + std::marker::Send + 'static>
+    ) {
+        // a newer set or a clear cancelled this timer
+        let Ok(mut timers) = wait.await else { return };
+        timers.remove(&key);
+        drop(timers);
+
+        let drained = value.clone();
+        let file = match tokio::task::spawn_blocking(move || write_process_memory_file.as_ref()(drained)).await {
+            Ok(Ok(file)) => file,
+            // the memory simply stays in the cache
+            _ => return
+        };
+
+        // /**
+        //  * Update the cache entry with the file reference containing the memory
+        //  * and remove the reference to the Memory, so that it can be freed.
+        //  *
+        //  * Only if the entry still holds the memory that was written, a
+        //  * later set would have started its own timer. Draining is not a
+        //  * use of the process, so the entry keeps its expiration
+        //  */
+        match process_memory_cache.get(&key).await {
+            Some(current) if current.memory.is_some()
+                && current.evaluation.timestamp == value.evaluation.timestamp
+                && current.evaluation.ordinate == value.evaluation.ordinate
+                && current.evaluation.cron == value.evaluation.cron => {
+                process_memory_cache.insert(key, CacheEntry {
+                    evaluation: value.evaluation,
+                    module_id: value.module_id,
+                    file: Some(file),
+                    memory: None,
+                    expiration: current.expiration
+                }).await;
+            },
+            _ => ()
+        }
+    }
+
     pub fn load_process_cache_usage(&self) -> ProcessesToLoad {
         ProcessesToLoad {
             size: self.process_memory_cache.entry_count(),
@@ -0,0 +1,704 @@
+use std::{sync::Arc, time::Duration};
+use async_trait::async_trait;
+use ao_common::domain::dal::Log;
+use ao_scheduler_utils::{index_common::{connect, ConnectReturn}, LocateMaker};
+use futures::{future, stream, StreamExt};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use crate::domain::{
+    client::arweave::InternalArweave,
+    dal::{LoadMessageMetaSchema, LoadMessageSchema, LoadProcessSchema, LoadTimestampSchema, LocateProcessSchema, MessageStream},
+    model::model::{AoGlobal, BlockSchema, Message, MessageMetaSchema, ProcessSchemaWithoutId, ProcessUrl, RawTagSchema, ScheduledMessage, TimestampSchema},
+    utils::error::{CuErrors, HttpError}
+};
+
+const MAX_RETRIES: u32 = 5;
+const RETRY_DELAY_MS: u64 = 500;
+/// pages loaded ahead of the page being evaluated
+const PREFETCH_PAGES: usize = 1;
+/// assignments hydrated from the gateway at a time
+const HYDRATE_CONCURRENCY: usize = 5;
+
+#[derive(Deserialize, Debug)]
+pub struct SuOwner {
+    pub address: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuProcess {
+    pub process_id: String,
+    /// the height, left padded
+    pub block: String,
+    pub owner: SuOwner,
+    pub tags: Vec<RawTagSchema>,
+    /// SU is currently sending back timestamp in milliseconds
+    pub timestamp: i64,
+    pub data: Option<String>,
+    pub anchor: Option<String>,
+    pub signature: Option<String>
+}
+
+/// TODO: SU currently sends these back as strings
+#[derive(Deserialize, Debug)]
+pub struct SuTimestamp {
+    pub timestamp: String,
+    pub block_height: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuMessage {
+    pub id: String,
+    pub owner: SuOwner,
+    pub data: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<RawTagSchema>,
+    pub signature: Option<String>,
+    pub anchor: Option<String>,
+    pub target: Option<String>
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuAssignment {
+    pub id: String,
+    pub tags: Vec<RawTagSchema>
+}
+
+/**
+ * See new shape in https://github.com/permaweb/ao/issues/563#issuecomment-2020597581
+ */
+#[derive(Deserialize, Debug)]
+pub struct SuNode {
+    pub message: Option<SuMessage>,
+    pub assignment: SuAssignment
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuEdge {
+    pub node: SuNode,
+    pub cursor: String
+}
+
+/**
+ * Map to the expected shape, depending on the response shape.
+ * See https://github.com/permaweb/ao/issues/563
+ */
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SuMessageMeta {
+    Assignment {
+        assignment: SuAssignment
+    },
+    Legacy {
+        process_id: String,
+        timestamp: i64,
+        nonce: i64
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuPageInfo {
+    pub has_next_page: bool
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuPage {
+    pub page_info: SuPageInfo,
+    pub edges: Vec<SuEdge>
+}
+
+pub fn find_tag_value(name: &str, tags: &Vec<RawTagSchema>) -> Option<String> {
+    tags.iter().find(|t| t.name == name).map(|t| t.value.clone())
+}
+
+/// Returns a left padded integer like '000001331218' as an integer
+fn parse_int(name: &str, value: Option<String>) -> Result<i64, CuErrors> {
+    match value {
+        Some(value) => value.trim().parse::<i64>().map_err(|_| {
+            CuErrors::HttpStatus(HttpError { status: 502, message: format!("SU returned an invalid {} '{}'", name, value) })
+        }),
+        None => Err(CuErrors::HttpStatus(HttpError { status: 502, message: format!("SU did not return a {}", name) }))
+    }
+}
+
+/**
+ * Not forwarded, so the signer is the who the message is from.
+ * Forwarded, so the owner is who the message was forwarded on behalf of
+ * (the From-Process) value
+ */
+pub fn map_from(tags: &Vec<RawTagSchema>, owner: &str) -> String {
+    match find_tag_value("From-Process", tags) {
+        Some(from) if !from.is_empty() => from,
+        _ => owner.to_string()
+    }
+}
+
+/**
+ * Forwarded by a MU, so use the signer (the MU wallet)
+ * as the Forwarded-By value
+ */
+pub fn map_forwarded_by(tags: &Vec<RawTagSchema>, owner: &str) -> Option<String> {
+    find_tag_value("From-Process", tags).map(|_| owner.to_string())
+}
+
+/// GET the url, retrying transport errors and server errors with an exponential backoff
+async fn get_json<T: DeserializeOwned>(client: &Client, logger: &Arc<dyn Log>, url: &str, name: &str) -> Result<T, CuErrors> {
+    let mut retry = 0;
+    let mut delay = RETRY_DELAY_MS;
+    loop {
+        let err = match client.get(url).send().await {
+            Ok(res) if res.status().is_success() => {
+                return match res.json::<T>().await {
+                    Ok(body) => Ok(body),
+                    Err(e) => Err(CuErrors::HttpStatus(HttpError { status: 502, message: format!("({}) could not parse SU response: {}", name, e) }))
+                };
+            },
+            Ok(res) => {
+                let is_client_error = res.status().is_client_error();
+                let status = res.status().as_u16() as u32;
+                let text = res.text().await.unwrap_or("".to_string());
+                let err = HttpError { status, message: format!("{}: {}", status, text) };
+                // the same request would be refused again
+                if is_client_error {
+                    return Err(CuErrors::HttpStatus(err));
+                }
+                err
+            },
+            Err(e) => HttpError { status: 502, message: format!("({}) {}", name, e) }
+        };
+
+        if retry >= MAX_RETRIES {
+            logger.error(format!("({}) Reached max number of retries: {}. Bubbling err", name, retry));
+            return Err(CuErrors::HttpStatus(err));
+        }
+        retry += 1;
+        delay += delay;
+        logger.log(format!("({}) Backing off -- retry {} starting in {} milliseconds...", name, retry, delay));
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+}
+
+async fn load_messages_page(
+    client: &Client,
+    logger: &Arc<dyn Log>,
+    page_size: i64,
+    su_url: &str,
+    process_id: &str,
+    from: Option<String>,
+    to: Option<i64>
+) -> Result<SuPage, CuErrors> {
+    let mut url = format!("{}/{}?process-id={}&limit={}", su_url, process_id, process_id, page_size);
+    if let Some(from) = from {
+        url.push_str(&format!("&from={}", from));
+    }
+    if let Some(to) = to {
+        url.push_str(&format!("&to={}", to));
+    }
+    get_json::<SuPage>(client, logger, &url, &format!("loadMessages({}, {})", su_url, process_id)).await
+}
+
+/**
+ * Loads the pages of messages scheduled on the process after from and up to to,
+ * on a task that stays PREFETCH_PAGES ahead of the consumer. The task stops
+ * when the stream is dropped
+ */
+fn prefetch_pages(
+    client: Client,
+    logger: Arc<dyn Log>,
+    page_size: i64,
+    su_url: &str,
+    process_id: &str,
+    from: Option<i64>,
+    to: Option<i64>
+) -> ReceiverStream<Result<SuPage, CuErrors>> {
+    let (tx, rx) = mpsc::channel(PREFETCH_PAGES);
+    let su_url = su_url.to_string();
+    let process_id = process_id.to_string();
+
+    tokio::spawn(async move {
+        let mut cursor = from.map(|from| from.to_string());
+        let mut total = 0;
+        loop {
+            logger.log(format!(
+                "Loading next page of max {} messages for process \"{}\" from SU \"{}\" between \"{}\" and \"{}\"",
+                page_size,
+                process_id,
+                su_url,
+                cursor.clone().unwrap_or("initial".to_string()),
+                to.map(|to| to.to_string()).unwrap_or("latest".to_string())
+            ));
+            let page = load_messages_page(&client, &logger, page_size, &su_url, &process_id, cursor.clone(), to).await;
+            let (next, has_next_page) = match &page {
+                Ok(page) => {
+                    total += page.edges.len();
+                    (page.edges.last().map(|edge| edge.cursor.clone()), page.page_info.has_next_page)
+                },
+                Err(_) => (None, false)
+            };
+            if tx.send(page).await.is_err() {
+                return;
+            }
+            if !has_next_page || next.is_none() {
+                break;
+            }
+            cursor = next;
+        }
+        logger.log(format!("Successfully loaded a total of {} scheduled messages for process \"{}\" from SU \"{}\"", total, process_id, su_url));
+    });
+
+    ReceiverStream::new(rx)
+}
+
+pub struct AoSu {
+    client: Client,
+    /// locate takes &mut self, so calls are serialized. Results are cached by ao-scheduler-utils
+    scheduler_utils: Mutex<ConnectReturn>,
+    page_size: i64,
+    /// assignments are hydrated from the gateway
+    arweave: Arc<InternalArweave>,
+    graphql_url: String,
+    arweave_url: String,
+    logger: Arc<dyn Log>
+}
+
+impl AoSu {
+    pub fn new(graphql_url: &str, arweave_url: &str, page_size: i64, arweave: Arc<InternalArweave>, logger: Arc<dyn Log>) -> Self {
+        AoSu {
+            client: Client::new(),
+            scheduler_utils: Mutex::new(connect(Some(100), Some(graphql_url), Some(true))),
+            page_size,
+            arweave,
+            graphql_url: graphql_url.to_string(),
+            arweave_url: arweave_url.trim_end_matches('/').to_string(),
+            logger
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str, name: &str) -> Result<T, CuErrors> {
+        get_json(&self.client, &self.logger, url, name).await
+    }
+
+    /// An assignment of a message that is already on chain is hydrated from the gateway
+    async fn hydrate(&self, scheduled: ScheduledMessage) -> Result<ScheduledMessage, CuErrors> {
+        if !scheduled.is_assignment {
+            return Ok(scheduled);
+        }
+        let bad_gateway = |message: String| CuErrors::HttpStatus(HttpError { status: 502, message });
+        let id = scheduled.message.id.clone();
+
+        let meta = self.arweave.load_tx_meta(&self.graphql_url, &id).await
+            .map_err(|e| bad_gateway(format!("Could not load the meta of assigned message {}: {:?}", id, e)))?;
+        let data = match self.arweave.load_tx_data(&self.arweave_url, &id).await {
+            Ok(res) if res.status().is_success() => res.text().await
+                .map_err(|e| bad_gateway(format!("Could not read the data of assigned message {}: {}", id, e)))?,
+            Ok(res) => return Err(bad_gateway(format!("Could not load the data of assigned message {}: {}", id, res.status()))),
+            Err(e) => return Err(bad_gateway(format!("Could not load the data of assigned message {}: {}", id, e)))
+        };
+
+        let owner = meta.owner.map(|owner| owner.address).unwrap_or_default();
+        let tags: Vec<RawTagSchema> = meta.tags.unwrap_or_default().into_iter()
+            .map(|tag| RawTagSchema { name: tag.name, value: tag.value })
+            .collect();
+        let mut scheduled = scheduled;
+        scheduled.message.from = map_from(&tags, &owner);
+        scheduled.message.forwarded_by = map_forwarded_by(&tags, &owner);
+        scheduled.message.owner = owner;
+        scheduled.message.tags = tags;
+        scheduled.message.signature = meta.signature;
+        scheduled.message.anchor = meta.anchor;
+        scheduled.message.data = Some(data);
+        Ok(scheduled)
+    }
+
+    /// Maps a node returned by the SU into a message of the evaluation stream
+    pub fn map_node(node: SuNode, ao_global: &AoGlobal) -> Result<ScheduledMessage, CuErrors> {
+        let tags = &node.assignment.tags;
+        let message_id = find_tag_value("Message", tags);
+        let nonce = parse_int("Nonce", find_tag_value("Nonce", tags))?;
+        let epoch = parse_int("Epoch", find_tag_value("Epoch", tags))?;
+        let timestamp = parse_int("Timestamp", find_tag_value("Timestamp", tags))?;
+        let block_height = parse_int("Block-Height", find_tag_value("Block-Height", tags))?;
+        let hash_chain = find_tag_value("Hash-Chain", tags);
+
+        // /**
+        // * No message, meaning this is an Assignment for an existing message
+        // * on chain, to be hydrated later
+        // */
+        let is_assignment = node.message.is_none();
+        let message = match node.message {
+            Some(message) => Message {
+                id: message.id,
+                signature: message.signature,
+                data: message.data,
+                from: map_from(&message.tags, &message.owner.address),
+                forwarded_by: map_forwarded_by(&message.tags, &message.owner.address),
+                owner: message.owner.address,
+                target: find_tag_value("Process", tags).or(message.target),
+                anchor: message.anchor,
+                tags: message.tags,
+                epoch: Some(epoch),
+                nonce: Some(nonce),
+                timestamp,
+                block_height,
+                hash_chain,
+                cron: false,
+                read_only: false
+            },
+            None => Message {
+                id: message_id.clone().unwrap_or(node.assignment.id.clone()),
+                signature: None,
+                data: None,
+                owner: "".to_string(),
+                target: find_tag_value("Process", tags),
+                anchor: None,
+                from: "".to_string(),
+                forwarded_by: None,
+                tags: vec![],
+                epoch: Some(epoch),
+                nonce: Some(nonce),
+                timestamp,
+                block_height,
+                hash_chain,
+                cron: false,
+                read_only: false
+            }
+        };
+
+        Ok(ScheduledMessage {
+            cron: None,
+            ordinate: nonce.to_string(),
+            name: format!("Scheduled Message {} {}:{}", message.id, timestamp, nonce),
+            is_assignment,
+            deep_hash: None,
+            message,
+            ao_global: ao_global.clone(),
+            block: BlockSchema { height: block_height, timestamp }
+        })
+    }
+}
+
+impl LoadMessageSchema for AoSu {
+    /**
+     * At most PREFETCH_PAGES pages and HYDRATE_CONCURRENCY assignments are held
+     * in memory, however many messages are scheduled on the process
+     */
+    fn load_messages<'a>(&'a self, su_url: &str, process_id: &str, from: Option<i64>, to: Option<i64>, ao_global: AoGlobal) -> MessageStream<'a> {
+        prefetch_pages(self.client.clone(), self.logger.clone(), self.page_size, su_url, process_id, from, to)
+            .map(|page| match page {
+                Ok(page) => stream::iter(page.edges.into_iter().map(Ok)).left_stream(),
+                Err(e) => stream::once(future::ready(Err(e))).right_stream()
+            })
+            .flatten()
+            .map(move |edge| {
+                let scheduled = edge.and_then(|edge| AoSu::map_node(edge.node, &ao_global));
+                async move { self.hydrate(scheduled?).await }
+            })
+            .buffered(HYDRATE_CONCURRENCY)
+            .boxed()
+    }
+}
+
+#[async_trait]
+impl LoadProcessSchema for AoSu {
+    async fn load_process(&self, su_url: &str, process_id: &str) -> Result<ProcessSchemaWithoutId, CuErrors> {
+        let url = format!("{}/processes/{}", su_url, process_id);
+        match self.get_json::<SuProcess>(&url, &format!("loadProcess({}, {})", su_url, process_id)).await {
+            Ok(process) => Ok(ProcessSchemaWithoutId {
+                signature: process.signature,
+                data: process.data,
+                anchor: process.anchor,
+                owner: process.owner.address,
+                tags: process.tags,
+                block: BlockSchema {
+                    height: parse_int("block", Some(process.block))?,
+                    timestamp: process.timestamp
+                }
+            }),
+            Err(e) => {
+                self.logger.error(format!("Error Encountered when loading process \"{}\" from SU \"{}\"", process_id, su_url));
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LoadTimestampSchema for AoSu {
+    async fn load_timestamp(&self, su_url: &str, process_id: &str) -> Result<TimestampSchema, CuErrors> {
+        let url = format!("{}/timestamp?process-id={}", su_url, process_id);
+        match self.get_json::<SuTimestamp>(&url, &format!("loadTimestamp({}, {})", su_url, process_id)).await {
+            Ok(res) => Ok(TimestampSchema {
+                timestamp: parse_int("timestamp", Some(res.timestamp))?,
+                height: parse_int("block_height", Some(res.block_height))?
+            }),
+            Err(e) => {
+                self.logger.error(format!("Error Encountered when loading timestamp for process \"{}\" from SU \"{}\"", process_id, su_url));
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LoadMessageMetaSchema for AoSu {
+    async fn load_message_meta(&self, su_url: &str, process_id: &str, message_tx_id: &str) -> Result<MessageMetaSchema, CuErrors> {
+        let url = format!("{}/{}?process-id={}", su_url, message_tx_id, process_id);
+        let meta = match self.get_json::<SuMessageMeta>(&url, &format!("loadMessageMeta({}, {}, {})", su_url, process_id, message_tx_id)).await {
+            Ok(meta) => meta,
+            Err(e) => {
+                self.logger.error(format!(
+                    "Error Encountered when loading message meta for message \"{}\" to process \"{}\" from SU \"{}\"",
+                    message_tx_id, process_id, su_url
+                ));
+                return Err(e);
+            }
+        };
+
+        match meta {
+            SuMessageMeta::Assignment { assignment } => Ok(MessageMetaSchema {
+                process_id: find_tag_value("Process", &assignment.tags).unwrap_or(process_id.to_string()),
+                height: find_tag_value("Block-Height", &assignment.tags).and_then(|height| height.trim().parse::<i64>().ok()),
+                timestamp: parse_int("Timestamp", find_tag_value("Timestamp", &assignment.tags))?,
+                nonce: parse_int("Nonce", find_tag_value("Nonce", &assignment.tags))?
+            }),
+            SuMessageMeta::Legacy { process_id, timestamp, nonce } => Ok(MessageMetaSchema {
+                process_id,
+                height: None,
+                timestamp,
+                nonce
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl LocateProcessSchema for AoSu {
+    async fn locate_process(&self, process_id: &str, scheduler_hint: Option<String>) -> Result<ProcessUrl, CuErrors> {
+        let mut scheduler_utils = self.scheduler_utils.lock().await;
+        match scheduler_utils.locate.locate(process_id, scheduler_hint.as_deref()).await {
+            Ok(entry) => Ok(ProcessUrl { url: entry.url.trim_end_matches('/').to_string() }),
+            Err(e) => Err(CuErrors::HttpStatus(HttpError { status: 502, message: format!("Could not locate the SU for process {}: {:?}", process_id, e) }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::model::model::{Module, Process};
+
+    mod ao_su {
+        use super::*;
+
+        fn ao_global() -> AoGlobal {
+            AoGlobal {
+                process: Process { id: "process-123".to_string(), owner: "woohoo".to_string(), tags: vec![] },
+                module: Module { id: "module-123".to_string(), owner: "owner-123".to_string(), tags: vec![] }
+            }
+        }
+
+        fn assignment_tags() -> Vec<RawTagSchema> {
+            vec![
+                RawTagSchema { name: "Process".to_string(), value: "process-123".to_string() },
+                RawTagSchema { name: "Message".to_string(), value: "message-123".to_string() },
+                RawTagSchema { name: "Epoch".to_string(), value: "0".to_string() },
+                RawTagSchema { name: "Nonce".to_string(), value: "3".to_string() },
+                RawTagSchema { name: "Timestamp".to_string(), value: "1702677252111".to_string() },
+                RawTagSchema { name: "Block-Height".to_string(), value: "000001331218".to_string() },
+                RawTagSchema { name: "Hash-Chain".to_string(), value: "hash-123".to_string() }
+            ]
+        }
+
+        mod map_node {
+            use super::*;
+
+            #[test]
+            fn test_map_a_scheduled_message() {
+                let node = SuNode {
+                    message: Some(SuMessage {
+                        id: "message-123".to_string(),
+                        owner: SuOwner { address: "mu-123".to_string() },
+                        data: Some("foobar".to_string()),
+                        tags: vec![RawTagSchema { name: "From-Process".to_string(), value: "process-456".to_string() }],
+                        signature: Some("sig-123".to_string()),
+                        anchor: None,
+                        target: Some("process-123".to_string())
+                    }),
+                    assignment: SuAssignment { id: "assignment-123".to_string(), tags: assignment_tags() }
+                };
+
+                let scheduled = AoSu::map_node(node, &ao_global()).unwrap();
+                assert!(!scheduled.is_assignment);
+                assert!(scheduled.ordinate == "3");
+                assert!(scheduled.cron.is_none());
+                assert!(scheduled.block.height == 1331218);
+                assert!(scheduled.message.id == "message-123");
+                assert!(scheduled.message.from == "process-456");
+                assert!(scheduled.message.forwarded_by == Some("mu-123".to_string()));
+                assert!(scheduled.message.owner == "mu-123");
+                assert!(scheduled.message.timestamp == 1702677252111);
+                assert!(scheduled.message.nonce == Some(3));
+                assert!(scheduled.message.hash_chain == Some("hash-123".to_string()));
+                assert!(scheduled.ao_global.module.id == "module-123");
+            }
+
+            #[test]
+            fn test_map_an_assignment() {
+                let node = SuNode {
+                    message: None,
+                    assignment: SuAssignment { id: "assignment-123".to_string(), tags: assignment_tags() }
+                };
+
+                let scheduled = AoSu::map_node(node, &ao_global()).unwrap();
+                assert!(scheduled.is_assignment);
+                assert!(scheduled.message.id == "message-123");
+                assert!(scheduled.message.target == Some("process-123".to_string()));
+            }
+
+            #[test]
+            fn test_reject_a_missing_nonce() {
+                let mut tags = assignment_tags();
+                tags.retain(|t| t.name != "Nonce");
+                let node = SuNode {
+                    message: None,
+                    assignment: SuAssignment { id: "assignment-123".to_string(), tags }
+                };
+
+                match AoSu::map_node(node, &ao_global()) {
+                    Ok(_) => panic!("Should not map a node without a nonce"),
+                    Err(CuErrors::HttpStatus(e)) => assert!(e.status == 502),
+                    Err(_) => panic!("Wrong error provided")
+                }
+            }
+        }
+
+        mod message_meta {
+            use super::*;
+
+            #[test]
+            fn test_parse_an_assignment_response() {
+                let meta = serde_json::from_value::<SuMessageMeta>(serde_json::json!({
+                    "message": { "id": "message-123", "tags": [] },
+                    "assignment": {
+                        "id": "assignment-123",
+                        "tags": [
+                            { "name": "Process", "value": "process-123" },
+                            { "name": "Nonce", "value": "3" },
+                            { "name": "Timestamp", "value": "1702677252111" },
+                            { "name": "Block-Height", "value": "1234" }
+                        ]
+                    }
+                })).unwrap();
+                match meta {
+                    SuMessageMeta::Assignment { assignment } => {
+                        assert!(find_tag_value("Process", &assignment.tags).unwrap() == "process-123");
+                        assert!(parse_int("Nonce", find_tag_value("Nonce", &assignment.tags)).unwrap() == 3);
+                    },
+                    SuMessageMeta::Legacy { .. } => panic!("Should parse the assignment shape")
+                }
+            }
+
+            #[test]
+            fn test_parse_a_legacy_response() {
+                let meta = serde_json::from_value::<SuMessageMeta>(serde_json::json!({
+                    "process_id": "process-123",
+                    "timestamp": 1702677252111i64,
+                    "nonce": 3
+                })).unwrap();
+                match meta {
+                    SuMessageMeta::Legacy { process_id, timestamp, nonce } => {
+                        assert!(process_id == "process-123");
+                        assert!(timestamp == 1702677252111);
+                        assert!(nonce == 3);
+                    },
+                    SuMessageMeta::Assignment { .. } => panic!("Should parse the legacy shape")
+                }
+            }
+        }
+
+        mod su_stub {
+            use super::*;
+            use std::sync::Mutex as SyncMutex;
+            use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+            use crate::tests::fixtures::log::get_logger;
+
+            /// answers requests in turn with the responses, and records the path of each
+            async fn su_stub(responses: Vec<(u16, String)>) -> (String, Arc<SyncMutex<Vec<String>>>) {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let url = format!("http://{}", listener.local_addr().unwrap());
+                let requests = Arc::new(SyncMutex::new(vec![]));
+                let recorded = requests.clone();
+                tokio::spawn(async move {
+                    for (status, body) in responses {
+                        let (mut socket, _) = listener.accept().await.unwrap();
+                        let mut buf = vec![0; 4096];
+                        let read = socket.read(&mut buf).await.unwrap();
+                        let request = String::from_utf8_lossy(&buf[..read]).to_string();
+                        recorded.lock().unwrap().push(request.split_whitespace().nth(1).unwrap_or_default().to_string());
+                        let response = format!(
+                            "HTTP/1.1 {} STUB\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            status, body.len(), body
+                        );
+                        socket.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+                (url, requests)
+            }
+
+            fn page(cursors: &[&str], has_next_page: bool) -> String {
+                let edges: Vec<serde_json::Value> = cursors.iter()
+                    .map(|cursor| serde_json::json!({
+                        "cursor": cursor,
+                        "node": { "assignment": { "id": format!("assignment-{}", cursor), "tags": assignment_tags() } }
+                    }))
+                    .collect();
+                serde_json::json!({ "page_info": { "has_next_page": has_next_page }, "edges": edges }).to_string()
+            }
+
+            #[tokio::test]
+            async fn test_get_json_returns_client_errors() {
+                let (url, requests) = su_stub(vec![(404, "not found".to_string())]).await;
+                match get_json::<SuTimestamp>(&Client::new(), &get_logger(), &url, "test").await {
+                    Ok(_) => panic!("Should not find the timestamp"),
+                    Err(CuErrors::HttpStatus(e)) => assert!(e.status == 404),
+                    Err(_) => panic!("Wrong error provided")
+                }
+                assert!(requests.lock().unwrap().len() == 1);
+            }
+
+            #[tokio::test]
+            async fn test_get_json_retries_server_errors() {
+                let (url, requests) = su_stub(vec![
+                    (503, "unavailable".to_string()),
+                    (200, r#"{ "timestamp": "1702677252111", "block_height": "1234" }"#.to_string())
+                ]).await;
+                match get_json::<SuTimestamp>(&Client::new(), &get_logger(), &url, "test").await {
+                    Ok(timestamp) => assert!(timestamp.block_height == "1234"),
+                    Err(e) => panic!("{:?}", e)
+                }
+                assert!(requests.lock().unwrap().len() == 2);
+            }
+
+            #[tokio::test]
+            async fn test_prefetch_pages_from_the_last_cursor() {
+                let (url, requests) = su_stub(vec![
+                    (200, page(&["cursor-1", "cursor-2"], true)),
+                    (200, page(&["cursor-3"], false))
+                ]).await;
+
+                let pages: Vec<SuPage> = prefetch_pages(Client::new(), get_logger(), 2, &url, "process-123", Some(100), None)
+                    .map(|page| page.unwrap())
+                    .collect()
+                    .await;
+                assert!(pages.len() == 2);
+                assert!(pages[0].edges.len() == 2);
+                assert!(pages[1].edges[0].cursor == "cursor-3");
+
+                let requests = requests.lock().unwrap();
+                assert!(requests[0] == "/process-123?process-id=process-123&limit=2&from=100");
+                assert!(requests[1] == "/process-123?process-id=process-123&limit=2&from=cursor-2");
+            }
+        }
+    }
+}
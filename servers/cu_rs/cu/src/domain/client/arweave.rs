@@ -82,7 +82,7 @@ impl InternalArweave {
      * @param {Env1} env
      * @returns {LoadTransactionMeta}
     */
-    pub async fn load_tx_meta(self, graphql_url: &str, id: &str) -> Result<Node, QueryGatewayErrors> {
+    pub async fn load_tx_meta(&self, graphql_url: &str, id: &str) -> Result<Node, QueryGatewayErrors> {
         #[allow(non_snake_case)]
         let GET_PROCESSES_QUERY = r#"
             query GetProcesses ($processIds: [ID!]!) {
@@ -0,0 +1,417 @@
+use std::{cmp::Ordering, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use ao_common::domain::dal::Log;
+use chrono::{DateTime, Utc};
+use moka::future::Cache;
+use serde_json::Value;
+use sqlx::{sqlite::SqliteRow, FromRow, Row, Sqlite};
+use tokio::io::AsyncWriteExt;
+use crate::domain::{
+    client::{ao_checkpoint::{compress, decompress, hash_memory}, ao_process::{AoProcess, CacheEntry}},
+    dal::{FindLatestCheckpointSchema, SaveCheckpointSchema},
+    model::model::{EvaluationSchema, ProcessCheckpoint},
+    schema_utils::{is_earlier_than, PartialEvaluationSchema},
+    utils::error::{CuErrors, HttpError}
+};
+use super::sqlite::{ConnGetter, SqliteClient, CHECKPOINTS_TABLE};
+
+const CHECKPOINT_FILE_PREFIX: &str = "checkpoint-";
+const CHECKPOINT_FILE_EXTENSION: &str = ".gz";
+/// a file is only ever written to a tmp path, then renamed once complete
+const TMP_FILE_EXTENSION: &str = ".tmp";
+/// older local Checkpoints of a process are removed once it has more than this many
+const MAX_CHECKPOINT_FILES_PER_PROCESS: usize = 2;
+
+#[derive(Debug)]
+pub struct CheckpointFileQuerySchema {
+    /// Comma delimited list: process_id, timestamp, ordinate, cron
+    pub id: String,
+    pub process_id: String,
+    pub module_id: String,
+    pub nonce: Option<i64>,
+    pub epoch: Option<i64>,
+    pub timestamp: i64,
+    pub ordinate: String,
+    pub block_height: i64,
+    pub cron: Option<String>,
+    /// hex encoded sha-256 of the uncompressed memory
+    pub sha: String,
+    /// in milliseconds
+    pub created_at: i64
+}
+
+impl<'r> FromRow<'r, SqliteRow> for CheckpointFileQuerySchema {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(
+            CheckpointFileQuerySchema {
+                id: row.try_get("id")?,
+                process_id: row.try_get("processId")?,
+                module_id: row.try_get("moduleId")?,
+                nonce: row.try_get("nonce")?,
+                epoch: row.try_get("epoch")?,
+                timestamp: row.try_get("timestamp")?,
+                ordinate: row.try_get("ordinate")?,
+                block_height: row.try_get("blockHeight")?,
+                cron: row.try_get("cron")?,
+                sha: row.try_get("sha")?,
+                created_at: row.try_get("createdAt")?
+            }
+        )
+    }
+}
+
+fn file_error(message: String) -> CuErrors {
+    CuErrors::HttpStatus(HttpError { status: 500, message })
+}
+
+/// Same as the id of the evaluation the Checkpoint was created at
+pub fn create_checkpoint_file_id(evaluation: &EvaluationSchema) -> String {
+    let mut id = format!("{},{},{}", evaluation.process_id, evaluation.timestamp, evaluation.ordinate);
+    if let Some(cron) = &evaluation.cron {
+        id.push_str(&format!(",{}", cron));
+    }
+    id
+}
+
+/// The id is only made up of ids, numbers and cron intervals, so it is a safe file name
+pub fn checkpoint_file_name(id: &str) -> String {
+    format!("{}{}{}", CHECKPOINT_FILE_PREFIX, id.replace(',', "_"), CHECKPOINT_FILE_EXTENSION)
+}
+
+// /**
+// * The Checkpoint files, latest first by timestamp, then cron and then nonce.
+// * ordinate is TEXT in the db, so it can't be sorted on there
+// */
+pub fn latest_first(mut rows: Vec<CheckpointFileQuerySchema>) -> Vec<CheckpointFileQuerySchema> {
+    let partial = |row: &CheckpointFileQuerySchema| PartialEvaluationSchema { timestamp: row.timestamp, cron: row.cron.clone() };
+    rows.sort_by(|a, b| {
+        if is_earlier_than(partial(a), partial(b)) {
+            Ordering::Greater
+        } else if is_earlier_than(partial(b), partial(a)) {
+            Ordering::Less
+        } else {
+            b.nonce.cmp(&a.nonce)
+        }
+    });
+    rows
+}
+
+/**
+ * Writes process memory to compressed files in PROCESS_CHECKPOINT_FILE_DIRECTORY,
+ * indexed in the checkpoints table, so a restarted CU does not need to
+ * evaluate every process from its first message
+ */
+pub struct CheckpointFile {
+    sql_client: Arc<SqliteClient>,
+    dir: PathBuf,
+    logger: Arc<dyn Log>
+}
+
+impl CheckpointFile {
+    pub fn new(sql_client: Arc<SqliteClient>, dir: &str, logger: Arc<dyn Log>) -> Self {
+        CheckpointFile {
+            sql_client,
+            dir: PathBuf::from(dir),
+            logger
+        }
+    }
+
+    /**
+     * Creates the directory and removes any file left behind by a write
+     * that was interrupted, ie. by the CU being stopped
+     */
+    pub async fn init(&self) {
+        if let Err(e) = tokio::fs::create_dir_all(&self.dir).await {
+            self.logger.error(format!("Could not create Checkpoint file directory {:?}: {}", self.dir, e));
+            return;
+        }
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.logger.error(format!("Could not read Checkpoint file directory {:?}: {}", self.dir, e));
+                return;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(CHECKPOINT_FILE_PREFIX) && name.ends_with(TMP_FILE_EXTENSION) {
+                _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+
+    fn file_path(&self, id: &str) -> PathBuf {
+        self.dir.join(checkpoint_file_name(id))
+    }
+
+    /**
+     * Every PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL, writes a local Checkpoint
+     * for every process in the cache, reading back the memory of those drained to a file
+     */
+    pub fn spawn_checkpoint_interval(self: &Arc<Self>, cache: Cache<String, CacheEntry>, interval: u64) {
+        let checkpoint_file = self.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_millis(interval));
+            // the first tick completes immediately, and the cache is empty on boot
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                checkpoint_file.logger.log(format!("Checkpointing {} processes in the memory cache", cache.entry_count()));
+                for (_key, entry) in cache.iter() {
+                    let memory = match (&entry.memory, &entry.file) {
+                        (Some(memory), _) => memory.clone(),
+                        (None, Some(file)) => match AoProcess::read_process_memory_file(file).await {
+                            Ok(memory) => memory,
+                            Err(e) => {
+                                checkpoint_file.logger.error(format!(
+                                    "Could not read memory file of process \"{}\". Skipping Checkpoint file... {}",
+                                    entry.evaluation.process_id, e
+                                ));
+                                continue;
+                            }
+                        },
+                        (None, None) => continue
+                    };
+                    if let Err(e) = checkpoint_file.save_checkpoint(&entry.module_id, &entry.evaluation, &memory).await {
+                        checkpoint_file.logger.error(format!(
+                            "Error occurred when writing Checkpoint file for process \"{}\". Skipping... {:?}",
+                            entry.evaluation.process_id, e
+                        ));
+                    }
+                }
+            }
+        });
+    }
+
+    async fn find_checkpoint_files(&self, process_id: &str, module_id: Option<&str>) -> Result<Vec<CheckpointFileQuerySchema>, CuErrors> {
+        let module_filter = if module_id.is_some() { " AND moduleId = ?" } else { "" };
+        let sql = format!(r"
+            SELECT id, processId, moduleId, nonce, epoch, timestamp, ordinate, blockHeight, cron, sha, createdAt
+            FROM {}
+            WHERE processId = ?{};
+        ", CHECKPOINTS_TABLE, module_filter);
+        let mut query = sqlx::query_as::<Sqlite, CheckpointFileQuerySchema>(&sql).bind(process_id);
+        if let Some(module_id) = module_id {
+            query = query.bind(module_id);
+        }
+        let rows = query.fetch_all(self.sql_client.get_conn()).await.map_err(CuErrors::DatabaseError)?;
+        Ok(latest_first(rows))
+    }
+
+    async fn remove_checkpoint_file(&self, id: &str) {
+        if let Err(e) = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", CHECKPOINTS_TABLE))
+            .bind(id)
+            .execute(self.sql_client.get_conn())
+            .await {
+            self.logger.error(format!("Could not remove Checkpoint file {} from db: {:?}", id, e));
+        }
+        _ = tokio::fs::remove_file(self.file_path(id)).await;
+    }
+
+    /// Keeps only the most recent Checkpoint files of the process
+    async fn prune(&self, process_id: &str) -> Result<(), CuErrors> {
+        let rows = self.find_checkpoint_files(process_id, None).await?;
+        for row in rows.iter().skip(MAX_CHECKPOINT_FILES_PER_PROCESS) {
+            self.remove_checkpoint_file(&row.id).await;
+        }
+        Ok(())
+    }
+
+    async fn write_atomic(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(TMP_FILE_EXTENSION);
+
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(data).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, path).await
+    }
+
+    /// The memory in the file, if the file exists and is not corrupted
+    async fn read_memory(&self, row: &CheckpointFileQuerySchema) -> Result<Vec<u8>, CuErrors> {
+        let data = tokio::fs::read(self.file_path(&row.id)).await
+            .map_err(|e| file_error(format!("Could not read Checkpoint file {}: {}", row.id, e)))?;
+        let memory = tokio::task::spawn_blocking(move || decompress(&data)).await
+            .map_err(|e| file_error(e.to_string()))?
+            .map_err(|e| file_error(format!("Could not decode Checkpoint file {}: {}", row.id, e)))?;
+
+        if hash_memory(&memory) != row.sha {
+            return Err(file_error(format!("Checkpoint file {} does not match its sha-256", row.id)));
+        }
+        Ok(memory)
+    }
+}
+
+#[async_trait]
+impl SaveCheckpointSchema for CheckpointFile {
+    async fn save_checkpoint(&self, module_id: &str, evaluation: &EvaluationSchema, memory: &[u8]) -> Result<Option<String>, CuErrors> {
+        let id = create_checkpoint_file_id(evaluation);
+        let existing = self.find_checkpoint_files(&evaluation.process_id, Some(module_id)).await?;
+        // the memory at this evaluation is already in a file, so simply noop
+        if existing.iter().any(|row| row.id == id) {
+            return Ok(None);
+        }
+
+        let owned = memory.to_vec();
+        let (sha, data) = tokio::task::spawn_blocking(move || (hash_memory(&owned), compress(&owned))).await
+            .map_err(|e| file_error(e.to_string()))?;
+        let data = data.map_err(|e| file_error(format!("Could not encode Checkpoint file for process \"{}\": {}", evaluation.process_id, e)))?;
+
+        let path = self.file_path(&id);
+        self.write_atomic(&path, &data).await
+            .map_err(|e| file_error(format!("Could not write Checkpoint file for process \"{}\": {}", evaluation.process_id, e)))?;
+
+        let inserted = sqlx::query(&format!(r"
+            INSERT OR REPLACE INTO {}
+            (id, processId, moduleId, nonce, epoch, timestamp, ordinate, blockHeight, cron, sha, createdAt)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
+        ", CHECKPOINTS_TABLE))
+            .bind(&id)
+            .bind(&evaluation.process_id)
+            .bind(module_id)
+            .bind(evaluation.nonce)
+            .bind(evaluation.epoch)
+            .bind(evaluation.timestamp)
+            .bind(&evaluation.ordinate)
+            .bind(evaluation.block_height)
+            .bind(&evaluation.cron)
+            .bind(&sha)
+            .bind(Utc::now().timestamp_millis())
+            .execute(self.sql_client.get_conn())
+            .await;
+        // a file that isn't in the db would never be found nor pruned
+        if let Err(e) = inserted {
+            _ = tokio::fs::remove_file(&path).await;
+            return Err(CuErrors::DatabaseError(e));
+        }
+
+        self.logger.log(format!("Wrote Checkpoint file {} for process \"{}\"", checkpoint_file_name(&id), evaluation.process_id));
+        self.prune(&evaluation.process_id).await?;
+
+        Ok(Some(checkpoint_file_name(&id)))
+    }
+}
+
+#[async_trait]
+impl FindLatestCheckpointSchema for CheckpointFile {
+    /// The newest Checkpoint file that can be read. Any invalid file is removed
+    async fn find_latest_checkpoint(&self, process_id: &str, module_id: &str) -> Result<Option<ProcessCheckpoint>, CuErrors> {
+        for row in self.find_checkpoint_files(process_id, Some(module_id)).await? {
+            let memory = match self.read_memory(&row).await {
+                Ok(memory) => memory,
+                Err(e) => {
+                    self.logger.error(format!("Invalid Checkpoint file for process \"{}\". Removing... {:?}", process_id, e));
+                    self.remove_checkpoint_file(&row.id).await;
+                    continue;
+                }
+            };
+
+            return Ok(Some(ProcessCheckpoint {
+                id: checkpoint_file_name(&row.id),
+                module_id: row.module_id,
+                evaluation: EvaluationSchema {
+                    process_id: row.process_id,
+                    message_id: None,
+                    deep_hash: None,
+                    timestamp: row.timestamp,
+                    epoch: row.epoch,
+                    nonce: row.nonce,
+                    ordinate: row.ordinate,
+                    block_height: row.block_height,
+                    cron: row.cron,
+                    evaluated_at: DateTime::from_timestamp_millis(row.created_at).unwrap_or_else(Utc::now),
+                    output: Value::Null
+                },
+                memory
+            }));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluation(cron: Option<&str>) -> EvaluationSchema {
+        EvaluationSchema {
+            process_id: "process-123".to_string(),
+            message_id: None,
+            deep_hash: None,
+            timestamp: 1702677252111,
+            epoch: Some(0),
+            nonce: Some(7),
+            ordinate: "7".to_string(),
+            block_height: 1234,
+            cron: cron.map(|cron| cron.to_string()),
+            evaluated_at: Utc::now(),
+            output: Value::Null
+        }
+    }
+
+    mod checkpoint_file {
+        use super::*;
+
+        mod create_checkpoint_file_id {
+            use super::*;
+
+            #[test]
+            fn test_match_the_evaluation_id() {
+                assert!(create_checkpoint_file_id(&evaluation(None)) == "process-123,1702677252111,7");
+                assert!(create_checkpoint_file_id(&evaluation(Some("1-10-minutes"))) == "process-123,1702677252111,7,1-10-minutes");
+            }
+        }
+
+        mod checkpoint_file_name {
+            use super::*;
+
+            #[test]
+            fn test_replace_the_commas() {
+                assert!(checkpoint_file_name("process-123,1702677252111,7,1-10-minutes") == "checkpoint-process-123_1702677252111_7_1-10-minutes.gz");
+            }
+        }
+
+        mod latest_first {
+            use super::*;
+
+            fn row(timestamp: i64, nonce: i64, cron: Option<&str>) -> CheckpointFileQuerySchema {
+                let mut evaluation = evaluation(cron);
+                evaluation.timestamp = timestamp;
+                evaluation.nonce = Some(nonce);
+                evaluation.ordinate = nonce.to_string();
+                CheckpointFileQuerySchema {
+                    id: create_checkpoint_file_id(&evaluation),
+                    process_id: evaluation.process_id,
+                    module_id: "module-123".to_string(),
+                    nonce: evaluation.nonce,
+                    epoch: evaluation.epoch,
+                    timestamp: evaluation.timestamp,
+                    ordinate: evaluation.ordinate,
+                    block_height: evaluation.block_height,
+                    cron: evaluation.cron,
+                    sha: "sha-123".to_string(),
+                    created_at: 0
+                }
+            }
+
+            #[test]
+            fn test_sort_by_timestamp_cron_and_then_nonce() {
+                let rows = vec![
+                    row(1702677252111, 9, None),
+                    row(1702677252999, 9, None),
+                    row(1702677252999, 10, None),
+                    row(1702677252999, 9, Some("1-10-minutes"))
+                ];
+                let ids: Vec<String> = latest_first(rows).into_iter().map(|row| row.id).collect();
+                // "10" < "9" as TEXT, so the nonce is what orders them
+                assert!(ids == vec![
+                    "process-123,1702677252999,9,1-10-minutes",
+                    "process-123,1702677252999,10",
+                    "process-123,1702677252999,9",
+                    "process-123,1702677252111,9"
+                ]);
+            }
+        }
+    }
+}
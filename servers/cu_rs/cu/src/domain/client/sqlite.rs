@@ -16,6 +16,7 @@ pub const BLOCKS_TABLE: &str = "blocks";
 pub const MODULES_TABLE: &str = "modules"; 
 pub const EVALUATIONS_TABLE: &str = "evaluations"; 
 pub const MESSAGES_TABLE: &str = "messages";
+pub const CHECKPOINTS_TABLE: &str = "checkpoints";
 
 #[derive(FromRow, Debug)]
 pub struct SqliteMasterEntry {
@@ -64,8 +65,10 @@ impl Repository for SqliteClient {
         client.create_modules().await;
         client.create_evaluations().await;
         client.create_messages().await;
+        client.create_checkpoints().await;
         client.create_block_indexes().await;
         client.create_message_indexes().await;
+        client.create_checkpoint_indexes().await;
 
         client
     }
@@ -168,6 +171,30 @@ impl SqliteClient {
         }
     }
 
+    /// an index of the local Checkpoint files, the memory itself is only ever in the file
+    pub async fn create_checkpoints(&self) {
+        match sqlx::query::<_>(format!(r"
+            CREATE TABLE IF NOT EXISTS {} (
+                id TEXT PRIMARY KEY,
+                processId TEXT,
+                moduleId TEXT,
+                nonce INTEGER,
+                epoch INTEGER,
+                timestamp INTEGER,
+                ordinate TEXT,
+                blockHeight INTEGER,
+                cron TEXT,
+                sha TEXT,
+                createdAt INTEGER
+            ) WITHOUT ROWID;
+        ", CHECKPOINTS_TABLE).as_str())
+        .execute(self.get_conn())
+        .await {
+            Ok(res) => self.logger.log(format!("Created Checkpoints Table {:?}", res)),
+            Err(e) => self.logger.error(format!("Failed to Create Checkpoints Table {:?}", e))
+        }
+    }
+
     pub async fn create_block_indexes(&self) {
         match sqlx::query::<_>(format!(r"
             CREATE INDEX IF NOT EXISTS idx_{}_height_timestamp
@@ -193,6 +220,19 @@ impl SqliteClient {
             Err(e) => self.logger.error(format!("Failed to Create Message Indexes {:?}", e))
         }
     }
+
+    pub async fn create_checkpoint_indexes(&self) {
+        match sqlx::query::<_>(format!(r"
+            CREATE INDEX IF NOT EXISTS idx_{}_processId_timestamp
+            ON {}
+            (processId, timestamp);
+        ", CHECKPOINTS_TABLE, CHECKPOINTS_TABLE).as_str())
+        .execute(self.get_conn())
+        .await {
+            Ok(res) => self.logger.log(format!("Created Checkpoint Indexes {:?}", res)),
+            Err(e) => self.logger.error(format!("Failed to Create Checkpoint Indexes {:?}", e))
+        }
+    }
 }
 
 async fn create_sqlite_client(url: &str, bootstrap: Option<bool>, wal_limit: Option<u64>) -> Result<Pool<Sqlite>, sqlx::Error> {
@@ -0,0 +1,724 @@
+use std::{path::PathBuf, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Instant};
+use async_trait::async_trait;
+use ao_common::domain::dal::Log;
+use moka::future::Cache;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{sync::Semaphore, time::timeout_at};
+use wasmedge_sdk::{
+    config::{CommonConfigOptions, ConfigBuilder, RuntimeConfigOptions, StatisticsConfigOptions},
+    error::HostFuncError,
+    params, CallingFrame, ExternalInstanceType, FuncType, ImportObject, ImportObjectBuilder, Module, NeverType, ValType, Vm, VmBuilder, WasmValue
+};
+use crate::domain::{
+    dal::EvaluatorSchema,
+    model::model::{EvaluateArgs, Output},
+    utils::error::{CuErrors, HttpError}
+};
+
+/// size of a wasm memory page
+const WASM_PAGE_SIZE: u64 = 65_536;
+/// chunk size used to find the end of a c string in memory
+const C_STRING_CHUNK: u32 = 4096;
+/// host errors the emscripten shims trap with
+const LONGJMP: u32 = 1;
+const ABORT: u32 = 2;
+/// returned by the syscalls a process has no business making, ENOSYS
+const ENOSYS: i32 = -52;
+/// wasi returns errnos as positive numbers
+const WASI_ESUCCESS: i32 = 0;
+const WASI_ENOSYS: i32 = 52;
+/// the wasi clock ticks in nanoseconds, the message timestamp in milliseconds
+const NANOS_PER_MS: u64 = 1_000_000;
+const BUFFER_ALLOCATION_ERROR: &str = "not enough memory for buffer allocation";
+/// how WasmEdge reports a run that used up its compute limit
+const COST_LIMIT_EXCEEDED: &str = "cost limit exceeded";
+/**
+ * Instructions assumed to run in a millisecond, to turn the time left before a deadline
+ * into a compute limit. The caller is answered at the deadline either way, the limit makes
+ * WasmEdge stop a message that runs past it so it frees its worker
+ */
+const INSTRUCTIONS_PER_MS: u64 = 100_000;
+
+/// what a module returns from handle(msg, env)
+#[derive(Deserialize, Debug)]
+struct HandleResult {
+    ok: bool,
+    response: Value
+}
+
+/// the limits a single evaluation runs under
+#[derive(Clone, Copy, Debug)]
+struct Limits {
+    memory_limit: u64,
+    compute_limit: u64,
+    /**
+     * the clocks are answered with the message timestamp, and random bytes are seeded
+     * with it, so evaluation stays deterministic
+     */
+    timestamp: i64
+}
+
+/**
+ * Evaluates messages with the WasmEdge runtime. Compiled modules are cached in memory, and wasm
+ * binaries in WASM_BINARY_FILE_DIRECTORY, else fetched from the gateway. A WasmEdge Vm is not Send,
+ * so an instance is bootstrapped on a blocking thread for each evaluation
+ */
+pub struct WasmEvaluator {
+    client: Client,
+    arweave_url: String,
+    binary_dir: PathBuf,
+    /// compiled modules, by module id
+    module_cache: Cache<String, Module>,
+    /// WASM_EVALUATION_MAX_WORKERS evaluations run at a time
+    workers: Arc<Semaphore>,
+    memory_max_limit: i64,
+    compute_max_limit: i64,
+    logger: Arc<dyn Log>
+}
+
+impl WasmEvaluator {
+    pub fn new(
+        arweave_url: &str,
+        binary_dir: &str,
+        module_cache_max_size: u64,
+        max_workers: usize,
+        memory_max_limit: i64,
+        compute_max_limit: i64,
+        logger: Arc<dyn Log>
+    ) -> Self {
+        WasmEvaluator {
+            client: Client::new(),
+            arweave_url: arweave_url.trim_end_matches('/').to_string(),
+            binary_dir: PathBuf::from(binary_dir),
+            module_cache: Cache::builder().max_capacity(module_cache_max_size).build(),
+            workers: Arc::new(Semaphore::new(max_workers.max(1))),
+            memory_max_limit,
+            compute_max_limit,
+            logger
+        }
+    }
+
+    /// the compiled module from memory, else compiles the wasm binary from a file, else from the gateway
+    async fn load_module(&self, module_id: &str) -> Result<Module, CuErrors> {
+        if let Some(module) = self.module_cache.get(module_id).await {
+            return Ok(module);
+        }
+
+        let file = self.binary_dir.join(format!("{}.wasm", module_id));
+        self.logger.log(format!("Checking for wasm file to load module \"{}\"...", module_id));
+        let wasm = match tokio::fs::read(&file).await {
+            Ok(wasm) => wasm,
+            Err(_) => {
+                self.logger.log(format!("Loading wasm transaction \"{}\"...", module_id));
+                let wasm = self.stream_transaction_data(module_id).await?;
+                if let Err(e) = tokio::fs::write(&file, &wasm).await {
+                    self.logger.error(format!("Failed to cache binary for module \"{}\" in a file. Skipping... {}", module_id, e));
+                }
+                wasm
+            }
+        };
+
+        let compiled = tokio::task::spawn_blocking(move || compile(&wasm)).await
+            .map_err(|e| e.to_string())
+            .and_then(|compiled| compiled);
+        let module = match compiled {
+            Ok(module) => module,
+            Err(e) => return Err(CuErrors::HttpStatus(HttpError { status: 422, message: format!("Could not compile module \"{}\": {}", module_id, e) }))
+        };
+
+        self.logger.log(format!("Caching compiled module \"{}\" in memory, for next time...", module_id));
+        self.module_cache.insert(module_id.to_string(), module.clone()).await;
+        Ok(module)
+    }
+
+    async fn stream_transaction_data(&self, tx_id: &str) -> Result<Vec<u8>, CuErrors> {
+        let bad_gateway = |message: String| CuErrors::HttpStatus(HttpError { status: 502, message });
+        let url = format!("{}/raw/{}", self.arweave_url, tx_id);
+        let res = self.client.get(&url).send().await
+            .map_err(|e| bad_gateway(format!("Error Encountered when fetching raw data for transaction '{}': {}", tx_id, e)))?;
+        if !res.status().is_success() {
+            let status = res.status().as_u16();
+            let text = res.text().await.unwrap_or_default();
+            return Err(bad_gateway(format!("Error Encountered when fetching raw data for transaction '{}': {}: {}", tx_id, status, text)));
+        }
+        res.bytes().await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| bad_gateway(format!("Could not read raw data for transaction '{}': {}", tx_id, e)))
+    }
+}
+
+#[async_trait]
+impl EvaluatorSchema for WasmEvaluator {
+    async fn evaluate(&self, args: EvaluateArgs) -> Result<Output, CuErrors> {
+        let module = self.load_module(&args.module_id).await?;
+        let internal = |message: String| CuErrors::HttpStatus(HttpError { status: 500, message });
+        let message = serde_json::to_string(&args.message).map_err(|e| internal(e.to_string()))?;
+        let env = serde_json::to_string(&args.ao_global).map_err(|e| internal(e.to_string()))?;
+        let timed_out = || CuErrors::HttpStatus(HttpError {
+            status: 408,
+            message: format!("Evaluation of message \"{}\" to process \"{}\" ran out of time", args.name, args.process_id)
+        });
+
+        let worker = match args.deadline {
+            Some(deadline) => timeout_at(deadline.into(), self.workers.clone().acquire_owned()).await.map_err(|_| timed_out())?,
+            None => self.workers.clone().acquire_owned().await
+        }.map_err(|e| internal(e.to_string()))?;
+        let compute_limit = args.module_options.compute_limit.min(self.compute_max_limit).max(0) as u64;
+        let timed_limit = match args.deadline {
+            Some(deadline) => Some(compute_until(deadline, Instant::now()).ok_or_else(timed_out)?),
+            None => None
+        };
+        let limits = Limits {
+            memory_limit: args.module_options.memory_limit.min(self.memory_max_limit).max(0) as u64,
+            compute_limit: timed_limit.map_or(compute_limit, |timed_limit| timed_limit.min(compute_limit)),
+            timestamp: args.message.timestamp
+        };
+
+        self.logger.log(format!("Evaluating message \"{}\" to process \"{}\"", args.name, args.process_id));
+        let prev_memory = args.memory;
+        let memory = prev_memory.clone();
+        // the worker is held until the instance is done, even when this future is dropped
+        let run = tokio::task::spawn_blocking(move || {
+            let _worker = worker;
+            handle(&module, memory, &message, &env, limits)
+        });
+        let joined = match args.deadline {
+            Some(deadline) => timeout_at(deadline.into(), run).await.map_err(|_| timed_out())?,
+            None => run.await
+        };
+        let result = joined
+            .map_err(|e| internal(format!("Evaluation of message \"{}\" did not complete: {}", args.name, e)))?;
+        if is_timed_out(&result, timed_limit, compute_limit) {
+            return Err(timed_out());
+        }
+
+        let output = merge_output(prev_memory, result);
+        if is_buffer_allocation_error(&output.output) {
+            self.logger.error(format!(
+                "WASM MEMORY ERROR: Detected buffer allocation error in Output for message \"{}\" sent to process \"{}\". Mapping to an error.",
+                args.name, args.process_id
+            ));
+        }
+        Ok(output)
+    }
+}
+
+/// memory and gas of a handle call that returned
+struct Evaluated {
+    memory: Vec<u8>,
+    gas_used: u64,
+    result: HandleResult
+}
+
+/**
+ * The memory is kept as it was before the message when the module errors, traps or runs
+ * out of memory or compute, so a bad message can't corrupt the process
+ */
+fn merge_output(prev_memory: Option<Vec<u8>>, result: Result<Evaluated, String>) -> Output {
+    let evaluated = match result {
+        Ok(evaluated) => evaluated,
+        Err(error) => return Output { memory: prev_memory, error: Some(Value::String(error)), ..Default::default() }
+    };
+    if !evaluated.result.ok {
+        return Output {
+            memory: prev_memory,
+            error: Some(evaluated.result.response),
+            gas_used: Some(evaluated.gas_used as i64),
+            ..Default::default()
+        };
+    }
+
+    let mut output = match serde_json::from_value::<Output>(evaluated.result.response) {
+        Ok(output) => output,
+        Err(e) => return Output { memory: prev_memory, error: Some(Value::String(format!("Invalid response from handle: {}", e))), ..Default::default() }
+    };
+    output.memory = if output.error.is_some() || is_buffer_allocation_error(&output.output) { prev_memory } else { Some(evaluated.memory) };
+    if let Value::Number(number) = &output.output {
+        output.output = Value::String(number.to_string());
+    }
+    output.gas_used = Some(evaluated.gas_used as i64);
+    output
+}
+
+/// the instructions that fit in the time left before the deadline, None once it has passed
+fn compute_until(deadline: Instant, now: Instant) -> Option<u64> {
+    let left = deadline.checked_duration_since(now).filter(|left| !left.is_zero())?;
+    Some((left.as_millis() as u64).max(1).saturating_mul(INSTRUCTIONS_PER_MS))
+}
+
+/// whether the run used up a compute limit that was lowered to meet a deadline
+fn is_timed_out(result: &Result<Evaluated, String>, timed_limit: Option<u64>, compute_limit: u64) -> bool {
+    match (result, timed_limit) {
+        (Err(error), Some(timed_limit)) => timed_limit < compute_limit && error.contains(COST_LIMIT_EXCEEDED),
+        _ => false
+    }
+}
+
+fn is_buffer_allocation_error(output: &Value) -> bool {
+    let text = match output {
+        Value::Object(_) => output.pointer("/data/output").and_then(|text| text.as_str()),
+        _ => output.as_str()
+    };
+    text.map(|text| text.ends_with(BUFFER_ALLOCATION_ERROR)).unwrap_or(false)
+}
+
+fn pages(bytes: u64) -> u32 {
+    bytes.div_ceil(WASM_PAGE_SIZE) as u32
+}
+
+fn common_options() -> CommonConfigOptions {
+    CommonConfigOptions::default()
+        .bulk_memory_operations(true)
+        .multi_value(true)
+        .mutable_globals(true)
+        .non_trap_conversions(true)
+        .sign_extension_operators(true)
+}
+
+/// the limits are set on the Vm, so a module is compiled once for every process running it
+fn compile(wasm: &[u8]) -> Result<Module, String> {
+    let config = ConfigBuilder::new(common_options()).build().map_err(|e| e.to_string())?;
+    Module::from_bytes(Some(&config), wasm).map_err(|e| e.to_string())
+}
+
+/**
+ * Bootstraps an instance, restores the memory and applies the message to it.
+ * The host wasi is never registered, a module gets the deterministic shim instead
+ */
+fn handle(module: &Module, memory: Option<Vec<u8>>, message: &str, env: &str, limits: Limits) -> Result<Evaluated, String> {
+    // /**
+    // * Every instruction costs 1, so the compute limit is an instruction limit
+    // * and a runaway process traps at the same instruction on every CU
+    // */
+    let statistics_options = StatisticsConfigOptions::default()
+        .count_instructions(true)
+        .measure_cost(true);
+    let runtime_options = RuntimeConfigOptions::default()
+        .max_memory_pages(pages(limits.memory_limit));
+    let config = ConfigBuilder::new(common_options())
+        .with_statistics_config(statistics_options)
+        .with_runtime_config(runtime_options)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let env_imports = emscripten_imports(module, limits)?;
+    let wasi_imports = wasi_imports(module, limits)?;
+    let mut vm = VmBuilder::new().with_config(config).build().map_err(|e| e.to_string())?
+        .register_import_module(&env_imports).map_err(|e| e.to_string())?
+        .register_import_module(&wasi_imports).map_err(|e| e.to_string())?
+        .register_module(None, module.clone()).map_err(|e| e.to_string())?;
+    if let Some(statistics) = vm.statistics_mut() {
+        statistics.set_cost_limit(limits.compute_limit);
+    }
+
+    let instance = vm.active_module().map_err(|e| e.to_string())?;
+    for ctors in ["_initialize", "__wasm_call_ctors"] {
+        if instance.func(ctors).is_ok() {
+            vm.run_func(None, ctors, params!()).map_err(|e| e.to_string())?;
+            break;
+        }
+    }
+
+    let mut wasm_memory = instance.memory("memory").map_err(|e| e.to_string())?;
+    if let Some(memory) = memory {
+        if memory.len() as u64 > limits.memory_limit {
+            return Err(format!("Memory of {} bytes exceeds the Memory-Limit of {} bytes", memory.len(), limits.memory_limit));
+        }
+        let needed = pages(memory.len() as u64);
+        let current = wasm_memory.size();
+        if needed > current {
+            wasm_memory.grow(needed - current).map_err(|e| e.to_string())?;
+        }
+        wasm_memory.write(memory, 0).map_err(|e| e.to_string())?;
+    }
+
+    let message_ptr = write_c_string(&vm, message)?;
+    let env_ptr = write_c_string(&vm, env)?;
+    let returned = vm.run_func(None, "handle", params!(message_ptr, env_ptr)).map_err(|e| e.to_string())?;
+    let response_ptr = returned.first().map(|ptr| ptr.to_i32()).ok_or("handle did not return a response".to_string())?;
+
+    let wasm_memory = vm.active_module().and_then(|instance| instance.memory("memory")).map_err(|e| e.to_string())?;
+    let response = read_c_string(|offset, len| wasm_memory.read(offset, len).ok(), response_ptr as u32, wasm_memory.size() as u64 * WASM_PAGE_SIZE)?;
+    let result = serde_json::from_str::<HandleResult>(&response).map_err(|e| format!("Invalid response from handle: {}", e))?;
+    let gas_used = vm.statistics().map(|statistics| statistics.cost()).unwrap_or(0);
+    let memory = wasm_memory.read(0, wasm_memory.size() * WASM_PAGE_SIZE as u32).map_err(|e| e.to_string())?;
+
+    Ok(Evaluated { memory, gas_used, result })
+}
+
+fn write_c_string(vm: &Vm, value: &str) -> Result<i32, String> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    let ptr = vm.run_func(None, "malloc", params!(bytes.len() as i32)).map_err(|e| e.to_string())?
+        .first()
+        .map(|ptr| ptr.to_i32())
+        .ok_or("malloc did not return a pointer".to_string())?;
+    if ptr == 0 {
+        return Err(BUFFER_ALLOCATION_ERROR.to_string());
+    }
+    let mut memory = vm.active_module().and_then(|instance| instance.memory("memory")).map_err(|e| e.to_string())?;
+    memory.write(bytes, ptr as u32).map_err(|e| e.to_string())?;
+    Ok(ptr)
+}
+
+/// reads the null terminated utf8 string at ptr, a chunk at a time
+fn read_c_string<F>(read: F, ptr: u32, memory_size: u64) -> Result<String, String>
+    where F: Fn(u32, u32) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut offset = ptr as u64;
+    while offset < memory_size {
+        let len = C_STRING_CHUNK.min((memory_size - offset) as u32);
+        let chunk = read(offset as u32, len).ok_or(format!("Could not read memory at {}", offset))?;
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                return String::from_utf8(bytes).map_err(|e| e.to_string());
+            },
+            None => bytes.extend_from_slice(&chunk)
+        }
+        offset += len as u64;
+    }
+    Err("Response from handle is not null terminated".to_string())
+}
+
+fn zeros(ty: &FuncType) -> Vec<WasmValue> {
+    ty.returns().unwrap_or(&[]).iter()
+        .map(|ty| match ty {
+            ValType::I64 => WasmValue::from_i64(0),
+            ValType::F32 => WasmValue::from_f32(0.0),
+            ValType::F64 => WasmValue::from_f64(0.0),
+            _ => WasmValue::from_i32(0)
+        })
+        .collect()
+}
+
+/**
+ * The host functions an emscripten module imports from env, normally provided by its js glue.
+ * Anything that would leak the host, like the clock or a syscall, gets a deterministic answer
+ */
+fn emscripten_imports(module: &Module, limits: Limits) -> Result<ImportObject<NeverType>, String> {
+    let mut builder = ImportObjectBuilder::new();
+    for import in module.imports().iter().filter(|import| import.module_name() == "env") {
+        let ty = match import.ty() {
+            Ok(ExternalInstanceType::Func(ty)) => ty,
+            _ => continue
+        };
+        let name = import.name().to_string();
+        let returns = ty.clone();
+
+        builder = match name.as_str() {
+            "emscripten_memcpy_big" | "_emscripten_memcpy_js" => builder.with_func_by_type(&name, ty, move |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                let mut memory = frame.memory_mut(0).ok_or(HostFuncError::User(ABORT))?;
+                let bytes = memory.read(args[1].to_i32() as u32, args[2].to_i32() as u32).map_err(|_| HostFuncError::User(ABORT))?;
+                memory.write(bytes, args[0].to_i32() as u32).map_err(|_| HostFuncError::User(ABORT))?;
+                Ok(zeros(&returns))
+            }, None),
+            // /**
+            // * Growing the heap is how an emscripten module allocates, refuse once it would
+            // * pass the Memory-Limit so malloc fails inside the module
+            // */
+            "emscripten_resize_heap" => builder.with_func_by_type(&name, ty, move |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                let mut memory = frame.memory_mut(0).ok_or(HostFuncError::User(ABORT))?;
+                let requested = args[0].to_i32() as u32 as u64;
+                let needed = pages(requested);
+                let current = memory.size();
+                if requested > limits.memory_limit || (needed > current && memory.grow(needed - current).is_err()) {
+                    return Ok(vec![WasmValue::from_i32(0)]);
+                }
+                Ok(vec![WasmValue::from_i32(1)])
+            }, None),
+            "emscripten_date_now" | "_emscripten_get_now" | "emscripten_get_now" => builder.with_func_by_type(&name, ty, move |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Ok(vec![WasmValue::from_f64(limits.timestamp as f64)])
+            }, None),
+            "_emscripten_throw_longjmp" | "emscripten_longjmp" => builder.with_func_by_type(&name, ty, |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Err(HostFuncError::User(LONGJMP))
+            }, None),
+            "abort" | "_abort_js" | "__assert_fail" => builder.with_func_by_type(&name, ty, |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Err(HostFuncError::User(ABORT))
+            }, None),
+            // /**
+            // * invoke_* calls a function in the table, a longjmp out of it is caught here
+            // * and flagged with setThrew, like the emscripten js glue does
+            // */
+            invoke if invoke.starts_with("invoke_") => builder.with_func_by_type(&name, ty, move |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                let instance = frame.module_instance().ok_or(HostFuncError::User(ABORT))?;
+                let executor = frame.executor_mut().ok_or(HostFuncError::User(ABORT))?;
+                let export = |name: &str| instance.func(name).map_err(|_| HostFuncError::User(ABORT));
+                let stack = export("stackSave")?.run(&executor, params!()).map_err(|_| HostFuncError::User(ABORT))?;
+                let func = instance.table("__indirect_function_table")
+                    .and_then(|table| table.get(args[0].to_i32() as u32))
+                    .ok()
+                    .and_then(|func| func.func_ref())
+                    .ok_or(HostFuncError::User(ABORT))?;
+                match func.run(&executor, args[1..].to_vec()) {
+                    Ok(returned) => Ok(returned),
+                    Err(_) => {
+                        export("stackRestore")?.run(&executor, stack).map_err(|_| HostFuncError::User(ABORT))?;
+                        export("setThrew")?.run(&executor, params!(1, 0)).map_err(|_| HostFuncError::User(ABORT))?;
+                        Ok(zeros(&returns))
+                    }
+                }
+            }, None),
+            syscall if syscall.starts_with("__syscall") || syscall.starts_with("fd_") => builder.with_func_by_type(&name, ty, |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Ok(vec![WasmValue::from_i32(ENOSYS)])
+            }, None),
+            _ => builder.with_func_by_type(&name, ty, move |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Ok(zeros(&returns))
+            }, None)
+        }.map_err(|e| format!("Could not provide import env.{}: {}", name, e))?;
+    }
+    builder.build::<NeverType>("env", None).map_err(|e| e.to_string())
+}
+
+/// splitmix64, so the random bytes a message gets are the same on every CU
+fn seeded_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    let mut bytes = Vec::with_capacity(len + 8);
+    while bytes.len() < len {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        bytes.extend_from_slice(&(z ^ (z >> 31)).to_le_bytes());
+    }
+    bytes.truncate(len);
+    bytes
+}
+
+fn write_memory(frame: &CallingFrame, offset: i32, bytes: &[u8]) -> Result<(), HostFuncError> {
+    let mut memory = frame.memory_mut(0).ok_or(HostFuncError::User(ABORT))?;
+    memory.write(bytes, offset as u32).map_err(|_| HostFuncError::User(ABORT))
+}
+
+/**
+ * A wasi_snapshot_preview1 that leaks nothing of the host. The clocks tell the message timestamp,
+ * random bytes are seeded with it, writes to any fd are discarded and everything else is ENOSYS
+ */
+fn wasi_imports(module: &Module, limits: Limits) -> Result<ImportObject<NeverType>, String> {
+    let mut builder = ImportObjectBuilder::new();
+    let random_seed = Arc::new(AtomicU64::new(limits.timestamp as u64));
+    for import in module.imports().iter().filter(|import| import.module_name() == "wasi_snapshot_preview1") {
+        let ty = match import.ty() {
+            Ok(ExternalInstanceType::Func(ty)) => ty,
+            _ => continue
+        };
+        let name = import.name().to_string();
+        let returns = ty.clone();
+
+        builder = match name.as_str() {
+            "clock_time_get" => builder.with_func_by_type(&name, ty, move |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                let now = (limits.timestamp.max(0) as u64).saturating_mul(NANOS_PER_MS);
+                write_memory(&frame, args[2].to_i32(), &now.to_le_bytes())?;
+                Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+            }, None),
+            "clock_res_get" => builder.with_func_by_type(&name, ty, |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                write_memory(&frame, args[1].to_i32(), &NANOS_PER_MS.to_le_bytes())?;
+                Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+            }, None),
+            "random_get" => {
+                let random_seed = random_seed.clone();
+                builder.with_func_by_type(&name, ty, move |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                    let seed = random_seed.fetch_add(1, Ordering::Relaxed);
+                    write_memory(&frame, args[0].to_i32(), &seeded_bytes(seed, args[1].to_i32() as u32 as usize))?;
+                    Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+                }, None)
+            },
+            // the iovecs are counted as written, so the module doesn't retry
+            "fd_write" => builder.with_func_by_type(&name, ty, |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                let memory = frame.memory_mut(0).ok_or(HostFuncError::User(ABORT))?;
+                let iovs = memory.read(args[1].to_i32() as u32, args[2].to_i32() as u32 * 8).map_err(|_| HostFuncError::User(ABORT))?;
+                let written: u32 = iovs.chunks_exact(8)
+                    .map(|iov| u32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]))
+                    .fold(0, |written, len| written.wrapping_add(len));
+                write_memory(&frame, args[3].to_i32(), &written.to_le_bytes())?;
+                Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+            }, None),
+            // no args and no environment
+            "args_sizes_get" | "environ_sizes_get" => builder.with_func_by_type(&name, ty, |frame: CallingFrame, args: Vec<WasmValue>, _data| {
+                write_memory(&frame, args[0].to_i32(), &0u32.to_le_bytes())?;
+                write_memory(&frame, args[1].to_i32(), &0u32.to_le_bytes())?;
+                Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+            }, None),
+            "args_get" | "environ_get" | "sched_yield" => builder.with_func_by_type(&name, ty, |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Ok(vec![WasmValue::from_i32(WASI_ESUCCESS)])
+            }, None),
+            "proc_exit" => builder.with_func_by_type(&name, ty, |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Err(HostFuncError::User(ABORT))
+            }, None),
+            _ => builder.with_func_by_type(&name, ty, move |_frame: CallingFrame, _args: Vec<WasmValue>, _data| {
+                Ok(match returns.returns() {
+                    Some([ValType::I32]) => vec![WasmValue::from_i32(WASI_ENOSYS)],
+                    _ => zeros(&returns)
+                })
+            }, None)
+        }.map_err(|e| format!("Could not provide import wasi_snapshot_preview1.{}: {}", name, e))?;
+    }
+    builder.build::<NeverType>("wasi_snapshot_preview1", None).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod wasm {
+        use super::*;
+
+        mod read_c_string {
+            use super::*;
+
+            #[test]
+            fn test_read_across_chunks() {
+                let mut memory = vec![b'a'; C_STRING_CHUNK as usize + 10];
+                memory.push(0);
+                let read = |offset: u32, len: u32| Some(memory[offset as usize..(offset + len) as usize].to_vec());
+                let value = read_c_string(read, 5, memory.len() as u64).unwrap();
+                assert!(value.len() == C_STRING_CHUNK as usize + 5);
+            }
+
+            #[test]
+            fn test_reject_unterminated() {
+                let memory = vec![b'a'; 100];
+                let read = |offset: u32, len: u32| Some(memory[offset as usize..(offset + len) as usize].to_vec());
+                assert!(read_c_string(read, 0, memory.len() as u64).is_err());
+            }
+        }
+
+        mod deadline {
+            use super::*;
+            use std::time::Duration;
+
+            fn evaluated() -> Result<Evaluated, String> {
+                Ok(Evaluated { memory: vec![], gas_used: 10, result: HandleResult { ok: true, response: Value::Null } })
+            }
+
+            #[test]
+            fn test_compute_until_the_deadline() {
+                let now = Instant::now();
+                assert!(compute_until(now + Duration::from_millis(50), now) == Some(50 * INSTRUCTIONS_PER_MS));
+                assert!(compute_until(now + Duration::from_micros(10), now) == Some(INSTRUCTIONS_PER_MS));
+                assert!(compute_until(now, now).is_none());
+                assert!(compute_until(now, now + Duration::from_millis(1)).is_none());
+            }
+
+            #[test]
+            fn test_timed_out_when_the_deadline_limit_is_used_up() {
+                let cost_limit = || Err(COST_LIMIT_EXCEEDED.to_string());
+                assert!(is_timed_out(&cost_limit(), Some(100), 1000));
+                // the compute limit of the process was the lower one, so the process is out of compute
+                assert!(!is_timed_out(&cost_limit(), Some(1000), 100));
+                assert!(!is_timed_out(&cost_limit(), None, 1000));
+                assert!(!is_timed_out(&Err("unreachable".to_string()), Some(100), 1000));
+                assert!(!is_timed_out(&evaluated(), Some(100), 1000));
+            }
+        }
+
+        mod handle {
+            use super::*;
+
+            const TIMESTAMP: i64 = 1702677252111;
+
+            fn process() -> Module {
+                compile(include_str!("../../tests/fixtures/process.wat").as_bytes()).unwrap()
+            }
+
+            fn limits(memory_pages: u64, compute_limit: u64) -> Limits {
+                Limits { memory_limit: memory_pages * WASM_PAGE_SIZE, compute_limit, timestamp: TIMESTAMP }
+            }
+
+            fn bytes_at(memory: &[u8], offset: usize, len: usize) -> Vec<u8> {
+                memory[offset..offset + len].to_vec()
+            }
+
+            #[test]
+            fn test_meter_the_instructions() {
+                // 127000 iterations of the loop
+                let evaluated = handle(&process(), None, "\u{7f}\u{0}", "{}", limits(4, 100_000_000)).unwrap();
+                assert!(evaluated.result.ok);
+                assert!(evaluated.gas_used > 127_000);
+
+                match handle(&process(), None, "\u{7f}\u{0}", "{}", limits(4, 10_000)) {
+                    Ok(_) => panic!("Should run out of compute"),
+                    Err(e) => assert!(e.contains(COST_LIMIT_EXCEEDED), "{}", e)
+                }
+            }
+
+            #[test]
+            fn test_trap_at_the_memory_limit() {
+                let evaluated = handle(&process(), None, "\u{1}\u{4}", "{}", limits(8, 100_000_000)).unwrap();
+                assert!(evaluated.memory.len() as u64 == 5 * WASM_PAGE_SIZE);
+
+                assert!(handle(&process(), None, "\u{1}\u{4}", "{}", limits(2, 100_000_000)).is_err());
+                // nor can the memory of a process be restored past the limit
+                let memory = vec![0; 3 * WASM_PAGE_SIZE as usize];
+                assert!(handle(&process(), Some(memory), "\u{1}\u{0}", "{}", limits(2, 100_000_000)).is_err());
+            }
+
+            #[test]
+            fn test_deterministic_wasi() {
+                let evaluated = handle(&process(), None, "\u{1}\u{0}", "{}", limits(4, 100_000_000)).unwrap();
+                assert!(bytes_at(&evaluated.memory, 0, 8) == (TIMESTAMP as u64 * NANOS_PER_MS).to_le_bytes().to_vec());
+                assert!(bytes_at(&evaluated.memory, 72, 4) == 8u32.to_le_bytes().to_vec());
+                assert!(bytes_at(&evaluated.memory, 80, 8) == seeded_bytes(TIMESTAMP as u64, 8));
+
+                let again = handle(&process(), None, "\u{1}\u{0}", "{}", limits(4, 100_000_000)).unwrap();
+                assert!(again.memory == evaluated.memory);
+            }
+        }
+
+        mod seeded_bytes {
+            use super::*;
+
+            #[test]
+            fn test_seeded_bytes() {
+                assert!(seeded_bytes(1, 20) == seeded_bytes(1, 20));
+                assert!(seeded_bytes(1, 20)[..8] == seeded_bytes(1, 8)[..]);
+                assert!(seeded_bytes(1, 8) != seeded_bytes(2, 8));
+                assert!(seeded_bytes(1, 0).is_empty());
+            }
+        }
+
+        mod merge_output {
+            use super::*;
+
+            fn evaluated(ok: bool, response: Value) -> Result<Evaluated, String> {
+                Ok(Evaluated { memory: vec![2], gas_used: 10, result: HandleResult { ok, response } })
+            }
+
+            #[test]
+            fn test_keep_the_new_memory() {
+                let output = merge_output(Some(vec![1]), evaluated(true, serde_json::json!({
+                    "Output": { "data": { "output": "hello" } },
+                    "Messages": [{ "Target": "process-456" }]
+                })));
+                assert!(output.memory == Some(vec![2]));
+                assert!(output.messages.len() == 1);
+                assert!(output.gas_used == Some(10));
+                assert!(output.error.is_none());
+            }
+
+            #[test]
+            fn test_keep_the_prev_memory_on_error() {
+                let output = merge_output(Some(vec![1]), evaluated(true, serde_json::json!({ "Error": "oops" })));
+                assert!(output.memory == Some(vec![1]));
+                assert!(output.error == Some(Value::String("oops".to_string())));
+
+                let output = merge_output(Some(vec![1]), Err("cost limit exceeded".to_string()));
+                assert!(output.memory == Some(vec![1]));
+                assert!(output.error.is_some());
+
+                let output = merge_output(Some(vec![1]), evaluated(false, Value::String("trap".to_string())));
+                assert!(output.memory == Some(vec![1]));
+            }
+
+            #[test]
+            fn test_keep_the_prev_memory_on_buffer_allocation_error() {
+                let output = merge_output(Some(vec![1]), evaluated(true, serde_json::json!({
+                    "Output": format!("[string]: {}", BUFFER_ALLOCATION_ERROR)
+                })));
+                assert!(output.memory == Some(vec![1]));
+            }
+        }
+    }
+}
@@ -0,0 +1,776 @@
+use std::{collections::VecDeque, sync::Arc};
+use futures::{stream, StreamExt};
+use crate::domain::{
+    core::read_state::{LoadedProcess, ProcessState},
+    dal::{FindBlocksSchema, LoadBlocksMetaSchema, LoadTimestampSchema, MessageStream, SaveBlocksSchema},
+    model::model::{AoGlobal, BlockSchema, Message, RawTagSchema, ScheduledMessage},
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+pub const CRON_INTERVAL: &str = "Cron-Interval";
+pub const CRON_TAG_PREFIX: &str = "Cron-Tag-";
+
+/// max number of blocks requested from the gateway per page
+const BLOCKS_PAGE_SIZE: i64 = 90;
+
+const MS_PER_SECOND: f64 = 1000.0;
+const MS_PER_MINUTE: f64 = MS_PER_SECOND * 60.0;
+const MS_PER_HOUR: f64 = MS_PER_MINUTE * 60.0;
+const MS_PER_DAY: f64 = MS_PER_HOUR * 24.0;
+const MS_PER_WEEK: f64 = MS_PER_DAY * 7.0;
+const MS_PER_YEAR: f64 = MS_PER_DAY * 365.25;
+const MS_PER_MONTH: f64 = MS_PER_YEAR / 12.0;
+
+pub fn to_seconds(millis: i64) -> i64 {
+    millis.div_euclid(1000)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CronUnit {
+    Blocks,
+    Seconds
+}
+
+/**
+ * A Cron-Interval on a process, along with the Cron-Tag-* tags
+ * that follow it, which are the tags of every message it generates
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cron {
+    /// the raw value of the Cron-Interval tag ie. '10-blocks' or '5-minutes'
+    pub interval: String,
+    pub unit: CronUnit,
+    /// number of blocks, or seconds, between each message
+    pub value: i64,
+    pub tags: Vec<RawTagSchema>
+}
+
+fn parse_error(message: String) -> CuErrors {
+    CuErrors::HttpStatus(HttpError { status: 422, message })
+}
+
+/**
+ * Interval Format: 'X-Y'
+ *
+ * Where X is the value
+ * Where Y is the unit:
+ * - 'blocks'
+ * - time unit ie. 'seconds' 'minutes' 'hours' 'days' 'weeks' 'months' 'years'
+ */
+fn parse_interval(interval: &str) -> Result<(CronUnit, i64), CuErrors> {
+    let mut parts = interval.split('-').map(|part| part.trim());
+    let value = parts.next().unwrap_or_default();
+    let unit = parts.next().unwrap_or_default().to_lowercase();
+
+    if unit == "block" || unit == "blocks" {
+        return match value.parse::<i64>() {
+            Ok(value) if value > 0 => Ok((CronUnit::Blocks, value)),
+            _ => Err(parse_error(format!("block-based cron must be a positive number of blocks: {}", interval)))
+        };
+    }
+
+    let ms_per_unit = match unit.as_str() {
+        "ms" | "msec" | "msecs" | "millisecond" | "milliseconds" => 1.0,
+        "s" | "sec" | "secs" | "second" | "seconds" => MS_PER_SECOND,
+        "m" | "min" | "mins" | "minute" | "minutes" => MS_PER_MINUTE,
+        "h" | "hr" | "hrs" | "hour" | "hours" => MS_PER_HOUR,
+        "d" | "day" | "days" => MS_PER_DAY,
+        "w" | "week" | "weeks" => MS_PER_WEEK,
+        "mo" | "month" | "months" => MS_PER_MONTH,
+        "y" | "yr" | "yrs" | "year" | "years" => MS_PER_YEAR,
+        _ => return Err(parse_error(format!("Unsupported cron interval unit: {}", interval)))
+    };
+    let value = match value.parse::<f64>() {
+        Ok(value) => to_seconds((value * ms_per_unit).round() as i64),
+        Err(_) => return Err(parse_error(format!("Invalid cron interval value: {}", interval)))
+    };
+    if value <= 0 {
+        return Err(parse_error(format!("time-based cron cannot be less than 1 second: {}", interval)));
+    }
+    Ok((CronUnit::Seconds, value))
+}
+
+/**
+ * Builds the crons of a process from its tags. Each Cron-Tag-* belongs
+ * to the most recent Cron-Interval before it:
+ *
+ * - { name: 'Cron-Interval', value: '5-minutes' }
+ * - { name: 'Cron-Tag-Foo', value: 'Bar' }
+ * - { name: 'Cron-Interval', value: '10-blocks' }
+ * - { name: 'Cron-Tag-Fizz', value: 'Buzz' }
+ */
+pub fn parse_crons(tags: &Vec<RawTagSchema>) -> Result<Vec<Cron>, CuErrors> {
+    let mut crons: Vec<Cron> = vec![];
+    for tag in tags {
+        if tag.name == CRON_INTERVAL {
+            let (unit, value) = parse_interval(&tag.value)?;
+            crons.push(Cron { interval: tag.value.clone(), unit, value, tags: vec![] });
+            continue;
+        }
+
+        if let Some(name) = tag.name.strip_prefix(CRON_TAG_PREFIX).filter(|name| !name.is_empty()) {
+            match crons.last_mut() {
+                Some(cron) => cron.tags.push(RawTagSchema { name: name.to_string(), value: tag.value.clone() }),
+                None => return Err(parse_error(format!("Unmatched Cron-Tag with no preceding Cron-Interval: {}", tag.name)))
+            }
+        }
+    }
+    Ok(crons)
+}
+
+/// Whether the block height, relative to the origin block height, matches the cron
+pub fn is_block_on_cron(height: i64, origin_height: i64, cron: &Cron) -> bool {
+    // don't count the origin height as a match
+    if height == origin_height { return false }
+    (height - origin_height) % cron.value == 0
+}
+
+/**
+ * Whether the timestamp, relative to the origin timestamp, matches the cron.
+ *
+ * The smallest unit of a cron is a second, and a modulo of milliseconds
+ * can match fractional overlaps, so both are converted to seconds first
+ */
+pub fn is_timestamp_on_cron(timestamp: i64, origin_timestamp: i64, cron: &Cron) -> bool {
+    let timestamp = to_seconds(timestamp);
+    let origin_timestamp = to_seconds(origin_timestamp);
+    // don't count the origin timestamp as a match
+    if timestamp == origin_timestamp { return false }
+    (timestamp - origin_timestamp) % cron.value == 0
+}
+
+/**
+ * The range of blocks that need to be loaded from the gateway, given the blocks
+ * already cached, or None if nothing is missing. Holes are merged into one range
+ */
+pub fn find_missing_blocks_in(blocks: &Vec<BlockSchema>, min: i64, max_timestamp: i64) -> Option<(i64, i64)> {
+    let max_block = match blocks.last() {
+        Some(block) => block,
+        None => return Some((min, max_timestamp))
+    };
+
+    let missing = (min..max_block.height).find(|height| blocks.binary_search_by_key(height, |block| block.height).is_err());
+    match missing {
+        Some(height) => Some((height, max_timestamp)),
+        // /**
+        // * New blocks are added every ~2 minutes. So if the latest block found is within
+        // * 90 seconds of maxTimestamp, we can be confident no block meta is missing
+        // */
+        None if to_seconds(max_timestamp) - to_seconds(max_block.timestamp) <= 90 => None,
+        None => Some((max_block.height, max_timestamp))
+    }
+}
+
+/// Merges two lists of blocks, each sorted by height, into one without duplicates
+pub fn merge_blocks(from_db: Vec<BlockSchema>, from_gateway: Vec<BlockSchema>) -> Vec<BlockSchema> {
+    let mut merged = from_db;
+    merged.extend(from_gateway);
+    merged.sort_by_key(|block| block.height);
+    merged.dedup_by_key(|block| block.height);
+    merged
+}
+
+/**
+ * Cron messages are generated between two boundaries. A boundary is a scheduled
+ * message, or the last evaluation, or the process itself on a cold start
+ */
+#[derive(Clone, Debug)]
+pub struct CronBoundary {
+    /// the ordinate given to every cron message after this boundary
+    pub ordinate: String,
+    pub block: BlockSchema
+}
+
+impl CronBoundary {
+    pub fn from_message(scheduled: &ScheduledMessage) -> Self {
+        CronBoundary {
+            ordinate: scheduled.ordinate.clone(),
+            block: BlockSchema { height: scheduled.message.block_height, timestamp: scheduled.message.timestamp }
+        }
+    }
+}
+
+/// Everything needed to generate the cron messages of a process
+pub struct CronSchedule {
+    process_id: String,
+    owner: String,
+    ao_global: AoGlobal,
+    origin: BlockSchema,
+    /// the cron ids are the index of the cron within its unit, and its interval ie. '0-10-blocks'
+    block_based: Vec<(String, Cron)>,
+    /// sorted most granular first, so time based messages are ordered consistently
+    time_based: Vec<(String, Cron)>,
+    /// sorted by height
+    blocks: Vec<BlockSchema>
+}
+
+impl CronSchedule {
+    pub fn new(process: &LoadedProcess, crons: Vec<Cron>, blocks: Vec<BlockSchema>) -> Self {
+        let with_ids = |crons: Vec<Cron>| -> Vec<(String, Cron)> {
+            crons.into_iter().enumerate()
+                .map(|(i, cron)| (format!("{}-{}", i, cron.interval), cron))
+                .collect()
+        };
+        let (block_based, time_based): (Vec<Cron>, Vec<Cron>) = crons.into_iter().partition(|cron| cron.unit == CronUnit::Blocks);
+        let mut time_based = time_based;
+        time_based.sort_by_key(|cron| cron.value);
+
+        CronSchedule {
+            process_id: process.id.clone(),
+            owner: process.owner.clone(),
+            ao_global: process.ao_global(),
+            origin: process.block.clone(),
+            block_based: with_ids(block_based),
+            time_based: with_ids(time_based),
+            blocks
+        }
+    }
+
+    /**
+     * The cron messages in the whole seconds after left, up to and including the second of
+     * right_timestamp, so each second is only ticked in one window. resume_after is the
+     * cron of the last evaluation, its second is ticked again for the siblings not yet evaluated
+     */
+    pub fn between(self: &Arc<Self>, left: &CronBoundary, right_timestamp: i64, resume_after: Option<String>) -> CronMessages {
+        let left_second = to_seconds(left.block.timestamp);
+        CronMessages {
+            schedule: self.clone(),
+            ordinate: left.ordinate.clone(),
+            cur_second: if resume_after.is_some() { left_second } else { left_second + 1 },
+            right_second: to_seconds(right_timestamp),
+            right_timestamp,
+            cur_block: left.block.clone(),
+            next_block: self.blocks.iter().position(|block| block.timestamp > left.block.timestamp).unwrap_or(self.blocks.len()),
+            resume_after,
+            pending: VecDeque::new()
+        }
+    }
+
+    fn message(&self, cron_id: &str, cron: &Cron, ordinate: &str, block: BlockSchema) -> ScheduledMessage {
+        ScheduledMessage {
+            cron: Some(cron_id.to_string()),
+            ordinate: ordinate.to_string(),
+            name: format!("Cron Message {},{},{}", block.timestamp, ordinate, cron_id),
+            is_assignment: false,
+            deep_hash: None,
+            message: Message {
+                id: String::new(),
+                signature: None,
+                data: None,
+                owner: self.owner.clone(),
+                target: Some(self.process_id.clone()),
+                anchor: None,
+                from: self.owner.clone(),
+                forwarded_by: None,
+                tags: cron.tags.clone(),
+                epoch: None,
+                nonce: None,
+                timestamp: block.timestamp,
+                block_height: block.height,
+                hash_chain: None,
+                cron: true,
+                read_only: false
+            },
+            ao_global: self.ao_global.clone(),
+            block
+        }
+    }
+}
+
+/**
+ * Ticks one whole second at a time from the left boundary up to the right one, time based
+ * messages are stamped at the start of their second. Block based messages are generated
+ * when a tick reaches the next block, before any time based messages for the same tick
+ */
+pub struct CronMessages {
+    schedule: Arc<CronSchedule>,
+    ordinate: String,
+    cur_second: i64,
+    right_second: i64,
+    right_timestamp: i64,
+    cur_block: BlockSchema,
+    /// index of the next block in the schedule, that is after the current block
+    next_block: usize,
+    resume_after: Option<String>,
+    pending: VecDeque<ScheduledMessage>
+}
+
+impl CronMessages {
+    fn tick(&mut self) {
+        let schedule = self.schedule.clone();
+        let origin = &schedule.origin;
+
+        if let Some(next) = schedule.blocks.get(self.next_block) {
+            if next.timestamp <= self.right_timestamp && self.cur_second >= to_seconds(next.timestamp) {
+                self.cur_block = next.clone();
+                self.next_block += 1;
+                for (id, cron) in schedule.block_based.iter() {
+                    if is_block_on_cron(self.cur_block.height, origin.height, cron) {
+                        self.pending.push_back(schedule.message(id, cron, &self.ordinate, self.cur_block.clone()));
+                    }
+                }
+            }
+        }
+
+        let timestamp = self.cur_second * 1000;
+        for (id, cron) in schedule.time_based.iter() {
+            if is_timestamp_on_cron(timestamp, origin.timestamp, cron) {
+                let block = BlockSchema { height: self.cur_block.height, timestamp };
+                self.pending.push_back(schedule.message(id, cron, &self.ordinate, block));
+            }
+        }
+
+        if let Some(resume_after) = self.resume_after.take() {
+            if let Some(evaluated) = self.pending.iter().position(|m| m.cron.as_ref() == Some(&resume_after)) {
+                self.pending.drain(..=evaluated);
+            }
+        }
+    }
+}
+
+impl Iterator for CronMessages {
+    type Item = ScheduledMessage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Some(message);
+            }
+            if self.cur_second > self.right_second {
+                return None;
+            }
+            self.tick();
+            self.cur_second += 1;
+        }
+    }
+}
+
+struct MergeState<'a> {
+    scheduled: MessageStream<'a>,
+    schedule: Arc<CronSchedule>,
+    left: CronBoundary,
+    right_most_timestamp: i64,
+    resume_after: Option<String>,
+    crons: Option<CronMessages>,
+    /// the scheduled message emitted once the crons before it are
+    right: Option<ScheduledMessage>,
+    done: bool
+}
+
+/**
+ * Emits the cron messages between each pair of scheduled messages, starting at left_most,
+ * and after the last scheduled message up to right_most_timestamp.
+ * Crons are only generated as the stream is consumed
+ */
+pub fn merge_cron_messages<'a>(
+    scheduled: MessageStream<'a>,
+    schedule: Arc<CronSchedule>,
+    left_most: CronBoundary,
+    right_most_timestamp: i64,
+    resume_after: Option<String>
+) -> MessageStream<'a> {
+    let state = MergeState {
+        scheduled,
+        schedule,
+        left: left_most,
+        right_most_timestamp,
+        resume_after,
+        crons: None,
+        right: None,
+        done: false
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(crons) = state.crons.as_mut() {
+                if let Some(message) = crons.next() {
+                    return Some((Ok(message), state));
+                }
+                state.crons = None;
+                if let Some(right) = state.right.take() {
+                    state.left = CronBoundary::from_message(&right);
+                    return Some((Ok(right), state));
+                }
+            }
+            if state.done {
+                return None;
+            }
+
+            match state.scheduled.next().await {
+                Some(Ok(message)) => {
+                    state.crons = Some(state.schedule.between(&state.left, message.message.timestamp, state.resume_after.take()));
+                    state.right = Some(message);
+                },
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                },
+                None => {
+                    state.done = true;
+                    // /**
+                    // * The right most timestamp is later than the last message, which happens when
+                    // * evaluating up to an arbitrary timestamp, so crons up to it are generated too
+                    // */
+                    if to_seconds(state.left.block.timestamp) < to_seconds(state.right_most_timestamp) {
+                        state.crons = Some(state.schedule.between(&state.left, state.right_most_timestamp, state.resume_after.take()));
+                    }
+                }
+            }
+        }
+    }).boxed()
+}
+
+impl Apis {
+    /**
+     * Interleaves the cron messages of the process with its scheduled messages.
+     * The scheduled messages are returned untouched when the process has no crons
+     */
+    pub async fn with_cron_messages<'a>(
+        &self,
+        process: &LoadedProcess,
+        state: &ProcessState,
+        to: Option<i64>,
+        scheduled: MessageStream<'a>
+    ) -> Result<MessageStream<'a>, CuErrors> {
+        let crons = match parse_crons(&process.tags) {
+            Ok(crons) => crons,
+            Err(e) => {
+                self.logger.error(format!("Failed to parse crons for process \"{}\": {:?}", process.id, e));
+                return Err(e);
+            }
+        };
+        if crons.is_empty() {
+            return Ok(scheduled);
+        }
+        self.logger.log(format!(
+            "Crons found for process \"{}\". Generating cron messages according to Crons: {:?}",
+            process.id,
+            crons.iter().map(|cron| cron.interval.as_str()).collect::<Vec<&str>>()
+        ));
+
+        // /**
+        // * The left most boundary is the last evaluation when hot starting,
+        // * or the block the process was scheduled at when cold starting.
+        // * The right most is the message being evaluated up to, or the current block on the SU
+        // */
+        let left_most = match &state.evaluation {
+            Some(evaluation) => CronBoundary {
+                ordinate: evaluation.ordinate.clone(),
+                block: BlockSchema { height: evaluation.block_height, timestamp: evaluation.timestamp }
+            },
+            None => CronBoundary { ordinate: "0".to_string(), block: process.block.clone() }
+        };
+        let right_most_timestamp = match to {
+            Some(to) => to,
+            None => self.ao_su.load_timestamp(&process.su_url, &process.id).await?.timestamp
+        };
+
+        let blocks = self.reconcile_blocks(left_most.block.height, right_most_timestamp).await?;
+        let schedule = Arc::new(CronSchedule::new(process, crons, blocks));
+        let resume_after = state.evaluation.as_ref().and_then(|evaluation| evaluation.cron.clone());
+
+        Ok(merge_cron_messages(scheduled, schedule, left_most, right_most_timestamp, resume_after))
+    }
+
+    /**
+     * The blocks from min up to max_timestamp. Cached blocks are used when available,
+     * and any missing are loaded from the gateway and cached for next time
+     */
+    async fn reconcile_blocks(&self, min: i64, max_timestamp: i64) -> Result<Vec<BlockSchema>, CuErrors> {
+        let from_db = self.ao_block.find_blocks(min, max_timestamp).await.map_err(CuErrors::DatabaseError)?;
+        let (missing_min, missing_max_timestamp) = match find_missing_blocks_in(&from_db, min, max_timestamp) {
+            Some(range) => range,
+            None => return Ok(from_db)
+        };
+
+        self.logger.log(format!("Loading blocks meta from height {} up to timestamp {} from the gateway", missing_min, missing_max_timestamp));
+        let from_gateway: Vec<BlockSchema> = self.ao_block
+            .load_blocks_meta(missing_min, missing_max_timestamp, &self.config.GRAPHQL_URL, BLOCKS_PAGE_SIZE).await?
+            .into_iter()
+            .map(|node| BlockSchema { height: node.height, timestamp: node.timestamp })
+            .collect();
+        // existing blocks are ignored by the insert
+        if let Err(e) = self.ao_block.save_blocks(&from_gateway).await {
+            self.logger.error(format!("Could not save blocks to db. Nooping: {}", e));
+        }
+
+        Ok(merge_blocks(from_db, from_gateway))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::model::model::{ModuleOptions, ModuleSchema};
+
+    fn tag(name: &str, value: &str) -> RawTagSchema {
+        RawTagSchema { name: name.to_string(), value: value.to_string() }
+    }
+
+    fn cron(unit: CronUnit, value: i64) -> Cron {
+        Cron { interval: format!("{}-x", value), unit, value, tags: vec![] }
+    }
+
+    fn process(tags: Vec<RawTagSchema>) -> LoadedProcess {
+        LoadedProcess {
+            id: "process-123".to_string(),
+            su_url: "https://su.foo".to_string(),
+            owner: "owner-123".to_string(),
+            tags,
+            block: BlockSchema { height: 100, timestamp: 1_000_000 },
+            module: ModuleSchema { id: "module-123".to_string(), tags: vec![], owner: "owner-456".to_string() },
+            module_options: ModuleOptions {
+                format: "wasm32-unknown-emscripten".to_string(),
+                input_encoding: "JSON-1".to_string(),
+                output_encoding: "JSON-1".to_string(),
+                memory_limit: 1024,
+                compute_limit: 1024,
+                extensions: vec![]
+            }
+        }
+    }
+
+    mod parse_crons {
+        use super::*;
+
+        #[test]
+        fn test_parse_the_crons_with_their_tags() {
+            let crons = parse_crons(&vec![
+                tag("Type", "Process"),
+                tag("Cron-Interval", "5-minutes"),
+                tag("Cron-Tag-Foo", "Bar"),
+                tag("Cron-Tag-Fizz", "Buzz"),
+                tag("Cron-Interval", "10-blocks"),
+                tag("Cron-Tag-Action", "Tick")
+            ]).unwrap();
+
+            assert!(crons == vec![
+                Cron {
+                    interval: "5-minutes".to_string(),
+                    unit: CronUnit::Seconds,
+                    value: 300,
+                    tags: vec![tag("Foo", "Bar"), tag("Fizz", "Buzz")]
+                },
+                Cron {
+                    interval: "10-blocks".to_string(),
+                    unit: CronUnit::Blocks,
+                    value: 10,
+                    tags: vec![tag("Action", "Tick")]
+                }
+            ]);
+        }
+
+        #[test]
+        fn test_convert_time_units_to_seconds() {
+            let value = |interval: &str| parse_crons(&vec![tag("Cron-Interval", interval)]).unwrap()[0].value;
+            assert!(value("1-second") == 1);
+            assert!(value("2-hours") == 7200);
+            assert!(value("1-day") == 86400);
+            assert!(value("1-week") == 604800);
+            assert!(value("1-year") == 31557600);
+            assert!(value("1-block") == 1);
+        }
+
+        #[test]
+        fn test_no_crons() {
+            assert!(parse_crons(&vec![tag("Type", "Process")]).unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_error_on_a_cron_tag_without_an_interval() {
+            assert!(parse_crons(&vec![tag("Cron-Tag-Foo", "Bar"), tag("Cron-Interval", "5-minutes")]).is_err());
+        }
+
+        #[test]
+        fn test_error_on_an_invalid_interval() {
+            assert!(parse_crons(&vec![tag("Cron-Interval", "500-ms")]).is_err());
+            assert!(parse_crons(&vec![tag("Cron-Interval", "0-blocks")]).is_err());
+            assert!(parse_crons(&vec![tag("Cron-Interval", "5-fortnights")]).is_err());
+            assert!(parse_crons(&vec![tag("Cron-Interval", "five-minutes")]).is_err());
+        }
+    }
+
+    mod on_cron {
+        use super::*;
+
+        #[test]
+        fn test_is_block_on_cron() {
+            let cron = cron(CronUnit::Blocks, 10);
+            assert!(is_block_on_cron(110, 100, &cron));
+            assert!(is_block_on_cron(120, 100, &cron));
+            assert!(!is_block_on_cron(115, 100, &cron));
+            assert!(!is_block_on_cron(100, 100, &cron));
+        }
+
+        #[test]
+        fn test_is_timestamp_on_cron() {
+            let cron = cron(CronUnit::Seconds, 10);
+            assert!(is_timestamp_on_cron(1_010_000, 1_000_000, &cron));
+            assert!(is_timestamp_on_cron(1_010_999, 1_000_000, &cron));
+            assert!(!is_timestamp_on_cron(1_009_999, 1_000_000, &cron));
+            assert!(!is_timestamp_on_cron(1_000_500, 1_000_000, &cron));
+        }
+    }
+
+    mod blocks {
+        use super::*;
+
+        fn block(height: i64, timestamp: i64) -> BlockSchema {
+            BlockSchema { height, timestamp }
+        }
+
+        #[test]
+        fn test_find_missing_blocks_in() {
+            assert!(find_missing_blocks_in(&vec![], 10, 5000) == Some((10, 5000)));
+            assert!(find_missing_blocks_in(&vec![block(10, 1000), block(12, 3000)], 10, 5000) == Some((11, 5000)));
+            assert!(find_missing_blocks_in(&vec![block(10, 1000), block(11, 2000)], 10, 1_000_000) == Some((11, 1_000_000)));
+            assert!(find_missing_blocks_in(&vec![block(10, 1000), block(11, 2000)], 10, 50_000) == None);
+        }
+
+        #[test]
+        fn test_merge_blocks() {
+            let merged = merge_blocks(
+                vec![block(10, 1000), block(12, 3000)],
+                vec![block(11, 2000), block(12, 3000), block(13, 4000)]
+            );
+            assert!(merged.iter().map(|b| b.height).collect::<Vec<i64>>() == vec![10, 11, 12, 13]);
+        }
+    }
+
+    mod cron_messages {
+        use super::*;
+
+        fn schedule(blocks: Vec<BlockSchema>) -> Arc<CronSchedule> {
+            let process = process(vec![]);
+            Arc::new(CronSchedule::new(&process, parse_crons(&vec![
+                tag("Cron-Interval", "10-seconds"),
+                tag("Cron-Tag-Action", "Tick"),
+                tag("Cron-Interval", "2-blocks"),
+                tag("Cron-Interval", "5-seconds")
+            ]).unwrap(), blocks))
+        }
+
+        fn left() -> CronBoundary {
+            CronBoundary { ordinate: "3".to_string(), block: BlockSchema { height: 100, timestamp: 1_000_000 } }
+        }
+
+        #[test]
+        fn test_generate_the_crons_between_boundaries() {
+            let blocks = vec![
+                BlockSchema { height: 101, timestamp: 1_004_000 },
+                BlockSchema { height: 102, timestamp: 1_008_000 }
+            ];
+            let crons: Vec<ScheduledMessage> = schedule(blocks).between(&left(), 1_011_000, None).collect();
+            let ids: Vec<(i64, String)> = crons.iter().map(|m| (m.message.timestamp, m.cron.clone().unwrap())).collect();
+
+            assert!(ids == vec![
+                (1_005_000, "0-5-seconds".to_string()),
+                (1_008_000, "0-2-blocks".to_string()),
+                (1_010_000, "0-5-seconds".to_string()),
+                (1_010_000, "1-10-seconds".to_string())
+            ]);
+            assert!(crons[1].message.block_height == 102);
+            assert!(crons[3].message.tags == vec![tag("Action", "Tick")]);
+            assert!(crons.iter().all(|m| m.ordinate == "3" && m.message.cron && m.message.nonce.is_none()));
+            assert!(crons[3].name == "Cron Message 1010000,3,1-10-seconds");
+        }
+
+        #[test]
+        fn test_be_deterministic() {
+            let ids = || schedule(vec![]).between(&left(), 1_031_000, None).map(|m| (m.message.timestamp, m.cron)).collect::<Vec<_>>();
+            assert!(ids() == ids());
+        }
+
+        #[test]
+        fn test_resume_after_the_last_evaluated_cron() {
+            let left = CronBoundary { ordinate: "3".to_string(), block: BlockSchema { height: 100, timestamp: 1_010_000 } };
+            let crons: Vec<ScheduledMessage> = schedule(vec![]).between(&left, 1_016_000, Some("0-5-seconds".to_string())).collect();
+            let ids: Vec<(i64, String)> = crons.iter().map(|m| (m.message.timestamp, m.cron.clone().unwrap())).collect();
+
+            assert!(ids == vec![
+                (1_010_000, "1-10-seconds".to_string()),
+                (1_015_000, "0-5-seconds".to_string())
+            ]);
+        }
+    }
+
+    mod merge_cron_messages {
+        use super::*;
+
+        fn scheduled(nonce: i64, timestamp: i64) -> ScheduledMessage {
+            let process = process(vec![]);
+            ScheduledMessage {
+                cron: None,
+                ordinate: nonce.to_string(),
+                name: format!("Scheduled Message {}", nonce),
+                is_assignment: false,
+                deep_hash: None,
+                message: Message {
+                    id: format!("message-{}", nonce),
+                    signature: None,
+                    data: None,
+                    owner: "owner-789".to_string(),
+                    target: Some(process.id.clone()),
+                    anchor: None,
+                    from: "owner-789".to_string(),
+                    forwarded_by: None,
+                    tags: vec![],
+                    epoch: Some(0),
+                    nonce: Some(nonce),
+                    timestamp,
+                    block_height: 100,
+                    hash_chain: None,
+                    cron: false,
+                    read_only: false
+                },
+                ao_global: process.ao_global(),
+                block: BlockSchema { height: 100, timestamp }
+            }
+        }
+
+        #[tokio::test]
+        async fn test_interleave_crons_with_scheduled_messages() {
+            let process = process(vec![tag("Cron-Interval", "5-seconds")]);
+            let schedule = Arc::new(CronSchedule::new(&process, parse_crons(&process.tags).unwrap(), vec![]));
+            let left_most = CronBoundary { ordinate: "0".to_string(), block: process.block.clone() };
+            let messages = stream::iter(vec![Ok(scheduled(1, 1_007_000)), Ok(scheduled(2, 1_010_000))]).boxed();
+
+            let merged: Vec<(i64, String, Option<String>)> = merge_cron_messages(messages, schedule, left_most, 1_016_000, None)
+                .map(|m| m.unwrap())
+                .map(|m| (m.message.timestamp, m.ordinate, m.cron))
+                .collect().await;
+
+            // a cron in the second of a scheduled message comes before it
+            assert!(merged == vec![
+                (1_005_000, "0".to_string(), Some("0-5-seconds".to_string())),
+                (1_007_000, "1".to_string(), None),
+                (1_010_000, "1".to_string(), Some("0-5-seconds".to_string())),
+                (1_010_000, "2".to_string(), None),
+                (1_015_000, "2".to_string(), Some("0-5-seconds".to_string()))
+            ]);
+        }
+
+        #[tokio::test]
+        async fn test_tick_whole_seconds_between_unaligned_messages() {
+            let process = process(vec![tag("Cron-Interval", "5-seconds")]);
+            let schedule = Arc::new(CronSchedule::new(&process, parse_crons(&process.tags).unwrap(), vec![]));
+            let left_most = CronBoundary { ordinate: "0".to_string(), block: process.block.clone() };
+            let messages = stream::iter(vec![Ok(scheduled(1, 1_007_300)), Ok(scheduled(2, 1_010_500))]).boxed();
+
+            let merged: Vec<(i64, String, Option<String>)> = merge_cron_messages(messages, schedule, left_most, 1_016_000, None)
+                .map(|m| m.unwrap())
+                .map(|m| (m.message.timestamp, m.ordinate, m.cron))
+                .collect().await;
+
+            // each cron fires once, at its second boundary
+            assert!(merged == vec![
+                (1_005_000, "0".to_string(), Some("0-5-seconds".to_string())),
+                (1_007_300, "1".to_string(), None),
+                (1_010_000, "1".to_string(), Some("0-5-seconds".to_string())),
+                (1_010_500, "2".to_string(), None),
+                (1_015_000, "2".to_string(), Some("0-5-seconds".to_string()))
+            ]);
+        }
+    }
+}
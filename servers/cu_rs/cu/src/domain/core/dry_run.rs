@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use crate::domain::{
+    client::ao_su::map_from,
+    core::read_state::LoadedProcess,
+    model::model::{BlockSchema, DryRunMessage, Message, Output, ScheduledMessage},
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+impl Apis {
+    /**
+     * Evaluates an unsigned message against a copy of the latest memory of the process.
+     * Nothing is saved and the process memory cache is left untouched
+     */
+    pub async fn dry_run(&self, process_id: &str, dry_run: DryRunMessage) -> Result<Output, CuErrors> {
+        self.check_process_restriction(process_id)?;
+        let process = self.load_process_meta(process_id).await?;
+        self.dry_run_process(process, dry_run).await
+    }
+
+    /// dry_run for a process already loaded
+    pub async fn dry_run_process(&self, mut process: LoadedProcess, dry_run: DryRunMessage) -> Result<Output, CuErrors> {
+        let process_id = process.id.clone();
+        // /**
+        // * The cache hands out a copy of the memory, so the dry run can't mutate it.
+        // * When nothing is cached yet, catch up on the scheduled messages first
+        // */
+        let state = match self.load_latest_state(&process.id).await {
+            state if state.memory.is_some() => state,
+            _ => self.read_process_state(&process, None).await?
+        };
+
+        let memory_limit = self.config.DRY_RUN_MEMORY_MAX_LIMIT;
+        if memory_limit > 0 {
+            let size = state.memory.as_ref().map(|memory| memory.len() as i64).unwrap_or(0);
+            if size > memory_limit {
+                return Err(CuErrors::HttpStatus(HttpError {
+                    status: 413,
+                    message: format!("Memory of process \"{}\" exceeds the dry run limit of {} bytes", process_id, memory_limit)
+                }));
+            }
+            process.module_options.memory_limit = process.module_options.memory_limit.min(memory_limit);
+        }
+
+        let (ordinate, timestamp, block_height) = match &state.evaluation {
+            Some(evaluation) => (evaluation.ordinate.clone(), evaluation.timestamp, evaluation.block_height),
+            None => ("0".to_string(), Utc::now().timestamp_millis(), process.block.height)
+        };
+        let tags = dry_run.tags.unwrap_or_default();
+        let owner = dry_run.owner.unwrap_or_default();
+        let message = ScheduledMessage {
+            cron: None,
+            ordinate,
+            name: "Dry Run Message".to_string(),
+            is_assignment: false,
+            deep_hash: None,
+            message: Message {
+                id: dry_run.id.unwrap_or_default(),
+                signature: dry_run.signature,
+                data: dry_run.data,
+                from: map_from(&tags, &owner),
+                owner,
+                target: Some(dry_run.target.unwrap_or(process_id.to_string())),
+                anchor: dry_run.anchor,
+                forwarded_by: None,
+                tags,
+                epoch: None,
+                nonce: None,
+                timestamp: dry_run.timestamp.unwrap_or(timestamp),
+                block_height: dry_run.block_height.unwrap_or(block_height),
+                hash_chain: None,
+                cron: false,
+                read_only: true
+            },
+            ao_global: process.ao_global(),
+            block: BlockSchema { height: block_height, timestamp }
+        };
+
+        // a message still running at the deadline is answered with a 408, and its compute limit frees the worker
+        let max_time = self.config.DRY_RUN_MAX_EXECUTION_TIME;
+        let deadline = (max_time > 0).then(|| Instant::now() + Duration::from_millis(max_time as u64));
+        let state = self.evaluate(&process, state, stream::iter(vec![Ok(message)]).boxed(), true, deadline).await?;
+
+        let mut output = state.output.unwrap_or_default();
+        output.memory = None;
+        Ok(output)
+    }
+}
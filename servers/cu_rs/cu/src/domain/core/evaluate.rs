@@ -0,0 +1,170 @@
+use std::{pin::Pin, time::Instant};
+use chrono::Utc;
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::timeout_at;
+use crate::domain::{
+    client::{ao_module::AoModule, ao_process::{CacheEntry, Expiration}},
+    core::read_state::{LoadedProcess, ProcessState},
+    dal::{FindMessageBeforeSchema, MessageStream, SaveEvaluationSchema},
+    model::model::{EvaluateArgs, EvaluationSchema, EvaluationSchemaExtended},
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+impl Apis {
+    /**
+     * Applies each message to the memory in state, in order. Unless no_save is set
+     * every evaluation is saved and the final memory is cached for the process.
+     * A message still running at the deadline fails with a 408, whatever the evaluator does
+     */
+    pub async fn evaluate(
+        &self,
+        process: &LoadedProcess,
+        state: ProcessState,
+        messages: MessageStream<'_>,
+        no_save: bool,
+        deadline: Option<Instant>
+    ) -> Result<ProcessState, CuErrors> {
+        let mut messages = messages.peekable();
+        if Pin::new(&mut messages).peek().await.is_none() {
+            return Ok(state);
+        }
+        let evaluator = match &self.evaluator {
+            Some(evaluator) => evaluator.clone(),
+            None => return Err(CuErrors::HttpStatus(HttpError { status: 503, message: "No evaluator is configured on this CU".to_string() }))
+        };
+
+        let stream_id = AoModule::stream_id();
+        let mut state = state;
+        while let Some(scheduled) = messages.next().await {
+            let scheduled = scheduled?;
+            // /**
+            // * We make sure to remove duplicate pushed (matching deepHash)
+            // * and duplicate assignments (matching messageId) from the eval stream
+            // *
+            // * Which prevents them from corrupting the process.
+            // */
+            if scheduled.deep_hash.is_some() || scheduled.is_assignment {
+                match self.ao_evaluation.find_message_before(
+                    scheduled.message.id.clone(),
+                    scheduled.deep_hash.clone(),
+                    scheduled.is_assignment,
+                    process.id.clone(),
+                    scheduled.message.epoch.unwrap_or(0),
+                    scheduled.message.nonce.unwrap_or(0)
+                ).await {
+                    Ok(_) => {
+                        self.logger.log(format!(
+                            "Prior Message to process \"{}\" with id \"{}\" was found and therefore has already been evaluated. Removing \"{}\" from eval stream",
+                            process.id,
+                            scheduled.deep_hash.clone().unwrap_or(scheduled.message.id.clone()),
+                            scheduled.name
+                        ));
+                        continue;
+                    },
+                    Err(CuErrors::HttpStatus(e)) if e.status == 404 => (),
+                    Err(e) => return Err(e)
+                }
+            }
+
+            let mut message = scheduled.message;
+            message.tags.retain(|t| t.name != "From" && t.name != "Owner");
+
+            let evaluated = evaluator.evaluate(EvaluateArgs {
+                name: scheduled.name.clone(),
+                no_save,
+                deep_hash: scheduled.deep_hash.clone(),
+                cron: scheduled.cron.clone(),
+                ordinate: scheduled.ordinate.clone(),
+                is_assignment: scheduled.is_assignment,
+                process_id: process.id.clone(),
+                memory: state.memory.take(),
+                message: message.clone(),
+                ao_global: scheduled.ao_global,
+                stream_id,
+                module_id: process.module.id.clone(),
+                module_options: process.module_options.clone(),
+                deadline
+            });
+            let mut output = match deadline {
+                Some(deadline) => timeout_at(deadline.into(), evaluated).await.map_err(|_| CuErrors::HttpStatus(HttpError {
+                    status: 408,
+                    message: format!("Evaluation of message \"{}\" to process \"{}\" ran out of time", scheduled.name, process.id)
+                }))?,
+                None => evaluated.await
+            }?;
+
+            if let Some(error) = &output.error {
+                self.logger.error(format!("Error occurred when applying message \"{}\" to process \"{}\": \"{}\"", scheduled.name, process.id, error));
+            }
+
+            let memory = output.memory.take();
+            let evaluation = EvaluationSchema {
+                process_id: process.id.clone(),
+                // cron messages do not have an id
+                message_id: if scheduled.cron.is_some() { None } else { Some(message.id.clone()) },
+                deep_hash: scheduled.deep_hash.clone(),
+                timestamp: message.timestamp,
+                epoch: message.epoch,
+                nonce: message.nonce,
+                ordinate: scheduled.ordinate.clone(),
+                block_height: message.block_height,
+                cron: scheduled.cron.clone(),
+                evaluated_at: Utc::now(),
+                output: serde_json::to_value(&output).unwrap_or(Value::Null)
+            };
+
+            if !no_save {
+                self.ao_evaluation.save_evaluation(EvaluationSchemaExtended {
+                    process_id: evaluation.process_id.clone(),
+                    message_id: evaluation.message_id.clone(),
+                    deep_hash: evaluation.deep_hash.clone(),
+                    timestamp: evaluation.timestamp,
+                    epoch: evaluation.epoch,
+                    nonce: evaluation.nonce,
+                    ordinate: evaluation.ordinate.clone(),
+                    block_height: evaluation.block_height,
+                    cron: evaluation.cron.clone(),
+                    evaluated_at: evaluation.evaluated_at,
+                    output: evaluation.output.clone(),
+                    is_assignment: scheduled.is_assignment
+                }).await?;
+            }
+
+            state = ProcessState {
+                memory,
+                evaluation: Some(evaluation),
+                output: Some(output),
+                evaluated: state.evaluated + 1
+            };
+        }
+
+        // /**
+        // * Make sure to attempt to cache the last result
+        // * in the process memory cache
+        // */
+        if let (false, Some(evaluation), Some(memory)) = (no_save, &state.evaluation, &state.memory) {
+            if state.evaluated > 0 {
+                self.ao_process.set(&process.id, CacheEntry {
+                    evaluation: evaluation.clone(),
+                    module_id: process.module.id.clone(),
+                    memory: Some(memory.clone()),
+                    file: None,
+                    expiration: Expiration::get_expiration_from_ms(self.ao_process.ttl())
+                }).await;
+
+                // /**
+                // * Replaying this many messages again would be expensive, so
+                // * eagerly create a Checkpoint instead of waiting for an eviction
+                // */
+                let threshold = self.config.EAGER_CHECKPOINT_THRESHOLD;
+                if threshold > 0 && state.evaluated >= threshold as u64 {
+                    self.ao_checkpoint.spawn_save_checkpoint(process.module.id.clone(), evaluation.clone(), memory.clone());
+                }
+            }
+        }
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::domain::{
+    core::read_state::ProcessState,
+    dal::{FindEvaluationSchema, LoadMessageMetaSchema},
+    model::model::{EvaluationSchema, MessageMetaSchema, Output},
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+fn evaluation_output(evaluation: EvaluationSchema) -> Result<Output, CuErrors> {
+    match serde_json::from_value::<Output>(evaluation.output) {
+        Ok(output) => Ok(output),
+        Err(e) => Err(CuErrors::HttpStatus(HttpError {
+            status: 500,
+            message: format!("Could not parse the output of message at ordinate {}: {}", evaluation.ordinate, e)
+        }))
+    }
+}
+
+/// The SU finds a message by its id alone, so it may be scheduled on another process
+fn check_message_process(process_id: &str, message_tx_id: &str, meta: &MessageMetaSchema) -> Result<(), CuErrors> {
+    if meta.process_id != process_id {
+        return Err(CuErrors::HttpStatus(HttpError {
+            status: 400,
+            message: format!("Message \"{}\" is scheduled on process \"{}\", not \"{}\"", message_tx_id, meta.process_id, process_id)
+        }));
+    }
+    Ok(())
+}
+
+/// The output of the message, when it is the last one the state was evaluated up to
+fn state_output(state: ProcessState, meta: &MessageMetaSchema) -> Option<Result<Output, CuErrors>> {
+    match state.evaluation {
+        Some(evaluation) if evaluation.timestamp == meta.timestamp && evaluation.ordinate == meta.nonce.to_string() => match state.output {
+            Some(output) => Some(Ok(output)),
+            None => Some(evaluation_output(evaluation))
+        },
+        _ => None
+    }
+}
+
+impl Apis {
+    /**
+     * Finds where the message is in the schedule of the process, evaluates
+     * the process up to it if not done already, and returns its output
+     */
+    pub async fn read_result(&self, process_id: &str, message_tx_id: &str) -> Result<Output, CuErrors> {
+        self.check_process_restriction(process_id)?;
+        let process = self.load_process_meta(process_id).await?;
+        let meta = self.ao_su.load_message_meta(&process.su_url, &process.id, message_tx_id).await?;
+        check_message_process(&process.id, message_tx_id, &meta)?;
+        let ordinate = meta.nonce.to_string();
+
+        if let Some(evaluation) = self.ao_evaluation.find_evaluation(&process.id, meta.timestamp, &ordinate, None).await? {
+            return evaluation_output(evaluation);
+        }
+
+        let state = self.read_process_state(&process, Some(meta.timestamp)).await?;
+        if let Some(output) = state_output(state, &meta) {
+            return output;
+        }
+        match self.ao_evaluation.find_evaluation(&process.id, meta.timestamp, &ordinate, None).await? {
+            Some(evaluation) => evaluation_output(evaluation),
+            None => Err(CuErrors::HttpStatus(HttpError {
+                status: 404,
+                message: format!("Message \"{}\" to process \"{}\" was not evaluated", message_tx_id, process_id)
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::{json, Value};
+
+    fn meta(process_id: &str) -> MessageMetaSchema {
+        MessageMetaSchema { process_id: process_id.to_string(), height: Some(1234), timestamp: 1702677252111, nonce: 3 }
+    }
+
+    fn evaluation(timestamp: i64, ordinate: &str, output: Value) -> EvaluationSchema {
+        EvaluationSchema {
+            process_id: "process-123".to_string(),
+            message_id: Some("message-123".to_string()),
+            deep_hash: None,
+            timestamp,
+            epoch: Some(0),
+            nonce: ordinate.parse().ok(),
+            ordinate: ordinate.to_string(),
+            block_height: 1234,
+            cron: None,
+            evaluated_at: Utc::now(),
+            output
+        }
+    }
+
+    fn state(evaluation: Option<EvaluationSchema>, output: Option<Output>) -> ProcessState {
+        ProcessState { memory: Some(b"memory".to_vec()), evaluation, output, evaluated: 1 }
+    }
+
+    mod check_message_process {
+        use super::*;
+
+        #[test]
+        fn test_accept_a_message_on_the_process() {
+            assert!(check_message_process("process-123", "message-123", &meta("process-123")).is_ok());
+        }
+
+        #[test]
+        fn test_reject_a_message_on_another_process() {
+            match check_message_process("process-123", "message-123", &meta("process-456")) {
+                Ok(_) => panic!("Should reject a message scheduled on another process"),
+                Err(CuErrors::HttpStatus(e)) => assert!(e.status == 400),
+                Err(_) => panic!("Wrong error provided")
+            }
+        }
+    }
+
+    mod state_output {
+        use super::*;
+
+        #[test]
+        fn test_output_of_the_stream() {
+            let output = Output { output: json!("hello"), ..Default::default() };
+            let state = state(Some(evaluation(1702677252111, "3", Value::Null)), Some(output));
+            match state_output(state, &meta("process-123")) {
+                Some(Ok(output)) => assert!(output.output == json!("hello")),
+                _ => panic!("Should return the output of the stream")
+            }
+        }
+
+        #[test]
+        fn test_output_of_the_evaluation() {
+            let state = state(Some(evaluation(1702677252111, "3", json!({ "Output": "hello" }))), None);
+            match state_output(state, &meta("process-123")) {
+                Some(Ok(output)) => assert!(output.output == json!("hello")),
+                _ => panic!("Should parse the output of the evaluation")
+            }
+        }
+
+        #[test]
+        fn test_no_output_of_another_message() {
+            assert!(state_output(state(Some(evaluation(1702677252111, "2", Value::Null)), None), &meta("process-123")).is_none());
+            assert!(state_output(state(Some(evaluation(1702677252000, "3", Value::Null)), None), &meta("process-123")).is_none());
+            assert!(state_output(state(None, None), &meta("process-123")).is_none());
+        }
+
+        #[test]
+        fn test_reject_an_unparseable_output() {
+            let state = state(Some(evaluation(1702677252111, "3", json!({ "Messages": "not a list" }))), None);
+            match state_output(state, &meta("process-123")) {
+                Some(Err(CuErrors::HttpStatus(e))) => assert!(e.status == 500),
+                _ => panic!("Should reject an unparseable output")
+            }
+        }
+    }
+}
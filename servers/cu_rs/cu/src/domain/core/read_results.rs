@@ -0,0 +1,199 @@
+use serde::Serialize;
+use serde_json::Value;
+use crate::domain::{
+    dal::FindEvaluationsSchema,
+    model::model::{EvaluationCursor, FromOrToEvaluationSchema, Sort},
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+/// max number of evaluation results in a page
+pub const MAX_RESULTS_LIMIT: i64 = 1000;
+
+/// Where to start or stop a page of results, from the query string
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultsBound {
+    Timestamp(i64),
+    /// a cursor is exclusive, since its evaluation is already on a prior page
+    Cursor(EvaluationCursor)
+}
+
+impl ResultsBound {
+    /// a value that is not a cursor is assumed to be a timestamp
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(cursor) = EvaluationCursor::decode(value) {
+            return Some(ResultsBound::Cursor(cursor));
+        }
+        value.trim().parse::<i64>().ok().map(ResultsBound::Timestamp)
+    }
+
+    fn criteria(&self) -> FromOrToEvaluationSchema {
+        match self {
+            ResultsBound::Timestamp(timestamp) => FromOrToEvaluationSchema { timestamp: Some(*timestamp), ordinate: None, cron: None },
+            ResultsBound::Cursor(cursor) => cursor.into()
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ResultsBound::Timestamp(timestamp) => timestamp.to_string(),
+            ResultsBound::Cursor(cursor) => format!("{},{}", cursor.timestamp, cursor.ordinate)
+        }
+    }
+
+    fn cursor(&self) -> Option<&EvaluationCursor> {
+        match self {
+            ResultsBound::Cursor(cursor) => Some(cursor),
+            ResultsBound::Timestamp(_) => None
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResultEdge {
+    pub node: Value,
+    pub cursor: String
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultsConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<ResultEdge>
+}
+
+fn check_limit(limit: i64) -> Result<(), CuErrors> {
+    if limit < 1 || limit > MAX_RESULTS_LIMIT {
+        return Err(CuErrors::HttpStatus(HttpError {
+            status: 400,
+            message: format!("limit must be between 1 and {}, got {}", MAX_RESULTS_LIMIT, limit)
+        }));
+    }
+    Ok(())
+}
+
+impl Apis {
+    /**
+     * A page of the evaluation outputs of a process, cron and scheduled alike,
+     * ordered by timestamp, ordinate and cron.
+     *
+     * The sort in either cursor takes precedence over the provided sort, so a client
+     * paging with cursors keeps the order it started with
+     */
+    pub async fn read_results(
+        &self,
+        process_id: &str,
+        from: Option<ResultsBound>,
+        to: Option<ResultsBound>,
+        sort: Sort,
+        limit: i64
+    ) -> Result<ResultsConnection, CuErrors> {
+        self.check_process_restriction(process_id)?;
+        check_limit(limit)?;
+
+        let cursors: Vec<&EvaluationCursor> = [&from, &to].into_iter()
+            .filter_map(|bound| bound.as_ref().and_then(|bound| bound.cursor()))
+            .collect();
+        let sort = cursors.first().map(|cursor| cursor.sort()).unwrap_or(sort);
+
+        // /**
+        // * We fetch an additional evaluation to know whether or not
+        // * there are additional pages to fetch, plus one for each cursor,
+        // * since the evaluation at a cursor is dropped from the page
+        // */
+        let evaluations = self.ao_evaluation.find_evaluations(
+            process_id.to_string(),
+            from.as_ref().map(|bound| bound.criteria()),
+            to.as_ref().map(|bound| bound.criteria()),
+            Some(sort),
+            limit.saturating_add(1 + cursors.len() as i64),
+            Some(false)
+        ).await?;
+
+        let mut evaluations: Vec<_> = evaluations.into_iter()
+            .filter(|evaluation| !cursors.iter().any(|cursor| cursor.is_at(evaluation)))
+            .collect();
+        let has_next_page = evaluations.len() as i64 > limit;
+        evaluations.truncate(limit as usize);
+
+        self.logger.log(format!(
+            "gathered Evaluation results for process {} from \"{}\" to \"{}\"",
+            process_id,
+            from.as_ref().map(|bound| bound.describe()).unwrap_or("earliest".to_string()),
+            to.as_ref().map(|bound| bound.describe()).unwrap_or("latest".to_string())
+        ));
+
+        Ok(ResultsConnection {
+            page_info: PageInfo { has_next_page },
+            edges: evaluations.into_iter()
+                .map(|evaluation| ResultEdge {
+                    cursor: EvaluationCursor::from_evaluation(&evaluation, sort).encode(),
+                    node: evaluation.output
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod results_bound {
+        use super::*;
+
+        #[test]
+        fn test_parse_a_timestamp() {
+            assert!(ResultsBound::parse("1702677252111") == Some(ResultsBound::Timestamp(1702677252111)));
+        }
+
+        #[test]
+        fn test_parse_a_cursor() {
+            let cursor = EvaluationCursor {
+                timestamp: 1702677252111,
+                ordinate: "3".to_string(),
+                cron: Some("1-10-minutes".to_string()),
+                sort: Sort::Desc.to_string()
+            };
+            match ResultsBound::parse(&cursor.encode()) {
+                Some(ResultsBound::Cursor(parsed)) => {
+                    assert!(parsed == cursor);
+                    assert!(parsed.sort() == Sort::Desc);
+                },
+                _ => panic!("Should parse the cursor")
+            }
+        }
+
+        #[test]
+        fn test_reject_garbage() {
+            assert!(ResultsBound::parse("not-a-cursor").is_none());
+        }
+    }
+
+    mod check_limit {
+        use super::*;
+
+        #[test]
+        fn test_accept_a_limit_up_to_the_max() {
+            assert!(check_limit(1).is_ok());
+            assert!(check_limit(MAX_RESULTS_LIMIT).is_ok());
+        }
+
+        #[test]
+        fn test_reject_a_limit_over_the_max() {
+            for limit in [0, MAX_RESULTS_LIMIT + 1, i64::MAX] {
+                match check_limit(limit) {
+                    Ok(_) => panic!("Should reject limit {}", limit),
+                    Err(CuErrors::HttpStatus(e)) => assert!(e.status == 400),
+                    Err(_) => panic!("Wrong error provided")
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::Utc;
+use validator::Validate;
+use crate::domain::{
+    client::{ao_process::{CacheEntry, Expiration, LATEST}, ao_su::find_tag_value},
+    dal::{FindModuleSchema, FindProcessSchema, LoadMessageSchema, LoadProcessSchema, LocateProcessSchema, SaveModuleSchema, SaveProcessSchema},
+    model::model::{AoGlobal, BlockSchema, EvaluationSchema, Module, ModuleOptions, ModuleSchema, Output, Process, ProcessCheckpoint, ProcessSchema, RawTagSchema},
+    schema_utils::{is_earlier_than, PartialEvaluationSchema},
+    strings::parse_bytes,
+    utils::error::{CuErrors, HttpError},
+    Apis
+};
+
+/// Module-Format values the evaluator is able to run
+pub const SUPPORTED_MODULE_FORMATS: [&str; 2] = ["wasm32-unknown-emscripten", "wasm32-unknown-emscripten2"];
+
+/// A process, where to find its messages and the module that evaluates them
+#[derive(Clone)]
+pub struct LoadedProcess {
+    pub id: String,
+    pub su_url: String,
+    pub owner: String,
+    pub tags: Vec<RawTagSchema>,
+    pub block: BlockSchema,
+    pub module: ModuleSchema,
+    pub module_options: ModuleOptions
+}
+
+impl LoadedProcess {
+    pub fn ao_global(&self) -> AoGlobal {
+        AoGlobal {
+            process: Process { id: self.id.clone(), owner: self.owner.clone(), tags: self.tags.clone() },
+            module: Module { id: self.module.id.clone(), owner: self.module.owner.clone(), tags: self.module.tags.clone() }
+        }
+    }
+}
+
+/// The memory of a process after evaluating up to a message
+pub struct ProcessState {
+    pub memory: Option<Vec<u8>>,
+    /// the last message evaluated, None when nothing has been evaluated yet
+    pub evaluation: Option<EvaluationSchema>,
+    /// the output of the last message evaluated by this stream
+    pub output: Option<Output>,
+    /// number of messages evaluated by this stream
+    pub evaluated: u64
+}
+
+impl ProcessState {
+    pub fn cold_start() -> Self {
+        ProcessState { memory: None, evaluation: None, output: None, evaluated: 0 }
+    }
+
+    pub fn timestamp(&self) -> Option<i64> {
+        self.evaluation.as_ref().map(|evaluation| evaluation.timestamp)
+    }
+}
+
+fn http_error(status: u32, message: String) -> CuErrors {
+    CuErrors::HttpStatus(HttpError { status, message })
+}
+
+fn is_not_found(err: &CuErrors) -> bool {
+    matches!(err, CuErrors::HttpStatus(e) if e.status == 404)
+}
+
+impl Apis {
+    /**
+     * Evaluates the process up to the message at the timestamp to, or the
+     * latest message on the SU, and returns its memory
+     */
+    pub async fn read_state(&self, process_id: &str, to: Option<i64>) -> Result<ProcessState, CuErrors> {
+        let start = Utc::now();
+        self.check_process_restriction(process_id)?;
+        let process = self.load_process_meta(process_id).await?;
+        let state = self.read_process_state(&process, to).await?;
+
+        self.logger.log(format!(
+            "readState for process \"{}\" up to \"{}\" took {} milliseconds, evaluated {} messages",
+            process_id,
+            to.map(|to| to.to_string()).unwrap_or(LATEST.to_string()),
+            (Utc::now() - start).num_milliseconds(),
+            state.evaluated
+        ));
+        Ok(state)
+    }
+
+    /// read_state for a process already loaded
+    pub async fn read_process_state(&self, process: &LoadedProcess, to: Option<i64>) -> Result<ProcessState, CuErrors> {
+        let state = match self.load_latest_state(&process.id).await {
+            state if state.memory.is_some() => state,
+            _ => self.load_checkpoint_state(process, to).await
+        };
+        if let (Some(to), Some(latest)) = (to, state.timestamp()) {
+            if latest == to {
+                return Ok(state);
+            }
+            if latest > to {
+                return Err(http_error(425, format!(
+                    "message at timestamp {} not found cached, and earlier than latest known timestamp {}", to, latest
+                )));
+            }
+        }
+
+        let messages = self.ao_su.load_messages(&process.su_url, &process.id, state.timestamp(), to, process.ao_global());
+        let messages = self.with_cron_messages(process, &state, to, messages).await?;
+        self.evaluate(process, state, messages, false, None).await
+    }
+
+    /// RESTRICT_PROCESSES is a blacklist, a non empty ALLOW_PROCESSES a whitelist
+    pub fn check_process_restriction(&self, process_id: &str) -> Result<(), CuErrors> {
+        let id = process_id.to_string();
+        if self.config.RESTRICT_PROCESSES.contains(&id)
+            || (!self.config.ALLOW_PROCESSES.is_empty() && !self.config.ALLOW_PROCESSES.contains(&id)) {
+            return Err(http_error(403, format!("Access Denied for process {}", process_id)));
+        }
+        Ok(())
+    }
+
+    /// The latest memory cached for the process, or a cold start
+    pub async fn load_latest_state(&self, process_id: &str) -> ProcessState {
+        match self.ao_process.get(process_id).await {
+            Some(entry) if entry.memory.is_some() => ProcessState {
+                memory: entry.memory,
+                evaluation: Some(entry.evaluation),
+                output: None,
+                evaluated: 0
+            },
+            _ => ProcessState::cold_start()
+        }
+    }
+
+    /**
+     * On a cold start, resume from the latest Checkpoint of the process, whichever
+     * of the local file and Arweave is later, instead of from its first message.
+     * A source that errors is skipped, and with none left it is a cold start
+     */
+    async fn load_checkpoint_state(&self, process: &LoadedProcess, to: Option<i64>) -> ProcessState {
+        let partial = |c: &ProcessCheckpoint| PartialEvaluationSchema { timestamp: c.evaluation.timestamp, cron: c.evaluation.cron.clone() };
+        let mut found: Option<ProcessCheckpoint> = None;
+        for (source, checkpoints) in &self.checkpoint_sources {
+            match checkpoints.find_latest_checkpoint(&process.id, &process.module.id).await {
+                Ok(Some(checkpoint)) if to.map_or(true, |to| checkpoint.evaluation.timestamp <= to) => {
+                    // on a tie, the earlier source is kept, as the local file is cheaper to have read
+                    found = match found {
+                        Some(latest) if !is_earlier_than(partial(&latest), partial(&checkpoint)) => Some(latest),
+                        _ => Some(checkpoint)
+                    };
+                },
+                Ok(Some(checkpoint)) => self.logger.log(format!(
+                    "{} Checkpoint {} for process \"{}\" is later than timestamp {}. Skipping...", source, checkpoint.id, process.id, to.unwrap_or_default()
+                )),
+                Ok(None) => (),
+                Err(e) => self.logger.error(format!("Could not load {} Checkpoint for process \"{}\". Skipping... {:?}", source, process.id, e))
+            }
+        }
+        let Some(checkpoint) = found else {
+            return ProcessState::cold_start();
+        };
+
+        self.logger.log(format!(
+            "Resuming process \"{}\" from Checkpoint {} at timestamp {}", process.id, checkpoint.id, checkpoint.evaluation.timestamp
+        ));
+        self.ao_process.set(&process.id, CacheEntry {
+            evaluation: checkpoint.evaluation.clone(),
+            module_id: checkpoint.module_id,
+            memory: Some(checkpoint.memory.clone()),
+            file: None,
+            expiration: Expiration::get_expiration_from_ms(self.ao_process.ttl())
+        }).await;
+
+        ProcessState {
+            memory: Some(checkpoint.memory),
+            evaluation: Some(checkpoint.evaluation),
+            output: None,
+            evaluated: 0
+        }
+    }
+
+    /// Finds the process in the db or else loads it from its SU, along with its module
+    pub async fn load_process_meta(&self, process_id: &str) -> Result<LoadedProcess, CuErrors> {
+        let (owner, tags, block, su_url) = match self.ao_process.find_process(process_id).await {
+            Ok(process) => {
+                let scheduler_hint = find_tag_value("Scheduler", &process.tags);
+                let su_url = self.ao_su.locate_process(process_id, scheduler_hint).await?.url;
+                (process.owner, process.tags, process.block, su_url)
+            },
+            Err(e) if is_not_found(&e) => {
+                self.logger.log(format!("Could not find process {} in db. Loading from chain...", process_id));
+                let su_url = self.ao_su.locate_process(process_id, None).await?.url;
+                let process = self.ao_su.load_process(&su_url, process_id).await?;
+                Apis::verify_process_tags(&process.tags)?;
+
+                let process_schema = ProcessSchema {
+                    id: process_id.to_string(),
+                    signature: process.signature.clone(),
+                    data: process.data.clone().unwrap_or_default(),
+                    anchor: process.anchor.clone(),
+                    owner: process.owner.clone(),
+                    tags: process.tags.clone(),
+                    block: process.block.clone()
+                };
+                match self.ao_process.save_process(process_schema).await {
+                    Ok(_) => self.logger.log(format!("Saved process {}", process_id)),
+                    Err(e) => self.logger.error(format!("Could not save process {} to db. Nooping: {}", process_id, e))
+                }
+                (process.owner, process.tags, process.block, su_url)
+            },
+            Err(e) => return Err(e)
+        };
+
+        let module_id = match find_tag_value("Module", &tags) {
+            Some(module_id) => module_id,
+            None => return Err(http_error(422, format!("Tag 'Module': was not found on process {}", process_id)))
+        };
+        let module = self.load_module(&module_id).await?;
+        let module_options = self.module_options(process_id, &tags, &module)?;
+
+        Ok(LoadedProcess {
+            id: process_id.to_string(),
+            su_url,
+            owner,
+            tags,
+            block,
+            module,
+            module_options
+        })
+    }
+
+    fn verify_process_tags(tags: &Vec<RawTagSchema>) -> Result<(), CuErrors> {
+        let has = |name: &str, value: &str| tags.iter().any(|t| t.name == name && t.value == value);
+        if !has("Data-Protocol", "ao") {
+            return Err(http_error(422, "Tag 'Data-Protocol': value 'ao' was not found on process".to_string()));
+        }
+        if !has("Type", "Process") {
+            return Err(http_error(422, "Tag 'Type': value 'Process' was not found on process".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Finds the module in the db or else loads its meta from the gateway
+    async fn load_module(&self, module_id: &str) -> Result<ModuleSchema, CuErrors> {
+        match self.ao_module.find_module(module_id).await {
+            Ok(Some(module)) => return Ok(module),
+            Ok(None) => (),
+            Err(e) if is_not_found(&e) => (),
+            Err(e) => return Err(e)
+        }
+
+        self.logger.log(format!("Could not find module {} in db. Loading from gateway...", module_id));
+        let node = match self.arweave.load_tx_meta(&self.config.GRAPHQL_URL, module_id).await {
+            Ok(node) => node,
+            Err(e) => return Err(http_error(502, format!("Could not load module {} from the gateway: {}", module_id, e)))
+        };
+        let module = ModuleSchema {
+            id: module_id.to_string(),
+            tags: node.tags.unwrap_or_default().into_iter()
+                .map(|tag| RawTagSchema { name: tag.name, value: tag.value })
+                .collect(),
+            owner: node.owner.map(|owner| owner.address).unwrap_or_default()
+        };
+        if module.validate().is_err() {
+            return Err(http_error(422, format!("Module {} has no owner", module_id)));
+        }
+
+        match self.ao_module.save_module(module.clone()).await {
+            Ok(_) => self.logger.log(format!("Saved module {}", module_id)),
+            Err(e) => self.logger.error(format!("Could not save module {} to db. Nooping: {}", module_id, e))
+        }
+        Ok(module)
+    }
+
+    /**
+     * Memory-Limit and Compute-Limit set on the process take precedence
+     * over those set on the module
+     */
+    pub fn module_options(&self, process_id: &str, process_tags: &Vec<RawTagSchema>, module: &ModuleSchema) -> Result<ModuleOptions, CuErrors> {
+        let module_tag = |name: &str| find_tag_value(name, &module.tags);
+        let limit_tag = |name: &str| find_tag_value(name, process_tags).or(module_tag(name));
+
+        let format = match module_tag("Module-Format") {
+            Some(format) if SUPPORTED_MODULE_FORMATS.contains(&format.trim()) => format.trim().to_string(),
+            _ => return Err(http_error(422, format!("Module-Format for module \"{}\" is not supported", module.id)))
+        };
+        let input_encoding = module_tag("Input-Encoding")
+            .ok_or(http_error(422, format!("Input-Encoding for module \"{}\" is not supported", module.id)))?;
+        let output_encoding = module_tag("Output-Encoding")
+            .ok_or(http_error(422, format!("Output-Encoding for module \"{}\" is not supported", module.id)))?;
+
+        let memory_limit = match limit_tag("Memory-Limit").and_then(|limit| parse_bytes(&limit)) {
+            Some(limit) if limit > 0 && limit <= self.config.PROCESS_WASM_MEMORY_MAX_LIMIT => limit,
+            _ => return Err(http_error(413, format!("Memory-Limit for process \"{}\" exceeds supported limit", process_id)))
+        };
+        let compute_limit = match limit_tag("Compute-Limit").and_then(|limit| limit.trim().parse::<i64>().ok()) {
+            Some(limit) if limit > 0 && limit <= self.config.PROCESS_WASM_COMPUTE_MAX_LIMIT => limit,
+            _ => return Err(http_error(413, format!("Compute-Limit for process \"{}\" exceeds supported limit", process_id)))
+        };
+
+        // no extensions are supported yet
+        let extensions: Vec<String> = module.tags.iter()
+            .filter(|t| t.name == "Extension")
+            .map(|t| t.value.clone())
+            .collect();
+        if !extensions.is_empty() {
+            return Err(http_error(422, format!("Module Extensions for module \"{}\" are not supported", module.id)));
+        }
+
+        Ok(ModuleOptions {
+            format,
+            input_encoding,
+            output_encoding,
+            memory_limit,
+            compute_limit,
+            extensions
+        })
+    }
+}
@@ -1,11 +1,11 @@
 use crate::domain::model::model::{
     BlockSchema, EvaluationSchema, EvaluationSchemaExtended, FromOrToEvaluationSchema, MessageMetaSchema, ModuleSchema, ProcessSchema, 
-    ProcessSchemaWithoutId, ProcessUrl, RawTagSchema, Sort, StreamSchema, TimestampSchema
+    ProcessSchemaWithoutId, ProcessUrl, Sort, TimestampSchema, AoGlobal, ScheduledMessage
 };
 use async_trait::async_trait;
-use reqwest::Url;
+use futures::stream::BoxStream;
 
-use super::{model::model::{gql_return_types, EntityId, EvaluateArgs, ModuleOptions, Output}, utils::error::CuErrors};
+use super::{model::model::{gql_return_types, EntityId, EvaluateArgs, Output, ProcessCheckpoint}, utils::error::CuErrors};
 
 // todo: the Vec<u8> types might be better as serde Value types?
 
@@ -46,8 +46,19 @@ pub trait SaveModuleSchema {
 
 #[async_trait]
 pub trait EvaluatorSchema {
-    async fn evaluator<F>(&self, evaluate: F, module_id: &str, module_options: ModuleOptions, args: EvaluateArgs) 
-        where F: Fn(EvaluateArgs) -> Result<Output, CuErrors>;
+    /// applies args.message to args.memory, the new memory is returned on the Output
+    async fn evaluate(&self, args: EvaluateArgs) -> Result<Output, CuErrors>;
+}
+
+#[async_trait]
+pub trait SaveCheckpointSchema {
+    /// returns the id of the Checkpoint, or None when no Checkpoint was created
+    async fn save_checkpoint(&self, module_id: &str, evaluation: &EvaluationSchema, memory: &[u8]) -> Result<Option<String>, CuErrors>;
+}
+
+#[async_trait]
+pub trait FindLatestCheckpointSchema {
+    async fn find_latest_checkpoint(&self, process_id: &str, module_id: &str) -> Result<Option<ProcessCheckpoint>, CuErrors>;
 }
 
 #[async_trait]
@@ -99,37 +110,30 @@ pub trait FindBlocksSchema {
     async fn find_blocks(&self, min_height: i64, max_timestamp: i64) -> Result<Vec<BlockSchema>, sqlx::error::Error>;
 }
 
-#[async_trait]
+/// the messages of a process, in the order they are evaluated
+pub type MessageStream<'a> = BoxStream<'a, Result<ScheduledMessage, CuErrors>>;
+
 pub trait LoadMessageSchema {
-    async fn load_message_schema(
-        su_url: Url,
-        process_id: String,
-        owner: String,
-        tags: Vec<RawTagSchema>,
-        module_id: String,
-        module_tags: Vec<RawTagSchema>,
-        module_owner: String,
-        from: Option<u64>,
-        to: Option<u64>
-    ) -> impl StreamSchema;
+    /// from is exclusive and to is inclusive, both are timestamps
+    fn load_messages<'a>(&'a self, su_url: &str, process_id: &str, from: Option<i64>, to: Option<i64>, ao_global: AoGlobal) -> MessageStream<'a>;
 }
 
 #[async_trait]
 pub trait LoadProcessSchema {
-    async fn load_process_schema(su_url: String, process_id: String) -> ProcessSchemaWithoutId;
+    async fn load_process(&self, su_url: &str, process_id: &str) -> Result<ProcessSchemaWithoutId, CuErrors>;
 }
 
 #[async_trait]
 pub trait LoadTimestampSchema {
-    async fn load_timestamp_schema(su_url: String, process_id: String) -> TimestampSchema;
+    async fn load_timestamp(&self, su_url: &str, process_id: &str) -> Result<TimestampSchema, CuErrors>;
 }
 
 #[async_trait]
 pub trait LoadMessageMetaSchema {
-    async fn load_message_meta_schema(su_url: String, process_id: String, message_tx_id: String) -> MessageMetaSchema;
+    async fn load_message_meta(&self, su_url: &str, process_id: &str, message_tx_id: &str) -> Result<MessageMetaSchema, CuErrors>;
 }
 
 #[async_trait]
 pub trait LocateProcessSchema {
-    async fn locate_process_schema(process_id: String, scheduler_hint: Option<String>) -> ProcessUrl;
+    async fn locate_process(&self, process_id: &str, scheduler_hint: Option<String>) -> Result<ProcessUrl, CuErrors>;
 }
\ No newline at end of file
@@ -8,18 +8,28 @@ mod utils {
 }
 pub mod client {
     pub mod ao_block;
+    pub mod ao_checkpoint;
     pub mod ao_evaluation;
     pub mod ao_module;
     pub mod ao_process;
+    pub mod ao_su;
     pub mod arweave;
+    pub mod checkpoint_file;
     pub mod sqlite;
+    pub mod wasm;
 }
 mod clients {
     pub mod gateway;
     pub mod wallet;
 }
-mod core {
+pub mod core {
     mod flows;
+    pub mod evaluate;
+    pub mod read_state;
+    pub mod read_result;
+    pub mod read_results;
+    pub mod dry_run;
+    pub mod cron;
 }
 pub mod model {        
     pub mod model;
@@ -40,10 +50,16 @@ use std::sync::Arc;
 use ao_common::domain::dal::Log;
 
 pub use crate::domain::model::domain_config_schema::DomainConfigSchema;
+pub use crate::domain::utils::error;
 pub use crate::domain::utils::maths;
 pub use crate::domain::utils::utils as schema_utils;
 pub use crate::domain::utils::strings;
 use crate::domain::client::arweave::InternalArweave;
+use crate::domain::client::{ao_block::AoBlock, ao_checkpoint::AoCheckpoint, ao_evaluation::AoEvaluation, ao_module::AoModule, ao_process::{write_process_memory_file_with, AoProcess, CacheEntry}, ao_su::AoSu, checkpoint_file::CheckpointFile, sqlite::{Repository, SqliteClient}, wasm::WasmEvaluator};
+use crate::domain::dal::{EvaluatorSchema, FindLatestCheckpointSchema};
+
+/// max number of messages requested from a SU per page
+const SU_PAGE_SIZE: i64 = 1000;
 
 #[allow(unused)]
 pub struct DomainContext {
@@ -52,9 +68,115 @@ pub struct DomainContext {
     logger: Arc<dyn Log>
 }
 
+/// Everything the business logic apis need, shared by every request
+pub struct Apis {
+    pub config: DomainConfigSchema,
+    pub logger: Arc<dyn Log>,
+    pub arweave: Arc<InternalArweave>,
+    pub ao_process: AoProcess,
+    pub ao_module: AoModule,
+    pub ao_evaluation: AoEvaluation,
+    pub ao_su: AoSu,
+    pub ao_block: AoBlock,
+    pub ao_checkpoint: Arc<AoCheckpoint>,
+    /// where a process is restored from when it isn't cached, named for the logs
+    pub checkpoint_sources: Vec<(&'static str, Arc<dyn FindLatestCheckpointSchema + Send + Sync>)>,
+    /// evaluating a message fails with a 503 when None
+    pub evaluator: Option<Arc<dyn EvaluatorSchema + Send + Sync>>
+}
+
 impl DomainContext {
-    pub async fn create_api(ctx: DomainContext) {
+    pub fn new(arweave: InternalArweave, domain_config_schema: DomainConfigSchema, logger: Arc<dyn Log>) -> Self {
+        DomainContext {
+            arweave,
+            domain_config_schema,
+            logger
+        }
+    }
+
+    pub async fn create_api(ctx: DomainContext) -> Apis {
         ctx.logger.log("Creating business logic apis".to_string());
 
+        let config = ctx.domain_config_schema;
+        let arweave = Arc::new(ctx.arweave);
+        let sql_client = Arc::new(SqliteClient::init(&format!("sqlite://{}.sqlite", config.DB_URL), ctx.logger.clone(), Some(true), None).await);
+
+        let ao_checkpoint = Arc::new(AoCheckpoint::new(
+            arweave.clone(),
+            &config.GRAPHQL_URL,
+            &config.CHECKPOINT_GRAPHQL_URL,
+            &config.ARWEAVE_URL,
+            &config.UPLOADER_URL,
+            config.PROCESS_CHECKPOINT_CREATION_THROTTLE as u64,
+            config.DISABLE_PROCESS_CHECKPOINT_CREATION,
+            config.PROCESS_IGNORE_ARWEAVE_CHECKPOINTS.clone(),
+            ctx.logger.clone()
+        ));
+
+        let on_eviction_checkpoint = ao_checkpoint.clone();
+        let eviction_logger = ctx.logger.clone();
+        let ao_process = AoProcess::create_process_memory_cache(
+            sql_client.clone(),
+            config.PROCESS_MEMORY_CACHE_MAX_SIZE as u64,
+            config.PROCESS_MEMORY_CACHE_TTL as u64,
+            config.PROCESS_MEMORY_CACHE_DRAIN_TO_FILE_THRESHOLD as u64,
+            // save the evicted process memory as a Checkpoint on Arweave
+            move |_key, value| {
+                let CacheEntry { evaluation, module_id, memory, file, .. } = value;
+                match (memory, file) {
+                    (Some(memory), _) => on_eviction_checkpoint.spawn_save_checkpoint(module_id, evaluation, memory),
+                    // the memory was drained to a file, so read it back in first
+                    (None, Some(file)) => {
+                        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+                            eviction_logger.error(format!("No runtime to read memory file of evicted process \"{}\" on. Skipping Checkpoint...", evaluation.process_id));
+                            return;
+                        };
+                        let ao_checkpoint = on_eviction_checkpoint.clone();
+                        let logger = eviction_logger.clone();
+                        handle.spawn(async move {
+                            match AoProcess::read_process_memory_file(&file).await {
+                                Ok(memory) => ao_checkpoint.spawn_save_checkpoint(module_id, evaluation, memory),
+                                Err(e) => logger.error(format!(
+                                    "Could not read memory file of evicted process \"{}\". Skipping Checkpoint... {}", evaluation.process_id, e
+                                ))
+                            }
+                        });
+                    },
+                    (None, None) => ()
+                }
+            },
+            write_process_memory_file_with(&config.PROCESS_MEMORY_CACHE_FILE_DIR)
+        );
+
+        let checkpoint_file = Arc::new(CheckpointFile::new(sql_client.clone(), &config.PROCESS_CHECKPOINT_FILE_DIRECTORY, ctx.logger.clone()));
+        checkpoint_file.init().await;
+        if config.PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL > 0 {
+            checkpoint_file.spawn_checkpoint_interval(ao_process.memory_cache(), config.PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL as u64);
+        }
+
+        Apis {
+            logger: ctx.logger.clone(),
+            arweave: arweave.clone(),
+            ao_process,
+            ao_module: AoModule::new(sql_client.clone()),
+            ao_evaluation: AoEvaluation::new(sql_client.clone()),
+            ao_block: AoBlock::new(sql_client),
+            checkpoint_sources: vec![
+                ("file", checkpoint_file as Arc<dyn FindLatestCheckpointSchema + Send + Sync>),
+                ("Arweave", ao_checkpoint.clone() as Arc<dyn FindLatestCheckpointSchema + Send + Sync>)
+            ],
+            ao_checkpoint,
+            ao_su: AoSu::new(&config.GRAPHQL_URL, &config.ARWEAVE_URL, SU_PAGE_SIZE, arweave, ctx.logger.clone()),
+            evaluator: Some(Arc::new(WasmEvaluator::new(
+                &config.ARWEAVE_URL,
+                &config.WASM_BINARY_FILE_DIRECTORY,
+                config.WASM_MODULE_CACHE_MAX_SIZE as u64,
+                config.WASM_EVALUATION_MAX_WORKERS as usize,
+                config.PROCESS_WASM_MEMORY_MAX_LIMIT,
+                config.PROCESS_WASM_COMPUTE_MAX_LIMIT,
+                ctx.logger
+            ))),
+            config
+        }
     }
 }
@@ -140,6 +140,14 @@ pub struct StartDomainConfigSchema {
     * before responding with a "busy" message to the client
     */
     pub BUSY_THRESHOLD: Option<String>,
+    /**
+    * The maximum amount of time, in milliseconds, a dry run may evaluate for
+    */
+    pub DRY_RUN_MAX_EXECUTION_TIME: Option<String>,
+    /**
+    * The maximum memory, in bytes, a dry run may grow the memory of a process to
+    */
+    pub DRY_RUN_MEMORY_MAX_LIMIT: Option<String>,
     /**
      * A list of process ids that the CU should restrict
      * aka. blacklist
@@ -179,6 +187,8 @@ impl StartDomainConfigSchema {
             PROCESS_MEMORY_CACHE_FILE_DIR: start_config_env.PROCESS_MEMORY_CACHE_FILE_DIR,
             PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: start_config_env.PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL,
             BUSY_THRESHOLD: start_config_env.BUSY_THRESHOLD,
+            DRY_RUN_MAX_EXECUTION_TIME: start_config_env.DRY_RUN_MAX_EXECUTION_TIME,
+            DRY_RUN_MEMORY_MAX_LIMIT: start_config_env.DRY_RUN_MEMORY_MAX_LIMIT,
             RESTRICT_PROCESSES: start_config_env.RESTRICT_PROCESSES,
             ALLOW_PROCESSES: start_config_env.ALLOW_PROCESSES
         }
@@ -290,6 +300,14 @@ impl StartSchemaParser<DomainConfigSchema> for StartDomainConfigSchema {
             Ok(val) => final_domain_config_schema.BUSY_THRESHOLD = val,
             Err(e) => return Err(e)
         };
+        match parse_positive_int_schema(self.DRY_RUN_MAX_EXECUTION_TIME.clone(), "DRY_RUN_MAX_EXECUTION_TIME") {
+            Ok(val) => final_domain_config_schema.DRY_RUN_MAX_EXECUTION_TIME = val,
+            Err(e) => return Err(e)
+        };
+        match parse_positive_int_schema(self.DRY_RUN_MEMORY_MAX_LIMIT.clone(), "DRY_RUN_MEMORY_MAX_LIMIT") {
+            Ok(val) => final_domain_config_schema.DRY_RUN_MEMORY_MAX_LIMIT = val,
+            Err(e) => return Err(e)
+        };
         match parse_array_schema(self.RESTRICT_PROCESSES.clone()) {
             Ok(val) => {
                 final_domain_config_schema.RESTRICT_PROCESSES = val;
@@ -400,6 +418,12 @@ impl<'a> Validate<StartDomainConfigSchemaConstraint, State<&'a StartDomainConfig
         if self.clone().BUSY_THRESHOLD.validate("BUSY_THRESHOLD", &PositiveIntSchemaConstraint).result().is_err() {
             violations.push(invalid_value(INVALID_DIGITS_INTEGER, "BUSY_THRESHOLD", "".to_string(), "".to_string()));
         }
+        if self.clone().DRY_RUN_MAX_EXECUTION_TIME.validate("DRY_RUN_MAX_EXECUTION_TIME", &PositiveIntSchemaConstraint).result().is_err() {
+            violations.push(invalid_value(INVALID_DIGITS_INTEGER, "DRY_RUN_MAX_EXECUTION_TIME", "".to_string(), "".to_string()));
+        }
+        if self.clone().DRY_RUN_MEMORY_MAX_LIMIT.validate("DRY_RUN_MEMORY_MAX_LIMIT", &PositiveIntSchemaConstraint).result().is_err() {
+            violations.push(invalid_value(INVALID_DIGITS_INTEGER, "DRY_RUN_MEMORY_MAX_LIMIT", "".to_string(), "".to_string()));
+        }
         if self.clone().RESTRICT_PROCESSES.validate("RESTRICT_PROCESSES", &UuidArrayConstraint::new()).result().is_err() {
             violations.push(invalid_value(INVALID_ARRAY, "RESTRICT_PROCESSES", "".to_string(), "".to_string()));
         }
@@ -542,6 +566,14 @@ pub struct DomainConfigSchema {
     * before responding with a "busy" message to the client
     */
     pub BUSY_THRESHOLD: i64,
+    /**
+    * The maximum amount of time, in milliseconds, a dry run may evaluate for
+    */
+    pub DRY_RUN_MAX_EXECUTION_TIME: i64,
+    /**
+    * The maximum memory, in bytes, a dry run may grow the memory of a process to
+    */
+    pub DRY_RUN_MEMORY_MAX_LIMIT: i64,
     /**
      * A list of process ids that the CU should restrict
      * aka. blacklist
@@ -581,6 +613,8 @@ impl Default for DomainConfigSchema {
             PROCESS_MEMORY_CACHE_FILE_DIR: "".to_string(),
             PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL: 0,
             BUSY_THRESHOLD: 0,
+            DRY_RUN_MAX_EXECUTION_TIME: 0,
+            DRY_RUN_MEMORY_MAX_LIMIT: 0,
             RESTRICT_PROCESSES: vec![],
             ALLOW_PROCESSES: vec![]
         }
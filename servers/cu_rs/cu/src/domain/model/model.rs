@@ -1,4 +1,5 @@
 use std::fmt::Display;
+use std::time::Instant;
 use chrono::{DateTime, Utc};
 use serde_json::Value;
 use sqlx::prelude::{Row,FromRow};
@@ -14,6 +15,7 @@ use serde::{Deserialize, Serialize};
 
 static DOMAIN_CONFIG_SCHEMA: OnceCell<Result<DomainConfigSchema, ValidationError>> = OnceCell::new();
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Sort {
     Asc,
     Desc
@@ -83,11 +85,14 @@ pub struct BlockSchema {
 
 pub type TimestampSchema = BlockSchema;
 
+/// Where a message is in the schedule of a process
 #[allow(unused)]
 pub struct MessageMetaSchema {
-    height: i64,
-    timestamp: i64,
-    nonce: i64
+    pub process_id: String,
+    /// legacy SU responses do not include the block height
+    pub height: Option<i64>,
+    pub timestamp: i64,
+    pub nonce: i64
 }
 
 #[derive(FromRow)]
@@ -114,25 +119,34 @@ pub struct ProcessSchema {
 
 #[allow(unused)]
 pub struct ProcessSchemaWithoutId {    
-    signature: Option<String>,
-    data: Option<Vec<u8>>,
-    anchor: Option<String>,
+    pub signature: Option<String>,
+    pub data: Option<String>,
+    pub anchor: Option<String>,
     /// min 1
-    owner: String,
-    tags: Vec<RawTagSchema>,
-    block: BlockSchema
+    pub owner: String,
+    pub tags: Vec<RawTagSchema>,
+    pub block: BlockSchema
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The result of applying one message to a process. Memory is never
+/// serialized, what is saved with an evaluation is everything else
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "PascalCase")]
 pub struct Output {
-    memory: Option<String>,
-    messages: Option<Vec<u8>>,
-    assignments: Option<Vec<u8>>,
-    spawns: Option<Vec<u8>>,
-    output: Option<Vec<u8>>,
-    gas_used: Option<i64>,
-    error: Option<Vec<u8>>
+    #[serde(skip)]
+    pub memory: Option<Vec<u8>>,
+    #[serde(default)]
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub assignments: Vec<Value>,
+    #[serde(default)]
+    pub spawns: Vec<Value>,
+    #[serde(default)]
+    pub output: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -183,6 +197,15 @@ pub struct EvaluationSchema {
     pub output: Value
 }
 
+/// The memory of a process at an evaluation, restored from a Checkpoint on Arweave
+pub struct ProcessCheckpoint {
+    /// the id of the Checkpoint data item
+    pub id: String,
+    pub module_id: String,
+    pub evaluation: EvaluationSchema,
+    pub memory: Vec<u8>
+}
+
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct EvaluationSchemaExtended {
@@ -208,13 +231,58 @@ pub struct FromOrToEvaluationSchema {
     pub cron: Option<String>
 }
 
-pub trait StreamSchema {
-    fn pipe(&self);
+/// The position of an evaluation in the results of a process, opaque to clients
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EvaluationCursor {
+    pub timestamp: i64,
+    pub ordinate: String,
+    pub cron: Option<String>,
+    /// ASC or DESC, the order of the page the cursor came from
+    pub sort: String
+}
+
+impl EvaluationCursor {
+    pub fn from_evaluation(evaluation: &EvaluationSchema, sort: Sort) -> Self {
+        EvaluationCursor {
+            timestamp: evaluation.timestamp,
+            ordinate: evaluation.ordinate.clone(),
+            cron: evaluation.cron.clone(),
+            sort: sort.to_string()
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        base64_url::encode(&serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// None when the value is not a cursor, for example a plain timestamp
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = base64_url::decode(value).ok()?;
+        serde_json::from_slice::<EvaluationCursor>(&bytes).ok()
+    }
+
+    pub fn sort(&self) -> Sort {
+        if self.sort.to_uppercase() == "DESC" { Sort::Desc } else { Sort::Asc }
+    }
+
+    pub fn is_at(&self, evaluation: &EvaluationSchema) -> bool {
+        self.timestamp == evaluation.timestamp && self.ordinate == evaluation.ordinate && self.cron == evaluation.cron
+    }
+}
+
+impl From<&EvaluationCursor> for FromOrToEvaluationSchema {
+    fn from(cursor: &EvaluationCursor) -> Self {
+        FromOrToEvaluationSchema {
+            timestamp: Some(cursor.timestamp),
+            ordinate: Some(cursor.ordinate.clone()),
+            cron: cursor.cron.clone()
+        }
+    }
 }
 
 #[allow(unused)]
 pub struct ProcessUrl {
-    url: String
+    pub url: String
 }
 
 pub mod gql_return_types {
@@ -250,36 +318,129 @@ pub mod gql_return_types {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Message {
-    #[serde(rename = "Id")]
+    /// Cron messages do not have an id
+    #[serde(rename = "Id", default, skip_serializing_if = "String::is_empty")]
     pub id: String,
-    #[serde(rename = "Timestamp")]
-    pub timestamp: i64,
+    #[serde(rename = "Signature", skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(rename = "Data", skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
     #[serde(rename = "Owner")]
     pub owner: String,
+    #[serde(rename = "Target", skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(rename = "Anchor", skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+    #[serde(rename = "From")]
+    pub from: String,
+    #[serde(rename = "Forwarded-By", skip_serializing_if = "Option::is_none")]
+    pub forwarded_by: Option<String>,
     #[serde(rename = "Tags")]
     pub tags: Vec<RawTagSchema>,
+    /// Cron messages do not have an epoch
+    #[serde(rename = "Epoch", skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<i64>,
+    /// Cron messages do not have a nonce
+    #[serde(rename = "Nonce", skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<i64>,
+    #[serde(rename = "Timestamp")]
+    pub timestamp: i64,
+    #[serde(rename = "Block-Height")]
+    pub block_height: i64,
+    #[serde(rename = "Hash-Chain", skip_serializing_if = "Option::is_none")]
+    pub hash_chain: Option<String>,
+    #[serde(rename = "Cron")]
+    pub cron: bool,
+    /// set on dry runs, whose results are never saved
+    #[serde(rename = "Read-Only", default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool
+}
+
+/// An unsigned message, evaluated by a dry run but never scheduled
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct DryRunMessage {
+    #[serde(rename = "Id")]
+    pub id: Option<String>,
+    #[serde(rename = "Signature")]
+    pub signature: Option<String>,
+    #[serde(rename = "Owner")]
+    pub owner: Option<String>,
+    #[serde(rename = "Target")]
+    pub target: Option<String>,
+    #[serde(rename = "Data")]
+    pub data: Option<String>,
+    #[serde(rename = "Tags")]
+    pub tags: Option<Vec<RawTagSchema>>,
+    #[serde(rename = "Anchor")]
+    pub anchor: Option<String>,
+    #[serde(rename = "Timestamp")]
+    pub timestamp: Option<i64>,
     #[serde(rename = "Block-Height")]
-    pub block_height: i64
+    pub block_height: Option<i64>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Process {
     #[serde(rename = "Id")]
     pub id: String,
+    #[serde(rename = "Owner")]
+    pub owner: String,
     #[serde(rename = "Tags")]
     pub tags: Vec<RawTagSchema>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Module {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Owner")]
+    pub owner: String,
+    #[serde(rename = "Tags")]
+    pub tags: Vec<RawTagSchema>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AoGlobal {
     #[serde(rename = "Process")]
-    pub process: Process
+    pub process: Process,
+    #[serde(rename = "Module")]
+    pub module: Module
+}
+
+/**
+ * A message in the evaluation stream, along with what the CU
+ * needs to know about it to evaluate it and save the result
+ */
+#[derive(Clone, Debug)]
+pub struct ScheduledMessage {
+    /**
+    * Scheduled messages do not have a cron,
+    * and so can be undefined
+    */
+    pub cron: Option<String>,
+    /**
+    * For a Scheduled Message, this will always simply be it's nonce.
+    * For a Cron Message, this will be the nonce of the most recent Scheduled Message.
+    */
+    pub ordinate: String,
+    /// only used for logging
+    pub name: String,
+    /**
+    * An assignment of a message that already exists on chain,
+    * the message itself is hydrated from a gateway
+    */
+    pub is_assignment: bool,
+    /**
+    * Only forwarded messages have a deepHash
+    */
+    pub deep_hash: Option<String>,
+    pub message: Message,
+    pub ao_global: AoGlobal,
+    pub block: BlockSchema
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
 pub struct EvaluateArgs {
     pub name: String,
     pub no_save: bool,
@@ -288,22 +449,25 @@ pub struct EvaluateArgs {
     pub ordinate: String,
     pub is_assignment: bool,
     pub process_id: String,
-    #[serde(rename = "Memory")]
-    pub memory: Option<i64>,
+    /// None when evaluating the first message of a process
+    pub memory: Option<Vec<u8>>,
     pub message: Message,
-    #[serde(rename = "AoGlobal")]
     pub ao_global: AoGlobal,
+    /// shared by every message in one evaluation stream
     pub stream_id: [u8; 8],
     pub module_id: String,
-    pub module_options: Option<ModuleOptions>
+    pub module_options: ModuleOptions,
+    /// the evaluation is stopped with a 408 once it passes, only set for dry runs
+    pub deadline: Option<Instant>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ModuleOptions {
     pub format: String,
     pub input_encoding: String,
     pub output_encoding: String,
+    /// in bytes
     pub memory_limit: i64,
     pub compute_limit: i64,
     pub extensions: Vec<String>
@@ -26,4 +26,49 @@ pub fn get_number_string(value: Option<i64>) -> String {
         return "".to_string();
     }
     value.unwrap().to_string()
-}
\ No newline at end of file
+}
+
+/// Parses a size like '500-mb' or '1gb' into bytes, units are powers of 1024
+pub fn parse_bytes(value: &str) -> Option<i64> {
+    let value = value.replace('-', "").replace('_', "").trim().to_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let multiplier: i64 = match unit.trim() {
+        "" | "b" => 1,
+        "kb" => 1024,
+        "mb" => 1024 * 1024,
+        "gb" => 1024 * 1024 * 1024,
+        "tb" => 1024 * 1024 * 1024 * 1024,
+        _ => return None
+    };
+    Some((number * multiplier as f64) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_bytes {
+        use super::*;
+
+        #[test]
+        fn test_parse_units() {
+            assert!(parse_bytes("512") == Some(512));
+            assert!(parse_bytes("512b") == Some(512));
+            assert!(parse_bytes("2kb") == Some(2048));
+            assert!(parse_bytes("500-mb") == Some(500 * 1024 * 1024));
+            assert!(parse_bytes("1_gb") == Some(1024 * 1024 * 1024));
+            assert!(parse_bytes(" 1.5GB ") == Some(1536 * 1024 * 1024));
+            assert!(parse_bytes("1tb") == Some(1024 * 1024 * 1024 * 1024));
+        }
+
+        #[test]
+        fn test_reject_an_invalid_size() {
+            assert!(parse_bytes("").is_none());
+            assert!(parse_bytes("mb").is_none());
+            assert!(parse_bytes("10pb").is_none());
+            assert!(parse_bytes("ten-mb").is_none());
+        }
+    }
+}
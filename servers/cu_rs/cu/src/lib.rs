@@ -12,6 +12,9 @@ pub mod utils {
 pub mod routes {
     pub mod index;
     pub mod state;
+    pub mod result;
+    pub mod results;
+    pub mod dry_run;
     pub mod middleware {
         pub mod with_error_handler;
     }
@@ -0,0 +1,34 @@
+use actix_web::web::{Data, Json, Query};
+use actix_web::{web::resource, HttpResponse, Resource};
+use actix_web::web;
+use serde::Deserialize;
+use crate::app_state::AppState;
+use crate::domain::model::model::DryRunMessage;
+use super::middleware::with_error_handler::{error_response, ErrorHandler};
+
+pub fn with_dry_run_routes() -> Resource {
+    resource("/dry-run")
+        .route(web::post().to(dry_run_handler).wrap(ErrorHandler))
+}
+
+#[derive(Deserialize)]
+pub struct DryRunQuery {
+    #[serde(rename = "process-id")]
+    process_id: Option<String>
+}
+
+/**
+ * Evaluates the message against the latest memory of the process,
+ * without writing the message to a SU or saving the result
+ */
+pub async fn dry_run_handler(app_data: Data<AppState>, query: Query<DryRunQuery>, body: Json<DryRunMessage>) -> HttpResponse {
+    let process_id = match query.into_inner().process_id {
+        Some(process_id) if !process_id.is_empty() => process_id,
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "a process-id query parameter is required" }))
+    };
+
+    match app_data.apis.dry_run(&process_id, body.into_inner()).await {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => error_response(&e)
+    }
+}
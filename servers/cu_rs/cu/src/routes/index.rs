@@ -1,9 +1,18 @@
 use actix_web::web::ServiceConfig;
+use super::dry_run::with_dry_run_routes;
+use super::result::with_result_routes;
+use super::results::with_results_routes;
 use super::state::with_state_routes;
 
 /// todo: need real paths
 pub fn index(cfg: &mut ServiceConfig) {
     cfg.service(
         with_state_routes()
+    ).service(
+        with_result_routes()
+    ).service(
+        with_results_routes()
+    ).service(
+        with_dry_run_routes()
     );
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
 use std::future::{ready, Ready};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error
+    http::StatusCode,
+    Error, HttpResponse
 };
 use futures_util::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
 use derive_more::{Display, Error};
+use crate::domain::error::CuErrors;
 
 pub struct ErrorHandler;
 
@@ -64,3 +66,12 @@ struct ErrorMessage {
 }
 
 impl actix_web::error::ResponseError for ErrorMessage {}
+
+/// HttpStatus errors keep their status, anything else is a 500
+pub fn error_response(err: &CuErrors) -> HttpResponse {
+    let status = match err {
+        CuErrors::HttpStatus(e) => StatusCode::from_u16(e.status as u16).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        _ => StatusCode::INTERNAL_SERVER_ERROR
+    };
+    HttpResponse::build(status).json(ErrorMessage { error: err.to_string() })
+}
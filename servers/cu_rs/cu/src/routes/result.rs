@@ -0,0 +1,36 @@
+use actix_web::web::{Data, Path, Query};
+use actix_web::{web::resource, HttpResponse, Resource};
+use actix_web::web;
+use serde::Deserialize;
+use crate::app_state::AppState;
+use super::middleware::with_error_handler::{error_response, ErrorHandler};
+
+pub fn with_result_routes() -> Resource {
+    resource("/result/{message_id}")
+        .route(web::get().to(result_handler).wrap(ErrorHandler))
+}
+
+#[derive(Deserialize)]
+pub struct ResultQuery {
+    #[serde(rename = "process-id")]
+    process_id: Option<String>
+}
+
+/**
+ * The output of evaluating the message, without the memory
+ */
+pub async fn result_handler(app_data: Data<AppState>, query: Query<ResultQuery>, path: Path<String>) -> HttpResponse {
+    let message_tx_id = path.into_inner();
+    let process_id = match query.into_inner().process_id {
+        Some(process_id) if !process_id.is_empty() => process_id,
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "the process-id query parameter is required" }))
+    };
+    if message_tx_id.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "a message tx id is required" }));
+    }
+
+    match app_data.apis.read_result(&process_id, &message_tx_id).await {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => error_response(&e)
+    }
+}
@@ -0,0 +1,108 @@
+use actix_web::web::{Data, Path, Query};
+use actix_web::{web::resource, HttpResponse, Resource};
+use actix_web::web;
+use serde::Deserialize;
+use crate::app_state::AppState;
+use crate::domain::core::read_results::{ResultsBound, MAX_RESULTS_LIMIT};
+use crate::domain::model::model::Sort;
+use super::middleware::with_error_handler::{error_response, ErrorHandler};
+
+/// default number of evaluation results in a page
+const DEFAULT_LIMIT: i64 = 25;
+
+pub fn with_results_routes() -> Resource {
+    resource("/results/{process_id}")
+        .route(web::get().to(results_handler).wrap(ErrorHandler))
+}
+
+#[derive(Deserialize)]
+pub struct ResultsQuery {
+    /// a cursor or a timestamp
+    from: Option<String>,
+    /// a cursor or a timestamp
+    to: Option<String>,
+    /// ASC or DESC
+    sort: Option<String>,
+    limit: Option<String>
+}
+
+/**
+ * A Relay style connection of evaluation outputs. To get the next page pass the cursor
+ * of the last edge as from when sorting ASC, or as to when sorting DESC
+ */
+pub async fn results_handler(app_data: Data<AppState>, query: Query<ResultsQuery>, path: Path<String>) -> HttpResponse {
+    let input = match InputSchema::parse(path.into_inner(), query.into_inner()) {
+        Ok(input) => input,
+        Err(message) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+    };
+
+    match app_data.apis.read_results(&input.process_id, input.from, input.to, input.sort, input.limit).await {
+        Ok(connection) => HttpResponse::Ok().json(connection),
+        Err(e) => error_response(&e)
+    }
+}
+
+struct InputSchema {
+    process_id: String,
+    from: Option<ResultsBound>,
+    to: Option<ResultsBound>,
+    sort: Sort,
+    limit: i64
+}
+
+impl InputSchema {
+    fn parse(process_id: String, query: ResultsQuery) -> Result<Self, String> {
+        if process_id.is_empty() {
+            return Err("an ao process id is required".to_string());
+        }
+        let bound = |name: &str, value: Option<String>| match value {
+            None => Ok(None),
+            Some(value) if value.is_empty() => Ok(None),
+            Some(value) => ResultsBound::parse(&value)
+                .map(Some)
+                .ok_or(format!("{} must be a cursor or a timestamp, got {}", name, value))
+        };
+        let from = bound("from", query.from)?;
+        let to = bound("to", query.to)?;
+        let sort = match query.sort.map(|sort| sort.to_uppercase()) {
+            None => Sort::Asc,
+            Some(sort) if sort == "ASC" => Sort::Asc,
+            Some(sort) if sort == "DESC" => Sort::Desc,
+            Some(sort) => return Err(format!("sort must be ASC or DESC, got {}", sort))
+        };
+        let limit = match query.limit {
+            None => DEFAULT_LIMIT,
+            Some(limit) => match limit.parse::<i64>() {
+                Ok(limit) if limit > 0 && limit <= MAX_RESULTS_LIMIT => limit,
+                _ => return Err(format!("limit must be a number between 1 and {}, got {}", MAX_RESULTS_LIMIT, limit))
+            }
+        };
+        Ok(InputSchema { process_id, from, to, sort, limit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod input_schema {
+        use super::*;
+
+        fn query(limit: Option<&str>) -> ResultsQuery {
+            ResultsQuery { from: None, to: None, sort: None, limit: limit.map(|limit| limit.to_string()) }
+        }
+
+        #[test]
+        fn test_parse_a_limit() {
+            assert!(InputSchema::parse("process-123".to_string(), query(None)).unwrap().limit == DEFAULT_LIMIT);
+            assert!(InputSchema::parse("process-123".to_string(), query(Some("1000"))).unwrap().limit == 1000);
+        }
+
+        #[test]
+        fn test_reject_a_limit_over_the_max() {
+            for limit in ["0", "1001", "9223372036854775807", "lots"] {
+                assert!(InputSchema::parse("process-123".to_string(), query(Some(limit))).is_err());
+            }
+        }
+    }
+}
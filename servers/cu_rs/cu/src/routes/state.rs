@@ -1,28 +1,99 @@
 use actix_web::web::{Data, Path, Query};
-use actix_web::{web::resource, Resource};
-use actix_web::{web, Responder};
+use actix_web::{web::resource, HttpResponse, Resource};
+use actix_web::web;
 use serde::Deserialize;
 use crate::app_state::AppState;
-use super::middleware::with_error_handler::ErrorHandler;
+use crate::domain::client::ao_process::LATEST;
+use crate::domain::core::read_state::ProcessState;
+use crate::domain::error::{CuErrors, HttpError};
+use super::middleware::with_error_handler::{error_response, ErrorHandler};
 
 pub fn with_state_routes() -> Resource {
     resource("/state/{process_id}")        
         .route(web::get().to(state_handler).wrap(ErrorHandler))
 }
 
-pub async fn state_handler(_app_data: Data<AppState>, query: Query<String>, path: Path<i64>) -> impl Responder {
-    let _input = InputSchema {
-        process_id: path.into_inner(),
-        to: query.0
-    };
+#[derive(Deserialize)]
+pub struct StateQuery {
+    /// a timestamp, or latest
+    to: Option<String>
+}
 
+/**
+ * The cu sends the memory as binary data,
+ * along with the message it was evaluated up to
+ */
+pub async fn state_handler(app_data: Data<AppState>, query: Query<StateQuery>, path: Path<String>) -> HttpResponse {
+    let input = match InputSchema::parse(path.into_inner(), query.into_inner().to) {
+        Ok(input) => input,
+        Err(message) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+    };
 
-    ""
+    match app_data.apis.read_state(&input.process_id, input.to).await {
+        Ok(ProcessState { memory: Some(memory), evaluation: Some(evaluation), .. }) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(("Ordinate", evaluation.ordinate))
+            .insert_header(("Timestamp", evaluation.timestamp.to_string()))
+            .insert_header(("Block-Height", evaluation.block_height.to_string()))
+            .body(memory),
+        // a cold start with no messages to evaluate has no memory to send
+        Ok(_) => error_response(&CuErrors::HttpStatus(HttpError {
+            status: 404,
+            message: format!("No messages have been evaluated for process {}", input.process_id)
+        })),
+        Err(e) => error_response(&e)
+    }
 }
 
-#[allow(unused)]
-#[derive(Deserialize)]
 struct InputSchema {
-    process_id: i64,
-    to: String
-}
\ No newline at end of file
+    process_id: String,
+    /// None means latest
+    to: Option<i64>
+}
+
+impl InputSchema {
+    fn parse(process_id: String, to: Option<String>) -> Result<Self, String> {
+        if process_id.is_empty() {
+            return Err("an ao process id is required".to_string());
+        }
+        let to = match to {
+            None => None,
+            Some(to) if to.is_empty() || to.to_uppercase() == LATEST => None,
+            Some(to) => match to.parse::<i64>() {
+                Ok(to) => Some(to),
+                Err(_) => return Err(format!("to must be a timestamp or latest, got {}", to))
+            }
+        };
+        Ok(InputSchema { process_id, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod input_schema {
+        use super::*;
+
+        #[test]
+        fn test_parse_a_timestamp() {
+            let input = InputSchema::parse("process-123".to_string(), Some("1702677252111".to_string())).unwrap();
+            assert!(input.process_id == "process-123");
+            assert!(input.to == Some(1702677252111));
+        }
+
+        #[test]
+        fn test_parse_latest() {
+            for to in [None, Some("".to_string()), Some("latest".to_string()), Some("LATEST".to_string())] {
+                let input = InputSchema::parse("process-123".to_string(), to).unwrap();
+                assert!(input.to.is_none());
+            }
+        }
+
+        #[test]
+        fn test_reject_an_invalid_input() {
+            assert!(InputSchema::parse("".to_string(), None).is_err());
+            assert!(InputSchema::parse("process-123".to_string(), Some("yesterday".to_string())).is_err());
+        }
+    }
+}
@@ -5,7 +5,7 @@ use chrono::Utc;
 #[allow(unused)]
 use crate::config::get_server_config_schema;
 #[allow(unused)]
-use crate::domain::client::ao_process::AoProcess;
+use crate::domain::client::ao_process::{write_process_memory_file_with, AoProcess, CacheEntry, Expiration};
 #[allow(unused)]
 use crate::domain::dal::{FindProcessSchema, SaveProcessSchema};
 #[allow(unused)]
@@ -26,7 +26,7 @@ async fn test_find_process() {
         config.PROCESS_MEMORY_CACHE_TTL as u64,
         config.PROCESS_MEMORY_CACHE_DRAIN_TO_FILE_THRESHOLD as u64,
         |_key, _value| {},
-        |_value| -> Vec<u8> { vec![] }
+        |_value| -> std::io::Result<Vec<u8>> { Ok(vec![]) }
     );
     let mut err_msg = "".to_string();
     let process_id = "process-123".to_string();
@@ -60,4 +60,118 @@ async fn test_find_process() {
     if !err_msg.is_empty() {
         panic!("{}", err_msg);
     }
-}
\ No newline at end of file
+}
+#[allow(unused)]
+fn cache_entry(timestamp: i64, memory: &[u8]) -> CacheEntry {
+    use crate::domain::model::model::EvaluationSchema;
+
+    CacheEntry {
+        evaluation: EvaluationSchema {
+            process_id: "process-123".to_string(),
+            message_id: Some("message-123".to_string()),
+            deep_hash: None,
+            timestamp,
+            epoch: Some(0),
+            nonce: Some(1),
+            ordinate: "1".to_string(),
+            block_height: 1234,
+            cron: None,
+            evaluated_at: Utc::now(),
+            output: serde_json::Value::Null
+        },
+        module_id: "module-123".to_string(),
+        memory: Some(memory.to_vec()),
+        file: None,
+        expiration: Expiration::get_expiration_from_ms(60_000)
+    }
+}
+
+#[tokio::test]
+async fn test_drain_to_file() {
+    use std::time::{Duration, Instant};
+
+    let db_file = "aoprocess2.db";
+    let db_url = format!("sqlite://{}", db_file);
+    let dir = "aoprocess2";
+    let sql_client_arc = Arc::new(SqliteClient::init(db_url.as_str(), get_logger(), Some(true), None).await);
+
+    let ao = AoProcess::create_process_memory_cache(
+        sql_client_arc.clone(),
+        1_000_000,
+        60_000,
+        200,
+        |_key, _value| {},
+        write_process_memory_file_with(dir)
+    );
+
+    // set does not wait on the drain timer
+    let start = Instant::now();
+    let mut entry = cache_entry(100, b"memory");
+    entry.expiration = Expiration::After(30_000);
+    ao.set("process-123", entry).await;
+    assert!(start.elapsed() < Duration::from_millis(200));
+    assert!(ao.has_drain_to_file_timer("process-123").await);
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(!ao.has_drain_to_file_timer("process-123").await);
+    let drained = ao.memory_cache().get("process-123").await.unwrap();
+    assert!(drained.memory.is_none());
+    // draining keeps the expiration of the entry, rather than that of the cache ttl
+    assert!(drained.expiration == Expiration::After(30_000));
+    let file = drained.file.unwrap();
+    assert!(AoProcess::read_process_memory_file(&file).await.unwrap() == b"memory".to_vec());
+
+    // the memory is read back in from the file
+    let value = ao.get("process-123").await.unwrap();
+    assert!(value.memory == Some(b"memory".to_vec()));
+    assert!(value.evaluation.timestamp == 100);
+    assert!(ao.memory_cache().get("process-123").await.unwrap().memory.is_some());
+
+    ao.clear_drain_to_file_timer("process-123").await;
+    sql_client_arc.clone().get_conn().close().await;
+    delete_db_files(db_file);
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_drain_to_file_timers_are_cancellable() {
+    use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+    let db_file = "aoprocess3.db";
+    let db_url = format!("sqlite://{}", db_file);
+    let sql_client_arc = Arc::new(SqliteClient::init(db_url.as_str(), get_logger(), Some(true), None).await);
+
+    let writes = Arc::new(AtomicUsize::new(0));
+    let counted = writes.clone();
+    let ao = AoProcess::create_process_memory_cache(
+        sql_client_arc.clone(),
+        1_000_000,
+        60_000,
+        200,
+        |_key, _value| {},
+        move |_value| -> std::io::Result<Vec<u8>> {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(b"file".to_vec())
+        }
+    );
+
+    // a newer set replaces the pending timer
+    ao.set("process-123", cache_entry(100, b"one")).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    ao.set("process-123", cache_entry(200, b"two")).await;
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(writes.load(Ordering::SeqCst) == 0);
+    assert!(ao.has_drain_to_file_timer("process-123").await);
+
+    // a cleared timer never drains
+    ao.clear_drain_to_file_timer("process-123").await;
+    assert!(!ao.has_drain_to_file_timer("process-123").await);
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(writes.load(Ordering::SeqCst) == 0);
+    let value = ao.memory_cache().get("process-123").await.unwrap();
+    assert!(value.memory == Some(b"two".to_vec()));
+    assert!(value.file.is_none());
+
+    sql_client_arc.clone().get_conn().close().await;
+    delete_db_files(db_file);
+}
@@ -0,0 +1,109 @@
+#[allow(unused)]
+use std::sync::Arc;
+#[allow(unused)]
+use chrono::Utc;
+#[allow(unused)]
+use serde_json::Value;
+#[allow(unused)]
+use crate::domain::model::model::EvaluationSchema;
+
+#[allow(unused)]
+fn evaluation(timestamp: i64, ordinate: &str) -> EvaluationSchema {
+    EvaluationSchema {
+        process_id: "process-123".to_string(),
+        message_id: Some("message-123".to_string()),
+        deep_hash: None,
+        timestamp,
+        epoch: Some(0),
+        nonce: ordinate.parse().ok(),
+        ordinate: ordinate.to_string(),
+        block_height: 1234,
+        cron: None,
+        evaluated_at: Utc::now(),
+        output: Value::Null
+    }
+}
+
+#[tokio::test]
+async fn test_save_and_find_latest_checkpoint_file() {
+    use crate::domain::{dal::{FindLatestCheckpointSchema, SaveCheckpointSchema}, client::{checkpoint_file::CheckpointFile, sqlite::{ConnGetter, Repository, SqliteClient}}};
+    use crate::tests::fixtures::log::get_logger;
+    use crate::tests::domain::client::test_sqlite::delete_db_files;
+
+    let db_file = "checkpointfile1.db";
+    let db_url = format!("sqlite://{}", db_file);
+    let dir = "checkpointfile1";
+
+    let client = Arc::new(SqliteClient::init(db_url.as_str(), get_logger(), Some(true), None).await);
+    let checkpoint_file = CheckpointFile::new(client.clone(), dir, get_logger());
+    checkpoint_file.init().await;
+
+    for (timestamp, ordinate, memory) in [(100, "1", b"one"), (300, "3", b"thr"), (200, "2", b"two")] {
+        match checkpoint_file.save_checkpoint("module-123", &evaluation(timestamp, ordinate), memory).await {
+            Ok(res) => assert!(res.is_some()),
+            Err(e) => panic!("{:?}", e)
+        }
+    }
+    // the same evaluation is only written once
+    match checkpoint_file.save_checkpoint("module-123", &evaluation(300, "3"), b"thr").await {
+        Ok(res) => assert!(res.is_none()),
+        Err(e) => panic!("{:?}", e)
+    }
+
+    match checkpoint_file.find_latest_checkpoint("process-123", "module-123").await {
+        Ok(Some(checkpoint)) => {
+            assert!(checkpoint.evaluation.timestamp == 300);
+            assert!(checkpoint.evaluation.ordinate == "3");
+            assert!(checkpoint.module_id == "module-123");
+            assert!(checkpoint.memory == b"thr".to_vec());
+        },
+        Ok(None) => panic!("Checkpoint file not found"),
+        Err(e) => panic!("{:?}", e)
+    }
+    match checkpoint_file.find_latest_checkpoint("process-123", "module-456").await {
+        Ok(res) => assert!(res.is_none()),
+        Err(e) => panic!("{:?}", e)
+    }
+    // only the most recent files are kept
+    let files = std::fs::read_dir(dir).unwrap().count();
+    assert!(files == 2);
+
+    client.get_conn().close().await;
+    delete_db_files(db_file);
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_find_latest_checkpoint_file_skips_corrupted_files() {
+    use crate::domain::{dal::{FindLatestCheckpointSchema, SaveCheckpointSchema}, client::{checkpoint_file::{checkpoint_file_name, create_checkpoint_file_id, CheckpointFile}, sqlite::{ConnGetter, Repository, SqliteClient}}};
+    use crate::tests::fixtures::log::get_logger;
+    use crate::tests::domain::client::test_sqlite::delete_db_files;
+
+    let db_file = "checkpointfile2.db";
+    let db_url = format!("sqlite://{}", db_file);
+    let dir = "checkpointfile2";
+
+    let client = Arc::new(SqliteClient::init(db_url.as_str(), get_logger(), Some(true), None).await);
+    let checkpoint_file = CheckpointFile::new(client.clone(), dir, get_logger());
+    checkpoint_file.init().await;
+
+    checkpoint_file.save_checkpoint("module-123", &evaluation(100, "1"), b"one").await.unwrap();
+    checkpoint_file.save_checkpoint("module-123", &evaluation(200, "2"), b"two").await.unwrap();
+    let corrupted = std::path::Path::new(dir).join(checkpoint_file_name(&create_checkpoint_file_id(&evaluation(200, "2"))));
+    std::fs::write(&corrupted, b"not gzip").unwrap();
+
+    match checkpoint_file.find_latest_checkpoint("process-123", "module-123").await {
+        Ok(Some(checkpoint)) => {
+            assert!(checkpoint.evaluation.timestamp == 100);
+            assert!(checkpoint.memory == b"one".to_vec());
+        },
+        Ok(None) => panic!("Checkpoint file not found"),
+        Err(e) => panic!("{:?}", e)
+    }
+    // the corrupted file is removed
+    assert!(!corrupted.exists());
+
+    client.get_conn().close().await;
+    delete_db_files(db_file);
+    _ = std::fs::remove_dir_all(dir);
+}
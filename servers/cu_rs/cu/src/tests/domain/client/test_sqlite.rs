@@ -1,5 +1,5 @@
 #[allow(unused)]
-use crate::domain::client::sqlite::{ConnGetter, SqliteMasterEntry, BLOCKS_TABLE, EVALUATIONS_TABLE, MESSAGES_TABLE, MODULES_TABLE, PROCESSES_TABLE};
+use crate::domain::client::sqlite::{ConnGetter, SqliteMasterEntry, BLOCKS_TABLE, CHECKPOINTS_TABLE, EVALUATIONS_TABLE, MESSAGES_TABLE, MODULES_TABLE, PROCESSES_TABLE};
 #[allow(unused)]
 use crate::domain::client::sqlite::{Repository, SqliteClient};
 #[allow(unused)]
@@ -82,6 +82,14 @@ async fn test_base_tables_created() {
         Some(row) => assert!(row.name == MESSAGES_TABLE.to_string()),
         None => panic!("table {} not found", MESSAGES_TABLE)
     };
+    let result = sqlx::query_as::<_, SqliteMasterEntry>(query)
+    .bind(CHECKPOINTS_TABLE)
+    .fetch_optional(client.get_conn())
+    .await.unwrap();
+    match result {
+        Some(row) => assert!(row.name == CHECKPOINTS_TABLE.to_string()),
+        None => panic!("table {} not found", CHECKPOINTS_TABLE)
+    };
 
     client.get_conn().close().await;
     delete_db_files(db_file);
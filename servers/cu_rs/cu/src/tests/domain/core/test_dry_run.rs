@@ -0,0 +1,102 @@
+#[allow(unused)]
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+#[allow(unused)]
+use async_trait::async_trait;
+#[allow(unused)]
+use crate::domain::{
+    dal::EvaluatorSchema,
+    error::CuErrors,
+    model::model::{DryRunMessage, EvaluateArgs, Output}
+};
+#[allow(unused)]
+use crate::tests::domain::core::test_read_state::{assert_status, cache_memory, loaded_process};
+#[allow(unused)]
+use crate::tests::fixtures::apis::{delete_apis_files, get_apis};
+
+/// remembers the memory limit and deadline of the last message it evaluated
+#[allow(unused)]
+#[derive(Default)]
+struct StubEvaluator {
+    evaluated: Mutex<Option<(i64, Option<Instant>)>>
+}
+
+#[async_trait]
+impl EvaluatorSchema for StubEvaluator {
+    async fn evaluate(&self, args: EvaluateArgs) -> Result<Output, CuErrors> {
+        *self.evaluated.lock().unwrap() = Some((args.module_options.memory_limit, args.deadline));
+        Ok(Output { memory: args.memory, ..Default::default() })
+    }
+}
+
+/// runs well past any dry run deadline
+#[allow(unused)]
+struct SlowEvaluator;
+
+#[async_trait]
+impl EvaluatorSchema for SlowEvaluator {
+    async fn evaluate(&self, args: EvaluateArgs) -> Result<Output, CuErrors> {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok(Output { memory: args.memory, ..Default::default() })
+    }
+}
+
+#[tokio::test]
+async fn test_dry_run_rejects_memory_over_the_limit() {
+    let name = "dryrun1";
+    let mut apis = get_apis(name).await;
+    let evaluator = Arc::new(StubEvaluator::default());
+    apis.evaluator = Some(evaluator.clone());
+    apis.config.DRY_RUN_MEMORY_MAX_LIMIT = 4;
+    cache_memory(&apis, 1702677252111).await;
+
+    assert_status(apis.dry_run_process(loaded_process(), DryRunMessage::default()).await, 413);
+    assert!(evaluator.evaluated.lock().unwrap().is_none());
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_dry_run_passes_the_deadline_to_the_evaluator() {
+    let name = "dryrun2";
+    let mut apis = get_apis(name).await;
+    let evaluator = Arc::new(StubEvaluator::default());
+    apis.evaluator = Some(evaluator.clone());
+    apis.config.DRY_RUN_MEMORY_MAX_LIMIT = 512;
+    apis.config.DRY_RUN_MAX_EXECUTION_TIME = 1000;
+    cache_memory(&apis, 1702677252111).await;
+
+    let start = Instant::now();
+    if let Err(e) = apis.dry_run_process(loaded_process(), DryRunMessage::default()).await {
+        panic!("{:?}", e);
+    }
+    match *evaluator.evaluated.lock().unwrap() {
+        Some((memory_limit, Some(deadline))) => {
+            // the memory limit of the process is lowered to the dry run limit
+            assert!(memory_limit == 512);
+            assert!(deadline > start && deadline <= start + Duration::from_millis(1000) + start.elapsed());
+        },
+        _ => panic!("Should have evaluated with a deadline")
+    }
+    // the cached memory is left untouched
+    assert!(apis.load_latest_state("process-123").await.memory == Some(b"memory".to_vec()));
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_dry_run_times_out_at_the_deadline() {
+    let name = "dryrun3";
+    let mut apis = get_apis(name).await;
+    apis.evaluator = Some(Arc::new(SlowEvaluator));
+    apis.config.DRY_RUN_MEMORY_MAX_LIMIT = 512;
+    apis.config.DRY_RUN_MAX_EXECUTION_TIME = 100;
+    cache_memory(&apis, 1702677252111).await;
+
+    let start = Instant::now();
+    assert_status(apis.dry_run_process(loaded_process(), DryRunMessage::default()).await, 408);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    // the cached memory is left untouched
+    assert!(apis.load_latest_state("process-123").await.memory == Some(b"memory".to_vec()));
+
+    delete_apis_files(name);
+}
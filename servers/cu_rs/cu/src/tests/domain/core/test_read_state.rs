@@ -0,0 +1,269 @@
+#[allow(unused)]
+use std::sync::Arc;
+#[allow(unused)]
+use async_trait::async_trait;
+#[allow(unused)]
+use chrono::Utc;
+#[allow(unused)]
+use serde_json::Value;
+#[allow(unused)]
+use crate::domain::{
+    client::ao_process::{CacheEntry, Expiration},
+    core::read_state::LoadedProcess,
+    dal::FindLatestCheckpointSchema,
+    error::{CuErrors, HttpError},
+    model::model::{BlockSchema, EvaluationSchema, ModuleOptions, ModuleSchema, ProcessCheckpoint, RawTagSchema},
+    Apis
+};
+#[allow(unused)]
+use crate::tests::fixtures::apis::{delete_apis_files, get_apis};
+
+#[allow(unused)]
+fn tag(name: &str, value: &str) -> RawTagSchema {
+    RawTagSchema { name: name.to_string(), value: value.to_string() }
+}
+
+#[allow(unused)]
+fn module(tags: Vec<RawTagSchema>) -> ModuleSchema {
+    ModuleSchema { id: "module-123".to_string(), owner: "owner-123".to_string(), tags }
+}
+
+#[allow(unused)]
+fn module_tags() -> Vec<RawTagSchema> {
+    vec![
+        tag("Module-Format", "wasm32-unknown-emscripten2"),
+        tag("Input-Encoding", "JSON-1"),
+        tag("Output-Encoding", "JSON-1"),
+        tag("Memory-Limit", "500-mb"),
+        tag("Compute-Limit", "9000000000000")
+    ]
+}
+
+#[allow(unused)]
+pub fn loaded_process() -> LoadedProcess {
+    LoadedProcess {
+        id: "process-123".to_string(),
+        su_url: "https://su.example".to_string(),
+        owner: "owner-123".to_string(),
+        tags: vec![],
+        block: BlockSchema { height: 123, timestamp: 1702677252111 },
+        module: module(module_tags()),
+        module_options: ModuleOptions {
+            format: "wasm32-unknown-emscripten2".to_string(),
+            input_encoding: "JSON-1".to_string(),
+            output_encoding: "JSON-1".to_string(),
+            memory_limit: 1024,
+            compute_limit: 1024,
+            extensions: vec![]
+        }
+    }
+}
+
+#[allow(unused)]
+fn evaluation(timestamp: i64) -> EvaluationSchema {
+    EvaluationSchema {
+        process_id: "process-123".to_string(),
+        message_id: Some("message-123".to_string()),
+        deep_hash: None,
+        timestamp,
+        epoch: Some(0),
+        nonce: Some(3),
+        ordinate: "3".to_string(),
+        block_height: 1234,
+        cron: None,
+        evaluated_at: Utc::now(),
+        output: Value::Null
+    }
+}
+
+#[allow(unused)]
+pub async fn cache_memory(apis: &Apis, timestamp: i64) {
+    apis.ao_process.set("process-123", CacheEntry {
+        evaluation: evaluation(timestamp),
+        module_id: "module-123".to_string(),
+        memory: Some(b"memory".to_vec()),
+        file: None,
+        expiration: Expiration::get_expiration_from_ms(apis.ao_process.ttl())
+    }).await;
+}
+
+/// finds a Checkpoint at the timestamp, or fails when there is none
+#[allow(unused)]
+struct StubCheckpoints {
+    timestamp: Option<i64>
+}
+
+#[async_trait]
+impl FindLatestCheckpointSchema for StubCheckpoints {
+    async fn find_latest_checkpoint(&self, _process_id: &str, module_id: &str) -> Result<Option<ProcessCheckpoint>, CuErrors> {
+        match self.timestamp {
+            Some(timestamp) => Ok(Some(ProcessCheckpoint {
+                id: format!("checkpoint-{}", timestamp),
+                module_id: module_id.to_string(),
+                evaluation: evaluation(timestamp),
+                memory: b"checkpoint".to_vec()
+            })),
+            None => Err(CuErrors::HttpStatus(HttpError { status: 502, message: "Could not find Checkpoints".to_string() }))
+        }
+    }
+}
+
+#[allow(unused)]
+fn checkpoint_sources(sources: Vec<(&'static str, Option<i64>)>) -> Vec<(&'static str, Arc<dyn FindLatestCheckpointSchema + Send + Sync>)> {
+    sources.into_iter()
+        .map(|(name, timestamp)| (name, Arc::new(StubCheckpoints { timestamp }) as Arc<dyn FindLatestCheckpointSchema + Send + Sync>))
+        .collect()
+}
+
+#[allow(unused)]
+pub fn assert_status<T>(result: Result<T, CuErrors>, status: u32) {
+    match result {
+        Ok(_) => panic!("Should have failed with a {}", status),
+        Err(CuErrors::HttpStatus(e)) => assert!(e.status == status),
+        Err(e) => panic!("Wrong error provided {:?}", e)
+    }
+}
+
+#[tokio::test]
+async fn test_module_options() {
+    let name = "readstate1";
+    let mut apis = get_apis(name).await;
+    apis.config.PROCESS_WASM_MEMORY_MAX_LIMIT = 1024 * 1024 * 1024;
+    apis.config.PROCESS_WASM_COMPUTE_MAX_LIMIT = 9000000000000;
+
+    let options = apis.module_options("process-123", &vec![], &module(module_tags())).unwrap();
+    assert!(options.format == "wasm32-unknown-emscripten2");
+    assert!(options.input_encoding == "JSON-1");
+    assert!(options.output_encoding == "JSON-1");
+    assert!(options.memory_limit == 500 * 1024 * 1024);
+    assert!(options.compute_limit == 9000000000000);
+    assert!(options.extensions.is_empty());
+
+    // the limits set on the process take precedence
+    let process_tags = vec![tag("Memory-Limit", "1-gb"), tag("Compute-Limit", "1000")];
+    let options = apis.module_options("process-123", &process_tags, &module(module_tags())).unwrap();
+    assert!(options.memory_limit == 1024 * 1024 * 1024);
+    assert!(options.compute_limit == 1000);
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_module_options_rejects_unsupported_modules() {
+    let name = "readstate2";
+    let mut apis = get_apis(name).await;
+    apis.config.PROCESS_WASM_MEMORY_MAX_LIMIT = 1024 * 1024 * 1024;
+    apis.config.PROCESS_WASM_COMPUTE_MAX_LIMIT = 9000000000000;
+
+    let mut tags = module_tags();
+    tags.retain(|t| t.name != "Module-Format");
+    tags.push(tag("Module-Format", "wasm64-unknown-emscripten"));
+    assert_status(apis.module_options("process-123", &vec![], &module(tags)), 422);
+
+    let mut tags = module_tags();
+    tags.retain(|t| t.name != "Input-Encoding");
+    assert_status(apis.module_options("process-123", &vec![], &module(tags)), 422);
+
+    let mut tags = module_tags();
+    tags.push(tag("Extension", "WeaveDrive"));
+    assert_status(apis.module_options("process-123", &vec![], &module(tags)), 422);
+
+    // over the limits of the cu, or unparseable
+    for (limit, value) in [("Memory-Limit", "2-gb"), ("Memory-Limit", "lots"), ("Compute-Limit", "9000000000001"), ("Compute-Limit", "0")] {
+        assert_status(apis.module_options("process-123", &vec![tag(limit, value)], &module(module_tags())), 413);
+    }
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_read_state_rejects_restricted_processes() {
+    let name = "readstate3";
+    let mut apis = get_apis(name).await;
+
+    apis.config.RESTRICT_PROCESSES = vec!["process-123".to_string()];
+    assert_status(apis.read_state("process-123", None).await, 403);
+
+    apis.config.RESTRICT_PROCESSES = vec![];
+    apis.config.ALLOW_PROCESSES = vec!["process-456".to_string()];
+    assert_status(apis.read_state("process-123", None).await, 403);
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_read_state_from_the_cache() {
+    let name = "readstate4";
+    let apis = get_apis(name).await;
+    cache_memory(&apis, 1702677252111).await;
+
+    match apis.read_process_state(&loaded_process(), Some(1702677252111)).await {
+        Ok(state) => {
+            assert!(state.memory == Some(b"memory".to_vec()));
+            assert!(state.timestamp() == Some(1702677252111));
+            assert!(state.evaluated == 0);
+        },
+        Err(e) => panic!("{:?}", e)
+    }
+    // the memory at an earlier message is gone
+    assert_status(apis.read_process_state(&loaded_process(), Some(1702677252000)).await, 425);
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_read_state_from_a_checkpoint() {
+    let name = "readstate5";
+    let mut apis = get_apis(name).await;
+    apis.checkpoint_sources = checkpoint_sources(vec![("file", Some(1702677252111))]);
+
+    match apis.read_process_state(&loaded_process(), Some(1702677252111)).await {
+        Ok(state) => {
+            assert!(state.memory == Some(b"checkpoint".to_vec()));
+            assert!(state.timestamp() == Some(1702677252111));
+            assert!(state.evaluated == 0);
+        },
+        Err(e) => panic!("{:?}", e)
+    }
+    // the restored memory is cached for the next read
+    match apis.ao_process.get("process-123").await {
+        Some(entry) => {
+            assert!(entry.memory == Some(b"checkpoint".to_vec()));
+            assert!(entry.evaluation.timestamp == 1702677252111);
+        },
+        None => panic!("Should cache the memory of the Checkpoint")
+    }
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_read_state_skips_failing_checkpoint_sources() {
+    let name = "readstate6";
+    let mut apis = get_apis(name).await;
+    apis.checkpoint_sources = checkpoint_sources(vec![("file", None), ("Arweave", Some(1702677252111))]);
+
+    match apis.read_process_state(&loaded_process(), Some(1702677252111)).await {
+        Ok(state) => {
+            assert!(state.memory == Some(b"checkpoint".to_vec()));
+            assert!(state.timestamp() == Some(1702677252111));
+        },
+        Err(e) => panic!("{:?}", e)
+    }
+
+    delete_apis_files(name);
+}
+
+#[tokio::test]
+async fn test_read_state_from_the_latest_checkpoint() {
+    let name = "readstate7";
+    let mut apis = get_apis(name).await;
+    apis.checkpoint_sources = checkpoint_sources(vec![("file", Some(1702677252000)), ("Arweave", Some(1702677252111))]);
+
+    match apis.read_process_state(&loaded_process(), Some(1702677252111)).await {
+        Ok(state) => assert!(state.timestamp() == Some(1702677252111)),
+        Err(e) => panic!("{:?}", e)
+    }
+
+    delete_apis_files(name);
+}
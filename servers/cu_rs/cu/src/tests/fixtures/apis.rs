@@ -0,0 +1,29 @@
+use ao_common::test_utils::{get_uploader_url, get_wallet_file};
+use crate::config::get_domain_config_schema;
+use crate::domain::{client::arweave::InternalArweave, Apis, DomainContext};
+use crate::tests::domain::client::test_sqlite::delete_db_files;
+use crate::tests::fixtures::log::get_logger;
+
+/**
+ * Apis over their own db and directories, named after the test, that
+ * create no Checkpoints and have no evaluator
+ */
+pub async fn get_apis(name: &str) -> Apis {
+    let mut config = get_domain_config_schema(true).as_ref().unwrap().clone();
+    config.DB_URL = name.to_string();
+    config.PROCESS_CHECKPOINT_FILE_DIRECTORY = format!("{}-checkpoints", name);
+    config.PROCESS_MEMORY_CACHE_FILE_DIR = format!("{}-memory", name);
+    config.PROCESS_MEMORY_CACHE_CHECKPOINT_INTERVAL = 0;
+    config.DISABLE_PROCESS_CHECKPOINT_CREATION = true;
+
+    let ctx = DomainContext::new(InternalArweave::new(get_wallet_file(), get_uploader_url()), config, get_logger());
+    let mut apis = DomainContext::create_api(ctx).await;
+    apis.evaluator = None;
+    apis
+}
+
+pub fn delete_apis_files(name: &str) {
+    delete_db_files(&format!("{}.sqlite", name));
+    _ = std::fs::remove_dir_all(format!("{}-checkpoints", name));
+    _ = std::fs::remove_dir_all(format!("{}-memory", name));
+}
@@ -0,0 +1,36 @@
+;; A process for the evaluator tests, without the emscripten runtime.
+;;
+;; handle loops 1000 times for every unit of the first byte of the message, grows
+;; the memory by as many pages as its second byte and traps if it can't. On the way
+;; it calls the wasi clock, random and fd_write, storing what they return at
+;; 0 (the time), 72 (the bytes written) and 80 (8 random bytes)
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (data (i32.const 16) "{\"ok\":true,\"response\":{\"Output\":\"done\"}}\00")
+  ;; an iovec of the first 8 bytes of the response
+  (data (i32.const 64) "\10\00\00\00\08\00\00\00")
+
+  (func (export "malloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+    (local.get $ptr))
+
+  (func (export "handle") (param $msg i32) (param $env i32) (result i32)
+    (local $i i32)
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+    (drop (call $random_get (i32.const 80) (i32.const 8)))
+    (drop (call $fd_write (i32.const 1) (i32.const 64) (i32.const 1) (i32.const 72)))
+    (local.set $i (i32.mul (i32.load8_u (local.get $msg)) (i32.const 1000)))
+    (block $done
+      (loop $loop
+        (br_if $done (i32.eqz (local.get $i)))
+        (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+        (br $loop)))
+    (if (i32.eq (memory.grow (i32.load8_u offset=1 (local.get $msg))) (i32.const -1))
+      (then unreachable))
+    (i32.const 16)))
@@ -6,8 +6,14 @@ pub mod domain {
         pub mod test_ao_evalution;
         pub mod test_ao_module;
         pub mod test_ao_process;
+        pub mod test_checkpoint_file;
     }    
+    pub mod core {
+        pub mod test_read_state;
+        pub mod test_dry_run;
+    }
 }
 pub mod fixtures {
     pub mod log;
+    pub mod apis;
 }
\ No newline at end of file
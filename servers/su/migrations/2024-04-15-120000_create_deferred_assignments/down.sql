@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_deferred_assignments_status;
+
+DROP TABLE IF EXISTS deferred_assignments;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS deferred_assignments (
+  row_id SERIAL PRIMARY KEY,
+  ticket VARCHAR(255) NOT NULL UNIQUE,
+  process_id VARCHAR(255) NOT NULL REFERENCES processes(process_id),
+  assign VARCHAR(255) NOT NULL,
+  exclude TEXT,
+  status VARCHAR(32) NOT NULL,
+  assignment_id VARCHAR(255),
+  error_message TEXT,
+  created_at BIGINT NOT NULL
+);
+
+CREATE INDEX idx_deferred_assignments_status ON deferred_assignments(status);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS pending_uploads (
+  row_id SERIAL PRIMARY KEY,
+  bundle BYTEA NOT NULL,
+  created_at BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_messages_unarchived;
+ALTER TABLE messages DROP COLUMN IF EXISTS bundle_hash;
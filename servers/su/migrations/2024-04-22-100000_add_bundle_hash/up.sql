@@ -0,0 +1,2 @@
+ALTER TABLE messages ADD COLUMN bundle_hash VARCHAR NULL;
+CREATE INDEX idx_messages_unarchived ON messages (timestamp) WHERE bundle_hash IS NULL;
@@ -0,0 +1 @@
+ALTER TABLE processes DROP COLUMN IF EXISTS scheduler_address;
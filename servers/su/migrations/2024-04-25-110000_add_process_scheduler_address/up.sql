@@ -0,0 +1 @@
+ALTER TABLE processes ADD COLUMN scheduler_address VARCHAR NULL;
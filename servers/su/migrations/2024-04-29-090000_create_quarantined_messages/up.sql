@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS quarantined_messages (
+  row_id SERIAL PRIMARY KEY,
+  original_row_id INTEGER NOT NULL,
+  process_id VARCHAR NOT NULL,
+  message_id VARCHAR NOT NULL,
+  assignment_id VARCHAR NULL,
+  message_data JSONB NOT NULL,
+  epoch INTEGER NOT NULL,
+  nonce INTEGER NOT NULL,
+  timestamp BIGINT NOT NULL,
+  bundle BYTEA NOT NULL,
+  hash_chain TEXT NOT NULL,
+  bundle_hash VARCHAR NULL,
+  reason TEXT NOT NULL,
+  quarantined_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_messages_process_id ON quarantined_messages (process_id);
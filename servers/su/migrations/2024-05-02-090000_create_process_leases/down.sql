@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_messages_process_epoch_nonce;
+DROP TABLE IF EXISTS process_leases;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS process_leases (
+  process_id VARCHAR PRIMARY KEY,
+  holder VARCHAR NOT NULL,
+  expires_at BIGINT NOT NULL
+);
+
+-- the unique index on (process_id, epoch, nonce) is created by the su at
+-- startup and after a repair, it can only be built once no schedule has a
+-- duplicate nonce left and a failed build here would keep the su from starting
@@ -131,6 +131,13 @@ impl Gateway for ArweaveGateway {
         Ok(NetworkInfo { height, current })
     }
 
+    async fn refresh_network_info(&self) -> Result<NetworkInfo, String> {
+        let network_info = ArweaveGateway::network_info_fetch().await?;
+        *self.height.lock().await = network_info.height.clone();
+        *self.current.lock().await = network_info.current.clone();
+        Ok(network_info)
+    }
+
     async fn status(&self, tx_id: &String) -> Result<TxStatus, String> {
         let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
         let gateway_url = config.gateway_url;
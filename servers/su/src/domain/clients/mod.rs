@@ -11,6 +11,9 @@ pub mod uploader;
 // database layer
 pub mod store;
 
+// cold storage for bundle bytes
+pub mod blob;
+
 // arweave gateway
 pub mod gateway;
 
@@ -6,6 +6,7 @@ table! {
         process_id -> Varchar,
         process_data -> Jsonb,
         bundle -> Bytea,
+        scheduler_address -> Nullable<Varchar>,
     }
 }
 
@@ -21,6 +22,7 @@ table! {
         timestamp -> BigInt,
         bundle -> Bytea,
         hash_chain -> Text,
+        bundle_hash -> Nullable<Varchar>,
     }
 }
 
@@ -40,4 +42,62 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(processes, messages, schedulers, process_schedulers,);
+table! {
+    deferred_assignments (row_id) {
+        row_id -> Int4,
+        ticket -> Varchar,
+        process_id -> Varchar,
+        assign -> Varchar,
+        exclude -> Nullable<Text>,
+        status -> Varchar,
+        assignment_id -> Nullable<Varchar>,
+        error_message -> Nullable<Text>,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    pending_uploads (row_id) {
+        row_id -> Int4,
+        bundle -> Bytea,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    quarantined_messages (row_id) {
+        row_id -> Int4,
+        original_row_id -> Int4,
+        process_id -> Varchar,
+        message_id -> Varchar,
+        assignment_id -> Nullable<Varchar>,
+        message_data -> Jsonb,
+        epoch -> Int4,
+        nonce -> Int4,
+        timestamp -> BigInt,
+        bundle -> Bytea,
+        hash_chain -> Text,
+        bundle_hash -> Nullable<Varchar>,
+        reason -> Text,
+        quarantined_at -> BigInt,
+    }
+}
+
+table! {
+    process_leases (process_id) {
+        process_id -> Varchar,
+        holder -> Varchar,
+        expires_at -> BigInt,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    processes,
+    messages,
+    schedulers,
+    process_schedulers,
+    deferred_assignments,
+    pending_uploads,
+    quarantined_messages,
+    process_leases,
+);
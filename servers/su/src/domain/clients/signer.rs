@@ -42,4 +42,8 @@ impl Signer for ArweaveSigner {
     fn get_public_key(&self) -> Vec<u8> {
         Bytes::copy_from_slice(&self.sdk.get_public_key().0).to_vec()
     }
+
+    fn verify(&self, pub_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+        SdkSigner::verify(pub_key, message, signature).map_err(|e| e.to_string())
+    }
 }
@@ -1,19 +1,37 @@
 use std::env::VarError;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use diesel::pg::PgConnection;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Text};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use super::super::core::dal::{
-    DataStore, JsonErrorType, Message, PaginatedMessages, Process, ProcessScheduler, Scheduler,
+    BlobErrorType, BlobStore, DataStore, DeferredAssignment, JsonErrorType, LeaseFence, Message,
+    MessageCursor, MessageFilter, PaginatedMessages, PendingUpload, PoolState, PoolStats, Process,
+    ProcessScheduler, ReadConsistency, ReplicationStatus, ScheduleCursor, ScheduleEntry, Scheduler,
     StoreErrorType,
 };
+use super::blob::init_blob_store;
 use crate::domain::config::AoConfig;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
+// created by create_nonce_index once no schedule has a duplicate nonce
+const NONCE_INDEX: &str = "idx_messages_process_epoch_nonce";
+
+type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+// a where clause on messages that can be or'd with others at runtime
+type MessageCondition =
+    Box<dyn BoxableExpression<super::schema::messages::table, Pg, SqlType = Bool>>;
+
+use diesel::result::DatabaseErrorKind;
 use diesel::result::Error as DieselError; // Import Diesel's Error
 
 impl From<DieselError> for StoreErrorType {
@@ -52,20 +70,49 @@ impl From<diesel::prelude::ConnectionError> for StoreErrorType {
     }
 }
 
+impl From<BlobErrorType> for StoreErrorType {
+    fn from(error: BlobErrorType) -> Self {
+        match error {
+            BlobErrorType::NotFound(e) => StoreErrorType::NotFound(e),
+            BlobErrorType::BlobError(e) => StoreErrorType::DatabaseError(e),
+        }
+    }
+}
+
 impl From<std::num::ParseIntError> for StoreErrorType {
     fn from(error: std::num::ParseIntError) -> Self {
         StoreErrorType::IntError(format!("data store int error: {}", error))
     }
 }
 
+/*
+    the pools are None once closed, their connections
+    close as soon as the last checked out one is returned
+*/
 pub struct StoreClient {
-    pool: Pool<ConnectionManager<PgConnection>>,
-    read_pool: Pool<ConnectionManager<PgConnection>>,
+    pool: RwLock<Option<DbPool>>,
+    read_pool: RwLock<Option<DbPool>>,
+    // where old bundles are moved to, None keeps them in postgres
+    blob_store: Option<Arc<dyn BlobStore>>,
+    compress: bool,
+}
+
+fn open_pool(pool: &RwLock<Option<DbPool>>) -> Result<DbPool, StoreErrorType> {
+    match pool.read() {
+        Ok(pool) => pool
+            .clone()
+            .ok_or_else(|| StoreErrorType::DatabaseError("Connection pool is closed.".to_string())),
+        Err(_) => Err(StoreErrorType::DatabaseError(
+            "Connection pool lock is poisoned.".to_string(),
+        )),
+    }
 }
 
 impl StoreClient {
     pub fn new() -> Result<Self, StoreErrorType> {
         let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
+        let blob_store = init_blob_store(&config)?;
+        let compress = config.cold_storage_compress;
         let database_url = config.database_url;
         let database_read_url = match config.database_read_url {
             Some(u) => u,
@@ -88,14 +135,19 @@ impl StoreClient {
             })?;
         
 
-        Ok(StoreClient { pool, read_pool })
+        Ok(StoreClient {
+            pool: RwLock::new(Some(pool)),
+            read_pool: RwLock::new(Some(read_pool)),
+            blob_store,
+            compress,
+        })
     }
 
     pub fn get_conn(
         &self,
     ) -> Result<diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>, StoreErrorType>
     {
-        self.pool.get().map_err(|_| {
+        open_pool(&self.pool)?.get().map_err(|_| {
             StoreErrorType::DatabaseError("Failed to get connection from pool.".to_string())
         })
     }
@@ -104,11 +156,77 @@ impl StoreClient {
         &self,
     ) -> Result<diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>, StoreErrorType>
     {
-        self.read_pool.get().map_err(|_| {
+        open_pool(&self.read_pool)?.get().map_err(|_| {
             StoreErrorType::DatabaseError("Failed to get connection from pool.".to_string())
         })
     }
 
+    /*
+        the bundle of a message, loaded from cold storage
+        if it has been moved out of the row. The hash is
+        checked so a corrupt blob is never served
+    */
+    fn load_bundle(&self, db_message: &DbMessage) -> Result<Vec<u8>, StoreErrorType> {
+        let hash = match &db_message.bundle_hash {
+            Some(h) => h,
+            None => return Ok(db_message.bundle.clone()),
+        };
+        let blob_store = self.blob_store.as_ref().ok_or_else(|| {
+            StoreErrorType::DatabaseError(
+                "Bundle is in cold storage but COLD_STORAGE is not configured".to_string(),
+            )
+        })?;
+
+        load_archived_bundle(blob_store.as_ref(), hash)
+    }
+
+    /*
+        db_messages holds one row past the limit when
+        there is another page after this one
+    */
+    fn paginate(
+        &self,
+        db_messages: Vec<DbMessage>,
+        limit_val: i64,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        let (messages_o, has_next_page) = page_of(&db_messages, limit_val);
+
+        let mut messages_mapped: Vec<Message> = vec![];
+        for db_message in messages_o.iter() {
+            let json = serde_json::from_value(db_message.message_data.clone())?;
+            let bytes: Vec<u8> = self.load_bundle(db_message)?;
+            let mapped = Message::from_val(&json, bytes)?;
+            messages_mapped.push(mapped);
+        }
+
+        let paginated = PaginatedMessages::from_messages(messages_mapped, has_next_page)?;
+        Ok(paginated)
+    }
+
+    /*
+        pick the pool to read from, the read pool is used
+        unless the caller needs a position it has not
+        replayed yet
+    */
+    fn get_read_conn_for(
+        &self,
+        consistency: &ReadConsistency,
+    ) -> Result<diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>, StoreErrorType>
+    {
+        match consistency {
+            ReadConsistency::Eventual => self.get_read_conn(),
+            ReadConsistency::Primary => self.get_conn(),
+            ReadConsistency::AtLeast(min_lsn) => {
+                let mut read_conn = self.get_read_conn()?;
+                if replica_caught_up(&replica_lsn(&mut read_conn)?, min_lsn)? {
+                    Ok(read_conn)
+                } else {
+                    self.get_conn()
+                }
+            }
+        }
+    }
+
     /*
         run at server startup to modify the database as needed
     */
@@ -125,7 +243,12 @@ impl StoreClient {
 }
 
 impl DataStore for StoreClient {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+    fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+        scheduler_address_in: &str,
+    ) -> Result<String, StoreErrorType> {
         use super::schema::processes::dsl::*;
         let conn = &mut self.get_conn()?;
 
@@ -133,6 +256,7 @@ impl DataStore for StoreClient {
             process_id: &process.process_id,
             process_data: serde_json::to_value(process).expect("Failed to serialize Process"),
             bundle: bundle_in,
+            scheduler_address: Some(scheduler_address_in),
         };
 
         match diesel::insert_into(processes)
@@ -146,9 +270,13 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
+    fn get_process(
+        &self,
+        process_id_in: &str,
+        consistency: &ReadConsistency,
+    ) -> Result<Process, StoreErrorType> {
         use super::schema::processes::dsl::*;
-        let conn = &mut self.get_read_conn()?;
+        let conn = &mut self.get_read_conn_for(consistency)?;
 
         let db_process_result: Result<Option<DbProcess>, DieselError> = processes
             .filter(process_id.eq(process_id_in))
@@ -165,6 +293,22 @@ impl DataStore for StoreClient {
         }
     }
 
+    fn get_process_identity(&self, process_id_in: &str) -> Result<Option<String>, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let result: Option<Option<String>> = processes
+            .filter(process_id.eq(process_id_in))
+            .select(scheduler_address)
+            .first(conn)
+            .optional()?;
+
+        match result {
+            Some(address) => Ok(address),
+            None => Err(StoreErrorType::NotFound("Process not found".to_string())),
+        }
+    }
+
     /*
         If we are trying to write an actual data item
         not just an assignment we need to check that it
@@ -173,7 +317,7 @@ impl DataStore for StoreClient {
     fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
         match &message.message {
             Some(m) => {
-                match self.get_message(&m.id) {
+                match self.get_message(&m.id, &ReadConsistency::Primary) {
                     Ok(parsed) => {
                         /*
                             If the message already exists and it contains
@@ -200,7 +344,12 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+    fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+        lease: &Option<LeaseFence>,
+    ) -> Result<String, StoreErrorType> {
         use super::schema::messages::dsl::*;
         let conn = &mut self.get_conn()?;
 
@@ -218,10 +367,33 @@ impl DataStore for StoreClient {
             hash_chain: &message.hash_chain()?,
         };
 
-        match diesel::insert_into(messages)
-            .values(&new_message)
-            .execute(conn)
-        {
+        /*
+            the lease was claimed before the nonce was read, it
+            is checked again here so a su whose lease ran out in
+            between can not write. The row is locked until the
+            insert commits so no other su can take it meanwhile
+        */
+        let saved = conn.transaction::<usize, DieselError, _>(|conn| {
+            if let Some(fence) = lease {
+                let held: Vec<HolderRow> = diesel::sql_query(
+                    "SELECT holder FROM process_leases \
+                     WHERE process_id = $1 AND holder = $2 AND expires_at >= $3 \
+                     FOR SHARE",
+                )
+                .bind::<Text, _>(new_message.process_id)
+                .bind::<Text, _>(&fence.holder)
+                .bind::<BigInt, _>(fence.now)
+                .get_results(conn)?;
+                if held.is_empty() {
+                    return Err(DieselError::RollbackTransaction);
+                }
+            }
+            diesel::insert_into(messages)
+                .values(&new_message)
+                .execute(conn)
+        });
+
+        match saved {
             Ok(row_count) => {
                 if row_count == 0 {
                     Err(StoreErrorType::DatabaseError(
@@ -231,7 +403,12 @@ impl DataStore for StoreClient {
                     Ok("saved".to_string())
                 }
             }
-            Err(e) => Err(StoreErrorType::from(e)),
+            Err(e) => Err(save_message_error(
+                e,
+                new_message.process_id,
+                *new_message.epoch,
+                *new_message.nonce,
+            )),
         }
     }
 
@@ -241,9 +418,10 @@ impl DataStore for StoreClient {
         from: &Option<String>,
         to: &Option<String>,
         limit: &Option<i32>,
+        consistency: &ReadConsistency,
     ) -> Result<PaginatedMessages, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_read_conn()?;
+        let conn = &mut self.get_read_conn_for(consistency)?;
         let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
 
         // Apply 'from' timestamp filtering if 'from' is provided
@@ -270,33 +448,85 @@ impl DataStore for StoreClient {
             .load(conn);
 
         match db_messages_result {
-            Ok(db_messages) => {
-                let has_next_page = db_messages.len() as i64 > limit_val;
-                // Take only up to the limit if there's an extra indicating a next page
-                let messages_o = if has_next_page {
-                    &db_messages[..(limit_val as usize)]
-                } else {
-                    &db_messages[..]
-                };
+            Ok(db_messages) => self.paginate(db_messages, limit_val),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
 
-                let mut messages_mapped: Vec<Message> = vec![];
-                for db_message in messages_o.iter() {
-                    let json = serde_json::from_value(db_message.message_data.clone())?;
-                    let bytes: Vec<u8> = db_message.bundle.clone();
-                    let mapped = Message::from_val(&json, bytes)?;
-                    messages_mapped.push(mapped);
-                }
+    fn search_messages(
+        &self,
+        process_id_in: &str,
+        after: &Option<MessageCursor>,
+        first: &Option<i32>,
+        filter: &MessageFilter,
+        consistency: &ReadConsistency,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn_for(consistency)?;
+        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
 
-                let paginated = PaginatedMessages::from_messages(messages_mapped, has_next_page)?;
-                Ok(paginated)
+        /*
+            a row comparison, so a page that ends part way
+            through the messages of one timestamp picks up
+            the rest of them on the next page
+        */
+        if let Some(cursor) = after {
+            query = query.filter(
+                diesel::dsl::sql::<Bool>("(timestamp, epoch, nonce) > (")
+                    .bind::<BigInt, _>(cursor.timestamp)
+                    .sql(", ")
+                    .bind::<Integer, _>(cursor.epoch)
+                    .sql(", ")
+                    .bind::<Integer, _>(cursor.nonce)
+                    .sql(")"),
+            );
+        }
+
+        /*
+            message_data holds the serialized Message so tags
+            and owner are matched with jsonb containment
+        */
+        for (name, values) in filter.tags.iter() {
+            let mut any_value: Option<MessageCondition> = None;
+            for value in values {
+                let condition: MessageCondition = Box::new(message_data.contains(json!({
+                    "message": { "tags": [{ "name": name, "value": value }] }
+                })));
+                any_value = Some(match any_value {
+                    Some(previous) => Box::new(previous.or(condition)),
+                    None => condition,
+                });
+            }
+            if let Some(condition) = any_value {
+                query = query.filter(condition);
             }
+        }
+
+        if let Some(owner_address) = &filter.owner {
+            query = query.filter(message_data.contains(json!({
+                "message": { "owner": { "address": owner_address } }
+            })));
+        }
+
+        let limit_val = first.unwrap_or(100) as i64;
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = query
+            .order((timestamp.asc(), epoch.asc(), nonce.asc()))
+            .limit(limit_val + 1)
+            .load(conn);
+
+        match db_messages_result {
+            Ok(db_messages) => self.paginate(db_messages, limit_val),
             Err(e) => Err(StoreErrorType::from(e)),
         }
     }
 
-    fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
+    fn get_message(
+        &self,
+        tx_id: &str,
+        consistency: &ReadConsistency,
+    ) -> Result<Message, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_read_conn()?;
+        let conn = &mut self.get_read_conn_for(consistency)?;
 
         /*
             get the oldest match. in the case of a message that has
@@ -312,7 +542,8 @@ impl DataStore for StoreClient {
             Ok(Some(db_message)) => {
                 let message_val: serde_json::Value =
                     serde_json::from_value(db_message.message_data.clone())?;
-                let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
+                let message: Message =
+                    Message::from_val(&message_val, self.load_bundle(&db_message)?)?;
                 Ok(message)
             }
             Ok(None) => Err(StoreErrorType::NotFound("Message not found".to_string())), // Adjust this error type as needed
@@ -320,9 +551,14 @@ impl DataStore for StoreClient {
         }
     }
 
+    /*
+        the scheduler builds the next nonce from this so it
+        always reads the primary, a lagging replica would
+        hand out a nonce that is already taken
+    */
     fn get_latest_message(&self, process_id_in: &str) -> Result<Option<Message>, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_read_conn()?;
+        let conn = &mut self.get_conn()?;
 
         // Get the latest DbMessage
         let latest_db_message_result = messages
@@ -334,10 +570,11 @@ impl DataStore for StoreClient {
             Ok(db_message) => {
                 // Deserialize the message_data into Message
                 let message_val: serde_json::Value =
-                    serde_json::from_value(db_message.message_data)
+                    serde_json::from_value(db_message.message_data.clone())
                         .map_err(|e| StoreErrorType::from(e))?;
 
-                let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
+                let message: Message =
+                    Message::from_val(&message_val, self.load_bundle(&db_message)?)?;
 
                 Ok(Some(message))
             }
@@ -497,6 +734,500 @@ impl DataStore for StoreClient {
             Err(e) => Err(StoreErrorType::from(e)),
         }
     }
+
+    fn save_deferred_assignment(
+        &self,
+        deferred: &DeferredAssignment,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::deferred_assignments::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_deferred = NewDeferredAssignment {
+            ticket: &deferred.ticket,
+            process_id: &deferred.process_id,
+            assign: &deferred.assign,
+            exclude: deferred.exclude.as_deref(),
+            status: &deferred.status,
+            created_at: &deferred.created_at,
+        };
+
+        match diesel::insert_into(deferred_assignments)
+            .values(&new_deferred)
+            .execute(conn)
+        {
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn update_deferred_assignment(
+        &self,
+        deferred: &DeferredAssignment,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::deferred_assignments::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match diesel::update(deferred_assignments.filter(ticket.eq(&deferred.ticket)))
+            .set((
+                status.eq(&deferred.status),
+                assignment_id.eq(&deferred.assignment_id),
+                error_message.eq(&deferred.error_message),
+            ))
+            .execute(conn)
+        {
+            Ok(_) => Ok("updated".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_deferred_assignment(
+        &self,
+        ticket_in: &str,
+    ) -> Result<DeferredAssignment, StoreErrorType> {
+        use super::schema::deferred_assignments::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let db_deferred_result: Result<Option<DbDeferredAssignment>, DieselError> =
+            deferred_assignments
+                .filter(ticket.eq(ticket_in))
+                .first(conn)
+                .optional();
+
+        match db_deferred_result {
+            Ok(Some(db_deferred)) => Ok(DeferredAssignment::from(db_deferred)),
+            Ok(None) => Err(StoreErrorType::NotFound(
+                "Deferred assignment not found".to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_pending_deferred_assignments(&self) -> Result<Vec<DeferredAssignment>, StoreErrorType> {
+        use super::schema::deferred_assignments::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match deferred_assignments
+            .filter(status.eq(DeferredAssignment::PENDING))
+            .order(row_id.asc())
+            .load::<DbDeferredAssignment>(conn)
+        {
+            Ok(db_deferred) => Ok(db_deferred
+                .into_iter()
+                .map(DeferredAssignment::from)
+                .collect()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn save_pending_upload(&self, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+        use super::schema::pending_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| StoreErrorType::DatabaseError(format!("{:?}", e)))?
+            .as_millis() as i64;
+
+        let new_pending_upload = NewPendingUpload {
+            bundle: bundle_in,
+            created_at: &now,
+        };
+
+        match diesel::insert_into(pending_uploads)
+            .values(&new_pending_upload)
+            .execute(conn)
+        {
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn get_pending_uploads(&self) -> Result<Vec<PendingUpload>, StoreErrorType> {
+        use super::schema::pending_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match pending_uploads
+            .order(row_id.asc())
+            .load::<DbPendingUpload>(conn)
+        {
+            Ok(db_pending_uploads) => Ok(db_pending_uploads
+                .into_iter()
+                .map(|db_pending_upload| PendingUpload {
+                    row_id: Some(db_pending_upload.row_id),
+                    bundle: db_pending_upload.bundle,
+                    created_at: db_pending_upload.created_at,
+                })
+                .collect()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn delete_pending_upload(&self, row_id_in: &i32) -> Result<String, StoreErrorType> {
+        use super::schema::pending_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match diesel::delete(pending_uploads.filter(row_id.eq(row_id_in))).execute(conn) {
+            Ok(_) => Ok("deleted".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    /*
+        the blob is written before the row is updated so a
+        failure part way through leaves the bundle readable,
+        blobs are content addressed so a retry just rewrites it
+    */
+    fn archive_bundles(&self, older_than: i64, limit: i64) -> Result<usize, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let blob_store = self.blob_store.as_ref().ok_or_else(|| {
+            StoreErrorType::DatabaseError("COLD_STORAGE is not configured".to_string())
+        })?;
+        let conn = &mut self.get_conn()?;
+
+        let rows: Vec<(i32, Vec<u8>)> = messages
+            .filter(bundle_hash.is_null())
+            .filter(timestamp.lt(older_than))
+            .select((row_id, bundle))
+            .order(timestamp.asc())
+            .limit(limit)
+            .load(conn)?;
+
+        for (row_id_in, bytes) in rows.iter() {
+            let hash = archive_bundle(blob_store.as_ref(), self.compress, bytes)?;
+
+            diesel::update(messages.filter(row_id.eq(row_id_in)))
+                .set((bundle.eq(Vec::<u8>::new()), bundle_hash.eq(Some(&hash))))
+                .execute(conn)?;
+        }
+
+        Ok(rows.len())
+    }
+
+    fn get_process_ids(
+        &self,
+        after: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+        let mut query = processes.select(process_id).into_boxed();
+        if let Some(after_id) = after {
+            query = query.filter(process_id.gt(after_id));
+        }
+        let ids: Vec<String> = query.order(process_id.asc()).limit(limit).load(conn)?;
+        Ok(ids)
+    }
+
+    fn get_schedule_entries(
+        &self,
+        process_id_in: &str,
+        after: &Option<ScheduleCursor>,
+        limit: i64,
+        consistency: &ReadConsistency,
+    ) -> Result<Vec<ScheduleEntry>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn_for(consistency)?;
+        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
+
+        if let Some(cursor) = after {
+            query = query.filter(
+                diesel::dsl::sql::<Bool>("(epoch, nonce, row_id) > (")
+                    .bind::<Integer, _>(cursor.epoch)
+                    .sql(", ")
+                    .bind::<Integer, _>(cursor.nonce)
+                    .sql(", ")
+                    .bind::<Integer, _>(cursor.row_id)
+                    .sql(")"),
+            );
+        }
+
+        let rows: Vec<(i32, String, Option<String>, i32, i32, String)> = query
+            .select((row_id, message_id, assignment_id, epoch, nonce, hash_chain))
+            .order((epoch.asc(), nonce.asc(), row_id.asc()))
+            .limit(limit)
+            .load(conn)?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(
+                    row_id_out,
+                    message_id_out,
+                    assignment_id_out,
+                    epoch_out,
+                    nonce_out,
+                    hash_chain_out,
+                )| {
+                    ScheduleEntry {
+                        row_id: row_id_out,
+                        message_id: message_id_out,
+                        assignment_id: assignment_id_out,
+                        epoch: epoch_out,
+                        nonce: nonce_out,
+                        hash_chain: hash_chain_out,
+                    }
+                },
+            )
+            .collect())
+    }
+
+    fn quarantine_messages(&self, row_ids: &[i32], reason: &str) -> Result<usize, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        let quarantined_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        // copy and delete together so a row is never in both or neither
+        conn.transaction::<usize, DieselError, _>(|conn| {
+            diesel::sql_query(
+                "INSERT INTO quarantined_messages \
+                 (original_row_id, process_id, message_id, assignment_id, message_data, \
+                 epoch, nonce, timestamp, bundle, hash_chain, bundle_hash, reason, quarantined_at) \
+                 SELECT row_id, process_id, message_id, assignment_id, message_data, \
+                 epoch, nonce, timestamp, bundle, hash_chain, bundle_hash, $2, $3 \
+                 FROM messages WHERE row_id = ANY($1)",
+            )
+            .bind::<Array<Integer>, _>(row_ids)
+            .bind::<Text, _>(reason)
+            .bind::<BigInt, _>(quarantined_at)
+            .execute(conn)?;
+            diesel::sql_query("DELETE FROM messages WHERE row_id = ANY($1)")
+                .bind::<Array<Integer>, _>(row_ids)
+                .execute(conn)
+        })
+        .map_err(StoreErrorType::from)
+    }
+
+    fn create_nonce_index(&self) -> Result<bool, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        // building the index fails on the first duplicate it finds
+        match diesel::sql_query(format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {} ON messages (process_id, epoch, nonce)",
+            NONCE_INDEX
+        ))
+        .execute(conn)
+        {
+            Ok(_) => Ok(true),
+            Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(false),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn claim_lease(
+        &self,
+        process_id_in: &str,
+        holder_in: &str,
+        now: i64,
+        expires_at_in: i64,
+    ) -> Result<bool, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        /*
+            the update only happens if we already hold the lease
+            or it ran out, otherwise no row comes back. The row
+            lock taken by the upsert makes two claims race safely
+        */
+        let claimed: Vec<HolderRow> = diesel::sql_query(
+            "INSERT INTO process_leases (process_id, holder, expires_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (process_id) DO UPDATE \
+             SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at \
+             WHERE process_leases.holder = EXCLUDED.holder OR process_leases.expires_at < $4 \
+             RETURNING holder",
+        )
+        .bind::<Text, _>(process_id_in)
+        .bind::<Text, _>(holder_in)
+        .bind::<BigInt, _>(expires_at_in)
+        .bind::<BigInt, _>(now)
+        .get_results(conn)?;
+        Ok(claimed.iter().any(|row| row.holder == holder_in))
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let pool_state = |pool: &RwLock<Option<DbPool>>| match open_pool(pool) {
+            Ok(pool) => {
+                let state = pool.state();
+                PoolState {
+                    max_size: pool.max_size(),
+                    connections: state.connections,
+                    idle_connections: state.idle_connections,
+                }
+            }
+            Err(_) => PoolState {
+                max_size: 0,
+                connections: 0,
+                idle_connections: 0,
+            },
+        };
+        PoolStats {
+            primary: pool_state(&self.pool),
+            read: pool_state(&self.read_pool),
+        }
+    }
+
+    fn close(&self) {
+        for pool in [&self.pool, &self.read_pool] {
+            if let Ok(mut pool) = pool.write() {
+                pool.take();
+            }
+        }
+    }
+
+    fn current_lsn(&self) -> Result<String, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        let row: LsnRow =
+            diesel::sql_query("SELECT pg_current_wal_lsn()::text AS lsn").get_result(conn)?;
+        Ok(row.lsn.unwrap_or("0/0".to_string()))
+    }
+
+    fn replication_status(&self) -> Result<ReplicationStatus, StoreErrorType> {
+        let primary_lsn = self.current_lsn()?;
+
+        let read_conn = &mut self.get_read_conn()?;
+        let replica_lsn = replica_lsn(read_conn)?;
+        let lag: LagRow = diesel::sql_query(
+            "SELECT (CASE WHEN pg_is_in_recovery() \
+             THEN COALESCE(EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()), 0) \
+             ELSE 0 END)::float8 AS lag_seconds",
+        )
+        .get_result(read_conn)?;
+
+        let lag_bytes = parse_lsn(&primary_lsn)?.saturating_sub(parse_lsn(&replica_lsn)?);
+
+        Ok(ReplicationStatus {
+            primary_lsn,
+            replica_lsn,
+            lag_bytes,
+            // the replay timestamp keeps aging while the primary is idle
+            lag_seconds: if lag_bytes == 0 { 0.0 } else { lag.lag_seconds },
+        })
+    }
+}
+
+/*
+    a save refused because of another su is a ScheduleConflict,
+    either its lease check failed or the unique index on
+    (process_id, epoch, nonce) caught a second writer
+*/
+fn save_message_error(
+    error: DieselError,
+    process_id_in: &str,
+    epoch_in: i32,
+    nonce_in: i32,
+) -> StoreErrorType {
+    match error {
+        DieselError::RollbackTransaction => StoreErrorType::ScheduleConflict(format!(
+            "this su no longer holds the lease on process {}",
+            process_id_in
+        )),
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info)
+            if info.constraint_name() == Some(NONCE_INDEX) =>
+        {
+            StoreErrorType::ScheduleConflict(format!(
+                "nonce {} in epoch {} of process {} is already taken",
+                nonce_in, epoch_in, process_id_in
+            ))
+        }
+        e => StoreErrorType::from(e),
+    }
+}
+
+// the rows of a page and whether there is another page after it
+fn page_of<T>(rows: &[T], limit_val: i64) -> (&[T], bool) {
+    let has_next_page = rows.len() as i64 > limit_val;
+    // Take only up to the limit if there's an extra indicating a next page
+    if has_next_page {
+        (&rows[..(limit_val as usize)], true)
+    } else {
+        (rows, false)
+    }
+}
+
+/*
+    zstd frames start with a magic number, an uncompressed
+    bundle starts with a 32 byte item count so it will not
+    match unless it holds billions of items
+*/
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+fn compress_bundle(bytes: &[u8]) -> Result<Vec<u8>, StoreErrorType> {
+    zstd::encode_all(bytes, 0)
+        .map_err(|e| StoreErrorType::DatabaseError(format!("bundle compression error: {}", e)))
+}
+
+fn decompress_bundle(bytes: Vec<u8>) -> Result<Vec<u8>, StoreErrorType> {
+    if !bytes.starts_with(&ZSTD_MAGIC) {
+        return Ok(bytes);
+    }
+    zstd::decode_all(&bytes[..])
+        .map_err(|e| StoreErrorType::DatabaseError(format!("bundle decompression error: {}", e)))
+}
+
+// writes the bundle to cold storage, returning the hash it is stored under
+fn archive_bundle(
+    blob_store: &dyn BlobStore,
+    compress: bool,
+    bytes: &[u8],
+) -> Result<String, StoreErrorType> {
+    let hash = hex::encode(Sha256::digest(bytes));
+    let data = match compress {
+        true => compress_bundle(bytes)?,
+        false => bytes.to_vec(),
+    };
+    blob_store.put(&hash, &data)?;
+    Ok(hash)
+}
+
+fn load_archived_bundle(blob_store: &dyn BlobStore, hash: &str) -> Result<Vec<u8>, StoreErrorType> {
+    let bytes = decompress_bundle(blob_store.get(hash)?)?;
+    if hex::encode(Sha256::digest(&bytes)) != *hash {
+        return Err(StoreErrorType::DatabaseError(format!(
+            "Bundle {} in cold storage does not match its hash",
+            hash
+        )));
+    }
+    Ok(bytes)
+}
+
+#[derive(QueryableByName)]
+struct HolderRow {
+    #[diesel(sql_type = Text)]
+    holder: String,
+}
+
+#[derive(QueryableByName)]
+struct LsnRow {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    lsn: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct LagRow {
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    lag_seconds: f64,
+}
+
+// the lsn a connection has replayed, or written if it is the primary
+fn replica_lsn(conn: &mut PgConnection) -> Result<String, StoreErrorType> {
+    let row: LsnRow = diesel::sql_query(
+        "SELECT (CASE WHEN pg_is_in_recovery() \
+         THEN pg_last_wal_replay_lsn() ELSE pg_current_wal_lsn() END)::text AS lsn",
+    )
+    .get_result(conn)?;
+    Ok(row.lsn.unwrap_or("0/0".to_string()))
+}
+
+// postgres lsns look like 16/B374D848, two hex halves of a u64
+pub fn parse_lsn(lsn: &str) -> Result<u64, StoreErrorType> {
+    let invalid = || StoreErrorType::IntError(format!("invalid lsn {}", lsn));
+    let (hi, lo) = lsn.split_once('/').ok_or_else(invalid)?;
+    let hi = u64::from_str_radix(hi, 16).map_err(|_| invalid())?;
+    let lo = u64::from_str_radix(lo, 16).map_err(|_| invalid())?;
+    Ok((hi << 32) | lo)
+}
+
+// whether the replica has replayed everything up to min_lsn
+fn replica_caught_up(replica_lsn: &str, min_lsn: &str) -> Result<bool, StoreErrorType> {
+    Ok(parse_lsn(replica_lsn)? >= parse_lsn(min_lsn)?)
 }
 
 #[derive(Queryable, Selectable)]
@@ -507,6 +1238,7 @@ pub struct DbProcess {
     pub process_id: String,
     pub process_data: serde_json::Value,
     pub bundle: Vec<u8>,
+    pub scheduler_address: Option<String>,
 }
 
 #[derive(Queryable, Selectable)]
@@ -523,6 +1255,7 @@ pub struct DbMessage {
     pub timestamp: i64,
     pub bundle: Vec<u8>,
     pub hash_chain: String,
+    pub bundle_hash: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -545,6 +1278,7 @@ pub struct NewProcess<'a> {
     pub process_id: &'a str,
     pub process_data: serde_json::Value,
     pub bundle: &'a [u8],
+    pub scheduler_address: Option<&'a str>,
 }
 
 #[derive(Queryable, Selectable)]
@@ -578,3 +1312,183 @@ pub struct NewProcessScheduler<'a> {
     pub process_id: &'a str,
     pub scheduler_row_id: &'a i32,
 }
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::deferred_assignments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbDeferredAssignment {
+    pub row_id: i32,
+    pub ticket: String,
+    pub process_id: String,
+    pub assign: String,
+    pub exclude: Option<String>,
+    pub status: String,
+    pub assignment_id: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::deferred_assignments)]
+pub struct NewDeferredAssignment<'a> {
+    pub ticket: &'a str,
+    pub process_id: &'a str,
+    pub assign: &'a str,
+    pub exclude: Option<&'a str>,
+    pub status: &'a str,
+    pub created_at: &'a i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::pending_uploads)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbPendingUpload {
+    pub row_id: i32,
+    pub bundle: Vec<u8>,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::pending_uploads)]
+pub struct NewPendingUpload<'a> {
+    pub bundle: &'a [u8],
+    pub created_at: &'a i64,
+}
+
+impl From<DbDeferredAssignment> for DeferredAssignment {
+    fn from(db_deferred: DbDeferredAssignment) -> Self {
+        DeferredAssignment {
+            row_id: Some(db_deferred.row_id),
+            ticket: db_deferred.ticket,
+            process_id: db_deferred.process_id,
+            assign: db_deferred.assign,
+            exclude: db_deferred.exclude,
+            status: db_deferred.status,
+            assignment_id: db_deferred.assignment_id,
+            error_message: db_deferred.error_message,
+            created_at: db_deferred.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::blob::FsBlobStore;
+    use super::*;
+
+    #[test]
+    fn test_archive_and_load_bundle() {
+        let root = std::env::temp_dir().join(format!("su-archive-test-{}", std::process::id()));
+        let blob_store = FsBlobStore::new(root.clone()).expect("failed to create store");
+        let bundle = b"bundle bytes bundle bytes bundle bytes".repeat(64);
+
+        for compress in [true, false] {
+            let hash = archive_bundle(&blob_store, compress, &bundle).expect("failed to archive");
+            let stored = blob_store.get(&hash).expect("failed to get");
+            assert_eq!(stored.starts_with(&ZSTD_MAGIC), compress);
+            if compress {
+                assert!(stored.len() < bundle.len());
+            }
+            assert_eq!(
+                load_archived_bundle(&blob_store, &hash).expect("failed to load"),
+                bundle
+            );
+        }
+
+        // a corrupt blob is never served
+        let hash = archive_bundle(&blob_store, true, &bundle).expect("failed to archive");
+        blob_store
+            .put(&hash, &compress_bundle(b"other bytes").unwrap())
+            .expect("failed to put");
+        assert!(load_archived_bundle(&blob_store, &hash).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_parse_lsn() {
+        assert_eq!(parse_lsn("0/0").unwrap(), 0);
+        assert_eq!(parse_lsn("0/B374D848").unwrap(), 0xB374D848);
+        assert_eq!(parse_lsn("16/B374D848").unwrap(), (0x16 << 32) | 0xB374D848);
+        assert!(parse_lsn("16B374D848").is_err());
+        assert!(parse_lsn("16/not-hex").is_err());
+        assert!(parse_lsn("").is_err());
+    }
+
+    #[test]
+    fn test_replica_caught_up() {
+        assert!(replica_caught_up("16/B374D848", "16/B374D848").unwrap());
+        assert!(replica_caught_up("17/0", "16/FFFFFFFF").unwrap());
+        // behind, the read falls back to the primary
+        assert!(!replica_caught_up("16/B374D847", "16/B374D848").unwrap());
+        assert!(!replica_caught_up("0/0", "1/0").unwrap());
+        assert!(replica_caught_up("16/0", "bad lsn").is_err());
+    }
+
+    #[test]
+    fn test_page_of() {
+        let rows = vec![1, 2, 3];
+        // queries ask for one row past the limit
+        assert_eq!(page_of(&rows, 2), (&rows[..2], true));
+        assert_eq!(page_of(&rows, 3), (&rows[..], false));
+        assert_eq!(page_of(&rows, 5), (&rows[..], false));
+        assert_eq!(page_of(&rows[..0], 2), (&rows[..0], false));
+    }
+
+    struct ConstraintError(&'static str);
+
+    impl diesel::result::DatabaseErrorInformation for ConstraintError {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint"
+        }
+        fn details(&self) -> Option<&str> {
+            None
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            Some("messages")
+        }
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            Some(self.0)
+        }
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_save_message_error() {
+        let unique = |constraint| {
+            DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(ConstraintError(constraint)),
+            )
+        };
+
+        match save_message_error(unique(NONCE_INDEX), "process", 0, 7) {
+            StoreErrorType::ScheduleConflict(e) => {
+                assert_eq!(e, "nonce 7 in epoch 0 of process process is already taken")
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        match save_message_error(DieselError::RollbackTransaction, "process", 0, 7) {
+            StoreErrorType::ScheduleConflict(e) => assert!(e.contains("lease")),
+            e => panic!("unexpected {:?}", e),
+        }
+
+        // any other unique index is a plain database error
+        assert!(matches!(
+            save_message_error(unique("messages_pkey"), "process", 0, 7),
+            StoreErrorType::DatabaseError(_)
+        ));
+        assert!(matches!(
+            save_message_error(DieselError::NotFound, "process", 0, 7),
+            StoreErrorType::DatabaseError(_)
+        ));
+    }
+}
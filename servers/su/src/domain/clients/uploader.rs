@@ -1,19 +1,67 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use async_trait::async_trait;
+use dashmap::DashMap;
 use reqwest::{Client, Url};
 
 extern crate serde;
 use serde::{Deserialize, Serialize};
 
 use tokio::spawn;
+use tokio::sync::Notify;
 use tokio::time::{sleep, Duration};
 
-use crate::domain::core::dal::{Uploader, UploaderErrorType};
+use crate::domain::core::dal::{BacklogBundle, Uploader, UploaderErrorType};
 use crate::domain::Log;
 
+const UPLOAD_ATTEMPTS: u32 = 100;
+
 pub struct UploaderClient {
     node_url: Url,
     logger: Arc<dyn Log>,
+    /*
+        bundles that have not been uploaded yet, so they
+        can be persisted if the server shuts down
+    */
+    pending: Arc<DashMap<u64, PendingBundle>>,
+    next_key: AtomicU64,
+    // tries per upload before it is marked failed
+    attempts: u32,
+    // set on shutdown, no new attempts are started
+    stopping: Arc<AtomicBool>,
+    running: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+}
+
+struct PendingBundle {
+    tx: Vec<u8>,
+    /*
+        only set for a bundle persisted by an earlier
+        shutdown, it removes the persisted copy
+    */
+    on_uploaded: Option<Box<dyn FnOnce() + Send + Sync>>,
+    // an upload task is working on it, a retry leaves it alone
+    uploading: bool,
+    // every attempt failed, it waits for a retry
+    failed: bool,
+}
+
+/*
+    held by an upload task, the last one
+    to finish wakes up a waiting stop
+*/
+struct RunningUpload {
+    running: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+}
+
+impl Drop for RunningUpload {
+    fn drop(&mut self) {
+        if self.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -47,20 +95,59 @@ impl UploaderClient {
         Ok(UploaderClient {
             node_url: url,
             logger,
+            pending: Arc::new(DashMap::new()),
+            next_key: AtomicU64::new(0),
+            attempts: UPLOAD_ATTEMPTS,
+            stopping: Arc::new(AtomicBool::new(false)),
+            running: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(Notify::new()),
         })
     }
 }
 
-impl Uploader for UploaderClient {
-    fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType> {
+impl UploaderClient {
+    /*
+        upload in the background, retrying until it succeeds
+        or runs out of attempts. The bundle stays in pending
+        until it succeeds so it can be retried or persisted.
+        Returns false if a task is already uploading it
+    */
+    fn spawn_upload(&self, key: u64) -> bool {
+        let tx = match self.pending.get_mut(&key) {
+            Some(mut bundle) if !bundle.uploading => {
+                bundle.uploading = true;
+                bundle.failed = false;
+                bundle.tx.clone()
+            }
+            _ => return false,
+        };
+
         let node_url_clone = self.node_url.clone();
-        let tx_clone = tx.clone();
         let logger_clone = Arc::clone(&self.logger);
+        let pending_clone = Arc::clone(&self.pending);
+        let stopping_clone = Arc::clone(&self.stopping);
+        let attempts = self.attempts;
+
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let running = RunningUpload {
+            running: Arc::clone(&self.running),
+            idle: Arc::clone(&self.idle),
+        };
 
         spawn(async move {
+            let _running = running;
             let client = Client::new();
 
-            for _attempt in 0..100 {
+            for _attempt in 0..attempts {
+                // another attempt already uploaded it
+                if !pending_clone.contains_key(&key) {
+                    break;
+                }
+                // left in pending to be persisted
+                if stopping_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+
                 let response = client
                     .post(
                         node_url_clone
@@ -68,7 +155,7 @@ impl Uploader for UploaderClient {
                             .expect("Failed to join URL"), // Handle URL joining error
                     )
                     .header("Content-Type", "application/octet-stream")
-                    .body(tx_clone.clone())
+                    .body(tx.clone())
                     .send()
                     .await;
 
@@ -76,6 +163,11 @@ impl Uploader for UploaderClient {
                     Ok(resp) if resp.status().is_success() => {
                         // Handle success
                         logger_clone.log("Upload successful".to_string());
+                        if let Some((_, bundle)) = pending_clone.remove(&key) {
+                            if let Some(on_uploaded) = bundle.on_uploaded {
+                                on_uploaded();
+                            }
+                        }
                         break; // Exit the loop on success
                     }
                     Ok(resp) => {
@@ -90,8 +182,165 @@ impl Uploader for UploaderClient {
                     }
                 }
             }
+
+            if let Some(mut bundle) = pending_clone.get_mut(&key) {
+                bundle.uploading = false;
+                // a stop leaves it to be persisted, otherwise it gave up
+                if !stopping_clone.load(Ordering::SeqCst) {
+                    bundle.failed = true;
+                    logger_clone.error(format!(
+                        "upload failed after {} attempts, it stays in the backlog until retried",
+                        attempts
+                    ));
+                }
+            }
         });
+        true
+    }
+}
 
+impl UploaderClient {
+    fn queue(
+        &self,
+        tx: Vec<u8>,
+        on_uploaded: Option<Box<dyn FnOnce() + Send + Sync>>,
+    ) -> Result<(), UploaderErrorType> {
+        let key = self.next_key.fetch_add(1, Ordering::SeqCst);
+        self.pending.insert(
+            key,
+            PendingBundle {
+                tx,
+                on_uploaded,
+                uploading: false,
+                failed: false,
+            },
+        );
+        self.spawn_upload(key);
         Ok(())
     }
 }
+
+#[async_trait]
+impl Uploader for UploaderClient {
+    fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType> {
+        self.queue(tx, None)
+    }
+
+    fn upload_persisted(
+        &self,
+        tx: Vec<u8>,
+        on_uploaded: Box<dyn FnOnce() + Send + Sync>,
+    ) -> Result<(), UploaderErrorType> {
+        self.queue(tx, Some(on_uploaded))
+    }
+
+    fn backlog(&self) -> Vec<BacklogBundle> {
+        self.pending
+            .iter()
+            .map(|entry| BacklogBundle {
+                tx: entry.value().tx.clone(),
+                failed: entry.value().failed,
+            })
+            .collect()
+    }
+
+    fn unpersisted_backlog(&self) -> Vec<Vec<u8>> {
+        self.pending
+            .iter()
+            .filter(|entry| entry.value().on_uploaded.is_none())
+            .map(|entry| entry.value().tx.clone())
+            .collect()
+    }
+
+    fn retry_backlog(&self) -> usize {
+        let keys: Vec<u64> = self.pending.iter().map(|entry| *entry.key()).collect();
+        keys.into_iter()
+            .filter(|key| self.spawn_upload(*key))
+            .count()
+    }
+
+    async fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        loop {
+            let notified = self.idle.notified();
+            if self.running.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockLogger;
+    impl Log for MockLogger {
+        fn log(&self, message: String) {
+            println!("{}", message)
+        }
+        fn error(&self, message: String) {
+            println!("{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_waits_for_attempts_in_flight() {
+        // nothing listens on port 1 so every attempt fails
+        let uploader = UploaderClient::new("http://127.0.0.1:1", Arc::new(MockLogger))
+            .expect("failed to create uploader");
+        uploader.upload(vec![1, 2, 3]).expect("failed to queue");
+        uploader
+            .upload_persisted(vec![4, 5, 6], Box::new(|| ()))
+            .expect("failed to queue");
+        assert_eq!(uploader.running.load(Ordering::SeqCst), 2);
+
+        uploader.stop().await;
+        assert_eq!(uploader.running.load(Ordering::SeqCst), 0);
+
+        // nothing was uploaded, only the bundle not persisted yet is snapshotted
+        assert_eq!(uploader.backlog().len(), 2);
+        assert_eq!(uploader.unpersisted_backlog(), vec![vec![1, 2, 3]]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_skips_uploads_in_progress() {
+        let uploader = UploaderClient::new("http://127.0.0.1:1", Arc::new(MockLogger))
+            .expect("failed to create uploader");
+        uploader.upload(vec![1, 2, 3]).expect("failed to queue");
+
+        // the first attempt is still retrying
+        assert_eq!(uploader.retry_backlog(), 0);
+        assert_eq!(uploader.running.load(Ordering::SeqCst), 1);
+
+        uploader.stop().await;
+        uploader.stopping.store(false, Ordering::SeqCst);
+
+        // the task gave up so the bundle can be retried once
+        assert_eq!(uploader.retry_backlog(), 1);
+        assert_eq!(uploader.retry_backlog(), 0);
+        uploader.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_upload_out_of_attempts_is_failed() {
+        let mut uploader = UploaderClient::new("http://127.0.0.1:1", Arc::new(MockLogger))
+            .expect("failed to create uploader");
+        uploader.attempts = 1;
+        uploader.upload(vec![1, 2, 3]).expect("failed to queue");
+        while uploader.running.load(Ordering::SeqCst) > 0 {
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        let backlog = uploader.backlog();
+        assert_eq!(backlog.len(), 1);
+        assert!(backlog[0].failed);
+
+        // a retry starts over
+        assert_eq!(uploader.retry_backlog(), 1);
+        assert!(!uploader.backlog()[0].failed);
+        uploader.stop().await;
+        assert!(!uploader.backlog()[0].failed);
+    }
+}
@@ -9,10 +9,29 @@ pub struct AoConfig {
     pub database_url: String,
     pub database_read_url: Option<String>,
     pub su_wallet_path: String,
+    pub su_wallet_paths: Vec<String>,
     pub gateway_url: String,
     pub upload_node_url: String,
     pub mode: String,
     pub scheduler_list_path: String,
+    pub deferred_assignment_interval: u64,
+    pub shutdown_timeout: u64,
+    pub clock_mode: String,
+    pub replica_lag_threshold: u64,
+    pub cold_storage: String,
+    pub cold_storage_path: Option<String>,
+    pub cold_storage_age: u64,
+    pub cold_storage_compress: bool,
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: String,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    pub admin_token: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub consistency_check_interval: u64,
+    pub su_instance_id: Option<String>,
+    pub lease_ttl: u64,
 }
 
 impl AoConfig {
@@ -28,18 +47,103 @@ impl AoConfig {
                 None
             }
         };
+        let deferred_assignment_interval = match env::var("DEFERRED_ASSIGNMENT_INTERVAL") {
+            Ok(val) => val.parse::<u64>().unwrap_or(120),
+            Err(_e) => 120,
+        };
+        let shutdown_timeout = match env::var("SHUTDOWN_TIMEOUT") {
+            Ok(val) => val.parse::<u64>().unwrap_or(30),
+            Err(_e) => 30,
+        };
+        let clock_mode = match env::var("CLOCK_MODE") {
+            Ok(val) => val,
+            Err(_e) => "wall".to_string(),
+        };
+        let replica_lag_threshold = match env::var("REPLICA_LAG_THRESHOLD") {
+            Ok(val) => val.parse::<u64>().unwrap_or(10),
+            Err(_e) => 10,
+        };
+        let cold_storage = match env::var("COLD_STORAGE") {
+            Ok(val) => val,
+            Err(_e) => "none".to_string(),
+        };
+        let consistency_check_interval = match env::var("CONSISTENCY_CHECK_INTERVAL") {
+            Ok(val) => val.parse::<u64>().unwrap_or(3600),
+            Err(_e) => 3600,
+        };
+        let lease_ttl = match env::var("LEASE_TTL") {
+            Ok(val) => val.parse::<u64>().unwrap_or(30),
+            Err(_e) => 30,
+        };
+        // 30 days
+        let cold_storage_age = match env::var("COLD_STORAGE_AGE") {
+            Ok(val) => val.parse::<u64>().unwrap_or(2592000),
+            Err(_e) => 2592000,
+        };
+        let cold_storage_compress = match env::var("COLD_STORAGE_COMPRESS") {
+            Ok(val) => val == "true",
+            Err(_e) => false,
+        };
+        let s3_region = match env::var("S3_REGION") {
+            Ok(val) => val,
+            Err(_e) => "us-east-1".to_string(),
+        };
+        // extra scheduler identities hosted next to SU_WALLET_PATH
+        let su_wallet_paths = match env::var("SU_WALLET_PATHS") {
+            Ok(val) => val
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            Err(_e) => vec![],
+        };
         Ok(AoConfig {
             database_url: env::var("DATABASE_URL")?,
             database_read_url,
             su_wallet_path: env::var("SU_WALLET_PATH")?,
+            su_wallet_paths,
             gateway_url: env::var("GATEWAY_URL")?,
             upload_node_url: env::var("UPLOAD_NODE_URL")?,
             mode: mode_out,
             scheduler_list_path: env::var("SCHEDULER_LIST_PATH")?,
+            deferred_assignment_interval,
+            shutdown_timeout,
+            clock_mode,
+            replica_lag_threshold,
+            cold_storage,
+            cold_storage_path: env::var("COLD_STORAGE_PATH").ok(),
+            cold_storage_age,
+            cold_storage_compress,
+            s3_endpoint: env::var("S3_ENDPOINT").ok(),
+            s3_bucket: env::var("S3_BUCKET").ok(),
+            s3_region,
+            s3_access_key_id: env::var("S3_ACCESS_KEY_ID").ok(),
+            s3_secret_access_key: env::var("S3_SECRET_ACCESS_KEY").ok(),
+            admin_token: env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+            consistency_check_interval,
+            su_instance_id: env::var("SU_INSTANCE_ID")
+                .ok()
+                .filter(|id| !id.is_empty())
+                .or_else(hostname),
+            lease_ttl,
         })
     }
 }
 
+/*
+    the default SU_INSTANCE_ID, it stays the same across
+    restarts so a restarted su keeps holding its leases
+*/
+fn hostname() -> Option<String> {
+    env::var("HOSTNAME")
+        .or_else(|_| std::fs::read_to_string("/proc/sys/kernel/hostname"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
 impl Config for AoConfig {
     fn su_wallet_path(&self) -> String {
         self.su_wallet_path.clone()
@@ -56,4 +160,37 @@ impl Config for AoConfig {
     fn scheduler_list_path(&self) -> String {
         self.scheduler_list_path.clone()
     }
+    fn deferred_assignment_interval(&self) -> u64 {
+        self.deferred_assignment_interval
+    }
+    fn shutdown_timeout(&self) -> u64 {
+        self.shutdown_timeout
+    }
+    fn clock_mode(&self) -> String {
+        self.clock_mode.clone()
+    }
+    fn replica_lag_threshold(&self) -> u64 {
+        self.replica_lag_threshold
+    }
+    fn cold_storage(&self) -> String {
+        self.cold_storage.clone()
+    }
+    fn cold_storage_age(&self) -> u64 {
+        self.cold_storage_age
+    }
+    fn admin_token(&self) -> Option<String> {
+        self.admin_token.clone()
+    }
+    fn su_wallet_paths(&self) -> Vec<String> {
+        self.su_wallet_paths.clone()
+    }
+    fn consistency_check_interval(&self) -> u64 {
+        self.consistency_check_interval
+    }
+    fn su_instance_id(&self) -> Option<String> {
+        self.su_instance_id.clone()
+    }
+    fn lease_ttl(&self) -> u64 {
+        self.lease_ttl
+    }
 }
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use super::bytes::DataItem;
+use super::flows::Deps;
+use super::responses::{
+    to_json, LocksResponse, ReloadSchedulersResponse, RetryUploadsResponse, UploadBacklogResponse,
+    UploadEntry,
+};
+use super::router;
+
+/*
+    operations for inspecting and nudging a running su,
+    the routes for these are only served when ADMIN_TOKEN
+    is set and the request carries it
+*/
+
+pub async fn locks(deps: Arc<Deps>) -> Result<String, String> {
+    let locks = deps.scheduler.lock_status();
+    to_json(&LocksResponse {
+        count: locks.len(),
+        locks,
+    })
+}
+
+pub async fn upload_backlog(deps: Arc<Deps>) -> Result<String, String> {
+    let uploads: Vec<UploadEntry> = deps
+        .uploader
+        .backlog()
+        .iter()
+        .map(|bundle| {
+            let id = match DataItem::from_bytes(bundle.tx.clone()) {
+                Ok(item) => Some(item.id()),
+                Err(_) => None,
+            };
+            UploadEntry {
+                id,
+                size: bundle.tx.len(),
+                failed: bundle.failed,
+            }
+        })
+        .collect();
+    to_json(&UploadBacklogResponse {
+        count: uploads.len(),
+        failed: uploads.iter().filter(|upload| upload.failed).count(),
+        uploads,
+    })
+}
+
+pub async fn retry_uploads(deps: Arc<Deps>) -> Result<String, String> {
+    let count = deps.uploader.retry_backlog();
+    deps.logger
+        .log(format!("admin retried {} pending uploads", count));
+    to_json(&RetryUploadsResponse { retried: count })
+}
+
+// picks up schedulers added to SCHEDULER_LIST_PATH since boot
+pub async fn reload_schedulers(deps: Arc<Deps>) -> Result<String, String> {
+    if deps.config.mode() != "router" {
+        return Err("Schedulers can only be reloaded in router mode".to_string());
+    }
+    let result = router::init_schedulers(deps.clone()).await?;
+    deps.logger
+        .log(format!("admin reloaded schedulers - {}", result));
+    to_json(&ReloadSchedulersResponse { result })
+}
+
+pub async fn pool_stats(deps: Arc<Deps>) -> Result<String, String> {
+    to_json(&deps.data_store.pool_stats())
+}
+
+pub async fn refresh_network_info(deps: Arc<Deps>) -> Result<String, String> {
+    let network_info = deps.gateway.refresh_network_info().await?;
+    to_json(&network_info)
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use bundlr_sdk::tags::Tag;
+use tracing::{info_span, Instrument};
 
 use super::bytes::{ByteErrorType, DataBundle, DataItem};
 use super::dal::{Gateway, Log, ScheduleProvider, Signer, TxStatus};
@@ -87,7 +88,11 @@ impl<'a> Builder<'a> {
 
         let mut assignment = DataItem::new(vec![], vec![], tags, self.signer.get_public_key())?;
         let assignment_message = assignment.get_message()?.to_vec();
-        let assignment_signature = self.signer.sign_tx(assignment_message).await?;
+        let assignment_signature = self
+            .signer
+            .sign_tx(assignment_message)
+            .instrument(info_span!("sign"))
+            .await?;
 
         assignment.signature = assignment_signature;
 
@@ -115,7 +120,11 @@ impl<'a> Builder<'a> {
             DataItem::new(vec![], buffer, bundle_tags, self.signer.get_public_key())?;
         let bundle_message = bundle_data_item.get_message()?.to_vec();
 
-        let signature = self.signer.sign_tx(bundle_message).await?;
+        let signature = self
+            .signer
+            .sign_tx(bundle_message)
+            .instrument(info_span!("sign"))
+            .await?;
 
         bundle_data_item.signature = signature;
 
@@ -218,7 +227,11 @@ impl<'a> Builder<'a> {
         let mut new_data_item = DataItem::new(vec![], buffer, tags, pub_key)?;
         let message = new_data_item.get_message()?.to_vec();
 
-        let signature = self.signer.sign_tx(message).await?;
+        let signature = self
+            .signer
+            .sign_tx(message)
+            .instrument(info_span!("sign"))
+            .await?;
 
         new_data_item.signature = signature;
 
@@ -241,36 +254,43 @@ impl<'a> Builder<'a> {
         base_layer: &Option<String>,
     ) -> Result<(), BuilderErrorType> {
         match base_layer {
-            Some(_) => {
-                let status: TxStatus = self.gateway.status(&tx_id).await?;
-
-                /*
-                    If there is not a Settlement-Depth tag on the Process
-                    we use a default value of 20 because after 18 there is
-                    assurance that it is confirmed.
-                */
-                let threshold = match process
-                    .tags
-                    .iter()
-                    .find(|tag| tag.name == "Settlement-Depth")
-                {
-                    Some(t) => match t.value.parse::<i32>() {
-                        Ok(parsed_value) => parsed_value,
-                        Err(_) => 20,
-                    },
-                    None => 20,
-                };
-
-                match status.number_of_confirmations {
-                    n if n >= threshold => Ok(()),
-                    _ => Err(BuilderErrorType::BuilderError(
-                        "Not enough confirmations to assign".to_string(),
-                    )),
-                }
-            }
+            Some(_) => match self.settlement_reached(tx_id, process).await? {
+                true => Ok(()),
+                false => Err(BuilderErrorType::BuilderError(
+                    "Not enough confirmations to assign".to_string(),
+                )),
+            },
             None => Ok(()),
         }
     }
+
+    /*
+        Check if a base layer tx has enough confirmations
+        to be assigned to the process
+    */
+    pub async fn settlement_reached(
+        &self,
+        tx_id: &String,
+        process: &Process,
+    ) -> Result<bool, BuilderErrorType> {
+        let status: TxStatus = self.gateway.status(tx_id).await?;
+
+        /*
+            If there is not a Settlement-Depth tag on the Process
+            we use a default value of 20 because after 18 there is
+            assurance that it is confirmed.
+        */
+        let threshold = match process
+            .tags
+            .iter()
+            .find(|tag| tag.name == "Settlement-Depth")
+        {
+            Some(t) => t.value.parse::<i32>().unwrap_or(20),
+            None => 20,
+        };
+
+        Ok(status.number_of_confirmations >= threshold)
+    }
 }
 
 #[cfg(test)]
@@ -294,6 +314,10 @@ mod tests {
             })
         }
 
+        async fn refresh_network_info(&self) -> Result<NetworkInfo, String> {
+            self.network_info().await
+        }
+
         async fn status(&self, _tx_id: &String) -> Result<TxStatus, String> {
             Ok(TxStatus {
                 block_height: 0,
@@ -312,6 +336,15 @@ mod tests {
         fn get_public_key(&self) -> Vec<u8> {
             vec![5, 6, 7, 8]
         }
+
+        fn verify(
+            &self,
+            _pub_key: &[u8],
+            _message: &[u8],
+            _signature: &[u8],
+        ) -> Result<(), String> {
+            Ok(())
+        }
     }
 
     struct MockLogger;
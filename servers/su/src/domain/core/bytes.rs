@@ -66,6 +66,57 @@ impl DataBundle {
 
         Ok(buffer)
     }
+
+    /*
+        parse the binary ANS-104 format written by to_bytes,
+        the tags of a bundle live on the data item that
+        carries it so they are left empty here
+    */
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self, ByteErrorType> {
+        if buffer.len() < 32 {
+            return Err(ByteErrorType::ByteError("bundle too short".to_string()));
+        }
+        let item_count = byte_array_to_usize(&buffer[0..32])?;
+        let headers_end = item_count
+            .checked_mul(64)
+            .and_then(|h| h.checked_add(32))
+            .filter(|h| *h <= buffer.len())
+            .ok_or_else(|| ByteErrorType::ByteError("invalid bundle item count".to_string()))?;
+
+        let mut bundle = DataBundle::new(vec![]);
+        let mut offset = headers_end;
+        for index in 0..item_count {
+            let header = &buffer[32 + 64 * index..32 + 64 * (index + 1)];
+            let size = byte_array_to_usize(&header[0..32])?;
+            let end = offset
+                .checked_add(size)
+                .filter(|e| *e <= buffer.len())
+                .ok_or_else(|| ByteErrorType::ByteError("invalid bundle item size".to_string()))?;
+
+            let item = DataItem::from_bytes(buffer[offset..end].to_vec())?;
+            if item.raw_id() != header[32..64] {
+                return Err(ByteErrorType::ByteError(format!(
+                    "bundle item {} does not match its header id",
+                    item.id()
+                )));
+            }
+            bundle.add_item(item);
+            offset = end;
+        }
+
+        Ok(bundle)
+    }
+}
+
+// little endian, only the low 8 bytes can be set
+fn byte_array_to_usize(bytes: &[u8]) -> Result<usize, ByteErrorType> {
+    if bytes[8..].iter().any(|b| *b != 0) {
+        return Err(ByteErrorType::ByteError("number too large".to_string()));
+    }
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&bytes[0..8]);
+    usize::try_from(u64::from_le_bytes(low))
+        .map_err(|_| ByteErrorType::ByteError("number too large".to_string()))
 }
 
 fn long_to_n_byte_array(n: usize, long: u64) -> Result<Vec<u8>, ByteErrorType> {
@@ -498,6 +549,13 @@ impl DataItem {
         }
     }
 
+    pub fn data_bytes(&self) -> Vec<u8> {
+        match &self.data {
+            Data::Bytes(d) => d.clone(),
+            Data::None => vec![],
+        }
+    }
+
     pub fn signature(&self) -> String {
         let sig_base64 = base64_url::encode(&self.signature);
         sig_base64
@@ -566,4 +624,20 @@ mod tests {
         let bundle_bytes = data_bundle.to_bytes();
         assert!(bundle_bytes.is_ok(), "Bundling failed");
     }
+
+    #[test]
+    fn test_bundle_from_bytes() {
+        let item_bytes = base64_url::decode(&ITEM_STR.to_string()).expect("failed to decode");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(data_item.clone());
+        data_bundle.add_item(data_item.clone());
+        let bundle_bytes = data_bundle.to_bytes().expect("bundling failed");
+
+        let parsed = DataBundle::from_bytes(&bundle_bytes).expect("failed to parse bundle");
+        assert_eq!(parsed.items.len(), 2);
+        assert_eq!(parsed.items[1].id(), data_item.id());
+
+        assert!(DataBundle::from_bytes(&bundle_bytes[..bundle_bytes.len() - 1]).is_err());
+    }
 }
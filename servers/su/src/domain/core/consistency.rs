@@ -0,0 +1,602 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use utoipa::ToSchema;
+
+use super::dal::{DataStore, ReadConsistency, ScheduleCursor, ScheduleEntry};
+use super::flows::Deps;
+use super::responses::{to_json, ConsistencySummary, RepairResponse};
+use super::scheduler::gen_hash_chain;
+
+/*
+    checks a process schedule as it is stored. Every
+    (epoch, nonce) is used once, nonces run from 0 with
+    no gaps and each hash_chain follows from the message
+    before it. A crash between saving and responding or
+    a second su writing the same process shows up here
+    instead of in a cu that replays the schedule
+*/
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct DuplicateNonce {
+    pub epoch: i32,
+    pub nonce: i32,
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct NonceGap {
+    pub epoch: i32,
+    pub expected: i32,
+    pub found: i32,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct ChainBreak {
+    pub epoch: i32,
+    pub nonce: i32,
+    pub message_id: String,
+    pub expected: String,
+    pub found: String,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct ConsistencyReport {
+    pub process_id: String,
+    pub messages: usize,
+    pub duplicates: Vec<DuplicateNonce>,
+    pub gaps: Vec<NonceGap>,
+    pub chain_breaks: Vec<ChainBreak>,
+    pub checked_at: i64,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.duplicates.is_empty() && self.gaps.is_empty() && self.chain_breaks.is_empty()
+    }
+}
+
+// the latest background results, only processes with problems are kept
+#[derive(Default)]
+pub struct ConsistencyState {
+    reports: DashMap<String, ConsistencyReport>,
+    last_run: AtomicI64,
+    processes_checked: AtomicU64,
+}
+
+impl ConsistencyState {
+    pub fn new() -> Self {
+        ConsistencyState::default()
+    }
+
+    pub fn record(&self, report: ConsistencyReport) {
+        match report.is_consistent() {
+            true => {
+                self.reports.remove(&report.process_id);
+            }
+            false => {
+                self.reports.insert(report.process_id.clone(), report);
+            }
+        }
+    }
+
+    pub fn summary(&self) -> ConsistencySummary {
+        let mut reports: Vec<ConsistencyReport> = self
+            .reports
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        reports.sort_by(|a, b| a.process_id.cmp(&b.process_id));
+        ConsistencySummary {
+            last_run: self.last_run.load(Ordering::SeqCst),
+            processes_checked: self.processes_checked.load(Ordering::SeqCst),
+            reports,
+            failed_uploads: 0,
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/*
+    reads a schedule a page at a time. Of the rows sharing
+    a nonce the one continuing the hash chain is kept, or
+    the oldest if none do, the others are conflicting
+*/
+pub struct ScheduleChecker {
+    report: ConsistencyReport,
+    conflicting: Vec<ScheduleEntry>,
+    previous: Option<ScheduleEntry>,
+    // the rows of the latest nonce, more may be on the next page
+    group: Vec<ScheduleEntry>,
+}
+
+impl ScheduleChecker {
+    pub fn new(process_id: &str) -> Self {
+        ScheduleChecker {
+            report: ConsistencyReport {
+                process_id: process_id.to_string(),
+                messages: 0,
+                duplicates: vec![],
+                gaps: vec![],
+                chain_breaks: vec![],
+                checked_at: now_millis(),
+            },
+            conflicting: vec![],
+            previous: None,
+            group: vec![],
+        }
+    }
+
+    // entries carry on from the last page in (epoch, nonce, row_id) order
+    pub fn add(&mut self, entries: Vec<ScheduleEntry>) {
+        for entry in entries {
+            let same_nonce = match self.group.first() {
+                Some(first) => first.epoch == entry.epoch && first.nonce == entry.nonce,
+                None => false,
+            };
+            if !same_nonce {
+                self.check_group();
+            }
+            self.report.messages += 1;
+            self.group.push(entry);
+        }
+    }
+
+    // the report and the rows that lost to another with the same nonce
+    pub fn finish(mut self) -> (ConsistencyReport, Vec<ScheduleEntry>) {
+        self.check_group();
+        (self.report, self.conflicting)
+    }
+
+    fn check_group(&mut self) {
+        let mut group = std::mem::take(&mut self.group);
+        if group.is_empty() {
+            return;
+        }
+        let (epoch, nonce) = (group[0].epoch, group[0].nonce);
+
+        let expected_nonce = match &self.previous {
+            Some(p) if p.epoch == epoch => p.nonce + 1,
+            _ => 0,
+        };
+        let expected_chain = match &self.previous {
+            Some(p) => gen_hash_chain(&p.hash_chain, p.assignment_id.as_deref()),
+            None => gen_hash_chain(&self.report.process_id, None),
+        }
+        .unwrap_or_default();
+
+        let kept_index = group
+            .iter()
+            .position(|entry| entry.hash_chain == expected_chain)
+            .unwrap_or(0);
+
+        if group.len() > 1 {
+            self.report.duplicates.push(DuplicateNonce {
+                epoch,
+                nonce,
+                message_ids: group.iter().map(|entry| entry.message_id.clone()).collect(),
+            });
+        }
+
+        let kept = group.remove(kept_index);
+        self.conflicting.extend(group);
+
+        // across a gap the chain can not be followed so only the gap is reported
+        if nonce != expected_nonce {
+            self.report.gaps.push(NonceGap {
+                epoch,
+                expected: expected_nonce,
+                found: nonce,
+            });
+        } else if kept.hash_chain != expected_chain {
+            self.report.chain_breaks.push(ChainBreak {
+                epoch,
+                nonce,
+                message_id: kept.message_id.clone(),
+                expected: expected_chain,
+                found: kept.hash_chain.clone(),
+            });
+        }
+
+        self.previous = Some(kept);
+    }
+}
+
+// entries must be in (epoch, nonce, row_id) order, returns the conflicting row ids
+pub fn check_entries(process_id: &str, entries: &[ScheduleEntry]) -> (ConsistencyReport, Vec<i32>) {
+    let mut checker = ScheduleChecker::new(process_id);
+    checker.add(entries.to_vec());
+    let (report, conflicting) = checker.finish();
+    (
+        report,
+        conflicting.iter().map(|entry| entry.row_id).collect(),
+    )
+}
+
+// schedule entries read per query, only one page is held at a time
+const ENTRY_PAGE_SIZE: i64 = 1000;
+
+fn check_schedule(
+    data_store: &dyn DataStore,
+    process_id: &str,
+    consistency: &ReadConsistency,
+) -> Result<(ConsistencyReport, Vec<ScheduleEntry>), String> {
+    let mut checker = ScheduleChecker::new(process_id);
+    let mut after: Option<ScheduleCursor> = None;
+    loop {
+        let entries =
+            data_store.get_schedule_entries(process_id, &after, ENTRY_PAGE_SIZE, consistency)?;
+        let last_page = (entries.len() as i64) < ENTRY_PAGE_SIZE;
+        after = entries.last().map(|entry| ScheduleCursor {
+            epoch: entry.epoch,
+            nonce: entry.nonce,
+            row_id: entry.row_id,
+        });
+        checker.add(entries);
+        if last_page {
+            return Ok(checker.finish());
+        }
+    }
+}
+
+pub async fn consistency_report(deps: Arc<Deps>) -> Result<String, String> {
+    let mut summary = deps.consistency.summary();
+    summary.failed_uploads = deps
+        .uploader
+        .backlog()
+        .iter()
+        .filter(|bundle| bundle.failed)
+        .count();
+    to_json(&summary)
+}
+
+// a router only knows which su holds a process, the schedules are on the su
+fn check_mode(mode: &str) -> Result<(), String> {
+    match mode {
+        "router" => Err("Schedules are only checked on a su, not the router".to_string()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn check_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
+    check_mode(&deps.config.mode())?;
+
+    let (report, _) = check_schedule(
+        deps.data_store.as_ref(),
+        &process_id,
+        &ReadConsistency::Primary,
+    )?;
+    deps.consistency.record(report.clone());
+    to_json(&report)
+}
+
+/*
+    quarantine the rows that share a nonce with the row
+    that was kept. The process lock and lease are held
+    throughout so no write on this or another su lands
+    while rows are moved. Gaps and chain breaks are only
+    reported, there is nothing to move
+*/
+pub async fn repair_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
+    check_mode(&deps.config.mode())?;
+
+    let locked_schedule_info = deps.scheduler.acquire_lock(process_id.clone()).await?;
+    let schedule_info = locked_schedule_info.lock().await;
+    deps.scheduler.claim_lease(&process_id)?;
+
+    let (_, conflicting) = check_schedule(
+        deps.data_store.as_ref(),
+        &process_id,
+        &ReadConsistency::Primary,
+    )?;
+    let row_ids: Vec<i32> = conflicting.iter().map(|entry| entry.row_id).collect();
+    let quarantined: Vec<String> = conflicting
+        .into_iter()
+        .map(|entry| entry.message_id)
+        .collect();
+
+    if !row_ids.is_empty() {
+        deps.data_store
+            .quarantine_messages(&row_ids, "duplicate nonce")?;
+        deps.logger.error(format!(
+            "quarantined messages {} on process {}",
+            quarantined.join(", "),
+            &process_id
+        ));
+        // the last repair lets the unique nonce index be built
+        if let Err(e) = deps.data_store.create_nonce_index() {
+            deps.logger
+                .error(format!("failed to create the unique nonce index - {:?}", e));
+        }
+    }
+
+    let (report, _) = check_schedule(
+        deps.data_store.as_ref(),
+        &process_id,
+        &ReadConsistency::Primary,
+    )?;
+    drop(schedule_info);
+
+    deps.consistency.record(report.clone());
+    to_json(&RepairResponse {
+        quarantined,
+        report,
+    })
+}
+
+// processes checked per blocking task by the background checker
+const CHECK_PAGE_SIZE: i64 = 100;
+
+// counts of what a run of the checker found
+#[derive(Default)]
+struct CheckTotals {
+    processes: u64,
+    inconsistent: u64,
+    duplicates: u64,
+    gaps: u64,
+    chain_breaks: u64,
+}
+
+/*
+    check every process a page at a time from the read
+    pool, so a large database neither holds a primary
+    connection nor keeps a whole schedule in memory
+*/
+async fn check_all_processes(deps: &Arc<Deps>) -> Result<CheckTotals, String> {
+    let mut totals = CheckTotals::default();
+    let mut after: Option<String> = None;
+
+    loop {
+        if deps.shutdown.is_draining() {
+            return Err("the server is draining".to_string());
+        }
+
+        let data_store = deps.data_store.clone();
+        let page_after = after.clone();
+        let reports = tokio::task::spawn_blocking(move || {
+            let mut reports = vec![];
+            for process_id in data_store.get_process_ids(&page_after, CHECK_PAGE_SIZE)? {
+                let (report, _) =
+                    check_schedule(data_store.as_ref(), &process_id, &ReadConsistency::Eventual)?;
+                reports.push(report);
+            }
+            Ok::<Vec<ConsistencyReport>, String>(reports)
+        })
+        .await
+        .map_err(|e| format!("{:?}", e))??;
+
+        let last_page = (reports.len() as i64) < CHECK_PAGE_SIZE;
+        after = reports.last().map(|report| report.process_id.clone());
+
+        for report in reports {
+            totals.processes += 1;
+            if !report.is_consistent() {
+                totals.inconsistent += 1;
+                totals.duplicates += report.duplicates.len() as u64;
+                totals.gaps += report.gaps.len() as u64;
+                totals.chain_breaks += report.chain_breaks.len() as u64;
+                deps.logger.error(format!(
+                    "process {} schedule is inconsistent - {} duplicate nonces, {} gaps, {} chain breaks",
+                    &report.process_id,
+                    report.duplicates.len(),
+                    report.gaps.len(),
+                    report.chain_breaks.len()
+                ));
+            }
+            deps.consistency.record(report);
+        }
+
+        if last_page {
+            return Ok(totals);
+        }
+    }
+}
+
+// check every process on an interval, CONSISTENCY_CHECK_INTERVAL=0 turns it off
+pub fn init_consistency_checker(deps: Arc<Deps>) {
+    let interval = deps.config.consistency_check_interval();
+    if interval == 0 || check_mode(&deps.config.mode()).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(interval)).await;
+            if deps.shutdown.is_draining() {
+                break;
+            }
+
+            let totals = match check_all_processes(&deps).await {
+                Ok(totals) => totals,
+                Err(e) => {
+                    deps.logger
+                        .error(format!("error checking schedules - {}", e));
+                    continue;
+                }
+            };
+
+            deps.consistency
+                .last_run
+                .store(now_millis(), Ordering::SeqCst);
+            deps.consistency
+                .processes_checked
+                .store(totals.processes, Ordering::SeqCst);
+
+            let metrics = &deps.metrics;
+            metrics.set(
+                "su_consistency_inconsistent_processes",
+                &[],
+                totals.inconsistent,
+            );
+            metrics.set("su_consistency_duplicate_nonces", &[], totals.duplicates);
+            metrics.set("su_consistency_nonce_gaps", &[], totals.gaps);
+            metrics.set("su_consistency_chain_breaks", &[], totals.chain_breaks);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROCESS_ID: &str = "kA6FuNbEzbPwDa9d2Ud1vUv2yDSBmKzu_rpwBTKPsfE";
+
+    fn entry(row_id: i32, nonce: i32, assignment: u8, hash_chain: &str) -> ScheduleEntry {
+        ScheduleEntry {
+            row_id,
+            message_id: format!("message-{}", row_id),
+            assignment_id: Some(base64_url::encode(&[assignment; 32])),
+            epoch: 0,
+            nonce,
+            hash_chain: hash_chain.to_string(),
+        }
+    }
+
+    // a valid schedule of n messages, assignment ids are [nonce + 1; 32]
+    fn schedule(n: i32) -> Vec<ScheduleEntry> {
+        let mut entries: Vec<ScheduleEntry> = vec![];
+        for nonce in 0..n {
+            let chain = match entries.last() {
+                Some(p) => gen_hash_chain(&p.hash_chain, p.assignment_id.as_deref()),
+                None => gen_hash_chain(PROCESS_ID, None),
+            }
+            .expect("invalid hash chain");
+            entries.push(entry(nonce + 1, nonce, nonce as u8 + 1, &chain));
+        }
+        entries
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let (report, conflicting) = check_entries(PROCESS_ID, &schedule(4));
+        assert!(report.is_consistent());
+        assert!(conflicting.is_empty());
+
+        // a second writer used nonce 2 again with a chain of its own
+        let mut entries = schedule(4);
+        entries.insert(3, entry(10, 2, 9, "forked"));
+        let (report, conflicting) = check_entries(PROCESS_ID, &entries);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].nonce, 2);
+        assert_eq!(conflicting, vec![10]);
+        assert!(report.chain_breaks.is_empty());
+        assert!(report.gaps.is_empty());
+
+        let mut entries = schedule(4);
+        entries.remove(1);
+        let (report, _) = check_entries(PROCESS_ID, &entries);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].expected, 1);
+        assert_eq!(report.gaps[0].found, 2);
+    }
+
+    #[test]
+    fn test_check_entries_chain_break() {
+        assert!(check_entries(PROCESS_ID, &[]).0.is_consistent());
+
+        let mut entries = schedule(4);
+        entries[2].hash_chain = "rewritten".to_string();
+        let (report, conflicting) = check_entries(PROCESS_ID, &entries);
+        assert!(conflicting.is_empty());
+        assert_eq!(report.chain_breaks.len(), 2);
+        assert_eq!(report.chain_breaks[0].nonce, 2);
+        assert_eq!(report.chain_breaks[0].found, "rewritten");
+        // the next message is checked against the chain that was stored
+        assert_eq!(report.chain_breaks[1].nonce, 3);
+    }
+
+    #[test]
+    fn test_check_entries_keeps_the_chain() {
+        // the duplicate row is older but does not continue the chain
+        let mut entries = schedule(3);
+        entries.insert(1, entry(0, 1, 9, "forked"));
+        let (report, conflicting) = check_entries(PROCESS_ID, &entries);
+        assert_eq!(conflicting, vec![0]);
+        assert!(report.chain_breaks.is_empty());
+
+        // neither continues it so the oldest row is kept
+        let mut entries = schedule(2);
+        entries.push(entry(20, 1, 9, "forked"));
+        entries[1].hash_chain = "also forked".to_string();
+        let (report, conflicting) = check_entries(PROCESS_ID, &entries);
+        assert_eq!(conflicting, vec![20]);
+        assert_eq!(report.chain_breaks.len(), 1);
+        assert_eq!(report.chain_breaks[0].message_id, "message-2");
+    }
+
+    #[test]
+    fn test_check_entries_new_epoch() {
+        let mut entries = schedule(3);
+        entries[2].epoch = 1;
+        entries[2].nonce = 0;
+        let (report, _) = check_entries(PROCESS_ID, &entries);
+        assert!(report.gaps.is_empty());
+
+        entries[2].nonce = 1;
+        let (report, _) = check_entries(PROCESS_ID, &entries);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].epoch, 1);
+        assert_eq!(report.gaps[0].expected, 0);
+    }
+
+    #[test]
+    fn test_schedule_checker_pages() {
+        // a duplicate nonce, so some page ends between the rows of one nonce
+        let mut entries = schedule(5);
+        entries.insert(3, entry(10, 2, 9, "forked"));
+        entries.remove(4);
+
+        for split in 0..=entries.len() {
+            let mut checker = ScheduleChecker::new(PROCESS_ID);
+            checker.add(entries[..split].to_vec());
+            checker.add(entries[split..].to_vec());
+            let (report, conflicting) = checker.finish();
+
+            assert_eq!(report.messages, 5);
+            assert_eq!(report.duplicates.len(), 1);
+            assert_eq!(report.duplicates[0].message_ids.len(), 2);
+            assert_eq!(report.gaps.len(), 1);
+            assert_eq!(report.gaps[0].found, 4);
+            assert!(report.chain_breaks.is_empty());
+            let row_ids: Vec<i32> = conflicting.iter().map(|entry| entry.row_id).collect();
+            assert_eq!(row_ids, vec![10]);
+        }
+    }
+
+    #[test]
+    fn test_consistency_state() {
+        let state = ConsistencyState::new();
+        let mut entries = schedule(3);
+        entries.remove(1);
+        state.record(check_entries("b", &entries).0);
+        state.record(check_entries("a", &entries).0);
+        state.record(check_entries("c", &[]).0);
+
+        let summary = state.summary();
+        let ids: Vec<&str> = summary
+            .reports
+            .iter()
+            .map(|report| report.process_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        // a repaired process drops out of the summary
+        state.record(check_entries("a", &[]).0);
+        assert_eq!(state.summary().reports.len(), 1);
+    }
+
+    #[test]
+    fn test_check_mode() {
+        assert!(check_mode("su").is_ok());
+        assert!(check_mode("router").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use async_trait::async_trait;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+pub use super::deferred::DeferredAssignment;
 pub use super::json::{JsonErrorType, Message, PaginatedMessages, Process};
 pub use super::router::{ProcessScheduler, Scheduler};
 
@@ -9,7 +11,7 @@ Interfaces for core dependencies. Implement these traits
 in clients etc... to inject side effects into the core
 */
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct NetworkInfo {
     pub height: String,
     pub current: String,
@@ -25,6 +27,8 @@ pub struct TxStatus {
 pub trait Gateway: Send + Sync {
     async fn check_head(&self, tx_id: String) -> Result<bool, String>;
     async fn network_info(&self) -> Result<NetworkInfo, String>;
+    // fetch network info now instead of waiting for the next refresh
+    async fn refresh_network_info(&self) -> Result<NetworkInfo, String>;
     async fn status(&self, tx_id: &String) -> Result<TxStatus, String>;
 }
 
@@ -37,6 +41,7 @@ pub trait Wallet: Send + Sync {
 pub trait Signer: Send + Sync {
     async fn sign_tx(&self, buffer: Vec<u8>) -> Result<Vec<u8>, String>;
     fn get_public_key(&self) -> Vec<u8>;
+    fn verify(&self, pub_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String>;
 }
 
 pub trait Log: Send + Sync {
@@ -57,6 +62,17 @@ pub trait Config: Send + Sync {
     fn gateway_url(&self) -> String;
     fn mode(&self) -> String;
     fn scheduler_list_path(&self) -> String;
+    fn deferred_assignment_interval(&self) -> u64;
+    fn shutdown_timeout(&self) -> u64;
+    fn clock_mode(&self) -> String;
+    fn replica_lag_threshold(&self) -> u64;
+    fn cold_storage(&self) -> String;
+    fn cold_storage_age(&self) -> u64;
+    fn admin_token(&self) -> Option<String>;
+    fn su_wallet_paths(&self) -> Vec<String>;
+    fn consistency_check_interval(&self) -> u64;
+    fn su_instance_id(&self) -> Option<String>;
+    fn lease_ttl(&self) -> u64;
 }
 
 #[derive(Debug)]
@@ -70,8 +86,59 @@ impl From<UploaderErrorType> for String {
     }
 }
 
+pub struct BacklogBundle {
+    pub tx: Vec<u8>,
+    // ran out of attempts, only a retry uploads it again
+    pub failed: bool,
+}
+
+#[async_trait]
 pub trait Uploader: Send + Sync {
     fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType>;
+    // upload a bundle persisted by an earlier shutdown, on_uploaded runs once it succeeds
+    fn upload_persisted(
+        &self,
+        tx: Vec<u8>,
+        on_uploaded: Box<dyn FnOnce() + Send + Sync>,
+    ) -> Result<(), UploaderErrorType>;
+    // bundles that have not been successfully uploaded yet
+    fn backlog(&self) -> Vec<BacklogBundle>;
+    // the backlog without the bundles that are already persisted
+    fn unpersisted_backlog(&self) -> Vec<Vec<u8>>;
+    /*
+        start another round of attempts for the bundles in the
+        backlog no upload is working on, returns how many
+    */
+    fn retry_backlog(&self) -> usize;
+    // stop starting attempts and wait for the ones in flight to finish
+    async fn stop(&self);
+}
+
+pub struct PendingUpload {
+    pub row_id: Option<i32>,
+    pub bundle: Vec<u8>,
+    pub created_at: i64,
+}
+
+#[derive(Debug)]
+pub enum BlobErrorType {
+    BlobError(String),
+    NotFound(String),
+}
+
+impl From<BlobErrorType> for String {
+    fn from(error: BlobErrorType) -> Self {
+        format!("{:?}", error)
+    }
+}
+
+/*
+    cold storage for bundle bytes, keys are the
+    hex sha256 of the uncompressed bundle
+*/
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), BlobErrorType>;
+    fn get(&self, key: &str) -> Result<Vec<u8>, BlobErrorType>;
 }
 
 #[derive(Debug)]
@@ -82,20 +149,140 @@ pub enum StoreErrorType {
     EnvVarError(String),
     IntError(String),
     MessageExists(String),
+    // the (epoch, nonce) was already taken, another su wrote the process
+    ScheduleConflict(String),
+}
+
+/*
+    Where a read is served from. The read replica can lag
+    behind the primary, so a client that just wrote can
+    ask for the lsn returned from the write and the read
+    falls back to the primary if the replica is behind it.
+*/
+#[derive(Debug, Clone)]
+pub enum ReadConsistency {
+    Eventual,
+    AtLeast(String),
+    Primary,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PoolState {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PoolStats {
+    pub primary: PoolState,
+    pub read: PoolState,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReplicationStatus {
+    pub primary_lsn: String,
+    pub replica_lsn: String,
+    pub lag_bytes: u64,
+    pub lag_seconds: f64,
+}
+
+/*
+    narrows the messages of a process, every tag has to
+    be present with one of its values and owner is the
+    address that signed the message
+*/
+pub struct MessageFilter {
+    pub tags: Vec<(String, Vec<String>)>,
+    pub owner: Option<String>,
+}
+
+/*
+    the position of a message in a process, messages
+    stamped in the same millisecond are told apart by
+    their epoch and nonce
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageCursor {
+    pub timestamp: i64,
+    pub epoch: i32,
+    pub nonce: i32,
+}
+
+// the lease a write is made under, None when leases are off
+pub struct LeaseFence {
+    pub holder: String,
+    pub now: i64,
+}
+
+// where a message sits in its process schedule, read without the bundle
+#[derive(Clone)]
+pub struct ScheduleEntry {
+    pub row_id: i32,
+    pub message_id: String,
+    pub assignment_id: Option<String>,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub hash_chain: String,
+}
+
+// the last entry of a page, row_id orders the rows that share a nonce
+pub struct ScheduleCursor {
+    pub epoch: i32,
+    pub nonce: i32,
+    pub row_id: i32,
 }
 
 pub trait DataStore: Send + Sync {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
-    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType>;
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
+    fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+        scheduler_address_in: &str,
+    ) -> Result<String, StoreErrorType>;
+    /*
+        the address of the identity that schedules the process,
+        None for processes created before identities were stored
+    */
+    fn get_process_identity(&self, process_id_in: &str) -> Result<Option<String>, StoreErrorType>;
+    fn get_process(
+        &self,
+        process_id_in: &str,
+        consistency: &ReadConsistency,
+    ) -> Result<Process, StoreErrorType>;
+    /*
+        with a lease the message is only saved if the holder
+        still has the lease on its process at now, otherwise
+        it is a ScheduleConflict
+    */
+    fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+        lease: &Option<LeaseFence>,
+    ) -> Result<String, StoreErrorType>;
     fn get_messages(
         &self,
         process_id_in: &str,
         from: &Option<String>,
         to: &Option<String>,
         limit: &Option<i32>,
+        consistency: &ReadConsistency,
+    ) -> Result<PaginatedMessages, StoreErrorType>;
+    // messages after the cursor matching the filter, oldest first
+    fn search_messages(
+        &self,
+        process_id_in: &str,
+        after: &Option<MessageCursor>,
+        first: &Option<i32>,
+        filter: &MessageFilter,
+        consistency: &ReadConsistency,
     ) -> Result<PaginatedMessages, StoreErrorType>;
-    fn get_message(&self, message_id_in: &str) -> Result<Message, StoreErrorType>;
+    fn get_message(
+        &self,
+        message_id_in: &str,
+        consistency: &ReadConsistency,
+    ) -> Result<Message, StoreErrorType>;
     fn get_latest_message(&self, process_id_in: &str) -> Result<Option<Message>, StoreErrorType>;
     fn save_process_scheduler(
         &self,
@@ -111,4 +298,65 @@ pub trait DataStore: Send + Sync {
     fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType>;
     fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType>;
     fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType>;
+    fn save_deferred_assignment(
+        &self,
+        deferred: &DeferredAssignment,
+    ) -> Result<String, StoreErrorType>;
+    fn update_deferred_assignment(
+        &self,
+        deferred: &DeferredAssignment,
+    ) -> Result<String, StoreErrorType>;
+    fn get_deferred_assignment(
+        &self,
+        ticket_in: &str,
+    ) -> Result<DeferredAssignment, StoreErrorType>;
+    fn get_pending_deferred_assignments(&self) -> Result<Vec<DeferredAssignment>, StoreErrorType>;
+    fn save_pending_upload(&self, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
+    fn get_pending_uploads(&self) -> Result<Vec<PendingUpload>, StoreErrorType>;
+    fn delete_pending_upload(&self, row_id_in: &i32) -> Result<String, StoreErrorType>;
+    fn current_lsn(&self) -> Result<String, StoreErrorType>;
+    fn replication_status(&self) -> Result<ReplicationStatus, StoreErrorType>;
+    /*
+        move the bundles of messages older than the timestamp
+        to cold storage, returns how many were moved
+    */
+    fn archive_bundles(&self, older_than: i64, limit: i64) -> Result<usize, StoreErrorType>;
+    fn pool_stats(&self) -> PoolStats;
+    // drop the connection pools, every later query fails
+    fn close(&self);
+    // a page of process ids in id order, after is the last id of the previous page
+    fn get_process_ids(
+        &self,
+        after: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType>;
+    // a page of the messages of a process in schedule order, after the cursor
+    fn get_schedule_entries(
+        &self,
+        process_id_in: &str,
+        after: &Option<ScheduleCursor>,
+        limit: i64,
+        consistency: &ReadConsistency,
+    ) -> Result<Vec<ScheduleEntry>, StoreErrorType>;
+    /*
+        move message rows out of the schedule into
+        quarantined_messages so they are no longer served
+    */
+    fn quarantine_messages(&self, row_ids: &[i32], reason: &str) -> Result<usize, StoreErrorType>;
+    /*
+        build the unique (process_id, epoch, nonce) index if it
+        is missing, false while a schedule still has a duplicate
+    */
+    fn create_nonce_index(&self) -> Result<bool, StoreErrorType>;
+    /*
+        take or renew the lease on a process until expires_at,
+        false if another holder has a lease that has not expired
+    */
+    fn claim_lease(
+        &self,
+        process_id_in: &str,
+        holder_in: &str,
+        now: i64,
+        expires_at_in: i64,
+    ) -> Result<bool, StoreErrorType>;
 }
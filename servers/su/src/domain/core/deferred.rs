@@ -0,0 +1,270 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::rand::SecureRandom;
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use utoipa::ToSchema;
+
+use super::dal::ReadConsistency;
+use super::flows::{init_builder, schedule_assignment, Deps};
+use super::responses::{to_json, DeferredTicket};
+
+/*
+    Base layer assignments that do not yet have enough
+    confirmations can be deferred instead of rejected.
+    The request is saved with a ticket and a background
+    watcher schedules it once the Settlement-Depth
+    of the process is reached.
+*/
+
+// give up on a deferred assignment after 24 hours
+const DEFERRED_ASSIGNMENT_TTL: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct DeferredAssignment {
+    #[serde(skip_serializing)]
+    pub row_id: Option<i32>,
+    pub ticket: String,
+    pub process_id: String,
+    pub assign: String,
+    pub exclude: Option<String>,
+    pub status: String,
+    pub assignment_id: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: i64,
+}
+
+impl DeferredAssignment {
+    pub const PENDING: &'static str = "pending";
+    pub const SCHEDULED: &'static str = "scheduled";
+    pub const FAILED: &'static str = "failed";
+}
+
+#[derive(Debug, PartialEq)]
+enum DeferredAction {
+    Schedule,
+    Wait,
+    Expire,
+}
+
+/*
+    a gateway error usually means the tx is not mined
+    yet, so it waits like an unconfirmed tx does until
+    the assignment times out
+*/
+fn next_action(reached: &Result<bool, String>, created_at: i64, now: i64) -> DeferredAction {
+    match reached {
+        Ok(true) => DeferredAction::Schedule,
+        _ if now - created_at > DEFERRED_ASSIGNMENT_TTL => DeferredAction::Expire,
+        _ => DeferredAction::Wait,
+    }
+}
+
+fn gen_ticket() -> Result<String, String> {
+    let mut randoms: [u8; 32] = [0; 32];
+    let sr = ring::rand::SystemRandom::new();
+    sr.fill(&mut randoms).map_err(|e| e.to_string())?;
+    Ok(base64_url::encode(&randoms))
+}
+
+fn system_time_i64() -> Result<i64, String> {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("{:?}", e))?;
+    Ok(duration.as_millis() as i64)
+}
+
+/*
+    save the assignment request and return a
+    ticket the client can use to check on it
+*/
+pub async fn defer_assignment(
+    deps: Arc<Deps>,
+    process_id: String,
+    assign: String,
+    exclude: Option<String>,
+) -> Result<String, String> {
+    let deferred = DeferredAssignment {
+        row_id: None,
+        ticket: gen_ticket()?,
+        process_id,
+        assign,
+        exclude,
+        status: DeferredAssignment::PENDING.to_string(),
+        assignment_id: None,
+        error_message: None,
+        created_at: system_time_i64()?,
+    };
+
+    deps.data_store.save_deferred_assignment(&deferred)?;
+    deps.logger.log(format!(
+        "deferred assignment of {} to {} - ticket {}",
+        &deferred.assign, &deferred.process_id, &deferred.ticket
+    ));
+
+    to_json(&DeferredTicket {
+        ticket: deferred.ticket,
+        status: deferred.status,
+    })
+}
+
+pub async fn read_deferred_assignment(deps: Arc<Deps>, ticket: String) -> Result<String, String> {
+    let deferred = deps.data_store.get_deferred_assignment(&ticket)?;
+    let result = match serde_json::to_string(&deferred) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+    Ok(result)
+}
+
+/*
+    check every pending assignment, scheduling the ones that
+    have reached the settlement depth of their process
+*/
+pub async fn check_deferred_assignments(deps: Arc<Deps>) -> Result<(), String> {
+    let builder = init_builder(&deps)?;
+    let pending = deps.data_store.get_pending_deferred_assignments()?;
+    let now = system_time_i64()?;
+
+    for mut deferred in pending {
+        let process = match deps
+            .data_store
+            .get_process(&deferred.process_id, &ReadConsistency::Primary)
+        {
+            Ok(p) => p,
+            Err(e) => {
+                deps.logger.error(format!(
+                    "deferred assignment {} process lookup failed - {:?}",
+                    &deferred.ticket, e
+                ));
+                continue;
+            }
+        };
+
+        let reached = builder
+            .settlement_reached(&deferred.assign, &process)
+            .await
+            .map_err(|e| format!("{:?}", e));
+        if let Err(e) = &reached {
+            deps.logger.error(format!(
+                "deferred assignment {} settlement check failed - {}",
+                &deferred.ticket, e
+            ));
+        }
+
+        match next_action(&reached, deferred.created_at, now) {
+            DeferredAction::Schedule => (),
+            DeferredAction::Wait => continue,
+            DeferredAction::Expire => {
+                deferred.status = DeferredAssignment::FAILED.to_string();
+                deferred.error_message = Some(match reached {
+                    Err(e) => format!("Timed out waiting for confirmations - {}", e),
+                    Ok(_) => "Timed out waiting for confirmations".to_string(),
+                });
+                deps.data_store.update_deferred_assignment(&deferred)?;
+                continue;
+            }
+        }
+
+        // leave it pending for the next startup if we are shutting down
+        let _write_guard = match deps.shutdown.begin_write() {
+            Ok(guard) => guard,
+            Err(_) => return Ok(()),
+        };
+
+        match schedule_assignment(
+            deps.clone(),
+            deferred.process_id.clone(),
+            deferred.assign.clone(),
+            Some("".to_string()),
+            deferred.exclude.clone(),
+        )
+        .await
+        {
+            Ok(message) => {
+                deferred.status = DeferredAssignment::SCHEDULED.to_string();
+                deferred.assignment_id = Some(message.assignment.id.clone());
+            }
+            Err(e) => {
+                deps.logger.error(format!(
+                    "deferred assignment {} failed - {}",
+                    &deferred.ticket, e
+                ));
+                deferred.status = DeferredAssignment::FAILED.to_string();
+                deferred.error_message = Some(e);
+            }
+        }
+        deps.data_store.update_deferred_assignment(&deferred)?;
+    }
+
+    Ok(())
+}
+
+/*
+    runs at server startup in su mode, polls the
+    gateway for pending deferred assignments. The
+    builder futures are not Send so this runs on
+    the local set of the server's runtime
+*/
+pub fn init_deferred_watcher(deps: Arc<Deps>) {
+    let interval = deps.config.deferred_assignment_interval();
+    tokio::task::spawn_local(async move {
+        loop {
+            sleep(Duration::from_secs(interval)).await;
+            if deps.shutdown.is_draining() {
+                break;
+            }
+            if let Err(e) = check_deferred_assignments(deps.clone()).await {
+                deps.logger
+                    .error(format!("error checking deferred assignments - {}", e));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_action() {
+        let now = 1_700_000_000_000;
+
+        assert_eq!(next_action(&Ok(true), now, now), DeferredAction::Schedule);
+        assert_eq!(
+            next_action(&Ok(false), now - 1000, now),
+            DeferredAction::Wait
+        );
+        // the gateway does not know the tx yet
+        assert_eq!(
+            next_action(&Err("404".to_string()), now - 1000, now),
+            DeferredAction::Wait
+        );
+
+        let expired = now - DEFERRED_ASSIGNMENT_TTL - 1;
+        assert_eq!(
+            next_action(&Ok(false), expired, now),
+            DeferredAction::Expire
+        );
+        assert_eq!(
+            next_action(&Err("404".to_string()), expired, now),
+            DeferredAction::Expire
+        );
+        // settled just in time is still scheduled
+        assert_eq!(
+            next_action(&Ok(true), expired, now),
+            DeferredAction::Schedule
+        );
+    }
+
+    #[test]
+    fn test_gen_ticket() {
+        let ticket = gen_ticket().expect("failed to generate ticket");
+        assert_eq!(
+            base64_url::decode(&ticket).expect("invalid ticket").len(),
+            32
+        );
+        assert_ne!(ticket, gen_ticket().expect("failed to generate ticket"));
+    }
+}
@@ -2,13 +2,28 @@ use std::sync::Arc;
 use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
 
 use dotenv::dotenv;
-use serde_json::json;
+use tokio::time::{sleep, Duration};
+use tracing::{field, info_span, instrument, Instrument, Span};
 
 use super::builder::Builder;
+use super::bytes::{DataBundle, DataItem};
+use super::consistency::ConsistencyState;
+use super::deferred;
+use super::identities::{self, Identities};
 use super::json::{Message, Process};
+use super::metrics::Metrics;
+use super::receipt::{sign_receipt, verify_receipt, Receipt, SignedReceipt};
+use super::responses::{
+    to_json, BundleItemResult, BundleResponse, HealthResponse, TimestampResponse,
+    VerifyReceiptResponse, WriteReceipt,
+};
 use super::scheduler;
+use super::shutdown::ShutdownState;
+use super::validation;
 
-use super::dal::{Config, DataStore, Gateway, Log, Signer, Uploader, Wallet};
+use super::dal::{
+    Config, DataStore, Gateway, Log, ReadConsistency, Signer, StoreErrorType, Uploader, Wallet,
+};
 
 pub struct Deps {
     pub data_store: Arc<dyn DataStore>,
@@ -26,6 +41,17 @@ pub struct Deps {
         dependencies injected.
     */
     pub scheduler: Arc<scheduler::ProcessScheduler>,
+
+    // every scheduler identity this su signs for, signer is the default
+    pub identities: Arc<Identities>,
+
+    // tracks in flight writes for graceful shutdown
+    pub shutdown: Arc<ShutdownState>,
+
+    pub metrics: Arc<Metrics>,
+
+    // the latest results of the background schedule checks
+    pub consistency: Arc<ConsistencyState>,
 }
 
 /*
@@ -38,6 +64,12 @@ pub fn init_builder(deps: &Arc<Deps>) -> Result<Builder, String> {
     return Ok(builder);
 }
 
+// a builder that signs as one of the hosted scheduler identities
+fn init_builder_with(deps: &Arc<Deps>, signer: Arc<dyn Signer>) -> Result<Builder<'_>, String> {
+    let builder = Builder::new(deps.gateway.clone(), signer, &deps.logger)?;
+    Ok(builder)
+}
+
 async fn upload(deps: &Arc<Deps>, build_result: Vec<u8>) -> Result<String, String> {
     let uploaded_tx = &deps.uploader.upload(build_result)?;
     let result = match serde_json::to_string(&uploaded_tx) {
@@ -47,23 +79,41 @@ async fn upload(deps: &Arc<Deps>, build_result: Vec<u8>) -> Result<String, Strin
     Ok(result)
 }
 
-async fn assignment_only(
+/*
+    build, save and upload an assignment of an existing
+    message or base layer tx to the process
+*/
+#[instrument(
+    name = "write",
+    skip_all,
+    fields(process_id = %process_id, message_id = %assign, nonce = field::Empty)
+)]
+pub async fn schedule_assignment(
     deps: Arc<Deps>,
     process_id: String,
     assign: String,
     base_layer: Option<String>,
     exclude: Option<String>,
-) -> Result<String, String> {
-    let builder = init_builder(&deps)?;
+) -> Result<Message, String> {
+    let signer = identities::signer_for_process(&deps, &process_id)?;
+    let builder = init_builder_with(&deps, signer)?;
 
-    let locked_schedule_info = deps.scheduler.acquire_lock(process_id.clone()).await?;
-    let mut schedule_info = locked_schedule_info.lock().await;
+    let lock_span = info_span!("lock");
+    let locked_schedule_info = deps
+        .scheduler
+        .acquire_lock(process_id.clone())
+        .instrument(lock_span.clone())
+        .await?;
+    let mut schedule_info = locked_schedule_info.lock().instrument(lock_span).await;
     let updated_info = deps
         .scheduler
-        .update_schedule_info(&mut *schedule_info, process_id.clone())
+        .update_schedule_info(&mut schedule_info, process_id.clone())
         .await?;
+    Span::current().record("nonce", updated_info.nonce);
 
-    let process = deps.data_store.get_process(&process_id)?;
+    let process = deps
+        .data_store
+        .get_process(&process_id, &ReadConsistency::Primary)?;
     let build_result = builder
         .build_assignment(
             assign.clone(),
@@ -72,22 +122,93 @@ async fn assignment_only(
             &base_layer,
             &exclude,
         )
+        .instrument(info_span!("build"))
         .await?;
 
     let message = Message::from_bundle(&build_result.bundle)?;
-    deps.data_store
-        .save_message(&message, &build_result.binary)?;
-    deps.logger.log(format!("saved message - {:?}", &message));
-    upload(&deps, build_result.binary.to_vec()).await?;
+    info_span!("save").in_scope(|| deps.scheduler.save_message(&message, &build_result.binary))?;
+    deps.logger.log("saved assignment".to_string());
+    upload(&deps, build_result.binary.to_vec())
+        .instrument(info_span!("upload"))
+        .await?;
     drop(schedule_info);
 
-    match system_time_u64() {
-        Ok(timestamp) => {
-            let response_json =
-                json!({ "timestamp": timestamp, "id": message.assignment.id.clone() });
-            Ok(response_json.to_string())
+    Ok(message)
+}
+
+async fn assignment_only(
+    deps: Arc<Deps>,
+    process_id: String,
+    assign: String,
+    base_layer: Option<String>,
+    exclude: Option<String>,
+    defer: Option<String>,
+) -> Result<String, String> {
+    /*
+        if the client asked to defer a base layer assignment
+        and it doesnt have enough confirmations yet, save
+        it and return a ticket instead of an error
+    */
+    if base_layer.is_some() && defer.is_some() {
+        let reached = {
+            let builder = init_builder(&deps)?;
+            let process = deps
+                .data_store
+                .get_process(&process_id, &ReadConsistency::Primary)?;
+            match builder.settlement_reached(&assign, &process).await {
+                Ok(reached) => reached,
+                /*
+                    the gateway does not know a tx that is not mined
+                    yet, the watcher keeps checking until it times out
+                */
+                Err(e) => {
+                    deps.logger.error(format!(
+                        "settlement check of {} failed, deferring - {:?}",
+                        &assign, e
+                    ));
+                    false
+                }
+            }
+        };
+        if !reached {
+            return deferred::defer_assignment(deps, process_id, assign, exclude).await;
         }
-        Err(e) => Err(format!("{:?}", e)),
+    }
+
+    let signer = identities::signer_for_process(&deps, &process_id)?;
+    let message =
+        schedule_assignment(deps.clone(), process_id, assign, base_layer, exclude).await?;
+    let receipt = receipt_response(&deps, &signer, Receipt::from_message(&message)?)
+        .instrument(info_span!("sign"))
+        .await?;
+    to_json(&receipt)
+}
+
+/*
+    the response to a write is a receipt signed by
+    the scheduler identity of the process covering
+    its place in the schedule
+*/
+async fn receipt_response(
+    deps: &Arc<Deps>,
+    signer: &Arc<dyn Signer>,
+    receipt: Receipt,
+) -> Result<WriteReceipt, String> {
+    let signed_receipt = sign_receipt(signer, receipt).await?;
+    /*
+        the lsn is not signed, a client passes it back
+        as min-lsn to read its own write from the replica
+    */
+    Ok(WriteReceipt {
+        signed_receipt,
+        lsn: deps.data_store.current_lsn()?,
+    })
+}
+
+fn read_consistency(min_lsn: Option<String>) -> ReadConsistency {
+    match min_lsn {
+        Some(lsn) => ReadConsistency::AtLeast(lsn),
+        None => ReadConsistency::Eventual,
     }
 }
 
@@ -96,7 +217,8 @@ async fn assignment_only(
     it detects which it is creating by the tags.
     If the process_id and assign params are set, it
     follows the Assignment flow instead. If one is
-    set both must be set.
+    set both must be set. defer only applies to
+    base layer assignments.
 */
 pub async fn write_item(
     deps: Arc<Deps>,
@@ -105,107 +227,198 @@ pub async fn write_item(
     assign: Option<String>,
     base_layer: Option<String>,
     exclude: Option<String>,
+    defer: Option<String>,
 ) -> Result<String, String> {
+    /*
+        held until the write is saved and queued for
+        upload, so a shutdown waits for it to finish
+    */
+    let _write_guard = deps.shutdown.begin_write()?;
+
     // XOR, if we have one of these, we must have both.
     if process_id.is_some() ^ assign.is_some() {
         return Err("If sending assign or process-id, you must send both.".to_string());
     } else if let (Some(process_id), Some(assign)) = (process_id, assign) {
-        return assignment_only(deps, process_id, assign, base_layer, exclude).await;
+        return assignment_only(deps, process_id, assign, base_layer, exclude, defer).await;
     }
 
     let builder = init_builder(&deps)?;
 
     let data_item = builder.parse_data_item(input.clone())?;
 
-    let tags = data_item.tags().clone();
-    let type_tag = tags.iter().find(|tag| tag.name == "Type");
-    let proto_tag_exists = tags.iter().any(|tag| tag.name == "Data-Protocol");
-    if !proto_tag_exists {
-        return Err("Data-Protocol tag not present".to_string());
+    if is_bundle(&data_item) {
+        return write_bundle(&deps, data_item).await;
     }
 
-    if let Some(type_tag) = type_tag {
-        if type_tag.value == "Process" {
-            let mod_tag_exists = tags.iter().any(|tag| tag.name == "Module");
-            let sched_tag_exists = tags.iter().any(|tag| tag.name == "Scheduler");
+    to_json(&write_data_item(&deps, input, data_item).await?)
+}
 
-            if !mod_tag_exists || !sched_tag_exists {
-                return Err(
-                    "Required Module and Scheduler tags for Process type not present".to_string(),
-                );
-            }
+// schedule a single Process or Message data item
+#[instrument(
+    name = "write",
+    skip_all,
+    fields(process_id = field::Empty, message_id = %data_item.id(), nonce = field::Empty)
+)]
+async fn write_data_item(
+    deps: &Arc<Deps>,
+    input: Vec<u8>,
+    data_item: DataItem,
+) -> Result<WriteReceipt, String> {
+    // checked before locking so a bad item never holds up a process
+    let item_type = validation::check_item(deps, &data_item)?;
 
-            /*
-                acquire the mutex locked scheduling info for the
-                process we are creating. So if a message is written
-                while the process is still being created it will wait
-            */
-            let locked_schedule_info = deps.scheduler.acquire_lock(data_item.id()).await?;
-            let mut schedule_info = locked_schedule_info.lock().await;
-            let updated_info = deps
-                .scheduler
-                .update_schedule_info(&mut *schedule_info, data_item.id())
-                .await?;
-
-            let build_result = builder.build_process(input, &*updated_info).await?;
-            upload(&deps, build_result.binary.to_vec()).await?;
-            let process = Process::from_bundle(&build_result.bundle)?;
-            deps.data_store
-                .save_process(&process, &build_result.binary)?;
-            deps.logger.log(format!("saved process - {:?}", &process));
-            drop(schedule_info);
-            match system_time_u64() {
-                Ok(timestamp) => {
-                    let response_json =
-                        json!({ "timestamp": timestamp, "id": process.process_id.clone() });
-                    Ok(response_json.to_string())
-                }
-                Err(e) => Err(format!("{:?}", e)),
-            }
-        } else if type_tag.value == "Message" {
-            /*
-                acquire the mutex locked scheduling info for the
-                process we are writing a message to. this ensures
-                no conflicts in the schedule
-            */
-            let locked_schedule_info = deps.scheduler.acquire_lock(data_item.target()).await?;
-            let mut schedule_info = locked_schedule_info.lock().await;
-            let updated_info = deps
-                .scheduler
-                .update_schedule_info(&mut *schedule_info, data_item.target())
-                .await?;
-
-            let build_result = builder.build_message(input, &*updated_info).await?;
-            let message = Message::from_bundle(&build_result.bundle)?;
+    if item_type == "Process" {
+        /*
+            validation checked the Scheduler tag is one of
+            our identities, it signs for the process from now on
+        */
+        let scheduler_address = data_item
+            .tags()
+            .iter()
+            .find(|tag| tag.name == "Scheduler")
+            .map(|tag| tag.value.clone())
+            .ok_or("Scheduler tag not present")?;
+        let signer = identities::signer_for_scheduler(deps, &scheduler_address)?;
+        let builder = init_builder_with(deps, signer.clone())?;
+        Span::current().record("process_id", data_item.id().as_str());
+
+        /*
+            acquire the mutex locked scheduling info for the
+            process we are creating. So if a message is written
+            while the process is still being created it will wait
+        */
+        let lock_span = info_span!("lock");
+        let locked_schedule_info = deps
+            .scheduler
+            .acquire_lock(data_item.id())
+            .instrument(lock_span.clone())
+            .await?;
+        let mut schedule_info = locked_schedule_info.lock().instrument(lock_span).await;
+        let updated_info = deps
+            .scheduler
+            .update_schedule_info(&mut schedule_info, data_item.id())
+            .await?;
+        Span::current().record("nonce", updated_info.nonce);
+
+        let build_result = builder
+            .build_process(input, &*updated_info)
+            .instrument(info_span!("build"))
+            .await?;
+        upload(deps, build_result.binary.to_vec())
+            .instrument(info_span!("upload"))
+            .await?;
+        let process = Process::from_bundle(&build_result.bundle)?;
+        info_span!("save").in_scope(|| {
             deps.data_store
-                .save_message(&message, &build_result.binary)?;
-            deps.logger.log(format!("saved message - {:?}", &message));
-            upload(&deps, build_result.binary.to_vec()).await?;
-            drop(schedule_info);
-            match system_time_u64() {
-                Ok(timestamp) => {
-                    let response_json =
-                        json!({ "timestamp": timestamp, "id": message.message_id()? });
-                    Ok(response_json.to_string())
-                }
-                Err(e) => Err(format!("{:?}", e)),
-            }
-        } else {
-            return Err("Type tag not present".to_string());
-        }
+                .save_process(&process, &build_result.binary, &scheduler_address)
+        })?;
+        deps.logger.log("saved process".to_string());
+        drop(schedule_info);
+        receipt_response(deps, &signer, Receipt::from_process(&process))
+            .instrument(info_span!("sign"))
+            .await
     } else {
-        return Err("Type tag not present".to_string());
+        /*
+            acquire the mutex locked scheduling info for the
+            process we are writing a message to. this ensures
+            no conflicts in the schedule
+        */
+        let signer = identities::signer_for_process(deps, &data_item.target())?;
+        let builder = init_builder_with(deps, signer.clone())?;
+        Span::current().record("process_id", data_item.target().as_str());
+
+        let lock_span = info_span!("lock");
+        let locked_schedule_info = deps
+            .scheduler
+            .acquire_lock(data_item.target())
+            .instrument(lock_span.clone())
+            .await?;
+        let mut schedule_info = locked_schedule_info.lock().instrument(lock_span).await;
+        let updated_info = deps
+            .scheduler
+            .update_schedule_info(&mut schedule_info, data_item.target())
+            .await?;
+        Span::current().record("nonce", updated_info.nonce);
+
+        let build_result = builder
+            .build_message(input, &*updated_info)
+            .instrument(info_span!("build"))
+            .await?;
+        let message = Message::from_bundle(&build_result.bundle)?;
+        info_span!("save")
+            .in_scope(|| deps.scheduler.save_message(&message, &build_result.binary))?;
+        deps.logger.log("saved message".to_string());
+        upload(deps, build_result.binary.to_vec())
+            .instrument(info_span!("upload"))
+            .await?;
+        drop(schedule_info);
+        receipt_response(deps, &signer, Receipt::from_message(&message)?)
+            .instrument(info_span!("sign"))
+            .await
     }
 }
 
+pub fn is_bundle(data_item: &DataItem) -> bool {
+    data_item
+        .tags()
+        .iter()
+        .any(|tag| tag.name == "Bundle-Format" && tag.value == "binary")
+}
+
+/*
+    a bundler or mu can post many items at once inside a
+    Bundle-Format binary data item. Each item is scheduled
+    in order as if it had been posted on its own, one
+    failing does not stop the ones after it
+*/
+async fn write_bundle(deps: &Arc<Deps>, data_item: DataItem) -> Result<String, String> {
+    let bundle = DataBundle::from_bytes(&data_item.data_bytes())
+        .map_err(|e| format!("Invalid bundle: {:?}", e))?;
+
+    let mut results = vec![];
+    for item in bundle.items {
+        let id = item.id();
+        let result = match item.as_bytes() {
+            Ok(bytes) => write_data_item(deps, bytes, item).await,
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        let item_result = match result {
+            Ok(receipt) => BundleItemResult {
+                id,
+                receipt: Some(receipt),
+                error: None,
+            },
+            Err(e) => BundleItemResult {
+                id,
+                receipt: None,
+                error: Some(e),
+            },
+        };
+        results.push(item_result);
+    }
+
+    deps.logger.log(format!(
+        "scheduled bundle {} with {} items",
+        data_item.id(),
+        results.len()
+    ));
+    to_json(&BundleResponse {
+        id: data_item.id(),
+        items: results,
+    })
+}
+
 pub async fn read_message_data(
     deps: Arc<Deps>,
     tx_id: String,
     from: Option<String>,
     to: Option<String>,
     limit: Option<i32>,
+    min_lsn: Option<String>,
 ) -> Result<String, String> {
-    if let Ok(message) = deps.data_store.get_message(&tx_id) {
+    let consistency = read_consistency(min_lsn);
+
+    if let Ok(message) = deps.data_store.get_message(&tx_id, &consistency) {
         let result = match serde_json::to_string(&message) {
             Ok(r) => r,
             Err(e) => return Err(format!("{:?}", e)),
@@ -213,8 +426,10 @@ pub async fn read_message_data(
         return Ok(result);
     }
 
-    if let Ok(_) = deps.data_store.get_process(&tx_id) {
-        let messages = deps.data_store.get_messages(&tx_id, &from, &to, &limit)?;
+    if deps.data_store.get_process(&tx_id, &consistency).is_ok() {
+        let messages = deps
+            .data_store
+            .get_messages(&tx_id, &from, &to, &limit, &consistency)?;
         let result = match serde_json::to_string(&messages) {
             Ok(r) => r,
             Err(e) => return Err(format!("{:?}", e)),
@@ -225,8 +440,14 @@ pub async fn read_message_data(
     Err("Message or Process not found".to_string())
 }
 
-pub async fn read_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
-    let process = deps.data_store.get_process(&process_id)?;
+pub async fn read_process(
+    deps: Arc<Deps>,
+    process_id: String,
+    min_lsn: Option<String>,
+) -> Result<String, String> {
+    let process = deps
+        .data_store
+        .get_process(&process_id, &read_consistency(min_lsn))?;
     let result = match serde_json::to_string(&process) {
         Ok(r) => r,
         Err(e) => return Err(format!("{:?}", e)),
@@ -242,11 +463,29 @@ fn system_time() -> Result<String, SystemTimeError> {
     Ok(millis_string)
 }
 
-fn system_time_u64() -> Result<u64, SystemTimeError> {
-    let start_time = SystemTime::now();
-    let duration = start_time.duration_since(UNIX_EPOCH)?;
-    let millis = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
-    Ok(millis)
+/*
+    verify a receipt previously returned by
+    a su, the body is the receipt json
+*/
+pub async fn verify_receipt_json(deps: Arc<Deps>, input: Vec<u8>) -> Result<String, String> {
+    let signed_receipt: SignedReceipt = match serde_json::from_slice(&input) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("Invalid receipt: {:?}", e)),
+    };
+    let hosted_addresses = deps.identities.addresses();
+    let response = match verify_receipt(&deps.signer, &signed_receipt, &hosted_addresses) {
+        Ok(_) => VerifyReceiptResponse {
+            valid: true,
+            address: Some(signed_receipt.address),
+            error: None,
+        },
+        Err(e) => VerifyReceiptResponse {
+            valid: false,
+            address: None,
+            error: Some(format!("{:?}", e)),
+        },
+    };
+    to_json(&response)
 }
 
 pub async fn timestamp(deps: Arc<Deps>) -> Result<String, String> {
@@ -257,9 +496,10 @@ pub async fn timestamp(deps: Arc<Deps>) -> Result<String, String> {
                 Ok(info) => {
                     let height = info.height.clone();
                     let height_string = format!("{:0>12}", height);
-                    let response_json =
-                        json!({ "timestamp": timestamp, "block_height": height_string });
-                    Ok(response_json.to_string())
+                    to_json(&TimestampResponse {
+                        timestamp,
+                        block_height: height_string,
+                    })
                 }
                 Err(e) => Err(format!("{:?}", e)),
             }
@@ -275,9 +515,97 @@ pub async fn health(deps: Arc<Deps>) -> Result<String, String> {
                 Ok(w) => w,
                 Err(e) => return Err(e),
             };
-            let response_json = json!({ "timestamp": timestamp, "address": wallet_address });
-            Ok(response_json.to_string())
+            to_json(&HealthResponse {
+                timestamp,
+                address: wallet_address,
+                addresses: deps.identities.addresses(),
+            })
         }
         Err(e) => Err(format!("{:?}", e)),
     }
-}
\ No newline at end of file
+}
+
+pub async fn replication(deps: Arc<Deps>) -> Result<String, String> {
+    let status = deps.data_store.replication_status()?;
+    let result = match serde_json::to_string(&status) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+    Ok(result)
+}
+
+/*
+    runs at server startup in su mode, logs an error
+    whenever the read replica falls further behind the
+    primary than the configured threshold
+*/
+pub fn init_replication_monitor(deps: Arc<Deps>) {
+    let threshold = deps.config.replica_lag_threshold() as f64;
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(30)).await;
+            if deps.shutdown.is_draining() {
+                break;
+            }
+            match deps.data_store.replication_status() {
+                Ok(status) if status.lag_seconds > threshold => deps.logger.error(format!(
+                    "read replica is {:.1}s ({} bytes) behind the primary",
+                    status.lag_seconds, status.lag_bytes
+                )),
+                Ok(_) => (),
+                Err(e) => deps
+                    .logger
+                    .error(format!("error checking replication - {:?}", e)),
+            }
+        }
+    });
+}
+
+/*
+    runs at server startup in su mode when COLD_STORAGE
+    is set, moves bundles older than COLD_STORAGE_AGE out
+    of postgres in batches so the table stops growing
+*/
+pub fn init_bundle_archiver(deps: Arc<Deps>) {
+    let age_millis = deps.config.cold_storage_age() as i64 * 1000;
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(60)).await;
+            if deps.shutdown.is_draining() {
+                break;
+            }
+            let now = match system_time() {
+                Ok(t) => t.parse::<i64>().unwrap_or(0),
+                Err(_) => continue,
+            };
+            let data_store = deps.data_store.clone();
+            let archived = tokio::task::spawn_blocking(move || {
+                let mut total: usize = 0;
+                loop {
+                    let count = data_store.archive_bundles(now - age_millis, 100)?;
+                    total += count;
+                    if count < 100 {
+                        return Ok::<usize, StoreErrorType>(total);
+                    }
+                }
+            })
+            .await;
+            match archived {
+                Ok(Ok(0)) => (),
+                Ok(Ok(count)) => deps
+                    .logger
+                    .log(format!("moved {} bundles to cold storage", count)),
+                Ok(Err(e)) => deps
+                    .logger
+                    .error(format!("error archiving bundles - {:?}", e)),
+                Err(e) => deps
+                    .logger
+                    .error(format!("error archiving bundles - {:?}", e)),
+            }
+        }
+    });
+}
+
+pub async fn metrics(deps: Arc<Deps>) -> Result<String, String> {
+    Ok(deps.metrics.render())
+}
@@ -0,0 +1,391 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Request, Schema, SimpleObject,
+};
+use bundlr_sdk::tags::Tag;
+
+use super::dal::{MessageCursor, MessageFilter, ReadConsistency, StoreErrorType};
+use super::flows::Deps;
+use super::json::{AssignmentInner, Message, MessageInner, Owner, PaginatedMessages, Process};
+use super::responses::to_json;
+
+/*
+    a read only graphql view of the schedule in the style
+    of the arweave gateways, messages are a relay
+    connection shaped like PaginatedMessages
+*/
+
+pub type SuSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+// the most messages one query can page through at once
+const MAX_FIRST: i32 = 1000;
+const DEFAULT_FIRST: i32 = 100;
+
+#[derive(SimpleObject)]
+#[graphql(name = "Owner")]
+pub struct OwnerNode {
+    address: String,
+    key: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Tag")]
+pub struct TagNode {
+    name: String,
+    value: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Process")]
+pub struct ProcessNode {
+    id: String,
+    block: String,
+    owner: OwnerNode,
+    tags: Vec<TagNode>,
+    timestamp: i64,
+    data: Option<String>,
+    anchor: Option<String>,
+    signature: Option<String>,
+}
+
+// the data item that was posted, absent for an assignment of a base layer tx
+#[derive(SimpleObject)]
+#[graphql(name = "MessageItem")]
+pub struct MessageItemNode {
+    id: String,
+    owner: OwnerNode,
+    data: Option<String>,
+    tags: Vec<TagNode>,
+    signature: String,
+    anchor: Option<String>,
+    target: Option<String>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Assignment")]
+pub struct AssignmentNode {
+    id: String,
+    owner: OwnerNode,
+    tags: Vec<TagNode>,
+    signature: String,
+    anchor: Option<String>,
+    target: Option<String>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Message")]
+pub struct MessageNode {
+    message: Option<MessageItemNode>,
+    assignment: AssignmentNode,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "PageInfo")]
+pub struct PageInfoNode {
+    has_next_page: bool,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "MessageEdge")]
+pub struct EdgeNode {
+    node: MessageNode,
+    cursor: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "MessageConnection")]
+pub struct ConnectionNode {
+    page_info: PageInfoNode,
+    edges: Vec<EdgeNode>,
+}
+
+// a message matches when it has the tag with any of the values
+#[derive(InputObject)]
+pub struct TagFilter {
+    name: String,
+    values: Vec<String>,
+}
+
+fn owner_node(owner: Owner) -> OwnerNode {
+    OwnerNode {
+        address: owner.address,
+        key: owner.key,
+    }
+}
+
+fn tag_nodes(tags: Vec<Tag>) -> Vec<TagNode> {
+    tags.into_iter()
+        .map(|tag| TagNode {
+            name: tag.name,
+            value: tag.value,
+        })
+        .collect()
+}
+
+impl From<Process> for ProcessNode {
+    fn from(process: Process) -> Self {
+        ProcessNode {
+            id: process.process_id,
+            block: process.block,
+            owner: owner_node(process.owner),
+            tags: tag_nodes(process.tags),
+            timestamp: process.timestamp,
+            data: process.data,
+            anchor: process.anchor,
+            signature: process.signature,
+        }
+    }
+}
+
+impl From<MessageInner> for MessageItemNode {
+    fn from(message: MessageInner) -> Self {
+        MessageItemNode {
+            id: message.id,
+            owner: owner_node(message.owner),
+            data: message.data,
+            tags: tag_nodes(message.tags),
+            signature: message.signature,
+            anchor: message.anchor,
+            target: message.target,
+        }
+    }
+}
+
+impl From<AssignmentInner> for AssignmentNode {
+    fn from(assignment: AssignmentInner) -> Self {
+        AssignmentNode {
+            id: assignment.id,
+            owner: owner_node(assignment.owner),
+            tags: tag_nodes(assignment.tags),
+            signature: assignment.signature,
+            anchor: assignment.anchor,
+            target: assignment.target,
+        }
+    }
+}
+
+impl From<Message> for MessageNode {
+    fn from(message: Message) -> Self {
+        MessageNode {
+            message: message.message.map(MessageItemNode::from),
+            assignment: message.assignment.into(),
+        }
+    }
+}
+
+/*
+    a cursor is timestamp:epoch:nonce, the timestamp
+    alone is shared by messages stamped in the same
+    millisecond and a page could end between them
+*/
+fn format_cursor(cursor: &MessageCursor) -> String {
+    format!("{}:{}:{}", cursor.timestamp, cursor.epoch, cursor.nonce)
+}
+
+fn parse_cursor(cursor: &str) -> Result<MessageCursor, String> {
+    let invalid = || format!("Invalid cursor {}", cursor);
+    let parts: Vec<&str> = cursor.split(':').collect();
+    match parts.as_slice() {
+        [timestamp, epoch, nonce] => Ok(MessageCursor {
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            epoch: epoch.parse().map_err(|_| invalid())?,
+            nonce: nonce.parse().map_err(|_| invalid())?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn edge_cursor(message: &Message) -> Result<String, String> {
+    let cursor = MessageCursor {
+        timestamp: message.timestamp().map_err(|e| format!("{:?}", e))?,
+        epoch: message.epoch().map_err(|e| format!("{:?}", e))?,
+        nonce: message.nonce().map_err(|e| format!("{:?}", e))?,
+    };
+    Ok(format_cursor(&cursor))
+}
+
+impl TryFrom<PaginatedMessages> for ConnectionNode {
+    type Error = String;
+
+    fn try_from(paginated: PaginatedMessages) -> Result<Self, Self::Error> {
+        let mut edges = vec![];
+        for edge in paginated.edges {
+            edges.push(EdgeNode {
+                cursor: edge_cursor(&edge.node)?,
+                node: edge.node.into(),
+            });
+        }
+        Ok(ConnectionNode {
+            page_info: PageInfoNode {
+                has_next_page: paginated.page_info.has_next_page,
+            },
+            edges,
+        })
+    }
+}
+
+// a missing row is a null in graphql, not an error
+fn optional<T, N: From<T>>(result: Result<T, StoreErrorType>) -> async_graphql::Result<Option<N>> {
+    match result {
+        Ok(value) => Ok(Some(value.into())),
+        Err(StoreErrorType::NotFound(_)) => Ok(None),
+        Err(e) => Err(format!("{:?}", e).into()),
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn process(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> async_graphql::Result<Option<ProcessNode>> {
+        let deps = ctx.data::<Arc<Deps>>()?;
+        optional(deps.data_store.get_process(&id, &ReadConsistency::Eventual))
+    }
+
+    /*
+        messages scheduled on a process oldest first,
+        after is the cursor of the last edge already read
+    */
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        process: String,
+        after: Option<String>,
+        first: Option<i32>,
+        tags: Option<Vec<TagFilter>>,
+        owner: Option<String>,
+    ) -> async_graphql::Result<ConnectionNode> {
+        let first = first.unwrap_or(DEFAULT_FIRST);
+        if !(1..=MAX_FIRST).contains(&first) {
+            return Err(format!("first must be between 1 and {}", MAX_FIRST).into());
+        }
+        let after = after.as_deref().map(parse_cursor).transpose()?;
+        let deps = ctx.data::<Arc<Deps>>()?;
+        let filter = MessageFilter {
+            tags: tags
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tag| (tag.name, tag.values))
+                .collect(),
+            owner,
+        };
+        let paginated = deps
+            .data_store
+            .search_messages(
+                &process,
+                &after,
+                &Some(first),
+                &filter,
+                &ReadConsistency::Eventual,
+            )
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(ConnectionNode::try_from(paginated)?)
+    }
+
+    // the message scheduled by an assignment
+    async fn assignment(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> async_graphql::Result<Option<MessageNode>> {
+        let deps = ctx.data::<Arc<Deps>>()?;
+        let message = deps
+            .data_store
+            .get_message(&id, &ReadConsistency::Eventual)
+            .and_then(|message| match message.assignment.id == id {
+                true => Ok(message),
+                false => Err(StoreErrorType::NotFound("Assignment not found".to_string())),
+            });
+        optional(message)
+    }
+}
+
+pub fn init_schema(deps: Arc<Deps>) -> SuSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(deps)
+        .finish()
+}
+
+// the body is a standard graphql request, query variables and operationName
+pub async fn execute(deps: Arc<Deps>, schema: &SuSchema, input: Vec<u8>) -> Result<String, String> {
+    if deps.config.mode() == "router" {
+        return Err("graphql is served by the schedulers, not the router".to_string());
+    }
+    let request: Request = match serde_json::from_slice(&input) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("Invalid graphql request: {:?}", e)),
+    };
+    let response = schema.execute(request).await;
+    to_json(&response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_sdl() {
+        let sdl = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .finish()
+            .sdl();
+        assert!(sdl.contains("process(id: String!): Process"));
+        assert!(sdl.contains("assignment(id: String!): Message"));
+        assert!(sdl.contains("type MessageConnection"));
+        assert!(sdl.contains("pageInfo: PageInfo!"));
+        assert!(sdl.contains("hasNextPage: Boolean!"));
+    }
+
+    #[tokio::test]
+    async fn test_messages_first_bounds() {
+        let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
+        for first in [0, MAX_FIRST + 1] {
+            let query = format!(
+                "{{ messages(process: \"p\", first: {}) {{ pageInfo {{ hasNextPage }} }} }}",
+                first
+            );
+            let response = schema.execute(query.as_str()).await;
+            assert_eq!(response.errors.len(), 1);
+            assert_eq!(
+                response.errors[0].message,
+                format!("first must be between 1 and {}", MAX_FIRST)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = MessageCursor {
+            timestamp: 1711676638471,
+            epoch: 0,
+            nonce: 12,
+        };
+        assert_eq!(format_cursor(&cursor), "1711676638471:0:12");
+        assert_eq!(parse_cursor("1711676638471:0:12"), Ok(cursor));
+
+        for invalid in ["1711676638471", "1711676638471:0", "a:0:12", "1:0:12:3"] {
+            assert_eq!(
+                parse_cursor(invalid),
+                Err(format!("Invalid cursor {}", invalid))
+            );
+        }
+    }
+
+    #[test]
+    fn test_optional() {
+        let found: Option<String> = optional(Ok("process".to_string())).unwrap();
+        assert_eq!(found, Some("process".to_string()));
+
+        let missing: Option<String> =
+            optional::<String, String>(Err(StoreErrorType::NotFound("gone".to_string()))).unwrap();
+        assert!(missing.is_none());
+
+        let failed =
+            optional::<String, String>(Err(StoreErrorType::DatabaseError("down".to_string())));
+        assert!(failed.is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::dal::Signer;
+use super::flows::Deps;
+use super::json::hash;
+
+/*
+    The scheduler identities hosted by this su. A process
+    picks its identity by its Scheduler tag when it is
+    created and every later assignment for it is signed
+    by that identity, so one deployment can serve several
+    Scheduler-Location addresses.
+*/
+pub struct Identities {
+    default_address: String,
+    signers: HashMap<String, Arc<dyn Signer>>,
+}
+
+pub fn signer_address(signer: &Arc<dyn Signer>) -> String {
+    base64_url::encode(&hash(&signer.get_public_key()))
+}
+
+impl Identities {
+    // default signs for processes created before identities were stored
+    pub fn new(default: Arc<dyn Signer>, others: Vec<Arc<dyn Signer>>) -> Self {
+        let default_address = signer_address(&default);
+        let mut signers = HashMap::new();
+        for signer in others {
+            signers.insert(signer_address(&signer), signer);
+        }
+        signers.insert(default_address.clone(), default);
+        Identities {
+            default_address,
+            signers,
+        }
+    }
+
+    pub fn default_signer(&self) -> Arc<dyn Signer> {
+        self.signers[&self.default_address].clone()
+    }
+
+    pub fn get(&self, address: &str) -> Option<Arc<dyn Signer>> {
+        self.signers.get(address).cloned()
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        let mut addresses: Vec<String> = self.signers.keys().cloned().collect();
+        addresses.sort();
+        addresses
+    }
+}
+
+// the identity a new process with this Scheduler tag is assigned to
+pub fn signer_for_scheduler(deps: &Arc<Deps>, scheduler: &str) -> Result<Arc<dyn Signer>, String> {
+    deps.identities
+        .get(scheduler)
+        .ok_or_else(|| format!("Scheduler {} is not hosted by this su", scheduler))
+}
+
+pub fn signer_for_process(deps: &Arc<Deps>, process_id: &str) -> Result<Arc<dyn Signer>, String> {
+    match deps.data_store.get_process_identity(process_id)? {
+        Some(address) => deps.identities.get(&address).ok_or_else(|| {
+            format!(
+                "Scheduler identity {} for process {} is not loaded",
+                address, process_id
+            )
+        }),
+        None => Ok(deps.identities.default_signer()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct MockSigner {
+        key: u8,
+    }
+
+    #[async_trait]
+    impl Signer for MockSigner {
+        async fn sign_tx(&self, buffer: Vec<u8>) -> Result<Vec<u8>, String> {
+            Ok(buffer)
+        }
+
+        fn get_public_key(&self) -> Vec<u8> {
+            vec![self.key; 4]
+        }
+
+        fn verify(
+            &self,
+            _pub_key: &[u8],
+            _message: &[u8],
+            _signature: &[u8],
+        ) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_identities() {
+        let default: Arc<dyn Signer> = Arc::new(MockSigner { key: 1 });
+        let other: Arc<dyn Signer> = Arc::new(MockSigner { key: 2 });
+        let other_address = signer_address(&other);
+        let identities = Identities::new(default.clone(), vec![other]);
+
+        assert_eq!(identities.addresses().len(), 2);
+        assert_eq!(
+            identities
+                .get(&other_address)
+                .expect("identity not found")
+                .get_public_key(),
+            vec![2; 4]
+        );
+        assert_eq!(identities.default_signer().get_public_key(), vec![1; 4]);
+        assert!(identities.get("unknown").is_none());
+    }
+}
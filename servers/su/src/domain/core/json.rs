@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
 
 use super::bytes::{ByteErrorType, DataBundle, DataItem};
 use bundlr_sdk::tags::*;
@@ -45,17 +46,18 @@ impl From<ByteErrorType> for JsonErrorType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Owner {
     pub address: String,
     pub key: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Process {
     pub process_id: String,
     pub block: String,
     pub owner: Owner,
+    #[schema(value_type = Vec<Tag>)]
     pub tags: Vec<Tag>,
     pub timestamp: i64,
     pub data: Option<String>,
@@ -63,45 +65,47 @@ pub struct Process {
     pub signature: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct MessageInner {
     pub id: String,
     pub owner: Owner,
     pub data: Option<String>,
+    #[schema(value_type = Vec<Tag>)]
     pub tags: Vec<Tag>,
     pub signature: String,
     pub anchor: Option<String>,
     pub target: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct AssignmentInner {
     pub id: String,
     pub owner: Owner,
+    #[schema(value_type = Vec<Tag>)]
     pub tags: Vec<Tag>,
     pub signature: String,
     pub anchor: Option<String>,
     pub target: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Message {
     pub message: Option<MessageInner>,
     pub assignment: AssignmentInner,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct PaginatedMessages {
     pub page_info: PageInfo,
     pub edges: Vec<Edge>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct PageInfo {
     pub has_next_page: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Edge {
     pub node: Message,
     pub cursor: String,
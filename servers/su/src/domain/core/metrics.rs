@@ -0,0 +1,56 @@
+use dashmap::DashMap;
+
+/*
+    in process counters exposed on /metrics in the
+    prometheus text format, series are keyed by their
+    name and labels so any flow can add one without
+    registering it up front
+*/
+#[derive(Default)]
+pub struct Metrics {
+    counters: DashMap<String, u64>,
+}
+
+fn series(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('"', "\\\"")))
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{}{{{}}}", name, labels)
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        *self.counters.entry(series(name, labels)).or_insert(0) += 1;
+    }
+
+    // for gauges, replaces the value instead of adding to it
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        self.counters.insert(series(name, labels), value);
+    }
+
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        match self.counters.get(&series(name, labels)) {
+            Some(count) => *count,
+            None => 0,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines: Vec<String> = self
+            .counters
+            .iter()
+            .map(|entry| format!("{} {}", entry.key(), entry.value()))
+            .collect();
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+}
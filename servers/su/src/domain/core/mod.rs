@@ -15,3 +15,33 @@ pub mod flows;
 
 // router logic
 pub mod router;
+
+// base layer assignments waiting on confirmations
+pub mod deferred;
+
+// draining in flight writes on shutdown
+pub mod shutdown;
+
+// signed receipts returned from writes
+pub mod receipt;
+
+// ao Data-Protocol rules checked on every write
+pub mod validation;
+
+// counters exposed on /metrics
+pub mod metrics;
+
+// runtime inspection and control for operators
+pub mod admin;
+
+// the wallets this su schedules processes as
+pub mod identities;
+
+// typed bodies for the http routes and their openapi schemas
+pub mod responses;
+
+// arweave style graphql reads over the schedule
+pub mod graphql;
+
+// background checks of stored schedules and quarantining bad rows
+pub mod consistency;
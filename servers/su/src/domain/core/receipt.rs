@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::bytes::{deep_hash_sync, ByteErrorType, DeepHashChunk};
+use super::dal::Signer;
+use super::json::{hash, JsonErrorType, Message, Process};
+
+/*
+    A receipt is returned from a write and signed by the
+    su wallet. It commits to the position of the item in
+    the schedule so a client can prove the ordering it was
+    given without querying the su again.
+*/
+
+const RECEIPT_AS_BUFFER: &[u8] = "ao-receipt".as_bytes();
+const RECEIPT_VERSION: &[u8] = "1".as_bytes();
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Receipt {
+    // the id returned by the su before receipts were added
+    pub id: String,
+    pub process_id: String,
+    pub message_id: String,
+    pub assignment_id: Option<String>,
+    pub epoch: Option<i32>,
+    pub nonce: Option<i32>,
+    pub timestamp: i64,
+    pub block_height: String,
+    pub hash_chain: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SignedReceipt {
+    #[serde(flatten)]
+    pub receipt: Receipt,
+    // base64url public key of the su wallet
+    pub owner: String,
+    // base64url wallet address derived from owner
+    pub address: String,
+    pub signature: String,
+}
+
+#[derive(Debug)]
+pub enum ReceiptErrorType {
+    ReceiptError(String),
+}
+
+impl From<ReceiptErrorType> for String {
+    fn from(error: ReceiptErrorType) -> Self {
+        format!("{:?}", error)
+    }
+}
+
+impl From<ByteErrorType> for ReceiptErrorType {
+    fn from(error: ByteErrorType) -> Self {
+        ReceiptErrorType::ReceiptError(format!("Byte error in receipt: {:?}", error))
+    }
+}
+
+impl From<JsonErrorType> for ReceiptErrorType {
+    fn from(error: JsonErrorType) -> Self {
+        ReceiptErrorType::ReceiptError(format!("Json error in receipt: {:?}", error))
+    }
+}
+
+impl From<String> for ReceiptErrorType {
+    fn from(error: String) -> Self {
+        ReceiptErrorType::ReceiptError(error)
+    }
+}
+
+impl From<base64_url::base64::DecodeError> for ReceiptErrorType {
+    fn from(error: base64_url::base64::DecodeError) -> Self {
+        ReceiptErrorType::ReceiptError(format!("Invalid base64 in receipt: {:?}", error))
+    }
+}
+
+fn chunk(value: &str) -> DeepHashChunk {
+    DeepHashChunk::Chunk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn optional_chunk<T: ToString>(value: &Option<T>) -> DeepHashChunk {
+    match value {
+        Some(v) => chunk(&v.to_string()),
+        None => chunk(""),
+    }
+}
+
+impl Receipt {
+    /*
+        message_id is the actual message for a Message
+        write and the assigned tx for an assignment
+    */
+    pub fn from_message(message: &Message) -> Result<Self, JsonErrorType> {
+        let id = match &message.message {
+            Some(m) => m.id.clone(),
+            None => message.assignment_id()?,
+        };
+
+        Ok(Receipt {
+            id,
+            process_id: message.process_id()?,
+            message_id: message.message_id()?,
+            assignment_id: Some(message.assignment_id()?),
+            epoch: Some(message.epoch()?),
+            nonce: Some(message.nonce()?),
+            timestamp: message.timestamp()?,
+            block_height: message.block_height()?,
+            hash_chain: Some(message.hash_chain()?),
+        })
+    }
+
+    // a process is not assigned so it has no position fields
+    pub fn from_process(process: &Process) -> Self {
+        Receipt {
+            id: process.process_id.clone(),
+            process_id: process.process_id.clone(),
+            message_id: process.process_id.clone(),
+            assignment_id: None,
+            epoch: None,
+            nonce: None,
+            timestamp: process.timestamp,
+            block_height: process.block.clone(),
+            hash_chain: None,
+        }
+    }
+
+    // the deep hash of every field is what the su signs
+    pub fn signature_data(&self) -> Result<Vec<u8>, ReceiptErrorType> {
+        let hashed = deep_hash_sync(DeepHashChunk::Chunks(vec![
+            DeepHashChunk::Chunk(RECEIPT_AS_BUFFER.into()),
+            DeepHashChunk::Chunk(RECEIPT_VERSION.into()),
+            chunk(&self.id),
+            chunk(&self.process_id),
+            chunk(&self.message_id),
+            optional_chunk(&self.assignment_id),
+            optional_chunk(&self.epoch),
+            optional_chunk(&self.nonce),
+            chunk(&self.timestamp.to_string()),
+            chunk(&self.block_height),
+            optional_chunk(&self.hash_chain),
+        ]))?;
+        Ok(hashed.to_vec())
+    }
+}
+
+pub async fn sign_receipt(
+    signer: &Arc<dyn Signer>,
+    receipt: Receipt,
+) -> Result<SignedReceipt, ReceiptErrorType> {
+    let signature = signer.sign_tx(receipt.signature_data()?).await?;
+    let owner = signer.get_public_key();
+    let address = base64_url::encode(&hash(&owner));
+
+    Ok(SignedReceipt {
+        receipt,
+        owner: base64_url::encode(&owner),
+        address,
+        signature: base64_url::encode(&signature),
+    })
+}
+
+/*
+    check the signature against the owner in the receipt,
+    a valid signature only counts if the owner is one of
+    the identities in hosted_addresses, otherwise anyone
+    could sign a receipt for a schedule position
+*/
+pub fn verify_receipt(
+    signer: &Arc<dyn Signer>,
+    signed_receipt: &SignedReceipt,
+    hosted_addresses: &[String],
+) -> Result<(), ReceiptErrorType> {
+    let owner = base64_url::decode(&signed_receipt.owner)?;
+    let signature = base64_url::decode(&signed_receipt.signature)?;
+
+    if base64_url::encode(&hash(&owner)) != signed_receipt.address {
+        return Err(ReceiptErrorType::ReceiptError(
+            "Receipt address does not match owner".to_string(),
+        ));
+    }
+
+    if !hosted_addresses.contains(&signed_receipt.address) {
+        return Err(ReceiptErrorType::ReceiptError(format!(
+            "Receipt was signed by {} which is not hosted by this su",
+            signed_receipt.address
+        )));
+    }
+
+    let message = signed_receipt.receipt.signature_data()?;
+    signer.verify(&owner, &message, &signature)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    // the public key tells apart the su wallet from a foreign one
+    struct MockSigner(Vec<u8>);
+    #[async_trait]
+    impl Signer for MockSigner {
+        // the "signature" is just the reversed message
+        async fn sign_tx(&self, buffer: Vec<u8>) -> Result<Vec<u8>, String> {
+            Ok(buffer.into_iter().rev().collect())
+        }
+
+        fn get_public_key(&self) -> Vec<u8> {
+            self.0.clone()
+        }
+
+        fn verify(&self, _pub_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+            let expected: Vec<u8> = message.iter().rev().cloned().collect();
+            match expected == signature {
+                true => Ok(()),
+                false => Err("invalid signature".to_string()),
+            }
+        }
+    }
+
+    fn receipt() -> Receipt {
+        Receipt {
+            id: "message-id".to_string(),
+            process_id: "process-id".to_string(),
+            message_id: "message-id".to_string(),
+            assignment_id: Some("assignment-id".to_string()),
+            epoch: Some(0),
+            nonce: Some(5),
+            timestamp: 1711676638471,
+            block_height: "000001393008".to_string(),
+            hash_chain: Some("hash-chain".to_string()),
+        }
+    }
+
+    fn su_signer() -> Arc<dyn Signer> {
+        Arc::new(MockSigner(vec![5, 6, 7, 8]))
+    }
+
+    fn hosted() -> Vec<String> {
+        vec![base64_url::encode(&hash(&[5, 6, 7, 8]))]
+    }
+
+    #[tokio::test]
+    async fn test_sign_and_verify_receipt() {
+        let signer = su_signer();
+        let signed = sign_receipt(&signer, receipt())
+            .await
+            .expect("failed to sign receipt");
+        assert!(verify_receipt(&signer, &signed, &hosted()).is_ok());
+
+        let json = serde_json::to_value(&signed).expect("failed to serialize receipt");
+        assert_eq!(json["id"], "message-id");
+        assert_eq!(json["timestamp"], 1711676638471i64);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_receipt_fails() {
+        let signer = su_signer();
+        let mut signed = sign_receipt(&signer, receipt())
+            .await
+            .expect("failed to sign receipt");
+        signed.receipt.nonce = Some(4);
+        assert!(verify_receipt(&signer, &signed, &hosted()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_receipt_signed_by_a_foreign_wallet_fails() {
+        let foreign: Arc<dyn Signer> = Arc::new(MockSigner(vec![1, 2, 3, 4]));
+        let signed = sign_receipt(&foreign, receipt())
+            .await
+            .expect("failed to sign receipt");
+        // the signature itself is valid, only the signer is wrong
+        assert!(verify_receipt(&foreign, &signed, &[signed.address.clone()]).is_ok());
+        assert!(verify_receipt(&su_signer(), &signed, &hosted()).is_err());
+    }
+}
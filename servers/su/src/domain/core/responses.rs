@@ -0,0 +1,166 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// the stored types some routes return as they are
+pub use super::consistency::{ChainBreak, ConsistencyReport, DuplicateNonce, NonceGap};
+pub use super::dal::{
+    Message, NetworkInfo, PaginatedMessages, PoolState, PoolStats, Process, ReplicationStatus,
+};
+pub use super::deferred::DeferredAssignment;
+pub use super::json::{AssignmentInner, Edge, MessageInner, Owner, PageInfo};
+pub use super::receipt::{Receipt, SignedReceipt};
+pub use super::scheduler::LockStatus;
+
+/*
+    the bodies returned by the http routes, they derive
+    ToSchema so /openapi.json is generated from the same
+    types the flows serialize
+*/
+
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("{:?}", e))
+}
+
+/*
+    bundlr_sdk Tag has no schema of its own, this
+    stands in for it wherever a type holds tags
+*/
+#[derive(ToSchema)]
+pub struct Tag {
+    pub name: String,
+    pub value: String,
+}
+
+// every route responds with this on an error
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatusResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub timestamp: String,
+    pub address: String,
+    // every scheduler identity hosted by this su
+    pub addresses: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TimestampResponse {
+    pub timestamp: String,
+    // zero padded to 12 digits
+    pub block_height: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WriteReceipt {
+    #[serde(flatten)]
+    pub signed_receipt: SignedReceipt,
+    // not signed, passed back as min-lsn to read the write from a replica
+    pub lsn: String,
+}
+
+// one item of a posted bundle, it has either a receipt or an error
+#[derive(Serialize, ToSchema)]
+pub struct BundleItemResult {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<WriteReceipt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BundleResponse {
+    pub id: String,
+    pub items: Vec<BundleItemResult>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeferredTicket {
+    pub ticket: String,
+    pub status: String,
+}
+
+/*
+    only used to describe POST / and GET /{tx_id}
+    which respond with one of several shapes
+*/
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum WriteResponse {
+    Receipt(Box<WriteReceipt>),
+    Bundle(BundleResponse),
+    Deferred(DeferredTicket),
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ReadResponse {
+    Message(Box<Message>),
+    Messages(PaginatedMessages),
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VerifyReceiptResponse {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LocksResponse {
+    pub count: usize,
+    pub locks: Vec<LockStatus>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UploadEntry {
+    // None when the bundle could not be parsed
+    pub id: Option<String>,
+    pub size: usize,
+    // every attempt failed, /admin/uploads/retry starts it again
+    pub failed: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UploadBacklogResponse {
+    pub count: usize,
+    pub failed: usize,
+    pub uploads: Vec<UploadEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RetryUploadsResponse {
+    pub retried: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReloadSchedulersResponse {
+    pub result: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConsistencySummary {
+    // millisecond timestamp of the last background check, 0 before the first
+    pub last_run: i64,
+    pub processes_checked: u64,
+    // only processes with a problem
+    pub reports: Vec<ConsistencyReport>,
+    // uploads that ran out of attempts and wait for an admin retry
+    pub failed_uploads: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RepairResponse {
+    // ids of the messages moved to quarantined_messages
+    pub quarantined: Vec<String>,
+    pub report: ConsistencyReport,
+}
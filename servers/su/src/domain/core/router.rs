@@ -1,8 +1,10 @@
+use crate::domain::core::bytes::{DataBundle, DataItem};
 use crate::domain::core::dal::StoreErrorType;
-use crate::domain::flows::{init_builder, Deps};
+use crate::domain::flows::{init_builder, is_bundle, Deps};
 use serde::Deserialize;
 use std::{fmt::Debug, sync::Arc};
 use tokio::{fs::File, io::AsyncReadExt};
+use tracing::instrument;
 
 /*
     The code in this file only runs on a su that is
@@ -68,6 +70,7 @@ pub async fn init_schedulers(deps: Arc<Deps>) -> Result<String, String> {
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
+#[instrument(name = "redirect", skip_all, fields(process_id = ?process_id))]
 pub async fn redirect_process_id(
     deps: Arc<Deps>,
     process_id: Option<String>,
@@ -87,6 +90,7 @@ pub async fn redirect_process_id(
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
+#[instrument(name = "redirect", skip_all, fields(tx_id = %tx_id, process_id = ?process_id))]
 pub async fn redirect_tx_id(
     deps: Arc<Deps>,
     tx_id: String,
@@ -113,6 +117,7 @@ pub async fn redirect_tx_id(
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
+#[instrument(name = "redirect", skip_all, fields(process_id = ?process_id, assign = ?assign))]
 pub async fn redirect_data_item(
     deps: Arc<Deps>,
     input: Vec<u8>,
@@ -140,62 +145,238 @@ pub async fn redirect_data_item(
 
     let builder = init_builder(&deps)?;
     let item = builder.parse_data_item(input.clone())?;
-    let tags = item.tags().clone();
-    let id = item.id().clone();
-    let target = item.target().clone();
-    let type_tag = tags
-        .iter()
-        .find(|tag| tag.name == "Type")
-        .ok_or("Cannot redirect data item, invalid Type Tag")?;
-
-    match type_tag.value.as_str() {
-        "Process" => {
-            /*
-                new process so we need to generate a
-                process_schedulers record and return the url
-            */
-            let mut schedulers = deps.data_store.get_all_schedulers()?;
-            if let Some(min_scheduler) = schedulers.iter_mut().min_by_key(|s| s.process_count) {
-                min_scheduler.process_count += 1;
-                deps.data_store.update_scheduler(min_scheduler)?;
-
-                let scheduler_row_id = if let Some(min_scheduler_row_id) = min_scheduler.row_id {
-                    min_scheduler_row_id
-                } else {
-                    /*
-                        this should be unreachable but return an error
-                        just in case so the router doesn't crash
-                    */
-                    return Err("Missing id on scheduler".to_string());
-                };
-
-                let process_scheduler = ProcessScheduler {
-                    row_id: None,
-                    scheduler_row_id: scheduler_row_id,
-                    process_id: id,
-                };
-                deps.data_store.save_process_scheduler(&process_scheduler)?;
-
-                Ok(Some(min_scheduler.url.clone()))
-            } else {
-                Err("Could not find a scheduler to assign".to_string())
+    let plan = plan_route(unpack_items(item)?)?;
+
+    /*
+        resolve every message target before anything is
+        written so a rejected bundle leaves no process
+        assigned to a scheduler
+    */
+    let mut urls = vec![];
+    for target in &plan.targets {
+        match deps.data_store.get_process_scheduler(target) {
+            Ok(process_scheduler) => {
+                let scheduler = deps
+                    .data_store
+                    .get_scheduler(&process_scheduler.scheduler_row_id)?;
+                urls.push(scheduler.url);
             }
+            Err(_) => return Err("Unable to locate scheduler for message target".to_string()),
         }
-        "Message" => {
-            /*
-                otherwise, fetch the correct scheduler based
-                on the messages's target
-            */
-            match deps.data_store.get_process_scheduler(&target) {
-                Ok(process_scheduler) => {
-                    let scheduler = deps
-                        .data_store
-                        .get_scheduler(&process_scheduler.scheduler_row_id)?;
-                    Ok(Some(scheduler.url))
-                }
-                Err(_) => Err("Unable to locate scheduler for message target".to_string()),
+    }
+    let url = single_url(urls)?;
+
+    if plan.new_processes.is_empty() {
+        return url
+            .map(Some)
+            .ok_or("Cannot redirect data item, nothing to route".to_string());
+    }
+
+    /*
+        new processes so we need to generate a process_schedulers
+        record for each, they go to the scheduler of the messages
+        they are posted with or the least loaded one
+    */
+    let mut scheduler = match url {
+        Some(url) => deps.data_store.get_scheduler_by_url(&url)?,
+        None => deps
+            .data_store
+            .get_all_schedulers()?
+            .into_iter()
+            .min_by_key(|s| s.process_count)
+            .ok_or("Could not find a scheduler to assign")?,
+    };
+
+    let scheduler_row_id = if let Some(row_id) = scheduler.row_id {
+        row_id
+    } else {
+        /*
+            this should be unreachable but return an error
+            just in case so the router doesn't crash
+        */
+        return Err("Missing id on scheduler".to_string());
+    };
+
+    scheduler.process_count += plan.new_processes.len() as i32;
+    deps.data_store.update_scheduler(&scheduler)?;
+
+    for process_id in plan.new_processes {
+        let process_scheduler = ProcessScheduler {
+            row_id: None,
+            scheduler_row_id,
+            process_id,
+        };
+        deps.data_store.save_process_scheduler(&process_scheduler)?;
+    }
+
+    Ok(Some(scheduler.url))
+}
+
+/*
+    a Bundle-Format binary item carries the items the su
+    will schedule, so those are what get routed and not
+    the bundle itself
+*/
+fn unpack_items(item: DataItem) -> Result<Vec<DataItem>, String> {
+    if !is_bundle(&item) {
+        return Ok(vec![item]);
+    }
+
+    let bundle = DataBundle::from_bytes(&item.data_bytes())
+        .map_err(|e| format!("Cannot redirect data item, invalid bundle: {:?}", e))?;
+    if bundle.items.is_empty() {
+        return Err("Cannot redirect data item, bundle has no items".to_string());
+    }
+    Ok(bundle.items)
+}
+
+// what has to be looked up or created to route some items
+#[derive(Debug, PartialEq)]
+struct RoutePlan {
+    new_processes: Vec<String>,
+    targets: Vec<String>,
+}
+
+fn plan_route(items: Vec<DataItem>) -> Result<RoutePlan, String> {
+    let mut plan = RoutePlan {
+        new_processes: vec![],
+        targets: vec![],
+    };
+
+    for item in &items {
+        let type_tag = item
+            .tags()
+            .into_iter()
+            .find(|tag| tag.name == "Type")
+            .ok_or(format!(
+                "Cannot redirect data item {}, invalid Type Tag",
+                item.id()
+            ))?;
+
+        match type_tag.value.as_str() {
+            "Process" => plan.new_processes.push(item.id()),
+            "Message" => plan.targets.push(item.target()),
+            _ => {
+                return Err(format!(
+                    "Cannot redirect data item {}, invalid Type Tag",
+                    item.id()
+                ))
             }
         }
-        _ => Err("Cannot redirect data item, invalid Type Tag".to_string()),
+    }
+
+    /*
+        a message to a process created in the same
+        bundle goes wherever that process goes
+    */
+    plan.targets
+        .retain(|target| !plan.new_processes.contains(target));
+    plan.targets.dedup();
+
+    Ok(plan)
+}
+
+/*
+    a bundle is posted to one scheduler so every
+    item in it has to be scheduled there
+*/
+fn single_url(mut urls: Vec<String>) -> Result<Option<String>, String> {
+    urls.sort();
+    urls.dedup();
+    match urls.len() {
+        0 => Ok(None),
+        1 => Ok(urls.pop()),
+        _ => Err(
+            "Cannot redirect bundle, its items are scheduled on different schedulers, post them separately"
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bundlr_sdk::tags::Tag;
+
+    fn signed_item(tags: Vec<Tag>, target: Vec<u8>, data: Vec<u8>, seed: u8) -> DataItem {
+        let mut item =
+            DataItem::new(target, data, tags, vec![0; 512]).expect("failed to build item");
+        item.signature = vec![seed; 512];
+        item
+    }
+
+    fn typed_item(type_value: &str, target: Vec<u8>, seed: u8) -> DataItem {
+        let tags = vec![Tag::new("Type", type_value)];
+        signed_item(tags, target, vec![], seed)
+    }
+
+    fn bundle_item(items: Vec<DataItem>) -> DataItem {
+        let mut bundle = DataBundle::new(vec![]);
+        for item in items {
+            bundle.add_item(item);
+        }
+        let tags = vec![
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
+        ];
+        signed_item(tags, vec![], bundle.to_bytes().expect("bundling failed"), 9)
+    }
+
+    #[test]
+    fn test_plan_route_single_item() {
+        let process = typed_item("Process", vec![], 1);
+        let message = typed_item("Message", vec![7; 32], 2);
+
+        let plan = plan_route(unpack_items(process.clone()).unwrap()).unwrap();
+        assert_eq!(plan.new_processes, vec![process.id()]);
+        assert!(plan.targets.is_empty());
+
+        let plan = plan_route(unpack_items(message.clone()).unwrap()).unwrap();
+        assert!(plan.new_processes.is_empty());
+        assert_eq!(plan.targets, vec![message.target()]);
+    }
+
+    #[test]
+    fn test_plan_route_bundle() {
+        let process = typed_item("Process", vec![], 1);
+        let to_new_process = typed_item("Message", process.raw_id(), 2);
+        let to_existing = typed_item("Message", vec![7; 32], 3);
+        let bundle = bundle_item(vec![process.clone(), to_new_process, to_existing.clone()]);
+
+        let items = unpack_items(bundle).expect("failed to unpack bundle");
+        assert_eq!(items.len(), 3);
+
+        let plan = plan_route(items).unwrap();
+        assert_eq!(
+            plan,
+            RoutePlan {
+                new_processes: vec![process.id()],
+                targets: vec![to_existing.target()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_route_rejects_invalid_items() {
+        let untyped = signed_item(vec![], vec![], vec![], 1);
+        assert!(plan_route(unpack_items(untyped).unwrap()).is_err());
+
+        let bundle = bundle_item(vec![
+            typed_item("Message", vec![7; 32], 1),
+            typed_item("Other", vec![], 2),
+        ]);
+        assert!(plan_route(unpack_items(bundle).unwrap()).is_err());
+
+        assert!(unpack_items(bundle_item(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_single_url() {
+        assert_eq!(single_url(vec![]), Ok(None));
+        assert_eq!(
+            single_url(vec!["https://su1".to_string(), "https://su1".to_string()]),
+            Ok(Some("https://su1".to_string()))
+        );
+        assert!(single_url(vec!["https://su1".to_string(), "https://su2".to_string()]).is_err());
     }
 }
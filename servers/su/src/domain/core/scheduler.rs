@@ -1,16 +1,50 @@
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use base64_url;
 use dashmap::DashMap;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex;
+use utoipa::ToSchema;
 
-use crate::domain::core::dal::{DataStore, Log, ScheduleProvider};
+use crate::domain::core::dal::{
+    Config, DataStore, LeaseFence, Log, ReadConsistency, ScheduleProvider, StoreErrorType,
+};
+use crate::domain::core::json::Message;
+use crate::domain::core::metrics::Metrics;
 
 pub struct SchedulerDeps {
     pub data_store: Arc<dyn DataStore>,
     pub logger: Arc<dyn Log>,
+    pub config: Arc<dyn Config>,
+    pub metrics: Arc<Metrics>,
+}
+
+/*
+    timestamps are the cursor readers page by, so in
+    both modes every timestamp of a process is strictly
+    greater than the last, a clock that stands still or
+    steps back gets a logical tick on the previous one.
+    hybrid mode also never goes below the latest timestamp
+    this su handed out to any process, so a message pushed
+    from one process to another is always stamped later
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockMode {
+    Wall,
+    Hybrid,
+}
+
+impl ClockMode {
+    pub fn from_config(value: &str) -> Self {
+        match value {
+            "hybrid" => ClockMode::Hybrid,
+            _ => ClockMode::Wall,
+        }
+    }
 }
 
 /*
@@ -26,9 +60,20 @@ pub struct ScheduleInfo {
 
 pub type LockedScheduleInfo = Arc<Mutex<ScheduleInfo>>;
 
+#[derive(Serialize, Debug, ToSchema)]
+pub struct LockStatus {
+    pub process_id: String,
+    pub held: bool,
+    pub waiting: usize,
+}
+
 /*
     ProcessScheduler provides a Mutex lock per process to
-    ensure there are no conflicts or missing nonces in the sequence
+    ensure there are no conflicts or missing nonces in the sequence.
+    The Mutex only covers this instance, so before a write the
+    scheduler also takes a lease on the process in the database.
+    Another su pointed at the same database refuses to write the
+    process until the lease runs out
 */
 pub struct ProcessScheduler {
     /*
@@ -37,16 +82,42 @@ pub struct ProcessScheduler {
     */
     locks: Arc<DashMap<String, LockedScheduleInfo>>,
     deps: Arc<SchedulerDeps>,
+    clock_mode: ClockMode,
+    // the latest timestamp handed out to any process, used in hybrid mode
+    latest_timestamp: AtomicI64,
+    // who this su is in process_leases
+    instance_id: String,
 }
 
 impl ProcessScheduler {
     pub fn new(deps: Arc<SchedulerDeps>) -> Self {
+        let clock_mode = ClockMode::from_config(&deps.config.clock_mode());
         ProcessScheduler {
             locks: Arc::new(DashMap::new()),
+            clock_mode,
+            latest_timestamp: AtomicI64::new(0),
+            instance_id: deps
+                .config
+                .su_instance_id()
+                .unwrap_or_else(random_instance_id),
             deps,
         }
     }
 
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /*
+        processes whose lock is held or waited on. The map keeps
+        one reference to each lock and every task that called
+        acquire_lock keeps another until it is done, so anything
+        above one that isn't the holder is waiting
+    */
+    pub fn lock_status(&self) -> Vec<LockStatus> {
+        lock_status(&self.locks)
+    }
+
     /*
         acquire the lock while also obtaining
         the info needed epoch, nonce etc.. to
@@ -76,17 +147,129 @@ impl ProcessScheduler {
         schedule_info: &'a mut ScheduleInfo,
         id: String,
     ) -> Result<&mut ScheduleInfo, String> {
-        let (current_epoch, current_nonce, current_hash_chain, current_timestamp) =
+        self.claim_lease(&id)?;
+
+        let (current_epoch, current_nonce, current_hash_chain, wall_timestamp, previous_timestamp) =
             match fetch_values(self.deps.clone(), &id).await {
                 Ok(vals) => vals,
                 Err(e) => return Err(format!("error acquiring scheduler lock {}", e)),
             };
+
+        /*
+            the last timestamp this instance handed out for the
+            process is also a floor, it is never older than
+            what we just read from the database
+        */
+        let mut previous_timestamp = previous_timestamp.max(schedule_info.timestamp);
+        if self.clock_mode == ClockMode::Hybrid {
+            previous_timestamp =
+                previous_timestamp.max(self.latest_timestamp.load(Ordering::SeqCst));
+        }
+        let (current_timestamp, regressed) = next_timestamp(wall_timestamp, previous_timestamp);
+        self.latest_timestamp
+            .fetch_max(current_timestamp, Ordering::SeqCst);
+        if regressed {
+            self.deps
+                .metrics
+                .increment("su_clock_regressions_total", &[]);
+            self.deps.logger.error(format!(
+                "clock regression on process {}, wall clock {} is {}ms behind previous timestamp {}, using {}",
+                &id,
+                wall_timestamp,
+                previous_timestamp - wall_timestamp,
+                previous_timestamp,
+                current_timestamp
+            ));
+        }
+
         schedule_info.epoch = current_epoch;
         schedule_info.nonce = current_nonce;
         schedule_info.hash_chain = current_hash_chain;
         schedule_info.timestamp = current_timestamp;
         Ok(schedule_info)
     }
+
+    /*
+        take or renew the lease on a process, every write
+        pushes it out another LEASE_TTL seconds. A process
+        leased to another su is an error, writing or
+        repairing it here would fork the schedule.
+        LEASE_TTL=0 turns leases off
+    */
+    pub fn claim_lease(&self, id: &str) -> Result<(), String> {
+        let fence = match self.lease_fence()? {
+            Some(fence) => fence,
+            None => return Ok(()),
+        };
+        let expires_at = fence.now + self.deps.config.lease_ttl() as i64 * 1000;
+        match self
+            .deps
+            .data_store
+            .claim_lease(id, &fence.holder, fence.now, expires_at)
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.deps.metrics.increment("su_lease_conflicts_total", &[]);
+                self.deps.logger.error(format!(
+                    "process {} is leased to another su, refusing to schedule it on {}",
+                    id, &self.instance_id
+                ));
+                Err(format!(
+                    "Process {} is scheduled by another su instance",
+                    id
+                ))
+            }
+            Err(e) => Err(format!("error claiming lease on process {} {:?}", id, e)),
+        }
+    }
+
+    // the lease this su writes under right now, None when leases are off
+    fn lease_fence(&self) -> Result<Option<LeaseFence>, String> {
+        if self.deps.config.lease_ttl() == 0 {
+            return Ok(None);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(Some(LeaseFence {
+            holder: self.instance_id.clone(),
+            now,
+        }))
+    }
+
+    /*
+        save a message built under the process lock. The lease
+        is checked again in the same transaction, a conflict
+        means another su took the process or wrote this nonce,
+        the write is refused and it is logged as an error
+        instead of handed back like any other bad request
+    */
+    pub fn save_message(&self, message: &Message, binary: &[u8]) -> Result<String, String> {
+        let lease = self.lease_fence()?;
+        match self.deps.data_store.save_message(message, binary, &lease) {
+            Err(StoreErrorType::ScheduleConflict(e)) => {
+                self.deps
+                    .metrics
+                    .increment("su_schedule_conflicts_total", &[]);
+                self.deps
+                    .logger
+                    .error(format!("refused to fork the schedule - {}", e));
+                Err(format!("Schedule conflict: {}", e))
+            }
+            result => result.map_err(String::from),
+        }
+    }
+}
+
+// used when there is no SU_INSTANCE_ID or host name, a restart is a new holder
+fn random_instance_id() -> String {
+    let mut randoms: [u8; 8] = [0; 8];
+    let sr = SystemRandom::new();
+    match sr.fill(&mut randoms) {
+        Ok(_) => format!("su-{}", hex::encode(randoms)),
+        Err(_) => format!("su-{}", std::process::id()),
+    }
 }
 
 pub trait DecodeHash: Sized {
@@ -110,7 +293,7 @@ impl DecodeHash for [u8; 32] {
     }
 }
 
-fn gen_hash_chain(
+pub fn gen_hash_chain(
     previous_or_seed: &str,
     previous_message_id: Option<&str>,
 ) -> Result<String, String> {
@@ -140,14 +323,24 @@ fn gen_hash_chain(
 }
 
 /*
-    retrieve the epoch, nonce, hash_chain and timestamp
-    increment the values here because this wont be called
-    again until the lock is released.
+    pick the timestamp for the next item in a process, it is
+    always greater than the previous one. The bool is true if
+    the wall clock was behind the previous timestamp.
+*/
+pub fn next_timestamp(wall: i64, previous: i64) -> (i64, bool) {
+    (wall.max(previous + 1), wall < previous)
+}
+
+/*
+    retrieve the epoch, nonce, hash_chain, the wall clock
+    time and the timestamp of the previous item in the
+    process. increment the values here because this wont
+    be called again until the lock is released.
 */
 async fn fetch_values(
     deps: Arc<SchedulerDeps>,
     process_id: &String,
-) -> Result<(i32, i32, String, i64), String> {
+) -> Result<(i32, i32, String, i64, i64), String> {
     let start_time = SystemTime::now();
     let duration = match start_time.duration_since(UNIX_EPOCH) {
         Ok(d) => d,
@@ -168,11 +361,24 @@ async fn fetch_values(
                 &previous_message.hash_chain().unwrap(),
                 Some(&previous_message.assignment_id().unwrap()),
             )?;
-            Ok((epoch, nonce, hash_chain, millis))
+            let previous_timestamp = previous_message.timestamp().unwrap_or(0);
+            Ok((epoch, nonce, hash_chain, millis, previous_timestamp))
         }
         None => {
             let hash_chain = gen_hash_chain(&process_id, None)?;
-            Ok((0, 0, hash_chain, millis))
+            /*
+                the first message can not be older than the
+                process, which doesnt exist yet if this is
+                the process being created
+            */
+            let previous_timestamp = match deps
+                .data_store
+                .get_process(process_id, &ReadConsistency::Primary)
+            {
+                Ok(process) => process.timestamp,
+                Err(_) => 0,
+            };
+            Ok((0, 0, hash_chain, millis, previous_timestamp))
         }
     }
 }
@@ -194,3 +400,485 @@ impl ScheduleProvider for ScheduleInfo {
         self.hash_chain.to_string()
     }
 }
+
+fn lock_status(locks: &DashMap<String, LockedScheduleInfo>) -> Vec<LockStatus> {
+    let mut statuses: Vec<LockStatus> = locks
+        .iter()
+        .filter_map(|entry| {
+            let references = Arc::strong_count(entry.value()) - 1;
+            let held = entry.value().try_lock().is_err();
+            let waiting = references.saturating_sub(held as usize);
+            match held || waiting > 0 {
+                true => Some(LockStatus {
+                    process_id: entry.key().clone(),
+                    held,
+                    waiting,
+                }),
+                false => None,
+            }
+        })
+        .collect();
+    statuses.sort_by_key(|status| std::cmp::Reverse(status.waiting));
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::core::dal::{
+        DeferredAssignment, MessageCursor, MessageFilter, PaginatedMessages, PendingUpload,
+        PoolStats, Process, ProcessScheduler as RouterProcessScheduler, ReplicationStatus,
+        ScheduleCursor, ScheduleEntry, Scheduler,
+    };
+    use crate::domain::core::json::{AssignmentInner, Owner};
+    use bundlr_sdk::tags::Tag;
+    use std::collections::HashMap;
+    use std::sync::Mutex as StdMutex;
+
+    /*
+        only leases, saves and the latest message are backed,
+        the leases follow the upsert in StoreClient::claim_lease
+    */
+    #[derive(Default)]
+    struct MockDataStore {
+        leases: StdMutex<HashMap<String, (String, i64)>>,
+        saved: StdMutex<usize>,
+        save_error: StdMutex<Option<StoreErrorType>>,
+        latest: StdMutex<Option<Message>>,
+    }
+
+    impl MockDataStore {
+        fn holds(&self, process_id: &str, holder: &str, now: i64) -> bool {
+            match self.leases.lock().unwrap().get(process_id) {
+                Some((lease_holder, expires_at)) => lease_holder == holder && *expires_at >= now,
+                None => false,
+            }
+        }
+    }
+
+    impl DataStore for MockDataStore {
+        fn save_process(&self, _: &Process, _: &[u8], _: &str) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_process_identity(&self, _: &str) -> Result<Option<String>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_process(&self, _: &str, _: &ReadConsistency) -> Result<Process, StoreErrorType> {
+            unimplemented!()
+        }
+        fn save_message(
+            &self,
+            message: &Message,
+            _: &[u8],
+            lease: &Option<LeaseFence>,
+        ) -> Result<String, StoreErrorType> {
+            if let Some(e) = self.save_error.lock().unwrap().take() {
+                return Err(e);
+            }
+            if let Some(fence) = lease {
+                let process_id = message.process_id().unwrap();
+                if !self.holds(&process_id, &fence.holder, fence.now) {
+                    return Err(StoreErrorType::ScheduleConflict("lease lost".to_string()));
+                }
+            }
+            *self.saved.lock().unwrap() += 1;
+            Ok("saved".to_string())
+        }
+        fn get_messages(
+            &self,
+            _: &str,
+            _: &Option<String>,
+            _: &Option<String>,
+            _: &Option<i32>,
+            _: &ReadConsistency,
+        ) -> Result<PaginatedMessages, StoreErrorType> {
+            unimplemented!()
+        }
+        fn search_messages(
+            &self,
+            _: &str,
+            _: &Option<MessageCursor>,
+            _: &Option<i32>,
+            _: &MessageFilter,
+            _: &ReadConsistency,
+        ) -> Result<PaginatedMessages, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_message(&self, _: &str, _: &ReadConsistency) -> Result<Message, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_latest_message(&self, _: &str) -> Result<Option<Message>, StoreErrorType> {
+            Ok(self.latest.lock().unwrap().clone())
+        }
+        fn save_process_scheduler(
+            &self,
+            _: &RouterProcessScheduler,
+        ) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_process_scheduler(&self, _: &str) -> Result<RouterProcessScheduler, StoreErrorType> {
+            unimplemented!()
+        }
+        fn save_scheduler(&self, _: &Scheduler) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn update_scheduler(&self, _: &Scheduler) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_scheduler(&self, _: &i32) -> Result<Scheduler, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_scheduler_by_url(&self, _: &String) -> Result<Scheduler, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn check_existing_message(&self, _: &Message) -> Result<(), StoreErrorType> {
+            unimplemented!()
+        }
+        fn save_deferred_assignment(
+            &self,
+            _: &DeferredAssignment,
+        ) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn update_deferred_assignment(
+            &self,
+            _: &DeferredAssignment,
+        ) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_deferred_assignment(&self, _: &str) -> Result<DeferredAssignment, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_pending_deferred_assignments(
+            &self,
+        ) -> Result<Vec<DeferredAssignment>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn save_pending_upload(&self, _: &[u8]) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_pending_uploads(&self) -> Result<Vec<PendingUpload>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn delete_pending_upload(&self, _: &i32) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn current_lsn(&self) -> Result<String, StoreErrorType> {
+            unimplemented!()
+        }
+        fn replication_status(&self) -> Result<ReplicationStatus, StoreErrorType> {
+            unimplemented!()
+        }
+        fn archive_bundles(&self, _: i64, _: i64) -> Result<usize, StoreErrorType> {
+            unimplemented!()
+        }
+        fn pool_stats(&self) -> PoolStats {
+            unimplemented!()
+        }
+        fn close(&self) {
+            unimplemented!()
+        }
+        fn get_process_ids(
+            &self,
+            _: &Option<String>,
+            _: i64,
+        ) -> Result<Vec<String>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn get_schedule_entries(
+            &self,
+            _: &str,
+            _: &Option<ScheduleCursor>,
+            _: i64,
+            _: &ReadConsistency,
+        ) -> Result<Vec<ScheduleEntry>, StoreErrorType> {
+            unimplemented!()
+        }
+        fn quarantine_messages(&self, _: &[i32], _: &str) -> Result<usize, StoreErrorType> {
+            unimplemented!()
+        }
+        fn create_nonce_index(&self) -> Result<bool, StoreErrorType> {
+            unimplemented!()
+        }
+        fn claim_lease(
+            &self,
+            process_id_in: &str,
+            holder_in: &str,
+            now: i64,
+            expires_at_in: i64,
+        ) -> Result<bool, StoreErrorType> {
+            let mut leases = self.leases.lock().unwrap();
+            let claimable = match leases.get(process_id_in) {
+                Some((holder, expires_at)) => holder == holder_in || *expires_at < now,
+                None => true,
+            };
+            if claimable {
+                leases.insert(
+                    process_id_in.to_string(),
+                    (holder_in.to_string(), expires_at_in),
+                );
+            }
+            Ok(claimable)
+        }
+    }
+
+    struct MockConfig {
+        instance_id: &'static str,
+        lease_ttl: u64,
+    }
+
+    impl Config for MockConfig {
+        fn su_wallet_path(&self) -> String {
+            unimplemented!()
+        }
+        fn upload_node_url(&self) -> String {
+            unimplemented!()
+        }
+        fn gateway_url(&self) -> String {
+            unimplemented!()
+        }
+        fn mode(&self) -> String {
+            "su".to_string()
+        }
+        fn scheduler_list_path(&self) -> String {
+            unimplemented!()
+        }
+        fn deferred_assignment_interval(&self) -> u64 {
+            unimplemented!()
+        }
+        fn shutdown_timeout(&self) -> u64 {
+            unimplemented!()
+        }
+        fn clock_mode(&self) -> String {
+            "wall".to_string()
+        }
+        fn replica_lag_threshold(&self) -> u64 {
+            unimplemented!()
+        }
+        fn cold_storage(&self) -> String {
+            unimplemented!()
+        }
+        fn cold_storage_age(&self) -> u64 {
+            unimplemented!()
+        }
+        fn admin_token(&self) -> Option<String> {
+            unimplemented!()
+        }
+        fn su_wallet_paths(&self) -> Vec<String> {
+            unimplemented!()
+        }
+        fn consistency_check_interval(&self) -> u64 {
+            unimplemented!()
+        }
+        fn su_instance_id(&self) -> Option<String> {
+            Some(self.instance_id.to_string())
+        }
+        fn lease_ttl(&self) -> u64 {
+            self.lease_ttl
+        }
+    }
+
+    struct MockLogger;
+    impl Log for MockLogger {
+        fn log(&self, message: String) {
+            println!("{}", message)
+        }
+        fn error(&self, message: String) {
+            println!("{}", message);
+        }
+    }
+
+    fn scheduler(
+        data_store: &Arc<MockDataStore>,
+        instance_id: &'static str,
+        lease_ttl: u64,
+    ) -> ProcessScheduler {
+        ProcessScheduler::new(Arc::new(SchedulerDeps {
+            data_store: data_store.clone(),
+            logger: Arc::new(MockLogger),
+            config: Arc::new(MockConfig {
+                instance_id,
+                lease_ttl,
+            }),
+            metrics: Arc::new(Metrics::new()),
+        }))
+    }
+
+    // an assignment of process, only the process tag is read
+    fn message(process_id: &str) -> Message {
+        Message {
+            message: None,
+            assignment: AssignmentInner {
+                id: "assignment".to_string(),
+                owner: Owner {
+                    address: "address".to_string(),
+                    key: "key".to_string(),
+                },
+                tags: vec![Tag::new("Process", process_id)],
+                signature: "signature".to_string(),
+                anchor: None,
+                target: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_claim_lease() {
+        let data_store = Arc::new(MockDataStore::default());
+        let first = scheduler(&data_store, "su-1", 30);
+        let second = scheduler(&data_store, "su-2", 30);
+
+        assert!(first.claim_lease("process").is_ok());
+        // renewing our own lease is fine, taking another su's is not
+        assert!(first.claim_lease("process").is_ok());
+        assert!(second.claim_lease("process").is_err());
+        assert_eq!(second.deps.metrics.get("su_lease_conflicts_total", &[]), 1);
+        assert!(second.claim_lease("other").is_ok());
+
+        // a lease that ran out can be taken over
+        data_store
+            .leases
+            .lock()
+            .unwrap()
+            .get_mut("process")
+            .unwrap()
+            .1 = 0;
+        assert!(second.claim_lease("process").is_ok());
+        assert!(first.claim_lease("process").is_err());
+    }
+
+    #[test]
+    fn test_claim_lease_off() {
+        let data_store = Arc::new(MockDataStore::default());
+        let first = scheduler(&data_store, "su-1", 0);
+        let second = scheduler(&data_store, "su-2", 0);
+
+        assert!(first.claim_lease("process").is_ok());
+        assert!(second.claim_lease("process").is_ok());
+        assert!(data_store.leases.lock().unwrap().is_empty());
+        assert!(first.save_message(&message("process"), &[]).is_ok());
+    }
+
+    #[test]
+    fn test_save_message() {
+        let data_store = Arc::new(MockDataStore::default());
+        let first = scheduler(&data_store, "su-1", 30);
+        let second = scheduler(&data_store, "su-2", 30);
+
+        first.claim_lease("process").unwrap();
+        assert!(first.save_message(&message("process"), &[]).is_ok());
+
+        // the lease was taken over after the nonce was read
+        let refused = second.save_message(&message("process"), &[]);
+        assert!(refused.unwrap_err().starts_with("Schedule conflict"));
+        assert_eq!(
+            second.deps.metrics.get("su_schedule_conflicts_total", &[]),
+            1
+        );
+
+        *data_store.save_error.lock().unwrap() = Some(StoreErrorType::ScheduleConflict(
+            "nonce 1 in epoch 0 of process process is already taken".to_string(),
+        ));
+        assert!(first
+            .save_message(&message("process"), &[])
+            .unwrap_err()
+            .starts_with("Schedule conflict"));
+        assert_eq!(
+            first.deps.metrics.get("su_schedule_conflicts_total", &[]),
+            1
+        );
+
+        // any other error is handed back without counting a conflict
+        *data_store.save_error.lock().unwrap() =
+            Some(StoreErrorType::DatabaseError("down".to_string()));
+        assert!(first.save_message(&message("process"), &[]).is_err());
+        assert_eq!(
+            first.deps.metrics.get("su_schedule_conflicts_total", &[]),
+            1
+        );
+        assert_eq!(*data_store.saved.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_schedule_info_clock_regression() {
+        let data_store = Arc::new(MockDataStore::default());
+        let ahead = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+            + 60_000;
+        let mut latest = message("process");
+        latest.assignment.id = "A".repeat(43);
+        for (name, value) in [
+            ("Epoch", "0".to_string()),
+            ("Nonce", "3".to_string()),
+            ("Timestamp", ahead.to_string()),
+            ("Hash-Chain", "A".repeat(43)),
+        ] {
+            latest.assignment.tags.push(Tag::new(name, &value));
+        }
+        *data_store.latest.lock().unwrap() = Some(latest);
+
+        let first = scheduler(&data_store, "su-1", 0);
+        let mut schedule_info = ScheduleInfo {
+            epoch: 0,
+            nonce: 0,
+            timestamp: 0,
+            hash_chain: String::new(),
+        };
+        let updated = first
+            .update_schedule_info(&mut schedule_info, "process".to_string())
+            .await
+            .expect("failed to update schedule info");
+        assert_eq!(updated.nonce, 4);
+        assert_eq!(updated.timestamp, ahead + 1);
+        assert_eq!(first.deps.metrics.get("su_clock_regressions_total", &[]), 1);
+    }
+
+    #[test]
+    fn test_next_timestamp() {
+        assert_eq!(next_timestamp(100, 50), (100, false));
+        // the same millisecond gets a tick, timestamps are cursors
+        assert_eq!(next_timestamp(100, 100), (101, false));
+        assert_eq!(next_timestamp(90, 100), (101, true));
+    }
+
+    fn schedule_info() -> LockedScheduleInfo {
+        Arc::new(Mutex::new(ScheduleInfo {
+            epoch: 0,
+            nonce: 0,
+            timestamp: 0,
+            hash_chain: "".to_string(),
+        }))
+    }
+
+    #[test]
+    fn test_lock_status() {
+        let locks: DashMap<String, LockedScheduleInfo> = DashMap::new();
+        locks.insert("idle".to_string(), schedule_info());
+        locks.insert("held".to_string(), schedule_info());
+        locks.insert("contended".to_string(), schedule_info());
+        assert!(lock_status(&locks).is_empty());
+
+        // a holder keeps a reference and the guard
+        let held = locks.get("held").unwrap().value().clone();
+        let _held_guard = held.try_lock().expect("lock was taken");
+
+        // one holder and two tasks waiting behind it
+        let contended = locks.get("contended").unwrap().value().clone();
+        let _contended_guard = contended.try_lock().expect("lock was taken");
+        let _waiters = [contended.clone(), contended.clone()];
+
+        let statuses = lock_status(&locks);
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].process_id, "contended");
+        assert!(statuses[0].held);
+        assert_eq!(statuses[0].waiting, 2);
+        assert_eq!(statuses[1].process_id, "held");
+        assert!(statuses[1].held);
+        assert_eq!(statuses[1].waiting, 0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout_at, Duration, Instant};
+
+use super::flows::Deps;
+
+// the error of a write refused because the server is draining
+pub const SHUTTING_DOWN: &str = "Server is shutting down";
+
+/*
+    ShutdownState tracks the writes that are in flight
+    so on SIGTERM the server can stop accepting new
+    ones and wait for the ones holding a process lock
+    to finish saving and uploading before it exits
+*/
+pub struct ShutdownState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/*
+    held for the duration of a write, releasing
+    it wakes up anything waiting for the server
+    to become idle
+*/
+pub struct WriteGuard {
+    state: Arc<ShutdownState>,
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        if self.state.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.state.idle.notify_waiters();
+        }
+    }
+}
+
+impl Default for ShutdownState {
+    fn default() -> Self {
+        ShutdownState {
+            draining: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+}
+
+impl ShutdownState {
+    pub fn new() -> Self {
+        ShutdownState::default()
+    }
+
+    pub fn begin_write(self: &Arc<Self>) -> Result<WriteGuard, String> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = WriteGuard {
+            state: self.clone(),
+        };
+        /*
+            check after incrementing so a write can never
+            slip in between start_draining and wait_idle
+        */
+        if self.is_draining() {
+            return Err(SHUTTING_DOWN.to_string());
+        }
+        Ok(guard)
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    // returns false if the deadline passed with writes still in flight
+    pub async fn wait_idle(&self, deadline: Instant) -> bool {
+        loop {
+            let notified = self.idle.notified();
+            if self.in_flight() == 0 {
+                return true;
+            }
+            if timeout_at(deadline, notified).await.is_err() {
+                return self.in_flight() == 0;
+            }
+        }
+    }
+}
+
+pub async fn wait_for_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
+/*
+    stop accepting writes, let in flight writes finish,
+    give the uploader until the deadline to empty its
+    backlog, then stop it and persist whatever is left
+    so it can be uploaded when the server starts back up
+*/
+pub async fn drain(deps: Arc<Deps>) {
+    let deadline = Instant::now() + Duration::from_secs(deps.config.shutdown_timeout());
+
+    deps.shutdown.start_draining();
+    deps.logger.log(format!(
+        "draining, waiting on {} in flight writes",
+        deps.shutdown.in_flight()
+    ));
+
+    if !deps.shutdown.wait_idle(deadline).await {
+        deps.logger.error(format!(
+            "shutdown timeout reached with {} writes in flight",
+            deps.shutdown.in_flight()
+        ));
+    }
+
+    // a failed upload is not retried on its own, it is only persisted
+    while deps.uploader.backlog().iter().any(|bundle| !bundle.failed) && Instant::now() < deadline {
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    /*
+        an upload still in flight may succeed after the
+        snapshot, so the snapshot is only taken once the
+        uploader has stopped
+    */
+    if timeout_at(deadline, deps.uploader.stop()).await.is_err() {
+        deps.logger.error(
+            "shutdown timeout reached with uploads in flight, they may be uploaded again"
+                .to_string(),
+        );
+    }
+
+    let backlog = deps.uploader.unpersisted_backlog();
+    for bundle in backlog.iter() {
+        if let Err(e) = deps.data_store.save_pending_upload(bundle) {
+            deps.logger
+                .error(format!("failed to persist pending upload - {:?}", e));
+        }
+    }
+    deps.logger
+        .log(format!("persisted {} pending uploads", backlog.len()));
+}
+
+/*
+    runs at server startup, queues the uploads
+    persisted during the last shutdown. A persisted
+    row is only deleted once its upload succeeds
+*/
+pub async fn resume_uploads(deps: Arc<Deps>) -> Result<String, String> {
+    let pending = deps.data_store.get_pending_uploads()?;
+    let count = pending.len();
+    for pending_upload in pending {
+        let data_store = deps.data_store.clone();
+        let logger = deps.logger.clone();
+        let row_id = pending_upload.row_id;
+        deps.uploader.upload_persisted(
+            pending_upload.bundle,
+            Box::new(move || {
+                if let Some(row_id) = row_id {
+                    if let Err(e) = data_store.delete_pending_upload(&row_id) {
+                        logger.error(format!("failed to delete pending upload - {:?}", e));
+                    }
+                }
+            }),
+        )?;
+    }
+    Ok(format!("resumed {} pending uploads", count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rejects_writes_while_draining() {
+        let state = Arc::new(ShutdownState::new());
+        let guard = state.begin_write().expect("write should be accepted");
+        state.start_draining();
+        assert!(state.begin_write().is_err());
+        assert_eq!(state.in_flight(), 1);
+        drop(guard);
+        assert_eq!(state.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle() {
+        let state = Arc::new(ShutdownState::new());
+        let guard = state.begin_write().expect("write should be accepted");
+        state.start_draining();
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(!state.wait_idle(deadline).await);
+
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(10)).await;
+            drop(guard);
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert!(state.wait_idle(deadline).await);
+    }
+}
@@ -0,0 +1,324 @@
+use std::sync::Arc;
+
+use super::bytes::DataItem;
+use super::dal::ReadConsistency;
+use super::flows::Deps;
+
+/*
+    Rules an item must follow to be scheduled under the
+    ao Data-Protocol, checked before any process lock is
+    taken so a bad item never holds up the schedule
+*/
+
+const DATA_PROTOCOL: &str = "ao";
+const SUPPORTED_VARIANTS: [&str; 1] = ["ao.TN.1"];
+
+// limits from the ANS-104 spec
+const MAX_TAGS: usize = 128;
+const MAX_TAG_NAME_BYTES: usize = 1024;
+const MAX_TAG_VALUE_BYTES: usize = 3072;
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationErrorType {
+    DataProtocol(String),
+    Variant(String),
+    Type(String),
+    TagLimit(String),
+    Module(String),
+    Scheduler(String),
+    Target(String),
+}
+
+impl ValidationErrorType {
+    // the label the violation is counted under in metrics
+    pub fn rule(&self) -> &'static str {
+        match self {
+            ValidationErrorType::DataProtocol(_) => "data_protocol",
+            ValidationErrorType::Variant(_) => "variant",
+            ValidationErrorType::Type(_) => "type",
+            ValidationErrorType::TagLimit(_) => "tag_limit",
+            ValidationErrorType::Module(_) => "module",
+            ValidationErrorType::Scheduler(_) => "scheduler",
+            ValidationErrorType::Target(_) => "target",
+        }
+    }
+}
+
+impl From<ValidationErrorType> for String {
+    fn from(error: ValidationErrorType) -> Self {
+        match error {
+            ValidationErrorType::DataProtocol(e)
+            | ValidationErrorType::Variant(e)
+            | ValidationErrorType::Type(e)
+            | ValidationErrorType::TagLimit(e)
+            | ValidationErrorType::Module(e)
+            | ValidationErrorType::Scheduler(e)
+            | ValidationErrorType::Target(e) => e,
+        }
+    }
+}
+
+// arweave ids are 32 bytes, 43 characters of unpadded base64url
+pub fn is_valid_id(id: &str) -> bool {
+    id.len() == 43
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn tag_values(data_item: &DataItem, name: &str) -> Vec<String> {
+    data_item
+        .tags()
+        .into_iter()
+        .filter(|tag| tag.name == name)
+        .map(|tag| tag.value)
+        .collect()
+}
+
+fn single_tag(data_item: &DataItem, name: &str) -> Result<Option<String>, String> {
+    let values = tag_values(data_item, name);
+    match values.len() {
+        0 => Ok(None),
+        1 => Ok(values.into_iter().next()),
+        _ => Err(format!("{} tag must only be present once", name)),
+    }
+}
+
+fn check_id_tag(
+    data_item: &DataItem,
+    name: &str,
+    error: fn(String) -> ValidationErrorType,
+) -> Result<(), ValidationErrorType> {
+    match single_tag(data_item, name).map_err(error)? {
+        Some(id) if is_valid_id(&id) => Ok(()),
+        Some(id) => Err(error(format!(
+            "{} tag must be a 43 character base64url id, got {}",
+            name, id
+        ))),
+        None => Err(error(format!("{} tag is required for a Process", name))),
+    }
+}
+
+/*
+    the checks that only need the item itself, returns
+    the Type of the item so the caller can dispatch on it
+*/
+pub fn validate_item(data_item: &DataItem) -> Result<String, ValidationErrorType> {
+    let tags = data_item.tags();
+    if tags.len() > MAX_TAGS {
+        return Err(ValidationErrorType::TagLimit(format!(
+            "Item has {} tags, the limit is {}",
+            tags.len(),
+            MAX_TAGS
+        )));
+    }
+    for tag in tags.iter() {
+        if tag.name.is_empty() || tag.name.len() > MAX_TAG_NAME_BYTES {
+            return Err(ValidationErrorType::TagLimit(format!(
+                "Tag names must be between 1 and {} bytes",
+                MAX_TAG_NAME_BYTES
+            )));
+        }
+        if tag.value.len() > MAX_TAG_VALUE_BYTES {
+            return Err(ValidationErrorType::TagLimit(format!(
+                "Value of tag {} is over {} bytes",
+                tag.name, MAX_TAG_VALUE_BYTES
+            )));
+        }
+    }
+
+    /*
+        some clients write Data-Protocol more than once,
+        that is fine as long as one of them is ao
+    */
+    let protocols = tag_values(data_item, "Data-Protocol");
+    if protocols.is_empty() {
+        return Err(ValidationErrorType::DataProtocol(
+            "Data-Protocol tag not present".to_string(),
+        ));
+    }
+    if !protocols.iter().any(|p| p == DATA_PROTOCOL) {
+        return Err(ValidationErrorType::DataProtocol(format!(
+            "Data-Protocol must be {}, got {}",
+            DATA_PROTOCOL,
+            protocols.join(", ")
+        )));
+    }
+
+    match single_tag(data_item, "Variant").map_err(ValidationErrorType::Variant)? {
+        Some(variant) if SUPPORTED_VARIANTS.contains(&variant.as_str()) => (),
+        Some(variant) => {
+            return Err(ValidationErrorType::Variant(format!(
+                "Unsupported Variant {}, expected one of {}",
+                variant,
+                SUPPORTED_VARIANTS.join(", ")
+            )))
+        }
+        None => {
+            return Err(ValidationErrorType::Variant(
+                "Variant tag not present".to_string(),
+            ))
+        }
+    }
+
+    let item_type = match single_tag(data_item, "Type").map_err(ValidationErrorType::Type)? {
+        Some(t) => t,
+        None => {
+            return Err(ValidationErrorType::Type(
+                "Type tag not present".to_string(),
+            ))
+        }
+    };
+
+    match item_type.as_str() {
+        "Process" => {
+            check_id_tag(data_item, "Module", ValidationErrorType::Module)?;
+            check_id_tag(data_item, "Scheduler", ValidationErrorType::Scheduler)?;
+        }
+        "Message" => {
+            let target = data_item.target();
+            if target.is_empty() {
+                return Err(ValidationErrorType::Target(
+                    "Message must have a target process".to_string(),
+                ));
+            }
+            if !is_valid_id(&target) {
+                return Err(ValidationErrorType::Target(format!(
+                    "Message target must be a 43 character base64url id, got {}",
+                    target
+                )));
+            }
+        }
+        other => {
+            return Err(ValidationErrorType::Type(format!(
+                "Type must be Process or Message, got {}",
+                other
+            )))
+        }
+    }
+
+    Ok(item_type)
+}
+
+/*
+    validate_item plus the checks that need the database,
+    every violation is counted by rule in metrics
+*/
+pub fn check_item(deps: &Arc<Deps>, data_item: &DataItem) -> Result<String, String> {
+    let result = validate_item(data_item).and_then(|item_type| {
+        if item_type == "Process" {
+            let scheduler = single_tag(data_item, "Scheduler")
+                .map_err(ValidationErrorType::Scheduler)?
+                .unwrap_or_default();
+            if deps.identities.get(&scheduler).is_none() {
+                return Err(ValidationErrorType::Scheduler(format!(
+                    "Scheduler {} is not hosted by this su",
+                    scheduler
+                )));
+            }
+        }
+        if item_type == "Message" {
+            let target = data_item.target();
+            if deps
+                .data_store
+                .get_process(&target, &ReadConsistency::Primary)
+                .is_err()
+            {
+                return Err(ValidationErrorType::Target(format!(
+                    "Message target {} is not a known process",
+                    target
+                )));
+            }
+        }
+        Ok(item_type)
+    });
+
+    match result {
+        Ok(item_type) => Ok(item_type),
+        Err(e) => {
+            deps.metrics
+                .increment("su_validation_failures_total", &[("rule", e.rule())]);
+            Err(e.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bundlr_sdk::tags::Tag;
+
+    const ID: &str = "-oM8CYgbqsRcpI3tE_cpGM3kgDlamnYjSGA4nptPao0";
+
+    fn item(target: Vec<u8>, tags: Vec<(&str, &str)>) -> DataItem {
+        let tags = tags
+            .iter()
+            .map(|(name, value)| Tag::new(name, value))
+            .collect();
+        DataItem::new(target, vec![1], tags, vec![]).expect("failed to build data item")
+    }
+
+    fn process_tags<'a>() -> Vec<(&'a str, &'a str)> {
+        vec![
+            ("Data-Protocol", "ao"),
+            ("Variant", "ao.TN.1"),
+            ("Type", "Process"),
+            ("Module", ID),
+            ("Scheduler", ID),
+        ]
+    }
+
+    #[test]
+    fn test_valid_items() {
+        let process = item(vec![], process_tags());
+        assert_eq!(validate_item(&process), Ok("Process".to_string()));
+
+        let target = base64_url::decode(ID).expect("failed to decode id");
+        let message = item(
+            target,
+            vec![
+                ("Data-Protocol", "ao"),
+                ("Variant", "ao.TN.1"),
+                ("Type", "Message"),
+            ],
+        );
+        assert_eq!(validate_item(&message), Ok("Message".to_string()));
+    }
+
+    #[test]
+    fn test_rule_violations() {
+        let mut tags = process_tags();
+        tags[0] = ("Data-Protocol", "other");
+        let err = validate_item(&item(vec![], tags)).unwrap_err();
+        assert_eq!(err.rule(), "data_protocol");
+
+        let mut tags = process_tags();
+        tags[1] = ("Variant", "ao.TN.0");
+        assert_eq!(
+            validate_item(&item(vec![], tags)).unwrap_err().rule(),
+            "variant"
+        );
+
+        let mut tags = process_tags();
+        tags[3] = ("Module", "not-an-id");
+        assert_eq!(
+            validate_item(&item(vec![], tags)).unwrap_err().rule(),
+            "module"
+        );
+
+        let mut tags = process_tags();
+        tags[2] = ("Type", "Message");
+        assert_eq!(
+            validate_item(&item(vec![], tags)).unwrap_err().rule(),
+            "target"
+        );
+
+        let mut tags = process_tags();
+        let long_value = "a".repeat(MAX_TAG_VALUE_BYTES + 1);
+        tags.push(("Name", &long_value));
+        assert_eq!(
+            validate_item(&item(vec![], tags)).unwrap_err().rule(),
+            "tag_limit"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
-use env_logger::Env;
-use log::{error, info};
+use tracing::{error, info};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
 
 use crate::domain::Log;
 
@@ -9,17 +11,74 @@ pub struct SuLog;
 
 /*
 Logging instance, using an instance of this
-instead of the env_logger macros throughout
-the code
+instead of the tracing macros throughout
+the code. Lines are json and carry the fields
+of every span they were logged in, so a write
+can be followed by its request id
 */
 
 impl SuLog {
-    pub fn init() -> Arc<dyn Log> {
-        env_logger::init_from_env(Env::default().default_filter_or("info"));
+    pub fn init(otlp_endpoint: Option<String>) -> Arc<dyn Log> {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        let json = tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true);
+        let registry = tracing_subscriber::registry().with(filter).with(json);
+
+        #[cfg(feature = "otlp")]
+        match otlp_endpoint {
+            Some(endpoint) => registry.with(otlp::layer(&endpoint)).init(),
+            None => registry.init(),
+        }
+
+        #[cfg(not(feature = "otlp"))]
+        {
+            registry.init();
+            if otlp_endpoint.is_some() {
+                error!("OTEL_EXPORTER_OTLP_ENDPOINT is set but the su was built without the otlp feature");
+            }
+        }
+
         Arc::new(SuLog {})
     }
 }
 
+// flush spans still waiting in the exporter batch
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub fn layer<S>(endpoint: &str) -> OpenTelemetryLayer<S, trace::Tracer>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", "ao-su")])),
+            )
+            .install_batch(runtime::Tokio)
+            .expect("Failed to install the otlp exporter");
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    }
+}
+
 impl Log for SuLog {
     fn log(&self, message: String) {
         info!("{}", message);
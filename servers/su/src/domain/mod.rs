@@ -10,28 +10,58 @@ use clients::{
     wallet::FileWallet,
 };
 use config::AoConfig;
-use core::dal::{Config, Gateway, Log};
+use core::dal::{Config, DataStore, Gateway, Log, Signer};
 use logger::SuLog;
 
+pub use core::admin;
+pub use core::consistency;
+pub use core::deferred;
 pub use core::flows;
+pub use core::graphql;
+pub use core::receipt;
+pub use core::responses;
 pub use core::router;
+pub use core::shutdown;
 pub use flows::Deps;
+pub use logger::shutdown as shutdown_logger;
 
 pub async fn init_deps(mode: Option<String>) -> Arc<Deps> {
-    let logger: Arc<dyn Log> = SuLog::init();
+    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+
+    let logger: Arc<dyn Log> = SuLog::init(config.otlp_endpoint.clone());
 
     let data_store = Arc::new(StoreClient::new().expect("Failed to create StoreClient"));
 
-    match data_store.run_migrations() {
-        Ok(m) => logger.log(m),
-        Err(e) => logger.log(format!("{:?}", e)),
+    /*
+        the schedule relies on the lease table, serving
+        without it could fork the schedule
+    */
+    let migrations = data_store
+        .run_migrations()
+        .expect("Failed to run migrations");
+    logger.log(migrations);
+
+    /*
+        a database with duplicate nonces still starts so they
+        can be repaired, writes rely on the lease until then
+    */
+    match data_store.create_nonce_index() {
+        Ok(true) => (),
+        Ok(false) => logger.error(
+            "the unique nonce index is missing because a schedule has a duplicate nonce, \
+             repair it with POST /admin/consistency/{process_id}/repair"
+                .to_string(),
+        ),
+        Err(e) => logger.error(format!("failed to create the unique nonce index - {:?}", e)),
     }
 
-    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+    let metrics = Arc::new(core::metrics::Metrics::new());
 
     let scheduler_deps = Arc::new(core::scheduler::SchedulerDeps {
         data_store: data_store.clone(),
         logger: logger.clone(),
+        config: config.clone(),
+        metrics: metrics.clone(),
     });
     let scheduler = Arc::new(core::scheduler::ProcessScheduler::new(scheduler_deps));
 
@@ -41,15 +71,47 @@ pub async fn init_deps(mode: Option<String>) -> Arc<Deps> {
             .expect("Failed to initialize gateway"),
     );
 
-    let signer =
+    let signer: Arc<dyn Signer> =
         Arc::new(ArweaveSigner::new(&config.su_wallet_path).expect("Invalid su wallet path"));
 
+    let other_signers: Vec<Arc<dyn Signer>> = config
+        .su_wallet_paths
+        .iter()
+        .map(|path| {
+            let other: Arc<dyn Signer> =
+                Arc::new(ArweaveSigner::new(path).expect("Invalid path in SU_WALLET_PATHS"));
+            other
+        })
+        .collect();
+    let identities = Arc::new(core::identities::Identities::new(
+        signer.clone(),
+        other_signers,
+    ));
+    logger.log(format!(
+        "scheduling as {}",
+        identities.addresses().join(", ")
+    ));
+    logger.log(format!(
+        "holding process leases as {}",
+        scheduler.instance_id()
+    ));
+    if config.su_instance_id.is_none() && config.lease_ttl > 0 {
+        logger.error(format!(
+            "SU_INSTANCE_ID is not set and the host name could not be read, after a restart this su waits up to {} seconds for its own leases to run out",
+            config.lease_ttl
+        ));
+    }
+
     let wallet = Arc::new(FileWallet);
 
     let uploader = Arc::new(
         UploaderClient::new(&config.upload_node_url, logger.clone()).expect("Invalid uploader url"),
     );
 
+    let shutdown = Arc::new(core::shutdown::ShutdownState::new());
+
+    let consistency = Arc::new(core::consistency::ConsistencyState::new());
+
     Arc::new(Deps {
         data_store,
         logger,
@@ -59,5 +121,9 @@ pub async fn init_deps(mode: Option<String>) -> Arc<Deps> {
         signer,
         wallet,
         uploader,
+        shutdown,
+        metrics,
+        identities,
+        consistency,
     })
 }
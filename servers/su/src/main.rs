@@ -1,25 +1,50 @@
 use std::env;
 use std::io::{self, Error, ErrorKind};
 use std::sync::Arc;
+use std::time::Instant;
 
 use actix_cors::Cors;
 use actix_web::{
-    http::header::LOCATION, middleware::Logger, web, App, HttpRequest, HttpResponse, HttpServer,
-    Responder,
+    dev::{Service, ServiceRequest},
+    http::header::{HeaderName, HeaderValue, AUTHORIZATION, LOCATION},
+    web, App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
+use ring::rand::SecureRandom;
+use tracing::Instrument;
 
 use serde::Deserialize;
-use serde_json::json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi};
 
-use su::domain::{flows, init_deps, router, Deps};
+use su::domain::graphql::{self, SuSchema};
+use su::domain::responses::{
+    to_json, AssignmentInner, BundleItemResult, BundleResponse, ChainBreak, ConsistencyReport,
+    ConsistencySummary, DeferredAssignment, DeferredTicket, DuplicateNonce, Edge, ErrorResponse,
+    HealthResponse, LockStatus, LocksResponse, Message, MessageInner, NetworkInfo, NonceGap, Owner,
+    PageInfo, PaginatedMessages, PoolState, PoolStats, Process, ReadResponse, Receipt,
+    ReloadSchedulersResponse, RepairResponse, ReplicationStatus, RetryUploadsResponse,
+    SignedReceipt, StatusResponse, Tag, TimestampResponse, UploadBacklogResponse, UploadEntry,
+    VerifyReceiptResponse, WriteReceipt, WriteResponse,
+};
+use su::domain::{
+    admin, consistency, deferred, flows, init_deps, router, shutdown, shutdown_logger, Deps,
+};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct FromTo {
+    /// sort key to read messages after
     from: Option<String>,
+    /// sort key to read messages up to
     to: Option<String>,
+    /// how many messages to return in a page
     limit: Option<i32>,
+    /// the process to read when tx_id is a message id
     #[serde(rename = "process-id")]
     process_id: Option<String>,
+    /// read at least up to this lsn, returned on every write
+    #[serde(rename = "min-lsn")]
+    min_lsn: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -27,8 +52,10 @@ struct TxId {
     tx_id: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ProcessId {
+    /// the process to route the request for in router mode
     #[serde(rename = "process-id")]
     process_id: Option<String>,
 }
@@ -38,24 +65,95 @@ struct ProcessIdRequired {
     process_id: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MinLsn {
+    /// read at least up to this lsn, returned on every write
+    #[serde(rename = "min-lsn")]
+    min_lsn: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct OptionalAssign {
+    /// the process to assign to, sent together with assign
     #[serde(rename = "process-id")]
     process_id: Option<String>,
+    /// id of an existing message or base layer tx to assign
     assign: Option<String>,
-    // base-layer is either present or not, it has no value
+    /// base-layer is either present or not, it has no value
     #[serde(rename = "base-layer")]
     base_layer: Option<String>,
+    /// comma separated fields to leave out of the assignment
     exclude: Option<String>,
+    /// defer is either present or not, it has no value
+    defer: Option<String>,
+}
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/*
+    a caller can pass its own X-Request-Id to tie our logs
+    to its own, anything that doesnt look like an id is
+    replaced with a fresh one
+*/
+fn request_id(req: &ServiceRequest) -> String {
+    let provided = req
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .filter(|id| id.chars().all(|c| c.is_ascii_graphic()));
+    match provided {
+        Some(id) => id.to_string(),
+        None => {
+            let mut randoms: [u8; 16] = [0; 16];
+            let sr = ring::rand::SystemRandom::new();
+            match sr.fill(&mut randoms) {
+                Ok(_) => hex::encode(randoms),
+                Err(_) => "unknown".to_string(),
+            }
+        }
+    }
+}
+
+fn error_body(err: String) -> String {
+    to_json(&ErrorResponse { error: err }).unwrap_or_default()
+}
+
+fn status_body(status: &str) -> String {
+    to_json(&StatusResponse {
+        status: status.to_string(),
+    })
+    .unwrap_or_default()
 }
 
 fn err_response(err: String) -> HttpResponse {
-    let error_json = json!({ "error": err });
     HttpResponse::BadRequest()
         .content_type("application/json")
-        .body(error_json.to_string())
+        .body(error_body(err))
 }
 
+// a write refused because the server started draining is not the client's fault
+fn write_err_response(err: String) -> HttpResponse {
+    if err == shutdown::SHUTTING_DOWN {
+        return HttpResponse::ServiceUnavailable()
+            .content_type("application/json")
+            .body(error_body(err));
+    }
+    err_response(err)
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    params(ProcessId),
+    responses(
+        (status = 200, description = "Su wallet addresses and time", body = HealthResponse),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
 async fn base(
     deps: web::Data<Arc<Deps>>,
     query_params: web::Query<ProcessId>,
@@ -82,6 +180,16 @@ async fn base(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/timestamp",
+    params(ProcessId),
+    responses(
+        (status = 200, description = "Current time and block height", body = TimestampResponse),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
 async fn timestamp_route(
     deps: web::Data<Arc<Deps>>,
     query_params: web::Query<ProcessId>,
@@ -108,12 +216,37 @@ async fn timestamp_route(
     }
 }
 
+/*
+    the body is a signed ans104 data item, a Process,
+    a Message or a Bundle-Format binary bundle of them.
+    With process-id and assign the body is ignored
+*/
+#[utoipa::path(
+    post,
+    path = "/",
+    params(OptionalAssign),
+    request_body(
+        content = Vec<u8>,
+        content_type = "application/octet-stream",
+        description = "Signed ans104 data item"
+    ),
+    responses(
+        (status = 200, description = "Signed receipt, bundle results or deferred ticket", body = WriteResponse),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 503, description = "Server is shutting down", body = ErrorResponse)
+    )
+)]
 async fn main_post_route(
     deps: web::Data<Arc<Deps>>,
     req_body: web::Bytes,
     req: HttpRequest,
     query_params: web::Query<OptionalAssign>,
 ) -> impl Responder {
+    if deps.shutdown.is_draining() {
+        return write_err_response(shutdown::SHUTTING_DOWN.to_string());
+    }
+
     match router::redirect_data_item(
         deps.get_ref().clone(),
         req_body.to_vec(),
@@ -139,16 +272,30 @@ async fn main_post_route(
         query_params.assign.clone(),
         query_params.base_layer.clone(),
         query_params.exclude.clone(),
+        query_params.defer.clone(),
     )
     .await
     {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => write_err_response(err.to_string()),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/{tx_id}",
+    params(
+        ("tx_id" = String, Path, description = "A message id or a process id"),
+        FromTo
+    ),
+    responses(
+        (status = 200, description = "The message, or a page of messages for a process", body = ReadResponse),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
 async fn main_get_route(
     deps: web::Data<Arc<Deps>>,
     req: HttpRequest,
@@ -160,6 +307,7 @@ async fn main_get_route(
     let to_sort_key = query_params.to.clone();
     let limit = query_params.limit.clone();
     let process_id = query_params.process_id.clone();
+    let min_lsn = query_params.min_lsn.clone();
 
     match router::redirect_tx_id(deps.get_ref().clone(), tx_id.clone(), process_id.clone()).await {
         Ok(Some(redirect_url)) => {
@@ -178,6 +326,7 @@ async fn main_get_route(
         from_sort_key,
         to_sort_key,
         limit,
+        min_lsn,
     )
     .await;
 
@@ -189,12 +338,27 @@ async fn main_get_route(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/processes/{process_id}",
+    params(
+        ("process_id" = String, Path, description = "Process id"),
+        MinLsn
+    ),
+    responses(
+        (status = 200, description = "The process", body = Process),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
 async fn read_process_route(
     deps: web::Data<Arc<Deps>>,
     req: HttpRequest,
     path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<MinLsn>,
 ) -> impl Responder {
     let process_id = path.process_id.clone();
+    let min_lsn = query_params.min_lsn.clone();
 
     match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
         Ok(Some(redirect_url)) => {
@@ -207,7 +371,7 @@ async fn read_process_route(
         Err(err) => return err_response(err.to_string()),
     }
 
-    match flows::read_process(deps.get_ref().clone(), process_id).await {
+    match flows::read_process(deps.get_ref().clone(), process_id, min_lsn).await {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
@@ -215,8 +379,473 @@ async fn read_process_route(
     }
 }
 
-async fn health_check() -> impl Responder {
+#[utoipa::path(
+    post,
+    path = "/receipts/verify",
+    request_body = SignedReceipt,
+    responses(
+        (status = 200, description = "Whether the receipt was signed by this su", body = VerifyReceiptResponse),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
+async fn verify_receipt_route(deps: web::Data<Arc<Deps>>, req_body: web::Bytes) -> impl Responder {
+    match flows::verify_receipt_json(deps.get_ref().clone(), req_body.to_vec()).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct Ticket {
+    ticket: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments/{ticket}",
+    params(
+        ("ticket" = String, Path, description = "Ticket returned by a deferred assignment"),
+        ProcessId
+    ),
+    responses(
+        (status = 200, description = "The deferred assignment", body = DeferredAssignment),
+        (status = 307, description = "The process lives on another su"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
+async fn read_deferred_assignment_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<Ticket>,
+    query_params: web::Query<ProcessId>,
+) -> impl Responder {
+    let ticket = path.ticket.clone();
+    let process_id = query_params.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), process_id).await {
+        Ok(Some(redirect_url)) => {
+            let target_url = format!("{}{}", redirect_url, req.uri());
+            return HttpResponse::TemporaryRedirect()
+                .insert_header((LOCATION, target_url))
+                .finish();
+        }
+        Ok(None) => (),
+        Err(err) => return err_response(err.to_string()),
+    }
+
+    match deferred::read_deferred_assignment(deps.get_ref().clone(), ticket).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    request_body(
+        content = Object,
+        description = "A graphql request with query, variables and operationName"
+    ),
+    responses(
+        (status = 200, description = "A graphql response with data and errors", body = Object),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
+async fn graphql_route(
+    deps: web::Data<Arc<Deps>>,
+    schema: web::Data<SuSchema>,
+    req_body: web::Bytes,
+) -> impl Responder {
+    match graphql::execute(deps.get_ref().clone(), schema.get_ref(), req_body.to_vec()).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/replication",
+    responses(
+        (status = 200, description = "Read replica lag", body = ReplicationStatus),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
+async fn replication_route(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    match flows::replication(deps.get_ref().clone()).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 400, description = "Error", body = ErrorResponse)
+    )
+)]
+async fn metrics_route(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    match flows::metrics(deps.get_ref().clone()).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(processed_str),
+        Err(err) => err_response(err.to_string()),
+    }
+}
+
+// liveness, the server stays alive while it drains
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses((status = 200, description = "ok or draining", body = StatusResponse))
+)]
+async fn health_check(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    let status = match deps.shutdown.is_draining() {
+        true => "draining",
+        false => "ok",
+    };
     HttpResponse::Ok()
+        .content_type("application/json")
+        .body(status_body(status))
+}
+
+// readiness, stop routing traffic here once draining starts
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "ready", body = StatusResponse),
+        (status = 503, description = "draining", body = StatusResponse)
+    )
+)]
+async fn ready_check(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    match deps.shutdown.is_draining() {
+        true => HttpResponse::ServiceUnavailable()
+            .content_type("application/json")
+            .body(status_body("draining")),
+        false => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(status_body("ready")),
+    }
+}
+
+/*
+    admin routes need ADMIN_TOKEN as a bearer token,
+    without a token configured they are not served
+*/
+fn check_admin(deps: &Arc<Deps>, req: &HttpRequest) -> Result<(), HttpResponse> {
+    check_admin_token(deps.config.admin_token(), req)
+}
+
+fn check_admin_token(token: Option<String>, req: &HttpRequest) -> Result<(), HttpResponse> {
+    let token = match token {
+        Some(t) => t,
+        None => return Err(HttpResponse::NotFound().finish()),
+    };
+    let provided = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or("");
+    match ring::constant_time::verify_slices_are_equal(provided.as_bytes(), token.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(HttpResponse::Unauthorized()
+            .content_type("application/json")
+            .body(error_body("Invalid admin token".to_string()))),
+    }
+}
+
+fn admin_response(result: Result<String, String>) -> HttpResponse {
+    match result {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/locks",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Process locks", body = LocksResponse),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_locks_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::locks(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/uploads",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Bundles waiting to upload", body = UploadBacklogResponse),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_uploads_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::upload_backlog(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/uploads/retry",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Number of uploads retried", body = RetryUploadsResponse),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_retry_uploads_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::retry_uploads(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/schedulers/reload",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Router schedulers reloaded", body = ReloadSchedulersResponse),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_reload_schedulers_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::reload_schedulers(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/pools",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Database pool usage", body = PoolStats),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_pools_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::pool_stats(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/network-info/refresh",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Refreshed network info", body = NetworkInfo),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_refresh_network_info_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(admin::refresh_network_info(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/consistency",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Processes the background check found problems in", body = ConsistencySummary),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_consistency_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(consistency::consistency_report(deps.get_ref().clone()).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/consistency/{process_id}",
+    security(("admin_token" = [])),
+    params(("process_id" = String, Path, description = "Process id")),
+    responses(
+        (status = 200, description = "A fresh check of the process schedule", body = ConsistencyReport),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_check_consistency_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(
+        consistency::check_process(deps.get_ref().clone(), path.process_id.clone()).await,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/consistency/{process_id}/repair",
+    security(("admin_token" = [])),
+    params(("process_id" = String, Path, description = "Process id")),
+    responses(
+        (status = 200, description = "Messages quarantined and the schedule after", body = RepairResponse),
+        (status = 400, description = "Error", body = ErrorResponse),
+        (status = 401, description = "Invalid admin token", body = ErrorResponse),
+        (status = 404, description = "ADMIN_TOKEN is not set")
+    )
+)]
+async fn admin_repair_consistency_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    if let Err(response) = check_admin(deps.get_ref(), &req) {
+        return response;
+    }
+    admin_response(
+        consistency::repair_process(deps.get_ref().clone(), path.process_id.clone()).await,
+    )
+}
+
+struct AdminSecurity;
+
+impl Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ao su"),
+    paths(
+        base,
+        main_post_route,
+        timestamp_route,
+        verify_receipt_route,
+        graphql_route,
+        health_check,
+        ready_check,
+        replication_route,
+        metrics_route,
+        admin_locks_route,
+        admin_uploads_route,
+        admin_retry_uploads_route,
+        admin_reload_schedulers_route,
+        admin_pools_route,
+        admin_refresh_network_info_route,
+        admin_consistency_route,
+        admin_check_consistency_route,
+        admin_repair_consistency_route,
+        main_get_route,
+        read_process_route,
+        read_deferred_assignment_route
+    ),
+    components(schemas(
+        AssignmentInner,
+        BundleItemResult,
+        BundleResponse,
+        ChainBreak,
+        ConsistencyReport,
+        ConsistencySummary,
+        DeferredAssignment,
+        DeferredTicket,
+        DuplicateNonce,
+        Edge,
+        ErrorResponse,
+        HealthResponse,
+        LockStatus,
+        LocksResponse,
+        Message,
+        MessageInner,
+        NetworkInfo,
+        NonceGap,
+        Owner,
+        PageInfo,
+        PaginatedMessages,
+        PoolState,
+        PoolStats,
+        Process,
+        ReadResponse,
+        Receipt,
+        ReloadSchedulersResponse,
+        RepairResponse,
+        ReplicationStatus,
+        RetryUploadsResponse,
+        SignedReceipt,
+        StatusResponse,
+        Tag,
+        TimestampResponse,
+        UploadBacklogResponse,
+        UploadEntry,
+        VerifyReceiptResponse,
+        WriteReceipt,
+        WriteResponse
+    )),
+    modifiers(&AdminSecurity)
+)]
+struct ApiDoc;
+
+async fn openapi_route() -> impl Responder {
+    match ApiDoc::openapi().to_json() {
+        Ok(spec) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(spec),
+        Err(err) => err_response(err.to_string()),
+    }
 }
 
 #[actix_web::main]
@@ -250,27 +879,235 @@ async fn main() -> io::Result<()> {
             Err(e) => run_deps.logger.log(format!("{}", e)),
             Ok(m) => run_deps.logger.log(format!("{}", m)),
         };
+    } else {
+        match shutdown::resume_uploads(run_deps.clone()).await {
+            Err(e) => run_deps.logger.error(e),
+            Ok(m) => run_deps.logger.log(m),
+        };
+        deferred::init_deferred_watcher(run_deps.clone());
+        flows::init_replication_monitor(run_deps.clone());
+        consistency::init_consistency_checker(run_deps.clone());
+        if run_deps.config.cold_storage() != "none" {
+            flows::init_bundle_archiver(run_deps.clone());
+        }
     }
 
-    HttpServer::new(move || {
+    let schema = web::Data::new(graphql::init_schema(run_deps.clone()));
+
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(
                 Cors::default()
                     .allow_any_origin()
                     .allow_any_method()
-                    .allow_any_header(),
+                    .allow_any_header()
+                    .expose_headers(vec![REQUEST_ID]),
             )
-            .wrap(Logger::default())
+            /*
+                every request runs in a span carrying its id so
+                the logs of the flows it calls can be tied to it
+            */
+            .wrap_fn(|req, srv| {
+                let request_id = request_id(&req);
+                let span = tracing::info_span!(
+                    "request",
+                    request_id = %request_id,
+                    method = %req.method(),
+                    path = %req.path()
+                );
+                let started = Instant::now();
+                let response = span.in_scope(|| srv.call(req));
+                async move {
+                    let mut response = response.await?;
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        response.headers_mut().insert(REQUEST_ID, value);
+                    }
+                    tracing::info!(
+                        status = response.status().as_u16(),
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "request finished"
+                    );
+                    Ok(response)
+                }
+                .instrument(span)
+            })
             .app_data(wrapped.clone())
+            .app_data(schema.clone())
             .app_data(web::PayloadConfig::new(10485760))
             .route("/", web::get().to(base))
             .route("/", web::post().to(main_post_route))
             .route("/timestamp", web::get().to(timestamp_route))
+            .route("/receipts/verify", web::post().to(verify_receipt_route))
+            .route("/graphql", web::post().to(graphql_route))
             .route("/health", web::get().to(health_check))
+            .route("/ready", web::get().to(ready_check))
+            .route("/replication", web::get().to(replication_route))
+            .route("/metrics", web::get().to(metrics_route))
+            .route("/openapi.json", web::get().to(openapi_route))
+            .service(
+                web::scope("/admin")
+                    .route("/locks", web::get().to(admin_locks_route))
+                    .route("/uploads", web::get().to(admin_uploads_route))
+                    .route("/uploads/retry", web::post().to(admin_retry_uploads_route))
+                    .route(
+                        "/schedulers/reload",
+                        web::post().to(admin_reload_schedulers_route),
+                    )
+                    .route("/pools", web::get().to(admin_pools_route))
+                    .route(
+                        "/network-info/refresh",
+                        web::post().to(admin_refresh_network_info_route),
+                    )
+                    .route("/consistency", web::get().to(admin_consistency_route))
+                    .route(
+                        "/consistency/{process_id}",
+                        web::get().to(admin_check_consistency_route),
+                    )
+                    .route(
+                        "/consistency/{process_id}/repair",
+                        web::post().to(admin_repair_consistency_route),
+                    ),
+            )
             .route("/{tx_id}", web::get().to(main_get_route))
             .route("/processes/{process_id}", web::get().to(read_process_route))
+            .route(
+                "/assignments/{ticket}",
+                web::get().to(read_deferred_assignment_route),
+            )
     })
+    .disable_signals()
     .bind(("0.0.0.0", port))?
-    .run()
-    .await
+    .run();
+
+    /*
+        handle SIGTERM ourselves so in flight writes and
+        uploads are drained before actix stops the workers
+    */
+    let server_handle = server.handle();
+    let shutdown_deps = run_deps.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        shutdown::drain(shutdown_deps.clone()).await;
+        server_handle.stop(true).await;
+        shutdown_deps.logger.log("server stopped".to_string());
+    });
+
+    let result = server.await;
+
+    /*
+        background tasks still hold deps, so the pools
+        are closed here rather than when deps drops
+    */
+    run_deps.data_store.close();
+    shutdown_logger();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_spec() {
+        let spec: serde_json::Value =
+            serde_json::from_str(&ApiDoc::openapi().to_json().expect("failed to build spec"))
+                .expect("spec is not json");
+
+        let post = &spec["paths"]["/"]["post"];
+        let params: Vec<&str> = post["parameters"]
+            .as_array()
+            .expect("no parameters")
+            .iter()
+            .filter_map(|p| p["name"].as_str())
+            .collect();
+        for name in ["process-id", "assign", "base-layer", "exclude"] {
+            assert!(params.contains(&name), "missing {}", name);
+        }
+        // the field doc comments end up as parameter descriptions
+        for param in post["parameters"].as_array().expect("no parameters") {
+            assert!(
+                param["description"].is_string(),
+                "undocumented {}",
+                param["name"]
+            );
+        }
+        assert!(post["requestBody"]["content"]["application/octet-stream"].is_object());
+
+        let get = &spec["paths"]["/{tx_id}"]["get"];
+        let params: Vec<&str> = get["parameters"]
+            .as_array()
+            .expect("no parameters")
+            .iter()
+            .filter_map(|p| p["name"].as_str())
+            .collect();
+        for name in ["tx_id", "from", "to", "limit", "process-id"] {
+            assert!(params.contains(&name), "missing {}", name);
+        }
+
+        // every schema a route or model points at is registered
+        let raw = spec.to_string();
+        for reference in raw.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap_or("");
+            assert!(
+                spec["components"]["schemas"][name].is_object(),
+                "unregistered schema {}",
+                name
+            );
+        }
+        assert!(spec["components"]["schemas"]["ErrorResponse"].is_object());
+    }
+
+    #[test]
+    fn test_request_id() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((REQUEST_ID, "caller-id-1"))
+            .to_srv_request();
+        assert_eq!(request_id(&req), "caller-id-1");
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((REQUEST_ID, "not an id"))
+            .to_srv_request();
+        let generated = request_id(&req);
+        assert_eq!(generated.len(), 32);
+        assert_ne!(generated, request_id(&req));
+    }
+
+    #[test]
+    fn test_check_admin_token() {
+        fn status(req: &HttpRequest, token: Option<String>) -> u16 {
+            match check_admin_token(token, req) {
+                Ok(_) => 200,
+                Err(response) => response.status().as_u16(),
+            }
+        }
+        let token = Some("secret".to_string());
+
+        let authorized = actix_web::test::TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert_eq!(status(&authorized, token.clone()), 200);
+        // the routes are not served without a configured token
+        assert_eq!(status(&authorized, None), 404);
+
+        let wrong = actix_web::test::TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer secreT"))
+            .to_http_request();
+        assert_eq!(status(&wrong, token.clone()), 401);
+
+        let not_bearer = actix_web::test::TestRequest::default()
+            .insert_header((AUTHORIZATION, "secret"))
+            .to_http_request();
+        assert_eq!(status(&not_bearer, token.clone()), 401);
+
+        let missing = actix_web::test::TestRequest::default().to_http_request();
+        assert_eq!(status(&missing, token), 401);
+    }
+
+    #[test]
+    fn test_write_err_response() {
+        let draining = write_err_response(shutdown::SHUTTING_DOWN.to_string());
+        assert_eq!(draining.status().as_u16(), 503);
+        let invalid = write_err_response("Data item is not valid".to_string());
+        assert_eq!(invalid.status().as_u16(), 400);
+    }
 }